@@ -1,14 +1,28 @@
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use kokoros::{
-    tts::koko::{TTSKoko, TTSOpts},
+    onn::ort_base::EXECUTION_PROVIDER,
+    tts::koko::{
+        DEFAULT_MAX_TOKENS, InitConfig, LogContext, MAX_INITIAL_SILENCE_TOKENS, MAX_STYLE_TOKENS,
+        TTSKoko, TTSOpts, default_silence_token, validate_tokens,
+    },
+    tts::lexicon::Lexicon,
+    tts::normalize::Pipeline,
+    tts::phoneme_cache::{DEFAULT_PHONEME_CACHE_CAPACITY, PhonemeCache},
+    tts::phonemizer::PhonemeRules,
+    tts::tokenize::tokenize,
+    tts::voices,
     utils::wav::{write_audio_chunk, WavHeader},
 };
+use serde::Deserialize;
 use std::net::{IpAddr, SocketAddr};
 use std::{
+    collections::HashMap,
     fs::{self},
-    io::Write,
+    io::{IsTerminal, Read, Write},
+    sync::Arc,
 };
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
 use tracing_subscriber::fmt::time::FormatTime;
 
 /// Custom Unix timestamp formatter for tracing logs
@@ -24,18 +38,366 @@ impl FormatTime for UnixTimestampFormatter {
     }
 }
 
+/// Spoken by `text` when no text is given and nothing is piped in
+const DEFAULT_TEXT: &str = "Hello, This is Kokoro, your remarkable AI TTS. It's a TTS model with merely 82 million parameters yet delivers incredible audio quality.
+                This is one of the top notch Rust based inference models, and I'm sure you'll love it. If you do, please give us a star. Thank you very much.
+                As the night falls, I wish you all a peaceful and restful sleep. May your dreams be filled with joy and happiness. Good night, and sweet dreams!";
+
+/// Text for the `text` mode: the argument, stdin for `-` or when input is
+/// piped in, otherwise [`DEFAULT_TEXT`]. `-` on an interactive terminal is an
+/// error rather than a silent wait for input.
+fn resolve_text(text: Option<String>) -> Result<String, Box<dyn std::error::Error>> {
+    let stdin = std::io::stdin();
+    let read_stdin = || -> std::io::Result<String> {
+        let mut buffer = String::new();
+        std::io::stdin().read_to_string(&mut buffer)?;
+        Ok(buffer)
+    };
+    match text {
+        Some(text) if text == "-" => {
+            if stdin.is_terminal() {
+                return Err("`text -` reads from stdin, but nothing is piped in. \
+                     Usage: cat script.txt | koko text - -o out.wav"
+                    .into());
+            }
+            let text = read_stdin()?;
+            if text.trim().is_empty() {
+                return Err("no text on stdin".into());
+            }
+            Ok(text)
+        }
+        Some(text) => Ok(text),
+        None if !stdin.is_terminal() => {
+            let text = read_stdin()?;
+            Ok(if text.trim().is_empty() {
+                DEFAULT_TEXT.to_string()
+            } else {
+                text
+            })
+        }
+        None => Ok(DEFAULT_TEXT.to_string()),
+    }
+}
+
+/// Output path for line `line` in file mode: `{line}` and `{n}` in `format`
+/// are replaced by the line number, and a format without either gets
+/// `_<line>` inserted before its extension (`out.wav` -> `out_3.wav`)
+fn line_output_path(format: &str, line: usize) -> String {
+    if format.contains("{line}") || format.contains("{n}") {
+        return format
+            .replace("{line}", &line.to_string())
+            .replace("{n}", &line.to_string());
+    }
+    let path = std::path::Path::new(format);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => path
+            .with_file_name(format!(
+                "{}_{}.{}",
+                stem.to_string_lossy(),
+                line,
+                extension.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}_{}", format, line),
+    }
+}
+
+/// Output path for `--single-file`: the file mode format with its line
+/// placeholder (and a separator right before it) removed
+fn single_output_path(format: &str) -> String {
+    let mut path = format.to_string();
+    for placeholder in ["{line}", "{n}"] {
+        for separator in ["_", "-", ""] {
+            path = path.replace(&format!("{}{}", separator, placeholder), "");
+        }
+    }
+    path
+}
+
+/// Streaming chunk delimiters from the `openai` flags, English where a flag isn't given
+fn chunk_delimiters(
+    break_words: Option<Vec<String>>,
+    sentence_punctuation: Option<String>,
+    clause_punctuation: Option<String>,
+) -> kokoros_openai::ChunkDelimiters {
+    let mut delimiters = kokoros_openai::ChunkDelimiters::default();
+    if let Some(words) = break_words {
+        delimiters.break_words = words
+            .iter()
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+    }
+    if let Some(chars) = sentence_punctuation {
+        delimiters.sentence_punctuation = chars.chars().collect();
+    }
+    if let Some(chars) = clause_punctuation {
+        delimiters.clause_punctuation = chars.chars().collect();
+    }
+    delimiters
+}
+
+/// Where a generated file goes: relative paths are resolved against
+/// `output_dir` when one is given. The file's directory is created if missing
+fn output_file_path(path: &str, output_dir: Option<&str>) -> std::io::Result<String> {
+    let path = match output_dir {
+        Some(dir) => std::path::Path::new(dir).join(path),
+        None => std::path::PathBuf::from(path),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Lines for stream mode: the file at `text_file`, otherwise stdin
+fn stream_input(text_file: Option<&str>) -> std::io::Result<Box<dyn AsyncBufRead + Unpin>> {
+    Ok(match text_file {
+        Some(path) => Box::new(std::io::Cursor::new(fs::read(path)?)),
+        None => Box::new(BufReader::new(tokio::io::stdin())),
+    })
+}
+
+/// Stream mode: write a WAV header to `out`, then the audio of each
+/// non-blank line of `input` as soon as `synthesize` returns it. Lines that
+/// fail are reported and skipped. `interactive` adds prompts for typed input.
+async fn stream_lines<R: AsyncBufRead + Unpin>(
+    input: R,
+    out: &mut impl Write,
+    interactive: bool,
+    mut synthesize: impl FnMut(&str) -> Result<Vec<f32>, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut lines = input.lines();
+
+    if interactive {
+        eprintln!("Entering streaming mode. Type text and press Enter. Use Ctrl+D to exit.");
+    }
+
+    // Write WAV header first
+    let header = WavHeader::new(1, 24000, 32);
+    header.write_header(out)?;
+    out.flush()?;
+
+    while let Some(line) = lines.next_line().await? {
+        let stripped_line = line.trim();
+        if stripped_line.is_empty() {
+            continue;
+        }
+
+        match synthesize(stripped_line) {
+            Ok(raw_audio) => {
+                // Write the raw audio samples directly
+                write_audio_chunk(out, &raw_audio)?;
+                out.flush()?;
+                if interactive {
+                    eprintln!("Audio written to stdout. Ready for another line of text.");
+                }
+            }
+            Err(e) => eprintln!("Error processing line: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Concatenate per-line audio with `gap_samples` of silence between lines
+fn join_with_gaps(lines: Vec<Vec<f32>>, gap_samples: usize) -> Vec<f32> {
+    let mut audio = Vec::new();
+    for (i, line) in lines.into_iter().enumerate() {
+        if i > 0 {
+            audio.resize(audio.len() + gap_samples, 0.0);
+        }
+        audio.extend(line);
+    }
+    audio
+}
+
+/// Settings read from `--config`. Every field is optional, and a flag given
+/// on the command line wins over the same setting here
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    model_path: Option<String>,
+    voices_path: Option<String>,
+    host: Option<IpAddr>,
+    port: Option<u16>,
+    /// Style for the CLI modes and the server's default voice
+    voice: Option<String>,
+    speed: Option<f32>,
+    /// Execution provider the binary must have been built for (`cpu` or `cuda`)
+    provider: Option<String>,
+    /// Server voice aliases; `--voice-alias` wins for the same alias
+    voice_aliases: Option<HashMap<String, String>>,
+}
+
+impl FileConfig {
+    fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config {}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("invalid config {}: {}", path, e).into())
+    }
+
+    /// Fill in every setting of `cli` that wasn't given on the command line
+    fn apply(self, cli: &mut Cli, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(provider) = &self.provider
+            && !provider.eq_ignore_ascii_case(EXECUTION_PROVIDER)
+        {
+            return Err(format!(
+                "config asks for the {} provider, but this build uses {}",
+                provider, EXECUTION_PROVIDER
+            )
+            .into());
+        }
+
+        override_unless_given(&mut cli.model_path, self.model_path, matches, "model_path");
+        override_unless_given(
+            &mut cli.data_paths,
+            self.voices_path.map(|path| vec![path]),
+            matches,
+            "data_paths",
+        );
+        override_unless_given(&mut cli.style, self.voice, matches, "style");
+        override_unless_given(&mut cli.speed, self.speed, matches, "speed");
+        if let (Mode::OpenAI(args), Some(openai)) =
+            (&mut cli.mode, matches.subcommand_matches("openai"))
+        {
+            override_unless_given(&mut args.ip, self.host, openai, "ip");
+            override_unless_given(&mut args.port, self.port, openai, "port");
+        }
+        if let Mode::OpenAI(args) = &mut cli.mode {
+            let voice_aliases = &mut args.voice_aliases;
+            let given = std::mem::take(voice_aliases);
+            voice_aliases.extend(self.voice_aliases.unwrap_or_default());
+            voice_aliases.extend(given);
+        }
+        Ok(())
+    }
+}
+
+fn parse_voice_alias(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((alias, voice)) if !alias.trim().is_empty() && !voice.trim().is_empty() => {
+            Ok((alias.trim().to_string(), voice.trim().to_string()))
+        }
+        _ => Err(format!("expected ALIAS=VOICE, got {:?}", value)),
+    }
+}
+
+fn given_on_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+fn override_unless_given<T>(field: &mut T, value: Option<T>, matches: &ArgMatches, id: &str) {
+    if let Some(value) = value
+        && !given_on_command_line(matches, id)
+    {
+        *field = value;
+    }
+}
+
+/// Results of `koko benchmark`
+#[derive(Debug, PartialEq)]
+struct BenchmarkSummary {
+    runs: usize,
+    mean_seconds: f64,
+    p95_seconds: f64,
+    /// Audio seconds produced per wall-clock second spent synthesizing
+    real_time_factor: f64,
+}
+
+impl BenchmarkSummary {
+    fn print(&self) {
+        eprintln!("{:<20} {:>10}", "runs", self.runs);
+        eprintln!("{:<20} {:>9.3}s", "mean latency", self.mean_seconds);
+        eprintln!("{:<20} {:>9.3}s", "p95 latency", self.p95_seconds);
+        eprintln!("{:<20} {:>9.2}x", "real-time factor", self.real_time_factor);
+    }
+}
+
+/// Summarize per-run latencies; `audio_seconds` is the audio all runs produced.
+/// p95 is nearest-rank, so with fewer than 20 runs it is the slowest run.
+fn benchmark_summary(latencies: &[std::time::Duration], audio_seconds: f64) -> BenchmarkSummary {
+    let mut seconds: Vec<f64> = latencies.iter().map(|d| d.as_secs_f64()).collect();
+    seconds.sort_by(f64::total_cmp);
+    let total: f64 = seconds.iter().sum();
+    let p95_rank = (seconds.len() as f64 * 0.95).ceil() as usize;
+    BenchmarkSummary {
+        runs: seconds.len(),
+        mean_seconds: total / seconds.len().max(1) as f64,
+        p95_seconds: seconds
+            .get(p95_rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default(),
+        real_time_factor: if total > 0.0 {
+            audio_seconds / total
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Outcome of synthesizing [`VALIDATE_VOICES_TEXT`] with one voice, for
+/// `koko validate-voices`
+#[derive(Debug, PartialEq)]
+struct VoiceCheck {
+    voice: String,
+    /// Seconds of audio produced, or why the voice failed
+    result: Result<f64, String>,
+}
+
+/// Phrase each voice synthesizes in `koko validate-voices`
+const VALIDATE_VOICES_TEXT: &str = "Hello.";
+
+/// Run `synthesize` for `voice`. Errors, panics (a voice tensor of the wrong
+/// shape indexes out of bounds) and empty or non-finite audio all fail it.
+/// A panic doesn't fail the voices checked after it, since the model lock
+/// it poisons is recovered.
+fn check_voice(
+    voice: &str,
+    sample_rate: u32,
+    synthesize: impl FnOnce() -> Result<Vec<f32>, Box<dyn std::error::Error>>,
+) -> VoiceCheck {
+    let result = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(synthesize)) {
+        Ok(Ok(audio)) if audio.is_empty() => Err("no audio".to_string()),
+        Ok(Ok(audio)) if !audio.iter().all(|s| s.is_finite()) => {
+            Err("non-finite samples".to_string())
+        }
+        Ok(Ok(audio)) => Ok(audio.len() as f64 / sample_rate as f64),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("synthesis panicked".to_string()),
+    };
+    VoiceCheck {
+        voice: voice.to_string(),
+        result,
+    }
+}
+
+/// Print one row per voice and a summary line, returning how many failed
+fn print_voice_checks(checks: &[VoiceCheck]) -> usize {
+    eprintln!("{:<24} result details", "voice");
+    for check in checks {
+        match &check.result {
+            Ok(seconds) => eprintln!("{:<24} pass   {:.2}s audio", check.voice, seconds),
+            Err(e) => eprintln!("{:<24} FAIL   {}", check.voice, e),
+        }
+    }
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    eprintln!(
+        "{} of {} voices passed",
+        checks.len() - failed,
+        checks.len()
+    );
+    failed
+}
+
 #[derive(Subcommand, Debug)]
 enum Mode {
     /// Generate speech for a string of text
     #[command(alias = "t", long_flag_alias = "text", short_flag_alias = 't')]
     Text {
-        /// Text to generate speech for
-        #[arg(
-            default_value = "Hello, This is Kokoro, your remarkable AI TTS. It's a TTS model with merely 82 million parameters yet delivers incredible audio quality.
-                This is one of the top notch Rust based inference models, and I'm sure you'll love it. If you do, please give us a star. Thank you very much.
-                As the night falls, I wish you all a peaceful and restful sleep. May your dreams be filled with joy and happiness. Good night, and sweet dreams!"
-        )]
-        text: String,
+        /// Text to generate speech for. Pass `-`, or leave it out and pipe text in,
+        /// to synthesize the whole of stdin as one document
+        text: Option<String>,
 
         /// Path to output the WAV file to on the filesystem
         #[arg(
@@ -53,7 +415,8 @@ enum Mode {
         /// Filesystem path to read lines from
         input_path: String,
 
-        /// Format for the output path of each WAV file, where {line} will be replaced with the line number
+        /// Format for the output path of each WAV file, where {line} (or {n}) will be replaced
+        /// with the line number. Without a placeholder the number goes before the extension
         #[arg(
             short = 'o',
             long = "output",
@@ -61,23 +424,199 @@ enum Mode {
             default_value = "tmp/output_{line}.wav"
         )]
         save_path_format: String,
+
+        /// Write all lines to one WAV file instead of one per line.
+        /// The output path then names that file, with any {line} placeholder dropped
+        #[arg(long = "single-file", default_value_t = false)]
+        single_file: bool,
+
+        /// Silence between lines with --single-file, in milliseconds
+        #[arg(long = "line-gap-ms", value_name = "MS", default_value_t = 500)]
+        line_gap_ms: u32,
     },
 
     /// Continuously read from stdin to generate speech, outputting to stdout, for each line
-    #[command(aliases = ["stdio", "stdin", "-"], long_flag_aliases = ["stdio", "stdin"])]
-    Stream,
+    #[command(aliases = ["stdio", "stdin", "-"], long_flag_aliases = ["stdio", "stdin", "stream"])]
+    Stream {
+        /// Stream the lines of this file instead of stdin, without the interactive prompts
+        #[arg(long = "text-file", value_name = "PATH")]
+        text_file: Option<String>,
+    },
+
+    /// Synthesize the sample text repeatedly and report latency and real-time factor
+    #[command(alias = "bench", long_flag_alias = "benchmark")]
+    Benchmark {
+        /// Number of measured runs, after one unmeasured warmup run
+        #[arg(short = 'n', long = "runs", value_name = "RUNS", default_value_t = 10)]
+        runs: usize,
+    },
+
+    /// Synthesize a short phrase with every loaded voice and report which
+    /// fail, exiting non-zero if any do
+    #[command(name = "validate-voices", long_flag_alias = "validate-voices")]
+    ValidateVoices,
 
     /// Start an OpenAI-compatible HTTP server
     #[command(name = "openai", alias = "oai", long_flag_aliases = ["oai", "openai"])]
-    OpenAI {
-        /// IP address to bind to (typically 127.0.0.1 or 0.0.0.0)
-        #[arg(long, default_value_t = [0, 0, 0, 0].into())]
-        ip: IpAddr,
-
-        /// Port to expose the HTTP server on
-        #[arg(long, default_value_t = 3000)]
-        port: u16,
-    },
+    OpenAI(Box<OpenAiArgs>),
+}
+
+/// Options of the `openai` mode, boxed in [`Mode`] as they far outweigh
+/// the other modes' options
+#[derive(Args, Debug)]
+struct OpenAiArgs {
+    /// IP address to bind to (typically 127.0.0.1 or 0.0.0.0)
+    #[arg(long, default_value_t = [0, 0, 0, 0].into())]
+    ip: IpAddr,
+
+    /// Port to expose the HTTP server on
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+
+    /// Bearer token enabling /v1/debug/synthesize-tokens and the /v1/admin/* endpoints (disabled when unset)
+    #[arg(long = "admin-token", value_name = "TOKEN")]
+    admin_token: Option<String>,
+
+    /// Number of non-streaming responses to cache for identical requests (0 disables)
+    #[arg(long = "cache-size", value_name = "ENTRIES", default_value_t = 0)]
+    cache_size: usize,
+
+    /// Expose unauthenticated debugging endpoints such as /v1/debug/chunks
+    #[arg(long = "debug-endpoints", default_value_t = false)]
+    debug_endpoints: bool,
+
+    /// Comma-separated voices to warm up in the background after startup
+    #[arg(long = "warmup-voices", value_name = "VOICES", value_delimiter = ',')]
+    warmup_voices: Vec<String>,
+
+    /// Maximum chunks per streaming request; longer inputs are chunked more coarsely
+    #[arg(long = "max-chunks", value_name = "CHUNKS")]
+    max_chunks: Option<usize>,
+
+    /// Most words in one streaming chunk; longer clauses without a break point
+    /// are cut between words (default twice the words-per-chunk target)
+    #[arg(
+        long = "max-chunk-words",
+        value_name = "WORDS",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_chunk_words: Option<usize>,
+
+    /// Streaming chunks in flight per request (defaults to --instances).
+    /// Raising it queues more work on each instance without loading extra models
+    #[arg(long = "stream-window", value_name = "CHUNKS")]
+    stream_window: Option<usize>,
+
+    /// Synthesized chunks buffered for a slow streaming client before
+    /// synthesis waits on it (default 4)
+    #[arg(long = "stream-buffer", value_name = "CHUNKS")]
+    stream_buffer: Option<usize>,
+
+    /// Send a few ms of silence as soon as an MP3 stream starts, for clients
+    /// that time out waiting on a long first chunk
+    #[arg(long = "stream-keep-alive", default_value_t = false)]
+    stream_keep_alive: bool,
+
+    /// Seconds a request may spend synthesizing before it fails with 504
+    /// or its stream ends. A chunk already on the model still runs to completion
+    #[arg(long = "request-timeout", value_name = "SECONDS")]
+    request_timeout: Option<f64>,
+
+    /// Extra attempts, each on the next instance, for a streaming chunk
+    /// whose synthesis fails before it is left out of the audio
+    #[arg(
+        long = "chunk-retries",
+        value_name = "RETRIES",
+        default_value_t = kokoros_openai::DEFAULT_CHUNK_RETRIES
+    )]
+    chunk_retries: usize,
+
+    /// Milliseconds of fade-in and fade-out at the ends of the audio (0 disables)
+    #[arg(
+        long = "fade-ms",
+        value_name = "MS",
+        default_value_t = kokoros_openai::DEFAULT_FADE_MS
+    )]
+    fade_ms: u32,
+
+    /// Longest accepted input in characters; longer requests get 413
+    #[arg(
+        long = "max-input-chars",
+        value_name = "CHARS",
+        default_value_t = kokoros_openai::DEFAULT_MAX_INPUT_CHARS
+    )]
+    max_input_chars: usize,
+
+    /// Origin allowed to call the API from a browser; repeat for more.
+    /// Any origin is allowed when none are given
+    #[arg(long = "cors-origin", value_name = "ORIGIN")]
+    cors_origins: Vec<String>,
+
+    /// Speech requests allowed in flight at once; further requests get 503
+    /// with Retry-After instead of queuing (unlimited when unset)
+    #[arg(long = "max-queue-depth", value_name = "REQUESTS")]
+    max_queue_depth: Option<usize>,
+
+    /// Always phonemize as en-us when a request has no lang_code and its
+    /// voice doesn't imply a language, instead of detecting the language
+    #[arg(long = "no-detect-language", default_value_t = false)]
+    no_detect_language: bool,
+
+    /// Cut the quiet lead-in from the first chunk of streamed audio, so
+    /// playback starts sooner
+    #[arg(long = "trim-leading-silence", default_value_t = false)]
+    trim_leading_silence: bool,
+
+    /// Softly limit peaks that would clip in 16-bit output, as blended
+    /// voices can produce
+    #[arg(long = "peak-limit", default_value_t = false)]
+    peak_limit: bool,
+
+    /// WAV file played before the speech of non-streaming responses, e.g. a chime
+    #[arg(long = "prefix-audio", value_name = "WAV")]
+    prefix_audio: Option<String>,
+
+    /// WAV file played after the speech of non-streaming responses
+    #[arg(long = "suffix-audio", value_name = "WAV")]
+    suffix_audio: Option<String>,
+
+    /// Reject input containing control characters with 400 instead of
+    /// stripping them before phonemization
+    #[arg(long = "reject-control-chars", default_value_t = false)]
+    reject_control_chars: bool,
+
+    /// Send the synthesized phonemes of non-streaming responses in an
+    /// X-Phonemes header, for checking pronunciation
+    #[arg(long = "phonemes-header", default_value_t = false)]
+    phonemes_header: bool,
+
+    /// Check that streams send their chunks in order, each exactly once,
+    /// logging an error for any that don't
+    #[arg(long = "verify-stream-order", default_value_t = false)]
+    verify_stream_order: bool,
+
+    /// Comma-separated words a streaming chunk shouldn't start with,
+    /// replacing the English conjunctions, e.g. `y,o,pero` for Spanish
+    #[arg(long = "break-words", value_name = "WORDS", value_delimiter = ',')]
+    break_words: Option<Vec<String>>,
+
+    /// Characters that end a sentence when chunking streams (default `.!?`)
+    #[arg(long = "sentence-punctuation", value_name = "CHARS")]
+    sentence_punctuation: Option<String>,
+
+    /// Characters long sentences are split at when chunking streams (default `,;:`)
+    #[arg(long = "clause-punctuation", value_name = "CHARS")]
+    clause_punctuation: Option<String>,
+
+    /// Voice used, with an X-Voice-Fallback header, when a request names
+    /// a voice that doesn't exist, instead of failing the request
+    #[arg(long = "fallback-voice", value_name = "VOICE")]
+    fallback_voice: Option<String>,
+
+    /// Voice used when a request names ALIAS, e.g. `alloy=af_heart`; repeat
+    /// for more. OpenAI's six voice names already have defaults
+    #[arg(long = "voice-alias", value_name = "ALIAS=VOICE", value_parser = parse_voice_alias)]
+    voice_aliases: Vec<(String, String)>,
 }
 
 #[derive(Parser, Debug)]
@@ -104,14 +643,20 @@ struct Cli {
     )]
     model_path: String,
 
-    /// Path to the voices data file on the filesystem
+    /// Path to the voices data file on the filesystem. Repeat to merge several files,
+    /// with voices in later files replacing same-named ones from earlier files
     #[arg(
         short = 'd',
         long = "data",
         value_name = "DATA_PATH",
         default_value = "data/voices-v1.0.bin"
     )]
-    data_path: String,
+    data_paths: Vec<String>,
+
+    /// Download the voices file from this URL at startup, replacing the first
+    /// --data file even when it already exists
+    #[arg(long = "voices-url", value_name = "URL")]
+    voices_url: Option<String>,
 
     /// Which single voice to use or voices to combine to serve as the style of speech
     #[arg(
@@ -140,13 +685,82 @@ struct Cli {
     mono: bool,
 
     /// Initial silence duration in tokens
-    #[arg(long = "initial-silence", value_name = "INITIAL_SILENCE")]
+    #[arg(
+        long = "initial-silence",
+        value_name = "INITIAL_SILENCE",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new()
+            .range(0..=MAX_INITIAL_SILENCE_TOKENS as u64)
+    )]
     initial_silence: Option<usize>,
 
     /// Number of TTS instances for parallel processing
     #[arg(long = "instances", value_name = "INSTANCES", default_value_t = 2)]
     instances: usize,
 
+    /// JSON file of text -> phonemes pairs consulted before espeak. Created if missing,
+    /// and updated with newly phonemized text when a run finishes; a server also
+    /// saves it every minute and when stopped with Ctrl+C or SIGTERM
+    #[arg(long = "phoneme-cache", value_name = "CACHE_PATH")]
+    phoneme_cache: Option<String>,
+
+    /// Most entries the phoneme cache keeps, evicting the oldest beyond it
+    #[arg(
+        long = "phoneme-cache-capacity",
+        value_name = "ENTRIES",
+        default_value_t = DEFAULT_PHONEME_CACHE_CAPACITY
+    )]
+    phoneme_cache_capacity: usize,
+
+    /// JSON file of pronunciation overrides, mapping a word to IPA between slashes
+    /// (`"Kokoro": "/kˈoʊkəɹoʊ/"`) or to a respelling (`"nginx": "engine x"`)
+    #[arg(long = "lexicon", value_name = "LEXICON_PATH")]
+    lexicon: Option<String>,
+
+    /// JSON list of `[pattern, replacement]` regex pairs rewriting espeak's phonemes,
+    /// in place of the built-in kokoro fix-ups
+    #[arg(long = "phoneme-rules", value_name = "RULES_PATH")]
+    phoneme_rules: Option<String>,
+
+    /// Comma-separated text preprocessing steps, applied in order before chunking
+    /// (strip_markdown, expand_urls, normalize_numbers, normalize_text,
+    /// normalize_whitespace, or none)
+    #[arg(
+        long = "preprocess",
+        value_name = "STEPS",
+        value_delimiter = ',',
+        default_value = "none"
+    )]
+    preprocess: Vec<String>,
+
+    /// Leave dotted acronyms (U.S.A.) for espeak in the normalize_text step
+    /// instead of hyphenating them
+    #[arg(long = "keep-acronyms", default_value_t = false)]
+    keep_acronyms: bool,
+
+    /// Maximum tokens per synthesized chunk; longer sentences are split
+    #[arg(long = "max-tokens", value_name = "TOKENS", default_value_t = DEFAULT_MAX_TOKENS)]
+    max_tokens: usize,
+
+    /// Token id repeated for --initial-silence, for experimenting with other
+    /// pause tokens (16 is the space between words)
+    #[arg(long = "silence-token", value_name = "TOKEN", default_value_t = default_silence_token())]
+    silence_token: i64,
+
+    /// Print each chunk's phonemes and token count instead of synthesizing.
+    /// Runs the same preprocessing, chunking and espeak steps, but never loads the model
+    #[arg(long = "phonemes-only", default_value_t = false)]
+    phonemes_only: bool,
+
+    /// Directory for the files written by text and file mode, created if missing.
+    /// Relative output paths are resolved against it
+    #[arg(long = "output-dir", value_name = "DIR")]
+    output_dir: Option<String>,
+
+    /// JSON file with model_path, voices_path, host, port, voice, speed and provider.
+    /// Flags given on the command line take precedence over it
+    #[arg(long = "config", value_name = "CONFIG_PATH")]
+    config: Option<String>,
+
     #[command(subcommand)]
     mode: Mode,
 }
@@ -161,26 +775,135 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .init();
     
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    // The server only overrides its own default voice and speed when asked to
+    let mut voice_given = given_on_command_line(&matches, "style");
+    let mut speed_given = given_on_command_line(&matches, "speed");
+    if let Some(path) = cli.config.take() {
+        let file_config = FileConfig::load(&path)?;
+        voice_given |= file_config.voice.is_some();
+        speed_given |= file_config.speed.is_some();
+        file_config.apply(&mut cli, &matches)?;
+    }
+
     let rt = tokio::runtime::Runtime::new()?;
     rt.block_on(async {
         let Cli {
             lan,
             model_path,
-            data_path,
+            data_paths,
+            voices_url,
             style,
             speed,
             initial_silence,
             mono,
             instances,
+            phoneme_cache,
+            phoneme_cache_capacity,
+            lexicon,
+            phoneme_rules,
+            preprocess,
+            keep_acronyms,
+            max_tokens,
+            silence_token,
+            phonemes_only,
+            output_dir,
             mode,
-        } = Cli::parse();
+            ..
+        } = cli;
+
+        // Leave room for the two padding tokens added around every chunk
+        if max_tokens == 0 || max_tokens > MAX_STYLE_TOKENS - 2 {
+            return Err(format!(
+                "--max-tokens must be between 1 and {}",
+                MAX_STYLE_TOKENS - 2
+            )
+            .into());
+        }
+        validate_tokens(&[silence_token]).map_err(|e| format!("invalid --silence-token: {}", e))?;
+
+        let cache = match &phoneme_cache {
+            Some(path) => Some(Arc::new(
+                PhonemeCache::load(path)?.with_capacity(phoneme_cache_capacity),
+            )),
+            None => None,
+        };
+        let lexicon = match &lexicon {
+            Some(path) => Some(Arc::new(Lexicon::load(path)?)),
+            None => None,
+        };
+        let phoneme_rules = match &phoneme_rules {
+            Some(path) => Arc::new(PhonemeRules::load(path)?),
+            None => Arc::new(PhonemeRules::default()),
+        };
+        let mut init_config = InitConfig {
+            phoneme_cache: cache.clone(),
+            lexicon,
+            phoneme_rules,
+            preprocessing: Pipeline::from_names(&preprocess)?.with_keep_acronyms(keep_acronyms),
+            max_tokens,
+            force_voices_download: voices_url.is_some(),
+            silence_token,
+            ..InitConfig::default()
+        };
+        if let Some(url) = voices_url {
+            init_config.voices_url = url;
+        }
+
+        if phonemes_only {
+            print_phonemes(mode, &init_config, &lan).await?;
+            save_phoneme_cache(phoneme_cache.as_deref(), cache.as_deref())?;
+            return Ok(());
+        }
 
-        let tts = TTSKoko::new(&model_path, &data_path).await;
+        let data_paths: Vec<&str> = data_paths.iter().map(String::as_str).collect();
+        let tts =
+            TTSKoko::from_config_with_voices(&model_path, &data_paths, init_config.clone()).await;
+        // The voices file is fresh now, server instances shouldn't fetch it again
+        init_config.force_voices_download = false;
 
         match mode {
             Mode::File {
                 input_path,
                 save_path_format,
+                single_file: true,
+                line_gap_ms,
+            } => {
+                let file_content = fs::read_to_string(input_path)?;
+                let mut lines_audio = Vec::new();
+                for line in file_content.lines() {
+                    let stripped_line = line.trim();
+                    if stripped_line.is_empty() {
+                        continue;
+                    }
+
+                    let stripped_line = tts.preprocess(stripped_line);
+                    lines_audio.push(tts.tts_raw_audio(
+                        &stripped_line,
+                        &lan,
+                        &style,
+                        speed,
+                        initial_silence,
+                        LogContext::default(),
+                    )?);
+                }
+
+                let gap_samples =
+                    (init_config.sample_rate as u64 * line_gap_ms as u64 / 1000) as usize;
+                let audio = join_with_gaps(lines_audio, gap_samples);
+                let save_path = output_file_path(
+                    &single_output_path(&save_path_format),
+                    output_dir.as_deref(),
+                )?;
+                tts.save_wav(&save_path, &audio, mono)?;
+                eprintln!("Audio saved to {}", save_path);
+            }
+
+            Mode::File {
+                input_path,
+                save_path_format,
+                ..
             } => {
                 let file_content = fs::read_to_string(input_path)?;
                 for (i, line) in file_content.lines().enumerate() {
@@ -189,7 +912,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         continue;
                     }
 
-                    let save_path = save_path_format.replace("{line}", &i.to_string());
+                    let save_path = output_file_path(
+                        &line_output_path(&save_path_format, i),
+                        output_dir.as_deref(),
+                    )?;
                     tts.tts(TTSOpts {
                         txt: stripped_line,
                         lan: &lan,
@@ -203,6 +929,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
 
             Mode::Text { text, save_path } => {
+                let text = resolve_text(text)?;
+                let save_path = output_file_path(&save_path, output_dir.as_deref())?;
                 let s = std::time::Instant::now();
                 tts.tts(TTSOpts {
                     txt: &text,
@@ -219,58 +947,511 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("Words per second: {:.2}", words_per_second);
             }
 
-            Mode::OpenAI { ip, port } => {
-                // Create multiple independent TTS instances for parallel processing
-                let mut tts_instances = Vec::new();
+            Mode::OpenAI(openai) => {
+                let OpenAiArgs {
+                    ip,
+                    port,
+                    admin_token,
+                    cache_size,
+                    debug_endpoints,
+                    warmup_voices,
+                    max_chunks,
+                    max_chunk_words,
+                    stream_window,
+                    stream_buffer,
+                    stream_keep_alive,
+                    request_timeout,
+                    chunk_retries,
+                    fade_ms,
+                    max_input_chars,
+                    cors_origins,
+                    max_queue_depth,
+                    no_detect_language,
+                    trim_leading_silence,
+                    peak_limit,
+                    prefix_audio,
+                    suffix_audio,
+                    reject_control_chars,
+                    phonemes_header,
+                    verify_stream_order,
+                    break_words,
+                    sentence_punctuation,
+                    clause_punctuation,
+                    fallback_voice,
+                    voice_aliases,
+                } = *openai;
+                // Create multiple independent TTS instances for parallel processing.
+                // Each has its own model session, the voices are read once and
+                // shared, so a reload reaches every instance at once
+                let mut tts_instances: Vec<TTSKoko> = Vec::new();
                 for i in 0..instances {
                     tracing::info!("Initializing TTS instance [{}] ({}/{})", format!("{:02x}", i), i + 1, instances);
-                    let instance = TTSKoko::new(&model_path, &data_path).await;
+                    let instance = match tts_instances.first() {
+                        Some(first) => first.new_instance_sharing_voices(),
+                        None => {
+                            TTSKoko::from_config_with_voices(
+                                &model_path,
+                                &data_paths,
+                                init_config.clone(),
+                            )
+                            .await
+                        }
+                    };
                     tts_instances.push(instance);
                 }
-                let app = kokoros_openai::create_server(tts_instances).await;
+                let config = kokoros_openai::ServerConfig {
+                    admin_token,
+                    cache_size,
+                    debug_endpoints,
+                    warmup_voices,
+                    max_chunks,
+                    max_chunk_words,
+                    stream_window,
+                    stream_buffer,
+                    default_voice: voice_given.then(|| style.clone()),
+                    default_speed: speed_given.then_some(speed),
+                    stream_keep_alive,
+                    request_timeout: request_timeout
+                        .map(std::time::Duration::try_from_secs_f64)
+                        .transpose()
+                        .map_err(|e| format!("invalid --request-timeout: {}", e))?,
+                    chunk_retries: Some(chunk_retries),
+                    fade_ms: Some(fade_ms),
+                    max_input_chars: Some(max_input_chars),
+                    cors_origins,
+                    max_queue_depth,
+                    fallback_voice,
+                    voice_aliases: voice_aliases.into_iter().collect(),
+                    detect_language: !no_detect_language,
+                    trim_leading_silence,
+                    peak_limit,
+                    prefix_audio_path: prefix_audio,
+                    suffix_audio_path: suffix_audio,
+                    reject_control_chars,
+                    phonemes_header,
+                    verify_stream_order,
+                    chunk_delimiters: chunk_delimiters(
+                        break_words,
+                        sentence_punctuation,
+                        clause_punctuation,
+                    ),
+                };
+                let app = kokoros_openai::create_server_with_config(tts_instances, config).await?;
                 let addr = SocketAddr::from((ip, port));
                 let binding = tokio::net::TcpListener::bind(&addr).await?;
                 tracing::info!("Starting OpenAI-compatible HTTP server on {}", addr);
-                kokoros_openai::serve(binding, app.into_make_service()).await?;
+                if let (Some(path), Some(cache)) = (&phoneme_cache, &cache) {
+                    tokio::spawn(save_phoneme_cache_periodically(path.clone(), cache.clone()));
+                }
+                // Returning lets the phoneme cache be saved below
+                kokoros_openai::serve(binding, app.into_make_service())
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await?;
+            }
+
+            Mode::Benchmark { runs } => {
+                if runs == 0 {
+                    return Err("--runs must be at least 1".into());
+                }
+                let text = tts.preprocess(DEFAULT_TEXT);
+                let synthesize = || {
+                    tts.tts_raw_audio(
+                        &text,
+                        &lan,
+                        &style,
+                        speed,
+                        initial_silence,
+                        LogContext::default(),
+                    )
+                };
+
+                eprintln!("Warming up...");
+                synthesize()?;
+                let mut latencies = Vec::with_capacity(runs);
+                let mut audio_samples = 0;
+                for run in 1..=runs {
+                    let start = std::time::Instant::now();
+                    audio_samples += synthesize()?.len();
+                    let latency = start.elapsed();
+                    eprintln!("run {}/{}: {:.3}s", run, runs, latency.as_secs_f64());
+                    latencies.push(latency);
+                }
+
+                let audio_seconds = audio_samples as f64 / init_config.sample_rate as f64;
+                benchmark_summary(&latencies, audio_seconds).print();
             }
 
-            Mode::Stream => {
-                let stdin = tokio::io::stdin();
-                let reader = BufReader::new(stdin);
-                let mut lines = reader.lines();
+            Mode::ValidateVoices => {
+                let text = tts.preprocess(VALIDATE_VOICES_TEXT);
+                let checks: Vec<VoiceCheck> = tts
+                    .get_available_voices()
+                    .iter()
+                    .map(|voice| {
+                        // Each voice in its own language, as the server would
+                        let lan = voices::style_language(voice).unwrap_or(&lan);
+                        check_voice(voice, init_config.sample_rate, || {
+                            tts.tts_raw_audio(&text, lan, voice, speed, None, LogContext::default())
+                        })
+                    })
+                    .collect();
+                let failed = print_voice_checks(&checks);
+                if failed > 0 {
+                    return Err(format!("{} of {} voices failed", failed, checks.len()).into());
+                }
+            }
 
+            Mode::Stream { text_file } => {
                 // Use std::io::stdout() for sync writing
                 let mut stdout = std::io::stdout();
+                let synthesize = |line: &str| {
+                    let line = tts.preprocess(line);
+                    tts.tts_raw_audio(
+                        &line,
+                        &lan,
+                        &style,
+                        speed,
+                        initial_silence,
+                        LogContext::default(),
+                    )
+                };
+                let input = stream_input(text_file.as_deref())?;
+                stream_lines(input, &mut stdout, text_file.is_none(), synthesize).await?;
+            }
+        }
 
-                eprintln!(
-                    "Entering streaming mode. Type text and press Enter. Use Ctrl+D to exit."
-                );
+        save_phoneme_cache(phoneme_cache.as_deref(), cache.as_deref())?;
 
-                // Write WAV header first
-                let header = WavHeader::new(1, 24000, 32);
-                header.write_header(&mut stdout)?;
-                stdout.flush()?;
+        Ok(())
+    })
+}
 
-                while let Some(line) = lines.next_line().await? {
-                    let stripped_line = line.trim();
-                    if stripped_line.is_empty() {
-                        continue;
-                    }
+fn save_phoneme_cache(
+    path: Option<&str>,
+    cache: Option<&PhonemeCache>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let (Some(path), Some(cache)) = (path, cache) {
+        cache.save(path)?;
+        tracing::info!("Saved {} cached phonemizations to {}", cache.len(), path);
+    }
+    Ok(())
+}
 
-                    // Process the line and get audio data
-                    match tts.tts_raw_audio(&stripped_line, &lan, &style, speed, initial_silence, None, None, None) {
-                        Ok(raw_audio) => {
-                            // Write the raw audio samples directly
-                            write_audio_chunk(&mut stdout, &raw_audio)?;
-                            stdout.flush()?;
-                            eprintln!("Audio written to stdout. Ready for another line of text.");
-                        }
-                        Err(e) => eprintln!("Error processing line: {}", e),
-                    }
-                }
+/// Seconds between saves of a server's phoneme cache
+const PHONEME_CACHE_SAVE_SECS: u64 = 60;
+
+/// Save a server's phoneme cache whenever it changed, every
+/// [`PHONEME_CACHE_SAVE_SECS`], so a crash loses at most that much of it
+async fn save_phoneme_cache_periodically(path: String, cache: Arc<PhonemeCache>) {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(PHONEME_CACHE_SAVE_SECS));
+    // The first tick completes straight away
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if !cache.has_unsaved_changes() {
+            continue;
+        }
+        let (path, cache) = (path.clone(), cache.clone());
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || cache.save(&path)).await {
+            tracing::warn!("Failed to save phoneme cache: {}", e);
+        }
+    }
+}
+
+/// Resolve on Ctrl+C, or SIGTERM on unix, so the server stops gracefully
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
             }
+            Err(_) => std::future::pending().await,
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down");
+}
 
+/// `--phonemes-only`: print the chunks synthesis would feed the model, with
+/// their phonemes and token counts, for the text, file or stdin lines of `mode`
+async fn print_phonemes(
+    mode: Mode,
+    init_config: &InitConfig,
+    lan: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let print_text = |text: &str| -> Result<(), Box<dyn std::error::Error>> {
+        let text = init_config.preprocess(text);
+        for (i, chunk) in init_config.split_text_into_chunks(&text).iter().enumerate() {
+            let phonemes = init_config.phonemize(chunk, lan)?;
+            println!("[{}] {} tokens: {}", i, tokenize(&phonemes).len(), chunk);
+            println!("    {}", phonemes);
+        }
         Ok(())
-    })
+    };
+
+    match mode {
+        Mode::Text { text, .. } => print_text(&resolve_text(text)?)?,
+        Mode::File { input_path, .. } => {
+            let file_content = fs::read_to_string(input_path)?;
+            for (i, line) in file_content.lines().enumerate() {
+                let stripped_line = line.trim();
+                if stripped_line.is_empty() {
+                    continue;
+                }
+                println!("line {}:", i);
+                print_text(stripped_line)?;
+            }
+        }
+        Mode::Stream { text_file } => {
+            let mut lines = stream_input(text_file.as_deref())?.lines();
+            while let Some(line) = lines.next_line().await? {
+                let stripped_line = line.trim();
+                if !stripped_line.is_empty() {
+                    print_text(stripped_line)?;
+                }
+            }
+        }
+        Mode::Benchmark { .. } => print_text(DEFAULT_TEXT)?,
+        Mode::OpenAI(_) | Mode::ValidateVoices => {
+            return Err("--phonemes-only works with the text, file and stream modes".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_output_path() {
+        assert_eq!(
+            line_output_path("tmp/output_{line}.wav", 2),
+            "tmp/output_2.wav"
+        );
+        assert_eq!(line_output_path("out_{n}.wav", 0), "out_0.wav");
+        // No placeholder: the index goes before the extension
+        assert_eq!(line_output_path("tmp/output.wav", 3), "tmp/output_3.wav");
+        assert_eq!(line_output_path("output", 1), "output_1");
+    }
+
+    #[test]
+    fn test_single_file_joins_lines_with_gaps() {
+        let lines = vec![vec![0.5; 100], vec![0.25; 40], vec![-0.5; 7]];
+        let expected = 100 + 40 + 7 + 2 * 12;
+        let audio = join_with_gaps(lines, 12);
+        assert_eq!(audio.len(), expected);
+        assert!(audio[100..112].iter().all(|&s| s == 0.0));
+
+        assert_eq!(
+            single_output_path("tmp/output_{line}.wav"),
+            "tmp/output.wav"
+        );
+        assert_eq!(single_output_path("book.wav"), "book.wav");
+    }
+
+    #[test]
+    fn test_output_dir() {
+        let dir = std::env::temp_dir().join(format!("koko-output-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        let cli = Cli::try_parse_from(["koko", "--output-dir", dir_str, "file", "in.txt"]).unwrap();
+        assert_eq!(cli.output_dir.as_deref(), Some(dir_str));
+
+        let save_path =
+            output_file_path(&line_output_path("lines/out_{line}.wav", 3), Some(dir_str)).unwrap();
+        fs::write(&save_path, b"RIFF").unwrap();
+        assert!(dir.join("lines/out_3.wav").is_file());
+        // Absolute paths stay where they are
+        let absolute = dir.join("abs.wav");
+        let absolute = absolute.to_str().unwrap();
+        assert_eq!(
+            output_file_path(absolute, Some("elsewhere")).unwrap(),
+            absolute
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_from_text_file() {
+        let path = std::env::temp_dir().join(format!("koko-stream-{}.txt", std::process::id()));
+        fs::write(&path, "First line.\n\n  Second line.\n").unwrap();
+        let path_str = path.to_str().unwrap();
+        let cli = Cli::try_parse_from(["koko", "--stream", "--text-file", path_str]).unwrap();
+        assert!(matches!(cli.mode, Mode::Stream { text_file: Some(_) }));
+
+        let mut spoken = Vec::new();
+        let mut wav = Vec::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(stream_lines(
+            stream_input(Some(path_str)).unwrap(),
+            &mut wav,
+            false,
+            |line| {
+                spoken.push(line.to_string());
+                Ok(vec![0.25; 10])
+            },
+        ))
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // A WAV header, then the samples of each non-blank line
+        assert_eq!(spoken, ["First line.", "Second line."]);
+        let mut header = Vec::new();
+        WavHeader::new(1, 24000, 32)
+            .write_header(&mut header)
+            .unwrap();
+        assert!(wav.starts_with(b"RIFF"));
+        assert_eq!(wav.len(), header.len() + 2 * 10 * 4);
+    }
+
+    #[test]
+    fn test_initial_silence_flag() {
+        let cli = Cli::try_parse_from(["koko", "--initial-silence", "4", "text", "Hi"]).unwrap();
+        assert_eq!(cli.initial_silence, Some(4));
+        // A token count can't be negative or more than a chunk can hold
+        assert!(Cli::try_parse_from(["koko", "--initial-silence", "-1", "text", "Hi"]).is_err());
+        let over = (MAX_INITIAL_SILENCE_TOKENS + 1).to_string();
+        assert!(Cli::try_parse_from(["koko", "--initial-silence", &over, "text", "Hi"]).is_err());
+
+        // Every chunk starts with that much silence, here tokens the mock
+        // model turns into audio like any other
+        let text = "Hi there. How are you?";
+        let chunks = InitConfig::default().plan_chunks(text, None).len();
+        assert!(chunks > 0);
+        let tts = TTSKoko::mock(InitConfig::default(), &["af_sky"]);
+        let synthesize = |initial_silence| {
+            tts.tts_raw_audio(
+                text,
+                "en-us",
+                "af_sky",
+                1.0,
+                initial_silence,
+                LogContext::default(),
+            )
+            .unwrap()
+            .len()
+        };
+        let without = synthesize(None);
+        assert_eq!(
+            synthesize(cli.initial_silence),
+            without + chunks * 4 * kokoros::onn::ort_koko::MOCK_SAMPLES_PER_TOKEN
+        );
+    }
+
+    #[test]
+    fn test_config_file_fills_in_unset_flags() {
+        let path = std::env::temp_dir().join(format!("koko-config-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{
+                "model_path": "models/kokoro.onnx",
+                "voices_path": "models/voices.bin",
+                "host": "127.0.0.1",
+                "port": 8880,
+                "voice": "af_sky",
+                "speed": 1.25,
+                "provider": "cpu",
+                "voice_aliases": {"alloy": "af_heart", "narrator": "bm_george"}
+            }"#,
+        )
+        .unwrap();
+        let file_config = FileConfig::load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            file_config,
+            FileConfig {
+                model_path: Some("models/kokoro.onnx".to_string()),
+                voices_path: Some("models/voices.bin".to_string()),
+                host: Some([127, 0, 0, 1].into()),
+                port: Some(8880),
+                voice: Some("af_sky".to_string()),
+                speed: Some(1.25),
+                provider: Some("cpu".to_string()),
+                voice_aliases: Some(HashMap::from([
+                    ("alloy".to_string(), "af_heart".to_string()),
+                    ("narrator".to_string(), "bm_george".to_string()),
+                ])),
+            }
+        );
+
+        let file_config = FileConfig {
+            provider: None,
+            ..file_config
+        };
+        let matches = Cli::command()
+            .try_get_matches_from([
+                "koko",
+                "--speed",
+                "0.8",
+                "openai",
+                "--port",
+                "9000",
+                "--voice-alias",
+                "alloy=af_nicole",
+            ])
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        file_config.apply(&mut cli, &matches).unwrap();
+
+        assert_eq!(cli.model_path, "models/kokoro.onnx");
+        assert_eq!(cli.data_paths, ["models/voices.bin"]);
+        assert_eq!(cli.style, "af_sky");
+        assert_eq!(cli.speed, 0.8);
+        let Mode::OpenAI(openai) = cli.mode else {
+            panic!("expected openai mode");
+        };
+        let OpenAiArgs {
+            ip,
+            port,
+            voice_aliases,
+            ..
+        } = *openai;
+        assert_eq!(ip, IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(port, 9000);
+        let voice_aliases: HashMap<_, _> = voice_aliases.into_iter().collect();
+        assert_eq!(voice_aliases["alloy"], "af_nicole");
+        assert_eq!(voice_aliases["narrator"], "bm_george");
+    }
+
+    #[test]
+    fn test_voice_checks() {
+        let checks = [
+            check_voice("af_sky", 24000, || Ok(vec![0.1; 12000])),
+            check_voice("af_broken", 24000, || {
+                Err("can not found from styles_map".into())
+            }),
+            check_voice("af_misshapen", 24000, || panic!("index out of bounds")),
+            check_voice("af_nan", 24000, || Ok(vec![f32::NAN])),
+        ];
+        assert_eq!(checks[0].result, Ok(0.5));
+        let broken = checks[1].result.as_ref().unwrap_err();
+        assert!(broken.contains("styles_map"));
+        assert_eq!(checks[2].result, Err("synthesis panicked".to_string()));
+        assert_eq!(checks[3].result, Err("non-finite samples".to_string()));
+        assert_eq!(print_voice_checks(&checks), 3);
+    }
+
+    #[test]
+    fn test_benchmark_summary() {
+        let latencies: Vec<_> = (1..=20)
+            .map(|i| std::time::Duration::from_millis(i * 100))
+            .collect();
+        // 21s of synthesis producing 42s of audio
+        let summary = benchmark_summary(&latencies, 42.0);
+        assert_eq!(summary.runs, 20);
+        assert!((summary.mean_seconds - 1.05).abs() < 1e-9);
+        assert!((summary.p95_seconds - 1.9).abs() < 1e-9);
+        assert!((summary.real_time_factor - 2.0).abs() < 1e-9);
+    }
 }
@@ -4,234 +4,123 @@
 //! It implements streaming and non-streaming audio generation with multiple format support.
 //!
 //! ## Implemented Features
+//! - `/healthz` - Readiness check that runs a short synthesis
 //! - `/v1/audio/speech` - Text-to-speech generation with streaming support
-//! - `/v1/audio/voices` - List available voices
-//! - `/v1/models` - List available models (static dummy list)
-//! - Multiple audio formats: MP3, WAV, PCM, OPUS, AAC, FLAC
+//! - `/v1/audio/speech/events` - Server-sent progress events for chunked generation
+//! - `/v1/audio/voices` - List available voices with language/gender metadata
+//! - `/v1/models` - List the loaded model and the OpenAI model names
+//! - `/v1/debug/synthesize-tokens` - Synthesize raw token ids (requires `admin_token`)
+//! - `/v1/admin/reload-voices` - Re-read the voices files (requires `admin_token`)
+//! - `/v1/debug/chunks` - Show how input is chunked and phonemized (requires `debug_endpoints`)
+//! - `/v1/debug/tokenize` - Show the padded token ids fed to the model (requires `debug_endpoints`)
+//! - Multiple audio formats: MP3, WAV, PCM, OPUS, AAC, FLAC, picked by
+//!   `response_format` or, without one, the `Accept` header
+//! - `json` responses carrying base64 WAV, for clients that can't take binary
+//! - `webm` responses (PCM in live WebM) for browser `<audio>` playback
 //! - Streaming audio generation for low-latency responses
 //!
 //! ## OpenAI API Compatibility Limitations
 //! - `return_download_link`: Not implemented (files are streamed directly)
-//! - `lang_code`: Not implemented (language auto-detected from voice prefix)
+//! - `lang_code`: Passed to espeak as is; without it the language comes from the
+//!   voice prefix, then detection on the input, then `en-us`. `espeak_variant`
+//!   appends an espeak voice variant, e.g. `en-us+f3`
 //! - `volume_multiplier`: Not implemented (audio returned at original levels)
 //! - `download_format`: Not implemented (only response_format used)
 //! - `normalization_options`: Not implemented (basic text processing only)
-//! - Streaming outputs MP3 for best client compatibility
+//! - Streaming outputs MP3 for best client compatibility, except `wav`, `pcm`, `webm`
+//!   and G.711 which stream as themselves
 
+mod cache;
+
+use std::collections::HashMap;
 use std::error::Error;
 use std::io;
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use axum::{
-    Json, Router,
-    body::Body,
-    extract::{Path, State},
-    http::{StatusCode, header},
-    response::{IntoResponse, Response},
+    Extension, Json, Router,
+    body::{Body, Bytes},
+    extract::{Path, Query, State, rejection::QueryRejection},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
 };
+use base64::prelude::*;
 use futures::stream::StreamExt;
 use kokoros::{
-    tts::koko::{InitConfig as TTSKokoInitConfig, TTSKoko},
-    utils::mp3::pcm_to_mp3,
-    utils::wav::{WavHeader, write_audio_chunk},
+    tts::koko::{
+        self, InitConfig as TTSKokoInitConfig, LogContext, MAX_INITIAL_SILENCE_TOKENS, PlanError,
+        SILENCE_TOKEN_MS, TTSKoko, pad_tokens, silence_tokens_for_ms, validate_tokens,
+    },
+    tts::language::detect_language,
+    tts::normalize::is_stray_control,
+    tts::ssml,
+    tts::tokenize::tokenize,
+    tts::voices::{self, voice_metadata},
+    utils::aac::pcm_to_aac,
+    utils::audio,
+    utils::filter,
+    utils::g711,
+    utils::mp3::{DEFAULT_BITRATE_KBPS, bitrate_from_kbps, pcm_to_mp3, tag_id3v2},
+    utils::wav::{SUPPORTED_BIT_DEPTHS, WavHeader, read_wav, swap_sample_bytes, write_samples},
+    utils::webm::WebmMuxer,
 };
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
-use tower_http::cors::CorsLayer;
-use tracing::{debug, error, info};
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{DefaultPredicate, Predicate},
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use cache::LruCache;
+
 /// Break words used for chunk splitting
 const BREAK_WORDS: &[&str] = &[
     "and", "or", "but", "&", "because", "if", "since", "though", "although", "however", "which",
 ];
 
-/// Split text into speech chunks for streaming
-///
-/// Prioritizes sentence boundaries over word count for natural speech breaks
-/// Then applies center-break word splitting for long chunks
-fn split_text_into_speech_chunks(text: &str, words_per_chunk: usize) -> Vec<String> {
-    let mut chunks = Vec::new();
-    let mut current_chunk = String::new();
-    let mut word_count = 0;
-
-    // First pass: split by punctuation
-    for word in text.split_whitespace() {
-        if !current_chunk.is_empty() {
-            current_chunk.push(' ');
-        }
-        // Check for numbered list patterns: 1. 2) 3: (4), 5(\s)[.\)\:]
-        let is_numbered_break = is_numbered_list_item(word);
-
-        if is_numbered_break && !current_chunk.is_empty() {
-            chunks.push(current_chunk.trim().to_string());
-            current_chunk.clear();
-            word_count = 0;
-        }
-        current_chunk.push_str(word);
-        word_count += 1;
-
-        // Check for unconditional breaks (always break regardless of word count)
-        let ends_with_unconditional = word.ends_with('.')
-            || word.ends_with('!')
-            || word.ends_with('?')
-            || word.ends_with(':')
-            || word.ends_with(';');
-
-        // Check for conditional breaks (commas - only break if enough words)
-        let ends_with_conditional = word.ends_with(',');
-
-        // Split conditions:
-        // 1. Unconditional punctuation - always break
-        // 2. Conditional punctuation + target word count reached
-        if ends_with_unconditional
-            || is_numbered_break
-            || (ends_with_conditional && word_count >= words_per_chunk)
-        {
-            chunks.push(current_chunk.trim().to_string());
-            current_chunk.clear();
-            word_count = 0;
-        }
-    }
-
-    if !current_chunk.trim().is_empty() {
-        chunks.push(current_chunk.trim().to_string());
-    }
-
-    // Second pass: apply center-break splitting for long chunks
-    // All chunks: ≥12 words
-    // First 2 chunks: punctuation priority, Others: break words only
-    let mut final_chunks = Vec::new();
-    for (index, chunk) in chunks.iter().enumerate() {
-        let threshold = 12;
-        let use_punctuation = index < 2; // First 2 chunks can use punctuation
-        let split_chunks = split_long_chunk_with_depth(chunk, threshold, use_punctuation, 0);
-        final_chunks.extend(split_chunks);
-    }
-
-    // Note: Do NOT move trailing break words to next chunk; we keep them attached
-    final_chunks
-}
-
-/// Check if a word is a numbered list item: 1. 2) 3: (4), 5(\s)[.\)\:]
-fn is_numbered_list_item(word: &str) -> bool {
-    // Pattern matches: number followed by . ) or :
-    // Examples: "1.", "2)", "3:", "(4)", "(5),"
-    let numbered_regex = Regex::new(r"^\(?[0-9]+[.\)\:],?$").unwrap();
-    numbered_regex.is_match(word)
-}
-
-fn split_long_chunk_with_depth(
-    chunk: &str,
-    threshold: usize,
-    use_punctuation: bool,
-    depth: usize,
-) -> Vec<String> {
-    // Prevent infinite recursion
-    if depth >= 3 {
-        return vec![chunk.to_string()];
-    }
-    let words: Vec<&str> = chunk.split_whitespace().collect();
-    let word_count = words.len();
-
-    // Only split if chunk meets the threshold
-    if word_count < threshold {
-        return vec![chunk.to_string()];
-    }
-
-    let center = word_count / 2;
-
-    if use_punctuation {
-        // Priority 1: Search for commas closest to center
-        if let Some(pos) = find_closest_punctuation(&words, center, &[","]) {
-            if pos >= 3 && pos < words.len() {
-                let first_chunk = words[..pos].join(" ");
-                let second_chunk = words[pos..].join(" ");
-
-                // Recursively split both chunks if they're still too long
-                let mut result = Vec::new();
-                result.extend(split_long_chunk_with_depth(
-                    &first_chunk,
-                    threshold,
-                    use_punctuation,
-                    depth + 1,
-                ));
-                result.extend(split_long_chunk_with_depth(
-                    &second_chunk,
-                    threshold,
-                    use_punctuation,
-                    depth + 1,
-                ));
-                return result;
-            }
-        }
-    }
-
-    // Priority 2: Search for break words closest to center
-    if let Some(pos) = find_closest_break_word(&words, center, BREAK_WORDS) {
-        if pos >= 3 && pos < words.len() {
-            let first_chunk = words[..pos].join(" ");
-            let second_chunk = words[pos..].join(" ");
-
-            // Recursively split both chunks if they're still too long
-            let mut result = Vec::new();
-            result.extend(split_long_chunk_with_depth(
-                &first_chunk,
-                threshold,
-                use_punctuation,
-                depth + 1,
-            ));
-            result.extend(split_long_chunk_with_depth(
-                &second_chunk,
-                threshold,
-                use_punctuation,
-                depth + 1,
-            ));
-            return result;
-        }
-    }
-
-    // No suitable break point found, return original chunk
-    vec![chunk.to_string()]
+/// Where streaming input may be split, see [`ServerConfig::chunk_delimiters`].
+/// Defaults to English: [`BREAK_WORDS`] and the punctuation of
+/// [`koko::split_text_into_speech_chunks`].
+#[derive(Debug, Clone)]
+pub struct ChunkDelimiters {
+    /// Lowercase words a chunk shouldn't start with, such as conjunctions;
+    /// they are moved to the end of the previous chunk instead
+    pub break_words: Vec<String>,
+    /// Characters that end a sentence
+    pub sentence_punctuation: Vec<char>,
+    /// Characters long sentences are split at
+    pub clause_punctuation: Vec<char>,
 }
 
-/// Find closest punctuation to center
-fn find_closest_punctuation(words: &[&str], center: usize, punctuation: &[&str]) -> Option<usize> {
-    let mut closest_pos = None;
-    let mut min_distance = usize::MAX;
-
-    for (i, word) in words.iter().enumerate() {
-        if punctuation.iter().any(|p| word.ends_with(p)) {
-            let distance = if i < center { center - i } else { i - center };
-            if distance < min_distance {
-                min_distance = distance;
-                closest_pos = Some(i + 1); // Split after the punctuation
-            }
+impl Default for ChunkDelimiters {
+    fn default() -> Self {
+        Self {
+            break_words: BREAK_WORDS.iter().map(|w| w.to_string()).collect(),
+            sentence_punctuation: koko::SENTENCE_PUNCTUATION.to_vec(),
+            clause_punctuation: koko::CLAUSE_PUNCTUATION.to_vec(),
         }
     }
-
-    closest_pos
 }
 
-/// Find closest break word to center
-fn find_closest_break_word(words: &[&str], center: usize, break_words: &[&str]) -> Option<usize> {
-    let mut closest_pos = None;
-    let mut min_distance = usize::MAX;
-
-    for (i, word) in words.iter().enumerate() {
-        if break_words.contains(&word.to_lowercase().as_str()) {
-            let distance = if i < center { center - i } else { i - center };
-            if distance < min_distance {
-                min_distance = distance;
-                closest_pos = Some(i); // Break word becomes first word of second chunk
-            }
-        }
+/// Split `chunk` between words into as few chunks of at most `max_words`
+/// as possible, evenly sized so the split doesn't leave a short stub
+fn split_by_word_count(chunk: &str, max_words: usize) -> Vec<String> {
+    let words: Vec<&str> = chunk.split_whitespace().collect();
+    if words.len() <= max_words || max_words == 0 {
+        return vec![chunk.to_string()];
     }
-
-    closest_pos
+    let parts = words.len().div_ceil(max_words);
+    let part_len = words.len().div_ceil(parts);
+    words.chunks(part_len).map(|part| part.join(" ")).collect()
 }
 
 // Helper: count words in a chunk
@@ -240,17 +129,22 @@ fn count_words(s: &str) -> usize {
 }
 
 // Helper: check if chunk starts with a break word (case-insensitive)
-fn starts_with_break_word(s: &str) -> bool {
+fn starts_with_break_word(s: &str, break_words: &[String]) -> bool {
     let mut it = s.split_whitespace();
     if let Some(first) = it.next() {
         let lw = first.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
-        return BREAK_WORDS.contains(&lw.as_str());
+        return break_words.contains(&lw);
     }
     false
 }
 
 // Normalize chunks for better prosody: merge very short chunks and avoid leading conjunctions
-fn normalize_chunks(mut chunks: Vec<String>, max_words: usize, min_words: usize) -> Vec<String> {
+fn normalize_chunks(
+    mut chunks: Vec<String>,
+    max_words: usize,
+    min_words: usize,
+    break_words: &[String],
+) -> Vec<String> {
     // Trim and drop empty
     chunks = chunks
         .into_iter()
@@ -289,7 +183,7 @@ fn normalize_chunks(mut chunks: Vec<String>, max_words: usize, min_words: usize)
     // Pass 2: Avoid leading conjunctions by attaching the leading word to the previous chunk when feasible
     let mut i = 1usize;
     while i < normalized.len() {
-        if starts_with_break_word(&normalized[i]) {
+        if starts_with_break_word(&normalized[i], break_words) {
             let first_word = normalized[i]
                 .split_whitespace()
                 .next()
@@ -329,7 +223,7 @@ fn normalize_chunks(mut chunks: Vec<String>, max_words: usize, min_words: usize)
     normalized
 }
 
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 enum AudioFormat {
     #[default]
@@ -339,6 +233,39 @@ enum AudioFormat {
     Aac,
     Flac,
     Pcm,
+    /// 8-bit G.711 mu-law at 8000 Hz, for telephony
+    Ulaw,
+    /// 8-bit G.711 A-law at 8000 Hz, for telephony
+    Alaw,
+    /// WAV, base64-encoded in an [`AudioJson`] body, for clients that
+    /// can't handle binary. A third larger than `wav`, and never streamed.
+    Json,
+    /// 16-bit PCM in a live WebM container (`A_PCM/INT/LIT`), playable by an
+    /// `<audio>` element while it streams. MediaSource Extensions don't
+    /// accept PCM tracks, and there is no Opus encoder to make one they do.
+    Webm,
+}
+
+/// Body of a `json` response
+#[derive(Serialize, Deserialize, Debug)]
+struct AudioJson {
+    /// Base64 (standard alphabet, padded) of the WAV file
+    audio: String,
+    /// Format inside `audio`, always `wav`
+    format: &'static str,
+}
+
+/// How a streaming `wav` response deals with the header's size fields
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum WavStreamingMode {
+    /// Send the header straight away with `0xFFFFFFFF` sizes and stream samples
+    /// after it. Chunked HTTP can't seek back to fix the sizes up, so this only
+    /// plays in players that tolerate an open-ended WAV (ffplay, browsers, VLC).
+    #[default]
+    Placeholder,
+    /// Collect all samples and send a single, correctly sized file at the end
+    Buffered,
 }
 
 #[derive(Deserialize)]
@@ -359,30 +286,178 @@ impl Default for Speed {
     }
 }
 
-#[derive(Deserialize)]
+/// Silence inserted between the elements of an array `input`
+const SEGMENT_SILENCE_MS: u32 = 500;
+
+/// `input` of a speech request: a single text, or pre-segmented text.
+///
+/// Each element of an array is a hard boundary: it is chunked and synthesized
+/// on its own, never merged with its neighbours, and consecutive elements are
+/// separated by [`SEGMENT_SILENCE_MS`] of silence. Blank elements are ignored.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum SpeechInput {
+    Text(String),
+    Segments(Vec<String>),
+}
+
+impl Default for SpeechInput {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl SpeechInput {
+    /// Characters across all of the input
+    fn char_count(&self) -> usize {
+        match self {
+            Self::Text(text) => text.chars().count(),
+            Self::Segments(segments) => segments.iter().map(|s| s.chars().count()).sum(),
+        }
+    }
+
+    /// The non-blank pieces of the input, none for empty text
+    fn into_segments(self) -> Vec<String> {
+        let segments = match self {
+            Self::Text(text) => vec![text],
+            Self::Segments(segments) => segments,
+        };
+        segments
+            .into_iter()
+            .filter(|s| !s.trim().is_empty())
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Default)]
 struct SpeechRequest {
     // Only one Kokoro model exists
     #[allow(dead_code)]
     model: String,
 
-    input: String,
+    input: SpeechInput,
 
+    /// Falls back to [`ServerConfig::default_voice`]
     #[serde(default)]
-    voice: Voice,
+    voice: Option<Voice>,
 
+    /// Falls back to the `Accept` header, then to mp3
     #[serde(default)]
-    response_format: AudioFormat,
+    response_format: Option<AudioFormat>,
 
+    /// Falls back to [`ServerConfig::default_speed`]
     #[serde(default)]
-    speed: Speed,
+    speed: Option<Speed>,
 
+    /// Leading pause as a raw number of silence tokens
     #[serde(default)]
     initial_silence: Option<usize>,
 
+    /// Leading pause in milliseconds, converted to silence tokens at
+    /// [`kokoros::tts::koko::SILENCE_TOKEN_MS`] per token. Mutually exclusive with `initial_silence`.
+    #[serde(default)]
+    initial_silence_ms: Option<u32>,
+
+    /// Upper bound on streaming chunks; longer inputs are split more coarsely
+    /// rather than rejected. Can only lower the server's `max_chunks`.
+    #[serde(default)]
+    max_chunks: Option<usize>,
+
+    /// Words per streaming chunk to aim for (defaults to [`STREAM_TARGET_WORDS`]).
+    /// Smaller chunks start playing sooner but add more audible boundaries.
+    #[serde(default)]
+    words_per_chunk: Option<usize>,
+
     /// Enable streaming audio generation (implemented)
     #[serde(default)]
     stream: Option<bool>,
 
+    /// Extra events for `/v1/audio/speech/events`, like OpenAI's `stream_options`
+    #[serde(default)]
+    stream_options: Option<StreamOptions>,
+
+    /// Header handling when streaming `wav`; ignored otherwise
+    #[serde(default)]
+    wav_streaming_mode: WavStreamingMode,
+
+    /// Gate low-level noise such as breaths between phrases (non-streaming only)
+    #[serde(default)]
+    noise_gate: Option<NoiseGateOptions>,
+
+    /// Normalize integrated loudness to this many LUFS, e.g. -16, so voices
+    /// and blends play back at a consistent volume (non-streaming only)
+    #[serde(default)]
+    target_lufs: Option<f32>,
+
+    /// Shift pitch by this many semitones (-12 to 12) without changing
+    /// tempo, unlike `speed` (non-streaming only)
+    #[serde(default)]
+    pitch_semitones: Option<f32>,
+
+    /// Play back this many times as fast (0.25 to 4) at the same pitch by
+    /// time-stretching the audio; 0.8 makes it 25% longer. `speed` instead
+    /// has the model speak faster or slower, which changes its prosody
+    /// (non-streaming only).
+    #[serde(default)]
+    tempo: Option<f32>,
+
+    /// Play the audio this many times back to back (1 to [`MAX_REPEAT`]),
+    /// for demos and load tests. It is synthesized once (non-streaming only).
+    #[serde(default)]
+    repeat: Option<usize>,
+
+    /// Silence between repeats, defaulting to [`SEGMENT_SILENCE_MS`]
+    #[serde(default)]
+    repeat_silence_ms: Option<u32>,
+
+    /// Remove DC offset and sub-audible drift from each chunk, which otherwise
+    /// pops where chunks are joined
+    #[serde(default)]
+    remove_dc: bool,
+
+    /// Reset espeak before phonemizing each chunk so identical input gets
+    /// identical phonemes, see [`TTSKokoInitConfig::deterministic_phonemes`]
+    #[serde(default)]
+    deterministic: bool,
+
+    /// Synthesize each input segment in one inference instead of chunking
+    /// it, avoiding chunk boundaries in short inputs. Input over the token
+    /// limit of one inference gets 400. Streams send one chunk per segment.
+    #[serde(default)]
+    disable_chunking: bool,
+
+    /// Encoder bitrate for lossy formats, see [`EncodeSettings`]
+    #[serde(default)]
+    bitrate_kbps: Option<u32>,
+
+    /// Encoder compression level for FLAC, see [`EncodeSettings`]
+    #[serde(default)]
+    compression_level: Option<u32>,
+
+    /// Sample bit depth for WAV and PCM, see [`EncodeSettings`]
+    #[serde(default)]
+    bit_depth: Option<u16>,
+
+    /// Resampler for the 8 kHz G.711 formats: `linear`, `cubic` or `sinc`,
+    /// see [`EncodeSettings`]
+    #[serde(default)]
+    resample_quality: Option<audio::ResampleQuality>,
+
+    /// Byte order of PCM samples, see [`EncodeSettings`]
+    #[serde(default)]
+    pcm_endianness: Option<PcmEndianness>,
+
+    /// Run every chunk on this TTS instance instead of spreading them, to
+    /// compare instances or isolate one misbehaving. Falls back to the
+    /// [`INSTANCE_HEADER`] header.
+    #[serde(default)]
+    instance: Option<usize>,
+
+    /// Title written to the ID3 tag of non-streaming MP3 responses, next
+    /// to their length
+    #[serde(default)]
+    title: Option<String>,
+
     // OpenAI API compatibility parameters - accepted but not implemented
     // These fields ensure request parsing compatibility with OpenAI clients
     /// Return download link after generation (not implemented)
@@ -390,11 +465,20 @@ struct SpeechRequest {
     #[allow(dead_code)]
     return_download_link: Option<bool>,
 
-    /// Language code for text processing (not implemented)
+    /// espeak language to phonemize with, see [`resolve_language`]
     #[serde(default)]
-    #[allow(dead_code)]
     lang_code: Option<String>,
 
+    /// espeak voice variant such as `f3` or `klatt`, appended to the
+    /// language as `en-us+f3`, see [`resolve_espeak_variant`]
+    #[serde(default)]
+    espeak_variant: Option<String>,
+
+    /// Leave dotted acronyms (`U.S.A.`) for espeak in the `normalize_text`
+    /// preprocessing step; unset keeps the server's setting
+    #[serde(default)]
+    keep_acronyms: Option<bool>,
+
     /// Volume multiplier for output audio (not implemented)
     #[serde(default)]
     #[allow(dead_code)]
@@ -411,15 +495,199 @@ struct SpeechRequest {
     normalization_options: Option<serde_json::Value>,
 }
 
+/// Options for `/v1/audio/speech/events`
+#[derive(Deserialize, Default)]
+struct StreamOptions {
+    /// Send a `usage` event with the totals of the stream before `done`
+    #[serde(default)]
+    include_usage: bool,
+}
+
+/// Noise gate settings; omitted fields use conservative defaults
+#[derive(Deserialize)]
+struct NoiseGateOptions {
+    /// Linear amplitude below which audio is attenuated
+    #[serde(default = "NoiseGateOptions::default_threshold")]
+    threshold: f32,
+
+    #[serde(default = "NoiseGateOptions::default_attack_ms")]
+    attack_ms: f32,
+
+    #[serde(default = "NoiseGateOptions::default_release_ms")]
+    release_ms: f32,
+}
+
+impl NoiseGateOptions {
+    fn default_threshold() -> f32 {
+        0.01
+    }
+
+    fn default_attack_ms() -> f32 {
+        5.0
+    }
+
+    fn default_release_ms() -> f32 {
+        50.0
+    }
+}
+
+/// Byte order of `pcm` output
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+enum PcmEndianness {
+    #[default]
+    Le,
+    Be,
+}
+
+/// Per-request encoder settings, validated against the response format
+///
+/// Defaults and accepted ranges per format:
+/// - MP3: `bitrate_kbps` 192, one of LAME's constant bitrates (8–320)
+/// - Opus: `bitrate_kbps` 64, 6–510
+/// - FLAC: `compression_level` 5, 0–8
+/// - WAV and JSON: `bit_depth` 32 (float), or 16 or 24 (integer)
+/// - PCM: `bit_depth` 16, or 24 or 32 like WAV; `pcm_endianness` `le`, or `be`
+/// - mu-law and A-law: `resample_quality` linear, or cubic or sinc, see
+///   [`audio::ResampleQuality`]
+///
+/// Opus and FLAC are currently encoded as MP3 at the MP3 default bitrate;
+/// their settings are validated so clients can rely on them once the
+/// encoders exist. Other formats (including AAC, which uses a fixed 64 kbps)
+/// reject these settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+struct EncodeSettings {
+    bitrate_kbps: Option<u32>,
+    compression_level: Option<u32>,
+    bit_depth: Option<u16>,
+    resample_quality: Option<audio::ResampleQuality>,
+    pcm_endianness: Option<PcmEndianness>,
+}
+
+impl EncodeSettings {
+    fn validate(self, format: AudioFormat) -> Result<Self, SpeechError> {
+        let bitrate_ok = match (format, self.bitrate_kbps) {
+            (_, None) => true,
+            (AudioFormat::Mp3, Some(kbps)) => bitrate_from_kbps(kbps).is_some(),
+            (AudioFormat::Opus, Some(kbps)) => (6..=510).contains(&kbps),
+            _ => false,
+        };
+        if !bitrate_ok {
+            return Err(SpeechError::BadRequest(format!(
+                "bitrate_kbps {:?} is not valid for {:?}",
+                self.bitrate_kbps, format
+            )));
+        }
+
+        let level_ok = match (format, self.compression_level) {
+            (_, None) => true,
+            (AudioFormat::Flac, Some(level)) => level <= 8,
+            _ => false,
+        };
+        if !level_ok {
+            return Err(SpeechError::BadRequest(format!(
+                "compression_level {:?} is not valid for {:?}",
+                self.compression_level, format
+            )));
+        }
+
+        let depth_ok = match (format, self.bit_depth) {
+            (_, None) => true,
+            (AudioFormat::Wav | AudioFormat::Json | AudioFormat::Pcm, Some(bits)) => {
+                SUPPORTED_BIT_DEPTHS.contains(&bits)
+            }
+            _ => false,
+        };
+        if !depth_ok {
+            return Err(SpeechError::BadRequest(format!(
+                "bit_depth {:?} is not valid for {:?}",
+                self.bit_depth, format
+            )));
+        }
+
+        if self.resample_quality.is_some()
+            && !matches!(format, AudioFormat::Ulaw | AudioFormat::Alaw)
+        {
+            return Err(SpeechError::BadRequest(format!(
+                "resample_quality is not valid for {:?}",
+                format
+            )));
+        }
+
+        if self.pcm_endianness.is_some() && format != AudioFormat::Pcm {
+            return Err(SpeechError::BadRequest(format!(
+                "pcm_endianness is not valid for {:?}",
+                format
+            )));
+        }
+
+        Ok(self)
+    }
+
+    /// Resampler used to reach the G.711 sample rate
+    fn resample_quality(&self) -> audio::ResampleQuality {
+        self.resample_quality.unwrap_or_default()
+    }
+
+    /// Byte order of PCM output
+    fn pcm_endianness(&self) -> PcmEndianness {
+        self.pcm_endianness.unwrap_or_default()
+    }
+
+    /// Sample bit depth for WAV and PCM output
+    fn bits_per_sample(&self, format: AudioFormat) -> u16 {
+        let default = match format {
+            AudioFormat::Pcm => 16,
+            _ => 32,
+        };
+        self.bit_depth.unwrap_or(default)
+    }
+
+    /// Bitrate for the MP3 encoder; only MP3 requests can override the default
+    fn mp3_bitrate_kbps(&self, format: AudioFormat) -> u32 {
+        match format {
+            AudioFormat::Mp3 => self.bitrate_kbps.unwrap_or(DEFAULT_BITRATE_KBPS),
+            _ => DEFAULT_BITRATE_KBPS,
+        }
+    }
+}
+
+/// Request body for `/v1/debug/synthesize-tokens`
+#[derive(Deserialize)]
+struct SynthesizeTokensRequest {
+    /// Token ids fed to the model as-is (include the `0` padding yourself)
+    tokens: Vec<i64>,
+
+    #[serde(default)]
+    voice: Voice,
+
+    #[serde(default)]
+    speed: Speed,
+
+    #[serde(default)]
+    response_format: AudioFormat,
+}
+
 /// Async TTS worker task
 #[derive(Debug)]
 struct TTSTask {
     id: usize,
     chunk: String,
     voice: String,
+    language: String,
     speed: f32,
     initial_silence: Option<usize>,
-    result_tx: mpsc::UnboundedSender<(usize, Vec<u8>)>,
+    /// Samples of silence appended after the chunk's audio
+    trailing_silence: usize,
+    /// Drop the quiet lead-in before the chunk's first sound
+    trim_leading_silence: bool,
+    /// Softly limit peaks above [`PEAK_LIMIT_CEILING`]
+    peak_limit: bool,
+    /// Samples ramped up at the start and down at the end of the chunk
+    fade_in: usize,
+    fade_out: usize,
+    remove_dc: bool,
+    result_tx: mpsc::Sender<(usize, Vec<u8>)>,
 }
 
 /// Streaming session manager
@@ -430,28 +698,116 @@ struct StreamingSession {
 }
 
 /// TTS worker pool manager with multiple TTS instances
-#[derive(Clone)]
-struct TTSWorkerPool {
-    tts_instances: Vec<Arc<TTSKoko>>,
+struct TTSWorkerPool<T = TTSKoko> {
+    tts_instances: Vec<Arc<T>>,
+    /// Chunks running on each instance, held by [`InstanceLease`]s. Shared
+    /// by every request, see [`AppState::busy`].
+    busy: Arc<[AtomicUsize]>,
+    /// Chunks allowed in flight at once, see [`ServerConfig::stream_window`]
+    window: usize,
+    /// Instance every chunk is leased on, see [`SpeechRequest::instance`]
+    pinned: Option<usize>,
+}
+
+// Not derived, which would require `T: Clone`
+impl<T> Clone for TTSWorkerPool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tts_instances: self.tts_instances.clone(),
+            busy: Arc::clone(&self.busy),
+            window: self.window,
+            pinned: self.pinned,
+        }
+    }
+}
+
+/// An instance picked by [`TTSWorkerPool::acquire`], counted as busy until dropped
+struct InstanceLease<T> {
+    instance: Arc<T>,
+    index: usize,
+    instance_id: String,
+    busy: Arc<[AtomicUsize]>,
 }
 
-impl TTSWorkerPool {
-    fn new(tts_instances: Vec<TTSKoko>) -> Self {
+impl<T> Drop for InstanceLease<T> {
+    fn drop(&mut self) {
+        self.busy[self.index].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A busy count of zero for each of `count` instances, see [`AppState::busy`]
+fn instance_load(count: usize) -> Arc<[AtomicUsize]> {
+    (0..count).map(|_| AtomicUsize::new(0)).collect()
+}
+
+impl<T> TTSWorkerPool<T> {
+    /// A pool over `tts_instances`, whose busy counts are `busy`
+    fn new(tts_instances: Vec<T>, busy: Arc<[AtomicUsize]>, window: Option<usize>) -> Self {
+        assert!(busy.len() >= tts_instances.len());
+        let window = window.unwrap_or(tts_instances.len()).max(1);
         Self {
+            busy,
             tts_instances: tts_instances.into_iter().map(Arc::new).collect(),
+            window,
+            pinned: None,
+        }
+    }
+
+    /// Lease every chunk on instance `index`, which must be in range
+    fn pinned(self, index: Option<usize>) -> Self {
+        Self {
+            pinned: index,
+            ..self
+        }
+    }
+
+    /// Lease the first idle instance counting from `start`, so a chunk
+    /// doesn't queue behind another one's lock while an instance sits idle.
+    /// When every instance is busy it leases the least busy one, the first
+    /// from `start` among equals. A pinned pool always leases its pinned
+    /// instance.
+    fn acquire(&self, start: usize) -> InstanceLease<T> {
+        let count = self.tts_instances.len();
+        if let Some(index) = self.pinned {
+            self.busy[index].fetch_add(1, Ordering::SeqCst);
+            return self.lease(index);
         }
+        let idle = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&index| {
+                self.busy[index]
+                    .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            });
+        let index = idle.unwrap_or_else(|| {
+            let index = (0..count)
+                .map(|offset| (start + offset) % count)
+                .min_by_key(|&index| self.busy[index].load(Ordering::SeqCst))
+                .unwrap_or(0);
+            self.busy[index].fetch_add(1, Ordering::SeqCst);
+            index
+        });
+        self.lease(index)
     }
 
-    fn get_instance(&self, worker_id: usize) -> (Arc<TTSKoko>, String) {
-        let index = worker_id % self.tts_instances.len();
-        let instance_id = format!("{:02x}", index);
-        (Arc::clone(&self.tts_instances[index]), instance_id)
+    /// A lease on `index`, whose busy count the caller has already raised
+    fn lease(&self, index: usize) -> InstanceLease<T> {
+        InstanceLease {
+            instance: Arc::clone(&self.tts_instances[index]),
+            index,
+            instance_id: format!("{:02x}", index),
+            busy: Arc::clone(&self.busy),
+        }
     }
 
     fn instance_count(&self) -> usize {
         self.tts_instances.len()
     }
 
+    fn window_size(&self) -> usize {
+        self.window
+    }
+
     // process_chunk method removed - now handled inline in sequential queue processing
 }
 
@@ -460,6 +816,29 @@ struct VoicesResponse {
     voices: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct VoiceObject {
+    id: String,
+    language: Option<&'static str>,
+    gender: Option<&'static str>,
+    region: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct VoiceDetailsResponse {
+    voices: Vec<VoiceObject>,
+}
+
+#[derive(Deserialize)]
+struct VoicesQuery {
+    /// Return bare voice names, as older clients expect
+    #[serde(default)]
+    flat: bool,
+
+    /// Only list voices for this espeak language, e.g. `en-us`
+    language: Option<String>,
+}
+
 #[derive(Serialize)]
 struct ModelObject {
     id: String,
@@ -474,43 +853,752 @@ struct ModelsResponse {
     data: Vec<ModelObject>,
 }
 
-pub async fn create_server(tts_instances: Vec<TTSKoko>) -> Router {
-    info!("Starting TTS server with {} instances", tts_instances.len());
-
-    // Use first instance for compatibility with non-streaming endpoints
-    let tts_single = tts_instances
-        .first()
-        .cloned()
-        .expect("At least one TTS instance required");
+/// Request body for `/v1/debug/chunks`
+#[derive(Deserialize)]
+struct DebugChunksRequest {
+    input: String,
 
-    Router::new()
-        .route("/", get(handle_home))
-        .route("/v1/audio/speech", post(handle_tts))
-        .route("/v1/audio/voices", get(handle_voices))
-        .route("/v1/models", get(handle_models))
-        .route("/v1/models/{model}", get(handle_model))
-        .layer(axum::middleware::from_fn(request_id_middleware))
-        .layer(CorsLayer::permissive())
-        .with_state((tts_single, tts_instances))
-}
+    #[serde(default = "DebugChunksRequest::default_words_per_chunk")]
+    words_per_chunk: usize,
 
-pub use axum::serve;
+    #[serde(default)]
+    voice: Option<Voice>,
 
-#[derive(Debug)]
-enum SpeechError {
-    // Deciding to modify this example in order to see errors
-    // (e.g. with tracing) is up to the developer
-    #[allow(dead_code)]
-    Koko(Box<dyn Error>),
+    #[serde(default)]
+    lang_code: Option<String>,
+}
 
-    #[allow(dead_code)]
-    Header(io::Error),
+impl DebugChunksRequest {
+    /// Same target the streaming path uses
+    fn default_words_per_chunk() -> usize {
+        20
+    }
+}
 
-    #[allow(dead_code)]
-    Chunk(io::Error),
+#[derive(Serialize)]
+struct DebugChunk {
+    text: String,
+    phonemes: String,
+    token_count: usize,
+}
 
-    #[allow(dead_code)]
-    Mp3Conversion(std::io::Error),
+#[derive(Serialize)]
+struct DebugChunksResponse {
+    language: String,
+    chunks: Vec<DebugChunk>,
+}
+
+/// Request body for `/v1/debug/tokens`
+#[derive(Deserialize)]
+struct DebugTokensRequest {
+    input: SpeechInput,
+
+    #[serde(default)]
+    voice: Option<Voice>,
+
+    #[serde(default)]
+    lang_code: Option<String>,
+
+    #[serde(default)]
+    initial_silence: Option<usize>,
+
+    #[serde(default)]
+    keep_acronyms: Option<bool>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DebugTokensResponse {
+    /// Tokens across all chunks, silence included but not the padding added
+    /// around each
+    total_tokens: usize,
+    chunks: usize,
+}
+
+/// Request body for `/v1/debug/tokenize`
+#[derive(Deserialize)]
+struct DebugTokenizeRequest {
+    input: SpeechInput,
+
+    #[serde(default)]
+    voice: Option<Voice>,
+
+    #[serde(default)]
+    lang_code: Option<String>,
+
+    #[serde(default)]
+    initial_silence: Option<usize>,
+
+    #[serde(default)]
+    keep_acronyms: Option<bool>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DebugModelInput {
+    text: String,
+    /// Token ids exactly as fed to the model, silence and `0` padding included
+    tokens: Vec<i64>,
+    /// Length of the style vector `mix_styles` picks for the chunk
+    style_length: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct DebugTokenizeResponse {
+    language: String,
+    chunks: Vec<DebugModelInput>,
+}
+
+/// Server-wide options shared by all requests
+#[derive(Clone, Debug, Default)]
+pub struct ServerConfig {
+    /// Bearer token required by `/v1/debug/synthesize-tokens` and the
+    /// `/v1/admin/*` endpoints. When unset, those endpoints reject every
+    /// request. The other `/v1/debug/*` routes are gated by
+    /// [`Self::debug_endpoints`] instead and take no token.
+    pub admin_token: Option<String>,
+
+    /// Number of non-streaming responses kept in the audio cache (0 disables it)
+    pub cache_size: usize,
+
+    /// Mount unauthenticated introspection endpoints such as `/v1/debug/chunks`.
+    /// Keep this off in production.
+    pub debug_endpoints: bool,
+
+    /// Voices synthesized once per instance in the background after startup,
+    /// so the first real request for them doesn't pay the warmup cost
+    pub warmup_voices: Vec<String>,
+
+    /// Default cap on streaming chunks per request (unlimited when unset)
+    pub max_chunks: Option<usize>,
+
+    /// Most words in one streaming chunk. A longer clause with no commas or
+    /// break words to split at is cut evenly between words. Defaults to
+    /// [`STREAM_MAX_CHUNK_FACTOR`] times the words-per-chunk target. As a
+    /// hard limit it also stops [`Self::max_chunks`] from coarsening chunks
+    /// past it, so a long input may then take more chunks than that cap.
+    pub max_chunk_words: Option<usize>,
+
+    /// Streaming chunks in flight per request, defaulting to the number of
+    /// TTS instances. A larger window keeps chunks queued on the instances'
+    /// model locks, so an instance starts its next chunk the moment it frees
+    /// up instead of waiting for earlier chunks to be sent, without the RAM
+    /// of loading another model. It adds no real parallelism though: each
+    /// queued chunk holds a blocking thread. A chunk goes to an idle
+    /// instance if there is one, otherwise to the least busy, where it can
+    /// still wait behind a slow chunk that started before it.
+    pub stream_window: Option<usize>,
+
+    /// Synthesized chunks held for a streaming client that reads slower than
+    /// they are made ([`DEFAULT_STREAM_BUFFER`] when unset). Once it is full
+    /// synthesis waits on the client, so a stream holds at most this many
+    /// chunks plus [`Self::stream_window`] in memory.
+    pub stream_buffer: Option<usize>,
+
+    /// Voice for requests that don't name one (`af_sky` when unset)
+    pub default_voice: Option<String>,
+
+    /// Speed for requests that don't give one (1.0 when unset)
+    pub default_speed: Option<f32>,
+
+    /// Voice used in place of a requested voice that doesn't exist, naming
+    /// the requested one in the [`VOICE_FALLBACK_HEADER`]. When unset,
+    /// requests for unknown voices fail.
+    pub fallback_voice: Option<String>,
+
+    /// Voice names substituted before synthesis, on top of
+    /// [`DEFAULT_VOICE_ALIASES`], so clients written for OpenAI's voices
+    /// work unchanged. An entry here replaces a default of the same name.
+    pub voice_aliases: HashMap<String, String>,
+
+    /// Send [`KEEP_ALIVE_SILENCE_MS`] of silence as soon as an MP3 stream
+    /// starts, so clients and proxies with short read timeouts see bytes
+    /// before a slow first chunk is ready. WAV streams already open with
+    /// their header, except in buffered mode, which is left alone.
+    pub stream_keep_alive: bool,
+
+    /// Longest a request may spend synthesizing. Non-streaming requests then
+    /// fail with 504 and streams end early but cleanly. Model inference runs
+    /// on blocking threads and can't be interrupted mid-chunk, so a chunk
+    /// already running finishes in the background; only new work stops.
+    pub request_timeout: Option<Duration>,
+
+    /// Length of the fade-in at the start of the audio and the fade-out at
+    /// its end, so speech doesn't start or stop with a click (defaults to
+    /// [`DEFAULT_FADE_MS`], 0 disables). Streams fade their first and last
+    /// chunks.
+    pub fade_ms: Option<u32>,
+
+    /// Extra attempts for a streaming chunk whose synthesis fails, each on
+    /// the next instance. A chunk that fails every attempt is left out of
+    /// the audio; 0 leaves it out after the first failure. Unset uses
+    /// [`DEFAULT_CHUNK_RETRIES`].
+    pub chunk_retries: Option<usize>,
+
+    /// Longest accepted `input`, in characters across all segments
+    /// (defaults to [`DEFAULT_MAX_INPUT_CHARS`]). Longer requests get 413.
+    pub max_input_chars: Option<usize>,
+
+    /// Origins allowed to call the API from a browser. Empty allows any
+    /// origin, which is only safe when nothing is behind authentication.
+    pub cors_origins: Vec<String>,
+
+    /// Speech requests allowed to be synthesizing or waiting for an
+    /// instance at once (unlimited when unset). Past it, requests get 503
+    /// with `Retry-After` instead of queuing. A stream holds its place
+    /// until the client has received all of it.
+    pub max_queue_depth: Option<usize>,
+
+    /// Guess the language of input whose request has no `lang_code` and
+    /// whose voice doesn't imply a language, rather than assuming `en-us`
+    pub detect_language: bool,
+
+    /// Cut the quiet lead-in some voices start with (a breath or a pause)
+    /// from the first chunk of a stream, so playback starts sooner. Skipped
+    /// when the request asks for `initial_silence`.
+    pub trim_leading_silence: bool,
+
+    /// Softly limit samples that would clip on the way to 16-bit PCM or
+    /// MP3, which blended voices can produce. Off by default, leaving audio
+    /// exactly as the model made it.
+    pub peak_limit: bool,
+
+    /// WAV file played before the speech of every non-streaming response,
+    /// e.g. a notification chime. Read once at startup and resampled to the
+    /// model's rate; streamed responses don't include it.
+    pub prefix_audio_path: Option<String>,
+
+    /// WAV file played after the speech, like [`Self::prefix_audio_path`]
+    pub suffix_audio_path: Option<String>,
+
+    /// Answer input containing control characters with 400 instead of
+    /// silently stripping them before phonemization
+    pub reject_control_chars: bool,
+
+    /// Send the phonemes a non-streaming response was synthesized from in
+    /// the [`PHONEMES_HEADER`], to check pronunciation without the debug
+    /// endpoints. Off by default since the header grows with the input.
+    pub phonemes_header: bool,
+
+    /// Check that every stream sends its chunks strictly in order, each
+    /// once and none missing except those that failed, logging an error for
+    /// each violation. For catching reordering bugs, see [`StreamOrderCheck`].
+    pub verify_stream_order: bool,
+
+    /// Break words and punctuation streaming input is chunked at, English
+    /// by default. Input in other languages chunks more naturally with its
+    /// own conjunctions and sentence marks.
+    pub chunk_delimiters: ChunkDelimiters,
+}
+
+/// Run blocking synthesis on its own thread, failing with
+/// [`SpeechError::Timeout`] once `timeout` passes. `work` gets a flag that
+/// turns true at that point and should check it between chunks, since the
+/// thread itself can't be stopped.
+async fn run_with_timeout<T: Send + 'static>(
+    timeout: Option<Duration>,
+    work: impl FnOnce(&std::sync::atomic::AtomicBool) -> Result<T, String> + Send + 'static,
+) -> Result<T, SpeechError> {
+    let cancelled = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let flag = cancelled.clone();
+    let handle = tokio::task::spawn_blocking(move || work(&flag));
+    let joined = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, handle).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                cancelled.store(true, std::sync::atomic::Ordering::Relaxed);
+                return Err(SpeechError::Timeout);
+            }
+        },
+        None => handle.await,
+    };
+    joined
+        .map_err(|e| SpeechError::Koko(Box::new(e)))?
+        .map_err(|e| SpeechError::Koko(e.into()))
+}
+
+/// Everything that changes the encoded bytes of a non-streaming response.
+/// New audio-affecting request options must be added here.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    segments: Vec<String>,
+    voice: String,
+    language: String,
+    speed_bits: u32,
+    format: AudioFormat,
+    sample_rate: u32,
+    initial_silence: Option<usize>,
+    noise_gate_bits: Option<[u32; 3]>,
+    target_lufs_bits: Option<u32>,
+    pitch_semitones_bits: Option<u32>,
+    tempo_bits: Option<u32>,
+    repeat: usize,
+    repeat_silence: usize,
+    remove_dc: bool,
+    deterministic: bool,
+    disable_chunking: bool,
+    /// Pinned requests are for comparing instances, so never share audio
+    instance: Option<usize>,
+    title: Option<String>,
+    encode_settings: EncodeSettings,
+}
+
+/// Encoded response stored in the audio cache
+#[derive(Clone)]
+struct CachedAudio {
+    content_type: &'static str,
+    data: Bytes,
+    /// Value of the [`AUDIO_DURATION_HEADER`]
+    duration_seconds: String,
+    /// Value of the [`PHONEMES_HEADER`], when enabled
+    phonemes: Option<String>,
+}
+
+type AudioCache = Mutex<LruCache<CacheKey, CachedAudio>>;
+
+/// Shared state handed to every handler
+#[derive(Clone)]
+struct AppState {
+    /// First instance, used by the non-streaming endpoints
+    tts_single: TTSKoko,
+    tts_instances: Vec<TTSKoko>,
+    config: Arc<ServerConfig>,
+    /// Rate the loaded model's audio is at, from its [`TTSKokoInitConfig`]
+    sample_rate: u32,
+    /// Present only when `cache_size` is non-zero
+    audio_cache: Option<Arc<AudioCache>>,
+    /// Speech requests being synthesized or waiting for an instance
+    in_flight: Arc<AtomicUsize>,
+    /// Chunks running on each of `tts_instances`, across every request, so
+    /// streams and non-streaming synthesis pick instances the others left idle
+    busy: Arc<[AtomicUsize]>,
+    health: Arc<HealthCache>,
+    jingles: Arc<Jingles>,
+}
+
+/// Audio from [`ServerConfig::prefix_audio_path`] and
+/// [`ServerConfig::suffix_audio_path`], at the model's sample rate
+#[derive(Default)]
+struct Jingles {
+    prefix: Vec<f32>,
+    suffix: Vec<f32>,
+}
+
+impl Jingles {
+    fn load(config: &ServerConfig, sample_rate: u32) -> Result<Self, String> {
+        let load = |path: &Option<String>| -> Result<Vec<f32>, String> {
+            let Some(path) = path else {
+                return Ok(Vec::new());
+            };
+            let (samples, rate) = std::fs::File::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| read_wav(io::BufReader::new(file)))
+                .map_err(|e| format!("{}: {}", path, e))?;
+            Ok(audio::resample(
+                &samples,
+                rate,
+                sample_rate,
+                audio::ResampleQuality::Sinc,
+            ))
+        };
+        Ok(Self {
+            prefix: load(&config.prefix_audio_path)?,
+            suffix: load(&config.suffix_audio_path)?,
+        })
+    }
+
+    /// `speech` between the prefix and the suffix
+    fn wrap(&self, speech: Vec<f32>) -> Vec<f32> {
+        if self.prefix.is_empty() && self.suffix.is_empty() {
+            return speech;
+        }
+        [self.prefix.as_slice(), &speech, &self.suffix].concat()
+    }
+}
+
+/// How long a `/healthz` result is reused before the model is probed again
+const HEALTH_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Time and outcome of the last `/healthz` probe
+#[derive(Default)]
+struct HealthCache(tokio::sync::Mutex<Option<(Instant, bool)>>);
+
+impl HealthCache {
+    /// Reuse a result younger than [`HEALTH_CACHE_TTL`], otherwise run `probe`.
+    /// The lock is held while probing, so concurrent checks share one probe.
+    async fn check<F: Future<Output = bool>>(&self, probe: impl FnOnce() -> F) -> bool {
+        let mut last = self.0.lock().await;
+        if let Some((checked_at, healthy)) = *last
+            && checked_at.elapsed() < HEALTH_CACHE_TTL
+        {
+            return healthy;
+        }
+        let healthy = probe().await;
+        *last = Some((Instant::now(), healthy));
+        healthy
+    }
+}
+
+/// Seconds a busy server asks clients to wait before retrying
+const BUSY_RETRY_AFTER_SECS: u64 = 1;
+
+/// One request's place in [`AppState::in_flight`], given back on drop
+struct QueueSlot(Arc<AtomicUsize>);
+
+impl QueueSlot {
+    /// Take a slot unless `limit` requests already hold one
+    fn try_acquire(in_flight: &Arc<AtomicUsize>, limit: Option<usize>) -> Option<Self> {
+        let taken = in_flight.fetch_add(1, Ordering::AcqRel);
+        let slot = Self(in_flight.clone());
+        if limit.is_some_and(|limit| taken >= limit) {
+            return None; // dropping `slot` undoes the increment
+        }
+        Some(slot)
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Fade at either end of the audio when [`ServerConfig::fade_ms`] is unset
+pub const DEFAULT_FADE_MS: u32 = 5;
+
+/// Chunks buffered for a slow client when [`ServerConfig::stream_buffer`] is unset
+pub const DEFAULT_STREAM_BUFFER: usize = 4;
+
+/// Retries for a failed streaming chunk when [`ServerConfig::chunk_retries`] is unset
+pub const DEFAULT_CHUNK_RETRIES: usize = 1;
+
+/// Level below which [`ServerConfig::trim_leading_silence`] treats the start
+/// of a stream as silence, about -40 dBFS
+const LEADING_SILENCE_THRESHOLD: f32 = 0.01;
+
+/// Highest level allowed through by [`ServerConfig::peak_limit`], a little
+/// under full scale so encoders don't overshoot it
+const PEAK_LIMIT_CEILING: f32 = 0.98;
+
+/// OpenAI's voice names mapped to the closest Kokoro voices
+pub const DEFAULT_VOICE_ALIASES: [(&str, &str); 6] = [
+    ("alloy", "af_alloy"),
+    ("echo", "am_echo"),
+    ("fable", "bm_fable"),
+    ("onyx", "am_onyx"),
+    ("nova", "af_nova"),
+    ("shimmer", "af_bella"),
+];
+
+/// Roughly two hours of speech
+pub const DEFAULT_MAX_INPUT_CHARS: usize = 100_000;
+
+/// Room in the request body for the JSON around `input`
+const BODY_OVERHEAD_BYTES: usize = 64 * 1024;
+
+impl ServerConfig {
+    fn max_input_chars(&self) -> usize {
+        self.max_input_chars.unwrap_or(DEFAULT_MAX_INPUT_CHARS)
+    }
+
+    /// Request body cap: the longest allowed input, written entirely as
+    /// six-byte `\uXXXX` escapes, plus the rest of the request
+    fn max_body_bytes(&self) -> usize {
+        self.max_input_chars()
+            .saturating_mul(6)
+            .saturating_add(BODY_OVERHEAD_BYTES)
+    }
+
+    fn voice(&self, requested: Option<Voice>) -> String {
+        let voice = requested
+            .map(|Voice(voice)| voice)
+            .or_else(|| self.default_voice.clone())
+            .unwrap_or_else(|| Voice::default().0);
+        self.voice_aliases
+            .get(&voice)
+            .map(String::as_str)
+            .or_else(|| {
+                DEFAULT_VOICE_ALIASES
+                    .iter()
+                    .find(|(alias, _)| *alias == voice)
+                    .map(|(_, target)| *target)
+            })
+            .map_or(voice, str::to_string)
+    }
+
+    /// `voice`, or the [`ServerConfig::fallback_voice`] when one is set and
+    /// `exists` doesn't know `voice`. The second value is the voice that
+    /// was replaced.
+    fn voice_or_fallback(
+        &self,
+        voice: String,
+        exists: impl Fn(&str) -> bool,
+    ) -> (String, Option<String>) {
+        match &self.fallback_voice {
+            Some(fallback) if !exists(&voice) => (fallback.clone(), Some(voice)),
+            _ => (voice, None),
+        }
+    }
+
+    /// espeak language for a request, and where it came from: the request's
+    /// `lang_code`, the language of the voice prefix, one detected from the
+    /// input (see [`ServerConfig::detect_language`]) or the `en-us` default
+    fn language(
+        &self,
+        lang_code: Option<String>,
+        voice: &str,
+        segments: &[String],
+    ) -> (String, &'static str) {
+        if let Some(lang_code) = lang_code.filter(|code| !code.trim().is_empty()) {
+            return (lang_code.trim().to_string(), "lang_code");
+        }
+        if let Some(language) = voices::style_language(voice) {
+            return (language.to_string(), "voice");
+        }
+        if self.detect_language
+            && let Some(language) = detect_language(&segments.join(" "))
+        {
+            return (language.to_string(), "detected");
+        }
+        ("en-us".to_string(), "default")
+    }
+
+    fn stream_buffer(&self) -> usize {
+        self.stream_buffer.unwrap_or(DEFAULT_STREAM_BUFFER).max(1)
+    }
+
+    fn fade_samples(&self, sample_rate: u32) -> usize {
+        let ms = self.fade_ms.unwrap_or(DEFAULT_FADE_MS);
+        (sample_rate as u64 * ms as u64 / 1000) as usize
+    }
+
+    fn speed(&self, requested: Option<Speed>) -> f32 {
+        requested
+            .map(|Speed(speed)| speed)
+            .or(self.default_speed)
+            .unwrap_or_else(|| Speed::default().0)
+    }
+}
+
+pub async fn create_server(tts_instances: Vec<TTSKoko>) -> Result<Router, String> {
+    create_server_with_config(tts_instances, ServerConfig::default()).await
+}
+
+/// Build the router, failing when the prefix or suffix audio can't be read
+pub async fn create_server_with_config(
+    tts_instances: Vec<TTSKoko>,
+    config: ServerConfig,
+) -> Result<Router, String> {
+    info!("Starting TTS server with {} instances", tts_instances.len());
+
+    // Use first instance for compatibility with non-streaming endpoints
+    let tts_single = tts_instances
+        .first()
+        .cloned()
+        .expect("At least one TTS instance required");
+
+    let sample_rate = tts_single.init_config().sample_rate;
+    let jingles = Jingles::load(&config, sample_rate)
+        .map_err(|e| format!("failed to load prefix or suffix audio: {}", e))?;
+
+    let audio_cache =
+        (config.cache_size > 0).then(|| Arc::new(Mutex::new(LruCache::new(config.cache_size))));
+
+    if !config.warmup_voices.is_empty() {
+        tokio::spawn(warm_up_voices(
+            tts_instances.clone(),
+            warmup_languages(&config),
+        ));
+    }
+
+    let busy = instance_load(tts_instances.len());
+    let state = AppState {
+        sample_rate,
+        tts_single,
+        tts_instances,
+        config: Arc::new(config),
+        audio_cache,
+        in_flight: Arc::new(AtomicUsize::new(0)),
+        busy,
+        health: Arc::new(HealthCache::default()),
+        jingles: Arc::new(jingles),
+    };
+
+    let mut router = Router::new()
+        .route("/", get(handle_home))
+        .route("/healthz", get(handle_healthz))
+        .route(
+            "/v1/audio/speech",
+            post(handle_tts).get(handle_tts_get).head(handle_tts_head),
+        )
+        .route("/v1/audio/speech/events", post(handle_tts_events))
+        .route("/v1/audio/voices", get(handle_voices))
+        .route("/v1/models", get(handle_models))
+        .route("/v1/models/{model}", get(handle_model))
+        .route(
+            "/v1/debug/synthesize-tokens",
+            post(handle_debug_synthesize_tokens),
+        )
+        .route("/v1/admin/reload-voices", post(handle_reload_voices));
+    if state.config.debug_endpoints {
+        router = router
+            .route("/v1/debug/chunks", post(handle_debug_chunks))
+            .route("/v1/debug/tokens", post(handle_debug_tokens))
+            .route("/v1/debug/tokenize", post(handle_debug_tokenize));
+    }
+
+    let cors = cors_layer(&state.config.cors_origins);
+    Ok(with_layers(router, cors).with_state(state))
+}
+
+/// Middleware shared by every route
+fn with_layers<S: Clone + Send + Sync + 'static>(router: Router<S>, cors: CorsLayer) -> Router<S> {
+    router
+        .layer(axum::middleware::from_fn(request_id_middleware))
+        .layer(
+            CompressionLayer::new().compress_when(DefaultPredicate::new().and(UncompressedAudio)),
+        )
+        .layer(cors)
+}
+
+/// Compress only complete WAV and PCM bodies. The other formats are
+/// already compressed, and streamed bodies (whose size isn't known up
+/// front) would have their chunks held back by the encoder.
+#[derive(Clone, Copy)]
+struct UncompressedAudio;
+
+impl Predicate for UncompressedAudio {
+    fn should_compress<B>(&self, response: &axum::http::Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        matches!(content_type, Some("audio/wav" | "audio/pcm"))
+            && response.body().size_hint().exact().is_some()
+    }
+}
+
+/// CORS for [`ServerConfig::cors_origins`]: any origin when the list is
+/// empty, otherwise only the listed ones, each echoed back on its own
+/// requests. Origins that aren't valid header values are skipped.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    if origins.is_empty() {
+        return CorsLayer::permissive();
+    }
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| {
+            HeaderValue::from_str(origin)
+                .inspect_err(|_| warn!("Ignoring invalid CORS origin '{}'", origin))
+                .ok()
+        })
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any)
+}
+
+pub use axum::serve;
+
+/// Each of the [`ServerConfig::warmup_voices`] with the language a request
+/// for it would be phonemized in
+fn warmup_languages(config: &ServerConfig) -> Vec<(String, String)> {
+    config
+        .warmup_voices
+        .iter()
+        .map(|voice| (voice.clone(), config.language(None, voice, &[]).0))
+        .collect()
+}
+
+/// Run a short synthesis with each voice, in its language, on every
+/// instance, logging progress. Failures are logged and skipped; this never
+/// blocks serving requests.
+async fn warm_up_voices(tts_instances: Vec<TTSKoko>, voices: Vec<(String, String)>) {
+    let start = Instant::now();
+    let total = voices.len();
+    'voices: for (i, (voice, language)) in voices.into_iter().enumerate() {
+        let voice_start = Instant::now();
+        for (instance_idx, tts) in tts_instances.iter().enumerate() {
+            let tts = tts.clone();
+            let style = voice.clone();
+            let language = language.clone();
+            let instance_id = format!("{:02x}", instance_idx);
+            let result = tokio::task::spawn_blocking(move || {
+                tts.tts_raw_audio(
+                    "Hello.",
+                    &language,
+                    &style,
+                    1.0,
+                    None,
+                    LogContext {
+                        instance_id: Some(&instance_id),
+                        ..LogContext::default()
+                    },
+                )
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+            })
+            .await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    warn!("Warmup of voice '{}' failed: {}", voice, e);
+                    continue 'voices;
+                }
+                Err(e) => {
+                    warn!("Warmup task for voice '{}' panicked: {}", voice, e);
+                    continue 'voices;
+                }
+            }
+        }
+        info!(
+            "Warmed up voice '{}' ({}/{}) in {:?}",
+            voice,
+            i + 1,
+            total,
+            voice_start.elapsed()
+        );
+    }
+    info!("Voice warmup finished in {:?}", start.elapsed());
+}
+
+#[derive(Debug)]
+enum SpeechError {
+    // Deciding to modify this example in order to see errors
+    // (e.g. with tracing) is up to the developer
+    #[allow(dead_code)]
+    Koko(Box<dyn Error>),
+
+    #[allow(dead_code)]
+    Header(io::Error),
+
+    #[allow(dead_code)]
+    Chunk(io::Error),
+
+    #[allow(dead_code)]
+    Mp3Conversion(std::io::Error),
+
+    /// The request itself is invalid; the message is returned to the client
+    BadRequest(String),
+
+    /// Missing or wrong admin token
+    Unauthorized,
+
+    /// Synthesis ran past [`ServerConfig::request_timeout`]
+    Timeout,
+
+    /// The request is over a size limit; the message is returned to the client
+    PayloadTooLarge(String),
+
+    /// [`ServerConfig::max_queue_depth`] requests are already in flight
+    Busy,
+
+    /// Some or all instances kept their old voices on a reload; the message
+    /// names them and is returned to the (admin) client
+    VoiceReload(String),
 }
 
 impl std::fmt::Display for SpeechError {
@@ -520,588 +1608,4972 @@ impl std::fmt::Display for SpeechError {
             SpeechError::Header(e) => write!(f, "Header error: {}", e),
             SpeechError::Chunk(e) => write!(f, "Chunk error: {}", e),
             SpeechError::Mp3Conversion(e) => write!(f, "MP3 conversion error: {}", e),
+            SpeechError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            SpeechError::Unauthorized => write!(f, "Unauthorized"),
+            SpeechError::Timeout => write!(f, "Synthesis timed out"),
+            SpeechError::PayloadTooLarge(msg) => write!(f, "Payload too large: {}", msg),
+            SpeechError::Busy => write!(f, "Server busy"),
+            SpeechError::VoiceReload(msg) => write!(f, "Voice reload failed: {}", msg),
+        }
+    }
+}
+
+/// Text over the token limit is the client's fault, espeak failing is ours
+impl From<PlanError> for SpeechError {
+    fn from(error: PlanError) -> Self {
+        match error {
+            PlanError::TooLong(message) => SpeechError::BadRequest(message),
+            PlanError::Phonemize(e) => SpeechError::Koko(Box::new(e)),
+        }
+    }
+}
+
+/// Error response body in the shape OpenAI's SDKs parse
+#[derive(Serialize, Debug)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorDetail {
+    message: String,
+    /// Broad class of the error, e.g. `invalid_request_error`
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Stable identifier of the error, for clients to match on
+    code: &'static str,
+}
+
+impl SpeechError {
+    /// Status, `type` and `code` of the error response, which stay the same
+    /// across releases
+    fn classify(&self) -> (StatusCode, &'static str, &'static str) {
+        match self {
+            SpeechError::BadRequest(_) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "invalid_request",
+            ),
+            SpeechError::PayloadTooLarge(_) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "invalid_request_error",
+                "payload_too_large",
+            ),
+            SpeechError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "authentication_error",
+                "invalid_admin_token",
+            ),
+            SpeechError::Timeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "server_error",
+                "synthesis_timeout",
+            ),
+            SpeechError::Busy => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_error",
+                "server_busy",
+            ),
+            SpeechError::Koko(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "synthesis_failed",
+            ),
+            SpeechError::VoiceReload(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "voice_reload_failed",
+            ),
+            SpeechError::Header(_) | SpeechError::Chunk(_) | SpeechError::Mp3Conversion(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                "encoding_failed",
+            ),
+        }
+    }
+}
+
+impl IntoResponse for SpeechError {
+    fn into_response(self) -> Response {
+        let (status, kind, code) = self.classify();
+        let message = match self {
+            SpeechError::BadRequest(msg)
+            | SpeechError::PayloadTooLarge(msg)
+            | SpeechError::VoiceReload(msg) => msg,
+            SpeechError::Unauthorized => "missing or invalid admin token".to_string(),
+            SpeechError::Timeout => "synthesis timed out".to_string(),
+            SpeechError::Busy => "too many requests in flight, retry shortly".to_string(),
+            // None of these errors make sense to expose to the user of the API
+            SpeechError::Koko(_) => "speech synthesis failed".to_string(),
+            _ => "audio encoding failed".to_string(),
+        };
+        let body = Json(ErrorBody {
+            error: ErrorDetail {
+                message,
+                kind,
+                code,
+            },
+        });
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = [(header::RETRY_AFTER, BUSY_RETRY_AFTER_SECS.to_string())];
+            return (status, retry_after, body).into_response();
+        }
+        (status, body).into_response()
+    }
+}
+
+/// Handle HEAD /v1/audio/speech
+///
+/// Answers capability probes with the headers of a default request (a
+/// streamed MP3) and no body, instead of 405. CORS headers come from the
+/// shared [`CorsLayer`].
+async fn handle_tts_head() -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "audio/mpeg")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header("X-Accel-Buffering", "no")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Returns a 200 OK response to make it easier to check if the server is
+/// running.
+async fn handle_home() -> &'static str {
+    "OK"
+}
+
+/// Handle /healthz
+///
+/// Unlike `/`, this only reports OK once the model has actually produced
+/// audio, so it can serve as a readiness probe. Results are cached for
+/// [`HEALTH_CACHE_TTL`].
+async fn handle_healthz(
+    State(AppState {
+        tts_single,
+        config,
+        health,
+        ..
+    }): State<AppState>,
+) -> Response {
+    let healthy = health
+        .check(|| async move {
+            let voice = config.voice(None);
+            let result = run_with_timeout(config.request_timeout, move |_| {
+                tts_single
+                    .tts_raw_audio("Hello.", "en-us", &voice, 1.0, None, LogContext::default())
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+            .await;
+            if let Err(e) = &result {
+                warn!("Health check synthesis failed: {}", e);
+            }
+            result.is_ok()
+        })
+        .await;
+    health_response(healthy)
+}
+
+fn health_response(healthy: bool) -> Response {
+    if healthy {
+        (StatusCode::OK, "OK").into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "Model unavailable").into_response()
+    }
+}
+
+/// The instances switched to deterministic phonemization for one request
+fn deterministic_instances(tts_instances: Vec<TTSKoko>) -> Vec<TTSKoko> {
+    tts_instances
+        .into_iter()
+        .map(|tts| tts.with_deterministic_phonemes(true))
+        .collect()
+}
+
+/// Check the `Authorization: Bearer <token>` header against the configured admin token
+fn check_admin_token(headers: &HeaderMap, config: &ServerConfig) -> Result<(), SpeechError> {
+    let Some(expected) = config.admin_token.as_deref() else {
+        return Err(SpeechError::Unauthorized);
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    if provided.is_some_and(|provided| constant_time_eq(provided.as_bytes(), expected.as_bytes())) {
+        Ok(())
+    } else {
+        Err(SpeechError::Unauthorized)
+    }
+}
+
+/// Byte equality whose run time doesn't depend on where `a` and `b` first
+/// differ, so a token can't be guessed a byte at a time from response times.
+/// Only the length leaks.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `samples` as raw PCM at `bits` per sample in the given byte order
+fn pcm_bytes(samples: &[f32], bits: u16, endianness: PcmEndianness) -> io::Result<Vec<u8>> {
+    let bytes_per_sample = bits as usize / 8;
+    let mut data = Vec::with_capacity(samples.len() * bytes_per_sample);
+    write_samples(&mut data, samples, bits)?;
+    if endianness == PcmEndianness::Be {
+        swap_sample_bytes(&mut data, bytes_per_sample);
+    }
+    Ok(data)
+}
+
+/// Encode raw samples into the requested format
+///
+/// Returns the content type, the encoded bytes and a format name for logging.
+fn encode_audio(
+    response_format: AudioFormat,
+    raw_audio: Vec<f32>,
+    sample_rate: u32,
+    settings: &EncodeSettings,
+) -> Result<(&'static str, Vec<u8>, &'static str), SpeechError> {
+    let mp3_bitrate = settings.mp3_bitrate_kbps(response_format);
+    let wav_bytes = || -> Result<Vec<u8>, SpeechError> {
+        let mut wav_data = Vec::default();
+        let header = WavHeader::new(1, sample_rate, settings.bits_per_sample(response_format));
+        header
+            .write_header(&mut wav_data)
+            .map_err(SpeechError::Header)?;
+        write_samples(&mut wav_data, &raw_audio, header.bits_per_sample)
+            .map_err(SpeechError::Chunk)?;
+        Ok(wav_data)
+    };
+    let encoded = match response_format {
+        AudioFormat::Wav => ("audio/wav", wav_bytes()?, "WAV"),
+        AudioFormat::Json => {
+            let body = AudioJson {
+                audio: BASE64_STANDARD.encode(wav_bytes()?),
+                format: "wav",
+            };
+            let json_data = serde_json::to_vec(&body).map_err(|e| SpeechError::Chunk(e.into()))?;
+
+            ("application/json", json_data, "JSON")
+        }
+        AudioFormat::Mp3 => {
+            let mp3_data = pcm_to_mp3(&raw_audio, sample_rate, mp3_bitrate)
+                .map_err(|e| SpeechError::Mp3Conversion(e))?;
+
+            ("audio/mpeg", mp3_data, "MP3")
+        }
+        AudioFormat::Aac => {
+            let aac_data =
+                pcm_to_aac(&raw_audio, sample_rate).map_err(SpeechError::Mp3Conversion)?;
+
+            ("audio/aac", aac_data, "AAC")
+        }
+        AudioFormat::Pcm => {
+            // For PCM, we return the raw audio data directly, 16-bit
+            // little-endian unless the request asked otherwise
+            let pcm_data = pcm_bytes(
+                &raw_audio,
+                settings.bits_per_sample(response_format),
+                settings.pcm_endianness(),
+            )
+            .map_err(SpeechError::Chunk)?;
+            ("audio/pcm", pcm_data, "PCM")
         }
+        AudioFormat::Ulaw => (
+            "audio/basic",
+            g711::encode_ulaw(&raw_audio, sample_rate, settings.resample_quality()),
+            "mu-law",
+        ),
+        AudioFormat::Alaw => (
+            "audio/PCMA",
+            g711::encode_alaw(&raw_audio, sample_rate, settings.resample_quality()),
+            "A-law",
+        ),
+        AudioFormat::Webm => {
+            let pcm = pcm_bytes(&raw_audio, 16, PcmEndianness::Le).map_err(SpeechError::Chunk)?;
+            let mut muxer = WebmMuxer::pcm(1, sample_rate);
+            let mut webm_data = muxer.header();
+            webm_data.extend(muxer.write_pcm(&pcm));
+            ("audio/webm", webm_data, "WebM")
+        }
+        // For now, unsupported formats fall back to MP3
+        _ => {
+            let mp3_data = pcm_to_mp3(&raw_audio, sample_rate, mp3_bitrate)
+                .map_err(|e| SpeechError::Mp3Conversion(e))?;
+
+            ("audio/mpeg", mp3_data, "MP3")
+        }
+    };
+
+    Ok(encoded)
+}
+
+/// Combine `initial_silence` (tokens) and `initial_silence_ms` into a token
+/// count, at most [`MAX_INITIAL_SILENCE_TOKENS`]
+fn resolve_initial_silence(
+    tokens: Option<usize>,
+    ms: Option<u32>,
+) -> Result<Option<usize>, SpeechError> {
+    let silence = match (tokens, ms) {
+        (Some(_), Some(_)) => {
+            return Err(SpeechError::BadRequest(
+                "initial_silence and initial_silence_ms are mutually exclusive".to_string(),
+            ));
+        }
+        (tokens, ms) => tokens.or(ms.map(silence_tokens_for_ms)),
+    };
+    if silence.is_some_and(|silence| silence > MAX_INITIAL_SILENCE_TOKENS) {
+        return Err(SpeechError::BadRequest(format!(
+            "initial silence must be at most {} tokens ({}ms)",
+            MAX_INITIAL_SILENCE_TOKENS,
+            MAX_INITIAL_SILENCE_TOKENS as u32 * SILENCE_TOKEN_MS
+        )));
+    }
+    Ok(silence)
+}
+
+/// Fail input longer than [`ServerConfig::max_input_chars`] with a 413
+fn check_input_length(input: &SpeechInput, config: &ServerConfig) -> Result<(), SpeechError> {
+    let chars = input.char_count();
+    let limit = config.max_input_chars();
+    if chars > limit {
+        return Err(SpeechError::PayloadTooLarge(format!(
+            "input is {} characters, the limit is {}",
+            chars, limit
+        )));
+    }
+    Ok(())
+}
+
+/// With [`ServerConfig::reject_control_chars`], fail input holding any
+/// character that would otherwise be stripped before phonemization
+fn check_control_chars(input: &SpeechInput, config: &ServerConfig) -> Result<(), SpeechError> {
+    if !config.reject_control_chars {
+        return Ok(());
+    }
+    let texts = match input {
+        SpeechInput::Text(text) => std::slice::from_ref(text),
+        SpeechInput::Segments(segments) => segments.as_slice(),
+    };
+    match texts
+        .iter()
+        .flat_map(|text| text.chars())
+        .find(|&c| is_stray_control(c))
+    {
+        Some(c) => Err(SpeechError::BadRequest(format!(
+            "input contains control character U+{:04X}",
+            c as u32
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Streaming chunk cap for a request: the lower of the request's and the server's
+fn resolve_max_chunks(
+    requested: Option<usize>,
+    config: &ServerConfig,
+) -> Result<Option<usize>, SpeechError> {
+    if requested == Some(0) {
+        return Err(SpeechError::BadRequest(
+            "max_chunks must be at least 1".to_string(),
+        ));
+    }
+    Ok(match (requested, config.max_chunks) {
+        (Some(requested), Some(server)) => Some(requested.min(server)),
+        (requested, server) => requested.or(server),
+    })
+}
+
+/// Header naming the TTS instance to pin a request to, for clients that
+/// can't change the body, see [`SpeechRequest::instance`]
+const INSTANCE_HEADER: &str = "x-tts-instance";
+
+/// Check a request's pinned instance against the `instance_count` it can
+/// run on, logging the pin so a request's chunks can be traced to it
+fn resolve_instance(
+    requested: Option<usize>,
+    instance_count: usize,
+    log_prefix: &str,
+) -> Result<Option<usize>, SpeechError> {
+    let Some(index) = requested else {
+        return Ok(None);
+    };
+    if index >= instance_count {
+        return Err(SpeechError::BadRequest(format!(
+            "instance must be below {}, the number of TTS instances",
+            instance_count
+        )));
+    }
+    debug!("{} Pinned to instance {:02x}", log_prefix, index);
+    Ok(Some(index))
+}
+
+/// Accepted range for a request's `words_per_chunk`
+const WORDS_PER_CHUNK_RANGE: std::ops::RangeInclusive<usize> = 3..=100;
+
+fn resolve_words_per_chunk(requested: Option<usize>) -> Result<usize, SpeechError> {
+    match requested {
+        Some(words) if !WORDS_PER_CHUNK_RANGE.contains(&words) => {
+            Err(SpeechError::BadRequest(format!(
+                "words_per_chunk must be between {} and {}",
+                WORDS_PER_CHUNK_RANGE.start(),
+                WORDS_PER_CHUNK_RANGE.end()
+            )))
+        }
+        requested => Ok(requested.unwrap_or(STREAM_TARGET_WORDS)),
+    }
+}
+
+/// Longest espeak variant name accepted, well above espeak's own
+const MAX_ESPEAK_VARIANT_LEN: usize = 32;
+
+/// `language` with the request's `espeak_variant` appended the way espeak
+/// names variants (`en-us+f3`). A variant already given in `lang_code` is
+/// checked the same way; a variant name is letters, digits, `_` and `-`.
+fn resolve_espeak_variant(
+    language: String,
+    variant: Option<String>,
+) -> Result<String, SpeechError> {
+    let (base, given) = match language.split_once('+') {
+        Some((base, given)) => (base, Some(given.to_string())),
+        None => (language.as_str(), None),
+    };
+    let variant = match (given, variant) {
+        (Some(_), Some(_)) => {
+            return Err(SpeechError::BadRequest(
+                "lang_code already names an espeak variant, drop espeak_variant".to_string(),
+            ));
+        }
+        (given, variant) => given.or(variant),
+    };
+    let Some(variant) = variant else {
+        return Ok(language);
+    };
+    let valid = (1..=MAX_ESPEAK_VARIANT_LEN).contains(&variant.len())
+        && variant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(SpeechError::BadRequest(format!(
+            "espeak variant {:?} is not a variant name such as f3 or klatt",
+            variant
+        )));
+    }
+    Ok(format!("{}+{}", base, variant))
+}
+
+async fn handle_tts(
+    State(state): State<AppState>,
+    request: axum::extract::Request,
+) -> Result<Response, SpeechError> {
+    let (request_id, request_start) = request
+        .extensions()
+        .get::<(String, Instant)>()
+        .cloned()
+        .unwrap_or_else(|| ("unknown".to_string(), Instant::now()));
+
+    let config = state.config.clone();
+    let queue_slot = QueueSlot::try_acquire(&state.in_flight, config.max_queue_depth)
+        .ok_or(SpeechError::Busy)?;
+    let overrides = SpeechOverrides::from_uri(request.uri())?;
+    let headers = request.headers().clone();
+
+    // OpenAI TTS always streams by default - client decides how to consume
+    // Only send complete file when explicitly requested via stream: false
+
+    // Parse the JSON body
+    let max_body_bytes = config.max_body_bytes();
+    let bytes = axum::body::to_bytes(request.into_body(), max_body_bytes)
+        .await
+        .map_err(|e| {
+            error!("Error reading request body: {:?}", e);
+            let e = e.into_inner();
+            if e.is::<http_body_util::LengthLimitError>() {
+                SpeechError::PayloadTooLarge(format!(
+                    "request body is over the {} byte limit",
+                    max_body_bytes
+                ))
+            } else {
+                SpeechError::BadRequest(format!("failed to read request body: {}", e))
+            }
+        })?;
+
+    let mut speech_request: SpeechRequest = serde_json::from_slice(&bytes).map_err(|e| {
+        error!("JSON parsing error: {:?}", e);
+        SpeechError::BadRequest(format!("invalid request body: {}", e))
+    })?;
+    overrides.apply(&mut speech_request);
+    speech_request.negotiate_format(&headers);
+    speech_request.instance_from_header(&headers)?;
+
+    synthesize_speech(state, speech_request, request_id, request_start, queue_slot).await
+}
+
+/// Query string accepted by `POST /v1/audio/speech`, taking precedence
+/// over the body
+#[derive(Deserialize)]
+struct SpeechOverrides {
+    /// Replaces `response_format`, so codecs can be compared with one
+    /// payload, e.g. `curl -d @request.json '...speech?format=flac'`
+    #[serde(default)]
+    format: Option<AudioFormat>,
+}
+
+impl SpeechOverrides {
+    fn from_uri(uri: &axum::http::Uri) -> Result<Self, SpeechError> {
+        let Query(overrides) =
+            Query::try_from_uri(uri).map_err(|e| SpeechError::BadRequest(e.body_text()))?;
+        Ok(overrides)
+    }
+
+    fn apply(self, request: &mut SpeechRequest) {
+        if let Some(format) = self.format {
+            request.response_format = Some(format);
+        }
+    }
+}
+
+impl AudioFormat {
+    /// The format served as `media_type`, for `Accept` negotiation
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.to_ascii_lowercase().as_str() {
+            "audio/mpeg" | "audio/mp3" => Some(Self::Mp3),
+            "audio/wav" | "audio/wave" | "audio/x-wav" => Some(Self::Wav),
+            "audio/opus" | "audio/ogg" => Some(Self::Opus),
+            "audio/aac" => Some(Self::Aac),
+            "audio/flac" => Some(Self::Flac),
+            "audio/pcm" | "audio/l16" => Some(Self::Pcm),
+            "audio/basic" => Some(Self::Ulaw),
+            "audio/pcma" => Some(Self::Alaw),
+            "audio/webm" => Some(Self::Webm),
+            _ => None,
+        }
+    }
+}
+
+/// The format a client asks for in its `Accept` header, used when the
+/// request doesn't name one. Wildcards and unknown types are skipped, and
+/// of the rest the highest `q` wins, the earliest on a tie.
+fn format_from_accept(headers: &HeaderMap) -> Option<AudioFormat> {
+    let mut best: Option<(AudioFormat, f32)> = None;
+    for value in headers.get_all(header::ACCEPT) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for range in value.split(',') {
+            let mut params = range.split(';');
+            let Some(format) = params
+                .next()
+                .and_then(|media_type| AudioFormat::from_media_type(media_type.trim()))
+            else {
+                continue;
+            };
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((format, quality));
+            }
+        }
+    }
+    best.map(|(format, _)| format)
+}
+
+impl SpeechRequest {
+    /// Take the format from `Accept` when neither the body nor the query
+    /// string picked one
+    fn negotiate_format(&mut self, headers: &HeaderMap) {
+        if self.response_format.is_none() {
+            self.response_format = format_from_accept(headers);
+        }
+    }
+
+    /// Take the pinned instance from [`INSTANCE_HEADER`] when the body
+    /// doesn't name one
+    fn instance_from_header(&mut self, headers: &HeaderMap) -> Result<(), SpeechError> {
+        let Some(value) = headers.get(INSTANCE_HEADER) else {
+            return Ok(());
+        };
+        if self.instance.is_none() {
+            let index = value.to_str().ok().and_then(|v| v.trim().parse().ok());
+            self.instance = Some(index.ok_or_else(|| {
+                SpeechError::BadRequest(format!("{} must be an instance index", INSTANCE_HEADER))
+            })?);
+        }
+        Ok(())
+    }
+}
+
+/// `GET /v1/audio/speech` parameters, for clients such as an `<audio src>`
+/// that can't send a body
+#[derive(Deserialize)]
+struct SpeechQuery {
+    input: String,
+
+    #[serde(default)]
+    voice: Option<Voice>,
+
+    #[serde(default)]
+    speed: Option<Speed>,
+
+    #[serde(default)]
+    response_format: Option<AudioFormat>,
+
+    /// Wins over `response_format`, as on the POST route
+    #[serde(default)]
+    format: Option<AudioFormat>,
+}
+
+impl From<SpeechQuery> for SpeechRequest {
+    fn from(query: SpeechQuery) -> Self {
+        Self {
+            input: SpeechInput::Text(query.input),
+            voice: query.voice,
+            speed: query.speed,
+            response_format: query.format.or(query.response_format),
+            ..Self::default()
+        }
+    }
+}
+
+/// Handle `GET /v1/audio/speech`, synthesizing exactly like the POST route
+async fn handle_tts_get(
+    State(state): State<AppState>,
+    request_info: Option<Extension<(String, Instant)>>,
+    headers: HeaderMap,
+    query: Result<Query<SpeechQuery>, QueryRejection>,
+) -> Result<Response, SpeechError> {
+    let (request_id, request_start) = request_info
+        .map(|Extension(info)| info)
+        .unwrap_or_else(|| ("unknown".to_string(), Instant::now()));
+    let queue_slot = QueueSlot::try_acquire(&state.in_flight, state.config.max_queue_depth)
+        .ok_or(SpeechError::Busy)?;
+    let Query(query) = query.map_err(|e| SpeechError::BadRequest(e.body_text()))?;
+    let mut speech_request = SpeechRequest::from(query);
+    speech_request.negotiate_format(&headers);
+    speech_request.instance_from_header(&headers)?;
+
+    synthesize_speech(state, speech_request, request_id, request_start, queue_slot).await
+}
+
+/// Synthesize and encode a parsed speech request, shared by the POST and
+/// GET routes. `queue_slot` is held until the response has been sent.
+async fn synthesize_speech(
+    AppState {
+        tts_single,
+        tts_instances,
+        config,
+        audio_cache,
+        sample_rate,
+        busy,
+        jingles,
+        ..
+    }: AppState,
+    speech_request: SpeechRequest,
+    request_id: String,
+    request_start: Instant,
+    queue_slot: QueueSlot,
+) -> Result<Response, SpeechError> {
+    let SpeechRequest {
+        input,
+        voice,
+        response_format,
+        speed,
+        initial_silence,
+        initial_silence_ms,
+        max_chunks,
+        words_per_chunk,
+        stream,
+        wav_streaming_mode,
+        noise_gate,
+        target_lufs,
+        pitch_semitones,
+        tempo,
+        repeat,
+        repeat_silence_ms,
+        remove_dc,
+        deterministic,
+        disable_chunking,
+        bitrate_kbps,
+        compression_level,
+        bit_depth,
+        resample_quality,
+        pcm_endianness,
+        instance,
+        title,
+        lang_code,
+        espeak_variant,
+        keep_acronyms,
+        ..
+    } = speech_request;
+    let response_format = response_format.unwrap_or_default();
+    check_input_length(&input, &config)?;
+    check_control_chars(&input, &config)?;
+    let instance = resolve_instance(
+        instance,
+        tts_instances.len().max(1),
+        &get_colored_request_id_with_relative(&request_id, request_start),
+    )?;
+    let (tts_single, tts_instances) = if deterministic {
+        (
+            tts_single.with_deterministic_phonemes(true),
+            deterministic_instances(tts_instances),
+        )
+    } else {
+        (tts_single, tts_instances)
+    };
+    let (voice, replaced_voice) =
+        config.voice_or_fallback(config.voice(voice), |voice| tts_single.has_voice(voice));
+    if let Some(replaced) = &replaced_voice {
+        warn!(
+            "{} Voice {} not found, using fallback {}",
+            get_colored_request_id_with_relative(&request_id, request_start),
+            replaced,
+            voice
+        );
+    }
+    let speed = config.speed(speed);
+
+    let segments = preprocess_segments(Some(&tts_single), input, keep_acronyms)?;
+    let (language, language_source) = config.language(lang_code, &voice, &segments);
+    let language = resolve_espeak_variant(language, espeak_variant)?;
+    debug!(
+        "{} Language: {} (from {})",
+        get_colored_request_id_with_relative(&request_id, request_start),
+        language,
+        language_source
+    );
+
+    let initial_silence = resolve_initial_silence(initial_silence, initial_silence_ms)?;
+    let max_chunks = resolve_max_chunks(max_chunks, &config)?;
+    let words_per_chunk = resolve_words_per_chunk(words_per_chunk)?;
+    let whole_plan = if disable_chunking {
+        // Planning phonemizes every segment, which blocks like synthesis does
+        let init_config = tts_single.init_config().clone();
+        let segments = segments.clone();
+        let language = language.clone();
+        let planned = tokio::task::spawn_blocking(move || {
+            plan_whole_segments(&init_config, &segments, &language, initial_silence)
+        })
+        .await
+        .map_err(|e| SpeechError::Koko(Box::new(e)))?;
+        Some(planned?)
+    } else {
+        None
+    };
+    // Capping a stream at one chunk leaves one chunk per segment
+    let max_chunks = if disable_chunking {
+        Some(1)
+    } else {
+        max_chunks
+    };
+    if let Some(lufs) = target_lufs
+        && !(-70.0..=0.0).contains(&lufs)
+    {
+        return Err(SpeechError::BadRequest(
+            "target_lufs must be between -70 and 0".to_string(),
+        ));
+    }
+    if let Some(semitones) = pitch_semitones
+        && !(-12.0..=12.0).contains(&semitones)
+    {
+        return Err(SpeechError::BadRequest(
+            "pitch_semitones must be between -12 and 12".to_string(),
+        ));
+    }
+    if let Some(tempo) = tempo
+        && !(0.25..=4.0).contains(&tempo)
+    {
+        return Err(SpeechError::BadRequest(
+            "tempo must be between 0.25 and 4".to_string(),
+        ));
+    }
+    let (repeat, repeat_silence) = resolve_repeat(repeat, repeat_silence_ms, sample_rate)?;
+
+    let encode_settings = EncodeSettings {
+        bitrate_kbps,
+        compression_level,
+        bit_depth,
+        resample_quality,
+        pcm_endianness,
+    }
+    .validate(response_format)?;
+
+    // OpenAI-compliant behavior: Stream by default, only send complete file if stream: false.
+    // A json body only exists once the audio is complete, so it defaults to not streaming.
+    let should_stream = stream.unwrap_or(response_format != AudioFormat::Json);
+    if should_stream && response_format == AudioFormat::Json {
+        return Err(SpeechError::BadRequest(
+            "json responses can't be streamed, set stream to false".to_string(),
+        ));
+    }
+
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+    debug!(
+        "{} Streaming decision: stream_param={:?}, final_decision={}",
+        colored_request_id, stream, should_stream
+    );
+
+    if should_stream {
+        let settings = StreamSettings {
+            voice,
+            language,
+            speed,
+            initial_silence,
+            remove_dc,
+            max_chunks,
+            words_per_chunk,
+            wav_streaming_mode,
+            ..StreamSettings::from_config(&config, sample_rate)
+        };
+        let worker_pool =
+            TTSWorkerPool::new(tts_instances, busy, config.stream_window).pinned(instance);
+        let response = handle_tts_streaming(
+            worker_pool,
+            segments,
+            settings,
+            response_format,
+            encode_settings,
+            request_id,
+            request_start,
+        )
+        .await?;
+        // Synthesis carries on after the handler returns, so the slot goes
+        // with the body and is given back once the stream is done
+        let response = response.map(|body| {
+            Body::from_stream(body.into_data_stream().map(move |chunk| {
+                let _slot = &queue_slot;
+                chunk
+            }))
+        });
+        return Ok(with_voice_fallback(response, replaced_voice.as_deref()));
+    }
+
+    // Identical non-streaming requests can be answered without synthesis
+    let cache_key = audio_cache.as_ref().map(|_| CacheKey {
+        segments: segments.clone(),
+        voice: voice.clone(),
+        language: language.clone(),
+        speed_bits: speed.to_bits(),
+        format: response_format,
+        sample_rate,
+        initial_silence,
+        noise_gate_bits: noise_gate.as_ref().map(|g| {
+            [
+                g.threshold.to_bits(),
+                g.attack_ms.to_bits(),
+                g.release_ms.to_bits(),
+            ]
+        }),
+        target_lufs_bits: target_lufs.map(f32::to_bits),
+        pitch_semitones_bits: pitch_semitones.map(f32::to_bits),
+        tempo_bits: tempo.map(f32::to_bits),
+        repeat,
+        repeat_silence,
+        remove_dc,
+        deterministic,
+        disable_chunking,
+        instance,
+        title: title.clone(),
+        encode_settings,
+    });
+    if let (Some(cache), Some(key)) = (&audio_cache, &cache_key) {
+        let (cached, hits, misses) = {
+            let mut cache = cache.lock().unwrap();
+            let cached = cache.get(key);
+            (cached, cache.hits(), cache.misses())
+        };
+        if let Some(CachedAudio {
+            content_type,
+            data,
+            duration_seconds,
+            phonemes,
+        }) = cached
+        {
+            let colored_request_id =
+                get_colored_request_id_with_relative(&request_id, request_start);
+            info!(
+                "{} TTS served from cache - {} bytes ({} hits, {} misses)",
+                colored_request_id,
+                data.len(),
+                hits,
+                misses
+            );
+            return audio_response(content_type, data, duration_seconds, phonemes.as_deref())
+                .map(|response| with_voice_fallback(response, replaced_voice.as_deref()));
+        }
+    }
+
+    // Non-streaming mode: the same chunks the serial path would synthesize,
+    // spread over every instance and joined back in order
+    let planned: Vec<Vec<(String, usize)>> = whole_plan.unwrap_or_else(|| {
+        segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let silence = if index == 0 { initial_silence } else { None };
+                tts_single.init_config().plan_chunks(segment, silence)
+            })
+            .collect()
+    });
+    let (instances, first_instance) = match instance {
+        _ if tts_instances.is_empty() => (vec![tts_single], 0),
+        Some(index) => (vec![tts_instances[index].clone()], index),
+        None => (tts_instances, 0),
+    };
+    let synthesis_voice = voice.clone();
+    let synthesis_language = language.clone();
+    let synthesis_request_id = request_id.clone();
+    let (mut raw_audio, phonemes) = run_with_timeout(config.request_timeout, move |cancelled| {
+        let jobs: Vec<&(String, usize)> = planned.iter().flatten().collect();
+        let mut chunks = synthesize_in_order(
+            &instances,
+            &busy[first_instance..first_instance + instances.len()],
+            &jobs,
+            cancelled,
+            |tts, instance, chunk_number, (chunk, silence)| {
+                let (mut audio, phonemes) = tts
+                    .tts_planned_chunk(
+                        chunk,
+                        *silence,
+                        &synthesis_language,
+                        &synthesis_voice,
+                        speed,
+                        LogContext {
+                            request_id: Some(&synthesis_request_id),
+                            instance_id: Some(&format!("{:02x}", first_instance + instance)),
+                            chunk_number: Some(chunk_number),
+                        },
+                    )
+                    .map_err(|e| e.to_string())?;
+                if remove_dc {
+                    strip_dc(&mut audio, sample_rate);
+                }
+                Ok((audio, phonemes))
+            },
+        )?
+        .into_iter();
+
+        let mut raw_audio = Vec::new();
+        let mut phonemes = Vec::new();
+        for (index, segment_chunks) in planned.iter().enumerate() {
+            if index > 0 {
+                raw_audio.resize(raw_audio.len() + segment_silence_samples(sample_rate), 0.0);
+            }
+            for (chunk, chunk_phonemes) in chunks.by_ref().take(segment_chunks.len()) {
+                raw_audio.extend(chunk);
+                phonemes.push(chunk_phonemes);
+            }
+        }
+        Ok((raw_audio, phonemes.join(" ")))
+    })
+    .await?;
+    let phonemes = config.phonemes_header.then_some(phonemes);
+
+    if let Some(semitones) = pitch_semitones {
+        raw_audio = audio::shift_pitch(&raw_audio, sample_rate, semitones);
+    }
+
+    if let Some(tempo) = tempo {
+        raw_audio = audio::change_tempo(&raw_audio, sample_rate, tempo);
+    }
+
+    if let Some(gate) = noise_gate {
+        filter::noise_gate(
+            &mut raw_audio,
+            sample_rate,
+            gate.threshold,
+            gate.attack_ms,
+            gate.release_ms,
+        );
+    }
+
+    if let Some(lufs) = target_lufs {
+        audio::normalize_loudness(&mut raw_audio, sample_rate, lufs);
+    }
+
+    raw_audio = repeat_audio(raw_audio, repeat, repeat_silence);
+
+    let fade_samples = config.fade_samples(sample_rate);
+    audio::fade_in(&mut raw_audio, fade_samples);
+    audio::fade_out(&mut raw_audio, fade_samples);
+    if config.peak_limit {
+        audio::peak_limit(&mut raw_audio, PEAK_LIMIT_CEILING);
+    }
+    let raw_audio = jingles.wrap(raw_audio);
+
+    let duration_seconds = audio_duration_seconds(raw_audio.len(), sample_rate);
+    let length_ms = raw_audio.len() as u64 * 1000 / sample_rate as u64;
+    let (content_type, mut audio_data, format_name) =
+        encode_audio(response_format, raw_audio, sample_rate, &encode_settings)?;
+    // Formats without an encoder yet come back as MP3 too
+    if content_type == "audio/mpeg" {
+        audio_data = tag_id3v2(&audio_data, length_ms, title.as_deref());
+    }
+    let audio_data = Bytes::from(audio_data);
+
+    if let (Some(cache), Some(key)) = (&audio_cache, cache_key) {
+        cache.lock().unwrap().insert(
+            key,
+            CachedAudio {
+                content_type,
+                data: audio_data.clone(),
+                duration_seconds: duration_seconds.clone(),
+                phonemes: phonemes.clone(),
+            },
+        );
+    }
+
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+    info!(
+        "{} TTS non-streaming completed - {} bytes, {} format",
+        colored_request_id,
+        audio_data.len(),
+        format_name
+    );
+
+    audio_response(
+        content_type,
+        audio_data,
+        duration_seconds,
+        phonemes.as_deref(),
+    )
+    .map(|response| with_voice_fallback(response, replaced_voice.as_deref()))
+}
+
+/// Requested voice a response was synthesized without, when it didn't
+/// exist and [`ServerConfig::fallback_voice`] was used instead
+const VOICE_FALLBACK_HEADER: &str = "X-Voice-Fallback";
+
+/// Name `replaced` in the [`VOICE_FALLBACK_HEADER`]. A name that can't be a
+/// header value is left out, the warning is logged either way.
+fn with_voice_fallback(mut response: Response, replaced: Option<&str>) -> Response {
+    if let Some(value) = replaced.and_then(|voice| HeaderValue::from_str(voice).ok()) {
+        response.headers_mut().insert(VOICE_FALLBACK_HEADER, value);
+    }
+    response
+}
+
+/// The planned chunks of each segment for `disable_chunking`, see
+/// [`TTSKokoInitConfig::plan_whole`]
+fn plan_whole_segments(
+    init_config: &TTSKokoInitConfig,
+    segments: &[String],
+    language: &str,
+    initial_silence: Option<usize>,
+) -> Result<Vec<Vec<(String, usize)>>, PlanError> {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            let silence = if index == 0 { initial_silence } else { None };
+            init_config.plan_whole(segment, language, silence)
+        })
+        .collect()
+}
+
+/// A complete (non-streaming) audio response. The explicit `Content-Length`
+/// lets clients show download progress; streamed responses are chunked
+/// and never carry one.
+fn audio_response(
+    content_type: &'static str,
+    data: Bytes,
+    duration_seconds: String,
+    phonemes: Option<&str>,
+) -> Result<Response, SpeechError> {
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(AUDIO_DURATION_HEADER, duration_seconds);
+    if let Some(phonemes) = phonemes {
+        response = response.header(PHONEMES_HEADER, phonemes_header_value(phonemes));
+    }
+    response
+        .body(Body::from(data))
+        .map_err(|e| SpeechError::Mp3Conversion(std::io::Error::other(e)))
+}
+
+/// Phonemes behind a non-streaming response, space separated and as raw
+/// UTF-8, when [`ServerConfig::phonemes_header`] is on
+const PHONEMES_HEADER: &str = "X-Phonemes";
+
+/// Longest [`PHONEMES_HEADER`] sent before it is cut short with `…`, well
+/// under the header size limits of common proxies
+const MAX_PHONEMES_HEADER_BYTES: usize = 2048;
+
+fn phonemes_header_value(phonemes: &str) -> HeaderValue {
+    // Header values can't hold line breaks
+    let mut value = phonemes.split_whitespace().collect::<Vec<_>>().join(" ");
+    if value.len() > MAX_PHONEMES_HEADER_BYTES {
+        let cut = (0..=MAX_PHONEMES_HEADER_BYTES)
+            .rev()
+            .find(|&i| value.is_char_boundary(i))
+            .unwrap_or(0);
+        value.truncate(cut);
+        value.push('…');
+    }
+    HeaderValue::from_bytes(value.as_bytes()).unwrap_or(HeaderValue::from_static(""))
+}
+
+/// Total audio length of a non-streaming response, so clients can size their
+/// buffers before the body has downloaded. Streaming responses can't know it
+/// up front and don't send it.
+const AUDIO_DURATION_HEADER: &str = "X-Audio-Duration-Seconds";
+
+/// Duration of `samples` mono samples, formatted for [`AUDIO_DURATION_HEADER`]
+fn audio_duration_seconds(samples: usize, sample_rate: u32) -> String {
+    format!("{:.3}", samples as f64 / sample_rate as f64)
+}
+
+/// Split a request's input into preprocessed segments, see [`SpeechInput`].
+/// Input with nothing but whitespace, as text or array, is rejected here so
+/// streaming and non-streaming requests fail the same way before synthesis.
+fn preprocess_segments(
+    tts: Option<&TTSKoko>,
+    input: SpeechInput,
+    keep_acronyms: Option<bool>,
+) -> Result<Vec<String>, SpeechError> {
+    let segments: Vec<String> = input
+        .into_segments()
+        .into_iter()
+        .map(|segment| match tts {
+            Some(tts) => tts.preprocess_with(&segment, keep_acronyms),
+            None => segment,
+        })
+        .collect();
+    if segments.is_empty() {
+        return Err(SpeechError::BadRequest(
+            "input contains no text".to_string(),
+        ));
+    }
+    Ok(segments)
+}
+
+/// Cutoff of the high-pass behind `remove_dc`, well below speech
+const DC_HIGH_PASS_HZ: f32 = 20.0;
+
+/// `remove_dc` cleanup for one chunk of audio
+fn strip_dc(samples: &mut [f32], sample_rate: u32) {
+    audio::remove_dc_offset(samples);
+    audio::high_pass(samples, sample_rate, DC_HIGH_PASS_HZ);
+}
+
+/// Most copies a request's `repeat` can ask for
+const MAX_REPEAT: usize = 20;
+
+/// Longest pause a request's `repeat_silence_ms` can ask for
+const MAX_REPEAT_SILENCE_MS: u32 = 10_000;
+
+/// Check a request's `repeat` options, giving the number of copies and the
+/// samples of silence between them
+fn resolve_repeat(
+    repeat: Option<usize>,
+    silence_ms: Option<u32>,
+    sample_rate: u32,
+) -> Result<(usize, usize), SpeechError> {
+    let repeat = repeat.unwrap_or(1);
+    if !(1..=MAX_REPEAT).contains(&repeat) {
+        return Err(SpeechError::BadRequest(format!(
+            "repeat must be between 1 and {}",
+            MAX_REPEAT
+        )));
+    }
+    let silence_ms = silence_ms.unwrap_or(SEGMENT_SILENCE_MS);
+    if silence_ms > MAX_REPEAT_SILENCE_MS {
+        return Err(SpeechError::BadRequest(format!(
+            "repeat_silence_ms must be at most {}",
+            MAX_REPEAT_SILENCE_MS
+        )));
+    }
+    Ok((
+        repeat,
+        (sample_rate as u64 * silence_ms as u64 / 1000) as usize,
+    ))
+}
+
+/// `times` copies of `audio` with `silence` samples between each
+fn repeat_audio(audio: Vec<f32>, times: usize, silence: usize) -> Vec<f32> {
+    if times <= 1 {
+        return audio;
+    }
+    let mut repeated = Vec::with_capacity(times * audio.len() + (times - 1) * silence);
+    for copy in 0..times {
+        if copy > 0 {
+            repeated.resize(repeated.len() + silence, 0.0);
+        }
+        repeated.extend_from_slice(&audio);
+    }
+    repeated
+}
+
+/// Number of samples in the pause between array input elements
+fn segment_silence_samples(sample_rate: u32) -> usize {
+    (sample_rate as u64 * SEGMENT_SILENCE_MS as u64 / 1000) as usize
+}
+
+/// Final pass over the chunker output before streaming.
+///
+/// Normalizes chunks, splits any still longer than `max_words` (a long
+/// clause without commas or break words) between words, drops any that have nothing to speak (whitespace or
+/// punctuation only, e.g. from `".. .."`) so they can't occupy a worker or be
+/// mistaken for the completion signal, then appends the single empty
+/// terminator chunk.
+fn prepare_stream_chunks(
+    chunks: Vec<String>,
+    target_words: usize,
+    min_words: usize,
+    max_words: usize,
+    break_words: &[String],
+) -> Vec<String> {
+    // Normalize chunks: merge very short ones and avoid leading conjunctions
+    let mut chunks: Vec<String> = normalize_chunks(chunks, target_words, min_words, break_words)
+        .into_iter()
+        .flat_map(|c| split_by_word_count(&c, max_words))
+        .filter(|c| c.chars().any(char::is_alphanumeric))
+        .collect();
+
+    // Add empty chunk at end as completion signal to client
+    chunks.push(String::new());
+    chunks
+}
+
+/// Words per chunk the streaming chunker aims for (tuned between 18 and 24)
+const STREAM_TARGET_WORDS: usize = 20;
+
+/// Chunks shorter than this are merged into the previous one
+const STREAM_MIN_WORDS: usize = 8;
+
+/// Unless [`ServerConfig::max_chunk_words`] is set, no streaming chunk has
+/// more than this many times the words-per-chunk target, so the maximum
+/// follows the request's `words_per_chunk`
+pub const STREAM_MAX_CHUNK_FACTOR: usize = 2;
+
+/// Chunks of a streaming request, see [`plan_stream_chunks`]
+struct StreamPlan {
+    /// Chunks in speech order, ending with the empty terminator
+    chunks: Vec<String>,
+    /// Indices of chunks that end an input segment and are followed by another
+    segment_ends: Vec<usize>,
+    /// Words-per-chunk target that was used
+    words_per_chunk: usize,
+}
+
+/// Split streaming input into chunks ready for [`start_ordered_synthesis`].
+///
+/// Each segment is chunked separately, aiming for `words_per_chunk` words,
+/// so no chunk spans two segments. When the split has more than `max_chunks`
+/// spoken chunks, the words-per-chunk target (and the merge threshold with
+/// it) is raised until the input fits, so the whole text is still
+/// synthesized, just at a coarser granularity. It is never raised past
+/// `max_chunk_words`, which no chunk exceeds.
+fn plan_stream_chunks(
+    segments: &[String],
+    words_per_chunk: usize,
+    max_chunks: Option<usize>,
+    max_chunk_words: Option<usize>,
+    delimiters: &ChunkDelimiters,
+) -> StreamPlan {
+    let total_words: usize = segments.iter().map(|s| count_words(s)).sum();
+    let mut target_words = words_per_chunk;
+    loop {
+        let min_words = target_words * STREAM_MIN_WORDS / STREAM_TARGET_WORDS;
+        let max_words = max_chunk_words.unwrap_or(target_words * STREAM_MAX_CHUNK_FACTOR);
+        let mut chunks = Vec::new();
+        let mut segment_ends = Vec::new();
+        for segment in segments {
+            let segment_chunks =
+                segment_stream_chunks(segment, target_words, min_words, max_words, delimiters);
+            if segment_chunks.is_empty() {
+                continue;
+            }
+            if !chunks.is_empty() {
+                segment_ends.push(chunks.len() - 1);
+            }
+            chunks.extend(segment_chunks);
+        }
+        // The empty terminator doesn't count against the cap
+        let spoken = chunks.len();
+        chunks.push(String::new());
+
+        // Once the merge threshold exceeds the input length everything merges
+        // into one chunk, so there's nothing left to gain from growing further
+        match max_chunks {
+            Some(max) if spoken > max && min_words <= total_words && target_words < max_words => {
+                target_words = target_words * 3 / 2;
+            }
+            _ => {
+                return StreamPlan {
+                    chunks,
+                    segment_ends,
+                    words_per_chunk: target_words,
+                };
+            }
+        }
+    }
+}
+
+/// Stream chunks of one input segment, without a terminator. Word chunking
+/// would split SSML tags, so [`ssml`] input is parsed here instead: each
+/// pause becomes a chunk holding just that break, and the text between is
+/// chunked as plain text.
+fn segment_stream_chunks(
+    segment: &str,
+    target_words: usize,
+    min_words: usize,
+    max_words: usize,
+    delimiters: &ChunkDelimiters,
+) -> Vec<String> {
+    let chunk_text = |text: &str| {
+        let mut chunks = prepare_stream_chunks(
+            koko::split_text_into_speech_chunks_with(
+                text,
+                target_words,
+                &delimiters.sentence_punctuation,
+                &delimiters.clause_punctuation,
+            ),
+            target_words,
+            min_words,
+            max_words,
+            &delimiters.break_words,
+        );
+        chunks.pop(); // per-segment terminator
+        chunks
+    };
+    if !ssml::is_ssml(segment) {
+        return chunk_text(segment);
+    }
+
+    let mut chunks = Vec::new();
+    for part in ssml::parse(segment) {
+        if part.pause_ms > 0 {
+            chunks.push(format!(
+                "<speak><break time=\"{}ms\"/></speak>",
+                part.pause_ms
+            ));
+        }
+        chunks.extend(chunk_text(&part.text));
+    }
+    chunks
+}
+
+/// Settings of one streamed synthesis, taken from the request and the
+/// [`ServerConfig`] once the request is validated
+#[derive(Debug, Clone)]
+struct StreamSettings {
+    voice: String,
+    language: String,
+    sample_rate: u32,
+    speed: f32,
+    /// Silence tokens before the first chunk
+    initial_silence: Option<usize>,
+    remove_dc: bool,
+    max_chunks: Option<usize>,
+    max_chunk_words: Option<usize>,
+    words_per_chunk: usize,
+    wav_streaming_mode: WavStreamingMode,
+    fade_samples: usize,
+    trim_leading_silence: bool,
+    peak_limit: bool,
+    chunk_delimiters: ChunkDelimiters,
+    stream_buffer: usize,
+    verify_order: bool,
+    keep_alive: bool,
+    request_timeout: Option<Duration>,
+    chunk_retries: usize,
+}
+
+impl StreamSettings {
+    /// The settings `config` decides, with the request's at their defaults
+    /// for the caller to fill in
+    fn from_config(config: &ServerConfig, sample_rate: u32) -> Self {
+        Self {
+            voice: String::new(),
+            language: String::new(),
+            sample_rate,
+            speed: 1.0,
+            initial_silence: None,
+            remove_dc: false,
+            max_chunks: None,
+            max_chunk_words: config.max_chunk_words,
+            words_per_chunk: STREAM_TARGET_WORDS,
+            wav_streaming_mode: WavStreamingMode::default(),
+            fade_samples: config.fade_samples(sample_rate),
+            trim_leading_silence: config.trim_leading_silence,
+            peak_limit: config.peak_limit,
+            chunk_delimiters: config.chunk_delimiters.clone(),
+            stream_buffer: config.stream_buffer(),
+            verify_order: config.verify_stream_order,
+            keep_alive: config.stream_keep_alive,
+            request_timeout: config.request_timeout,
+            chunk_retries: config.chunk_retries.unwrap_or(DEFAULT_CHUNK_RETRIES),
+        }
+    }
+}
+
+/// `(chunk_id, pcm_le)` pairs in speech order, at the `sample_bits` given to
+/// [`start_ordered_synthesis`]. Bounded, so synthesis waits on slow readers.
+type OrderedAudio = mpsc::Receiver<(usize, Vec<u8>)>;
+
+/// Chunk the input and synthesize chunks in parallel across the TTS instances.
+///
+/// Uses micro-chunking and parallel processing for low-latency streaming.
+/// Maintains speech order while allowing out-of-order chunk completion: the
+/// returned receiver yields chunks in order, ending with an empty buffer.
+/// Also returns the number of chunks, including the empty terminator chunk.
+///
+/// The receiver holds up to `stream_buffer` chunks; while it is full no new
+/// chunks start, so a slow client throttles synthesis instead of piling up
+/// audio.
+fn start_ordered_synthesis(
+    worker_pool: TTSWorkerPool,
+    segments: Vec<String>,
+    settings: StreamSettings,
+    sample_bits: u16,
+    request_id: String,
+    request_start: Instant,
+) -> Result<(usize, OrderedAudio), SpeechError> {
+    let StreamSettings {
+        voice,
+        language,
+        sample_rate,
+        speed,
+        initial_silence,
+        remove_dc,
+        max_chunks,
+        max_chunk_words,
+        words_per_chunk,
+        fade_samples,
+        trim_leading_silence,
+        peak_limit,
+        chunk_delimiters,
+        stream_buffer,
+        verify_order,
+        request_timeout,
+        chunk_retries,
+        ..
+    } = settings;
+
+    let StreamPlan {
+        chunks,
+        segment_ends,
+        words_per_chunk: planned_words_per_chunk,
+    } = plan_stream_chunks(
+        &segments,
+        words_per_chunk,
+        max_chunks,
+        max_chunk_words,
+        &chunk_delimiters,
+    );
+    let total_chunks = chunks.len();
+
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+    if planned_words_per_chunk != words_per_chunk {
+        info!(
+            "{} Input exceeds {} chunks, raised words per chunk from {} to {}",
+            colored_request_id,
+            max_chunks.unwrap_or_default(),
+            words_per_chunk,
+            planned_words_per_chunk
+        );
+    }
+    debug!(
+        "{} Processing {} chunks for streaming with window size {} over {} instances",
+        colored_request_id,
+        total_chunks,
+        worker_pool.window_size(),
+        worker_pool.instance_count()
+    );
+
+    if chunks.is_empty() {
+        return Err(SpeechError::Mp3Conversion(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No text to process",
+        )));
+    }
+
+    // Create channels for sequential chunk processing
+    let (task_tx, task_rx) = mpsc::unbounded_channel::<TTSTask>();
+    let (audio_tx, audio_rx) = mpsc::channel::<(usize, Vec<u8>)>(stream_buffer); // Tag chunks with order ID
+
+    // Track total bytes transferred
+    let total_bytes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    // Create session for tracking
+    let session = StreamingSession {
+        session_id: Uuid::new_v4(),
+        start_time: Instant::now(),
+    };
+
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+    info!(
+        "{} TTS session started - {} chunks streaming",
+        colored_request_id, total_chunks
+    );
+
+    // Queue all tasks in order for sequential processing
+    for (id, chunk) in chunks.into_iter().enumerate() {
+        let task = TTSTask {
+            id,
+            chunk,
+            voice: voice.clone(),
+            language: language.clone(),
+            speed,
+            initial_silence: if id == 0 { initial_silence } else { None },
+            trailing_silence: if segment_ends.contains(&id) {
+                segment_silence_samples(sample_rate)
+            } else {
+                0
+            },
+            trim_leading_silence: id == 0 && trim_leading_silence && initial_silence.is_none(),
+            peak_limit,
+            // The last chunk is the empty terminator
+            fade_in: if id == 0 { fade_samples } else { 0 },
+            fade_out: if id + 2 == total_chunks {
+                fade_samples
+            } else {
+                0
+            },
+            remove_dc,
+            result_tx: audio_tx.clone(),
+        };
+
+        task_tx.send(task).unwrap();
+    }
+
+    // Drop the task sender to signal completion
+    drop(task_tx);
+
+    // Windowed parallel processing: allow up to `window_size` chunks in flight,
+    // each on an idle TTS instance when there is one
+    let window_size = worker_pool.window_size();
+    let chunk_request_id = request_id.clone();
+    let chunk_total_bytes = total_bytes.clone();
+    let spawn_chunk = move |chunk_counter: usize, task: TTSTask| -> ChunkHandle {
+        let request_id_clone = chunk_request_id.clone();
+        let dropped_request_id = chunk_request_id.clone();
+        let task_id = task.id;
+        let total_bytes_clone = chunk_total_bytes.clone();
+
+        // Instances are picked when synthesis starts, moving on past the
+        // failed instance for retries
+        let worker_pool = worker_pool.clone();
+        let chunk_text = task.chunk.clone();
+        let voice = task.voice.clone();
+        let language = task.language.clone();
+        let speed = task.speed;
+        let initial_silence = task.initial_silence;
+        let trailing_silence = task.trailing_silence;
+        let trim_leading_silence = task.trim_leading_silence;
+        let peak_limit = task.peak_limit;
+        let (fade_in, fade_out) = (task.fade_in, task.fade_out);
+        let remove_dc = task.remove_dc;
+        let chunk_num = chunk_counter;
+
+        // Spawn parallel processing
+        tokio::spawn(async move {
+            // Handle empty chunks (completion signals) without TTS processing
+            if chunk_text.trim().is_empty() {
+                // Empty chunk - send as completion signal
+                return Ok((task_id, Vec::new()));
+            }
+
+            let dropped_text = chunk_text.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let mut start = chunk_num;
+                with_retries(chunk_retries, |attempt| {
+                    let lease = worker_pool.acquire(start);
+                    start = lease.index + 1;
+                    let actual_instance_id = &lease.instance_id;
+                    let audio_result = lease.instance.tts_raw_audio(
+                        &chunk_text,
+                        &language,
+                        &voice,
+                        speed,
+                        initial_silence,
+                        LogContext {
+                            request_id: Some(&request_id_clone),
+                            instance_id: Some(actual_instance_id),
+                            chunk_number: Some(chunk_num),
+                        },
+                    );
+                    audio_result.map_err(|e| {
+                        let e = format!("TTS processing error: {:?}", e);
+                        if attempt < chunk_retries {
+                            warn!(
+                                "{} Chunk {} failed on instance {}, retrying: {}",
+                                get_colored_request_id_with_relative(
+                                    &request_id_clone,
+                                    request_start
+                                ),
+                                chunk_num,
+                                actual_instance_id,
+                                e
+                            );
+                        }
+                        e
+                    })
+                })
+            })
+            .await;
+
+            // Convert audio to PCM
+            match result {
+                Ok(Ok(mut audio_samples)) => {
+                    if remove_dc {
+                        strip_dc(&mut audio_samples, sample_rate);
+                    }
+                    if trim_leading_silence {
+                        audio::trim_leading_silence(&mut audio_samples, LEADING_SILENCE_THRESHOLD);
+                    }
+                    audio::fade_in(&mut audio_samples, fade_in);
+                    audio::fade_out(&mut audio_samples, fade_out);
+                    if peak_limit {
+                        audio::peak_limit(&mut audio_samples, PEAK_LIMIT_CEILING);
+                    }
+                    audio_samples.resize(audio_samples.len() + trailing_silence, 0.0);
+                    let mut pcm_data =
+                        Vec::with_capacity(audio_samples.len() * sample_bits as usize / 8);
+                    // Either transport depth is supported, so this can't fail
+                    let _ = write_samples(&mut pcm_data, &audio_samples, sample_bits);
+                    total_bytes_clone
+                        .fetch_add(pcm_data.len(), std::sync::atomic::Ordering::Relaxed);
+                    Ok((task_id, pcm_data))
+                }
+                Ok(Err(e)) => {
+                    error!(
+                        "{} Dropping chunk {} after {} attempt(s), its audio is missing: {:?} ({})",
+                        get_colored_request_id_with_relative(&dropped_request_id, request_start),
+                        chunk_num,
+                        chunk_retries + 1,
+                        dropped_text,
+                        e
+                    );
+                    Err(e)
+                }
+                Err(e) => Err(format!("Task execution error: {:?}", e)),
+            }
+        })
+    };
+    let audio_tx_clone = audio_tx.clone();
+    let deadline = request_timeout.map(|timeout| Instant::now() + timeout);
+    tokio::spawn(async move {
+        let mut order_check = verify_order.then(StreamOrderCheck::default);
+        let forwarded = forward_in_order(
+            task_rx,
+            total_chunks,
+            window_size,
+            deadline,
+            order_check.as_mut(),
+            &audio_tx_clone,
+            spawn_chunk,
+        )
+        .await;
+        let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+        match forwarded {
+            Forwarded::Completed => {}
+            Forwarded::TimedOut => warn!(
+                "{} TTS session timed out, ending the stream early",
+                colored_request_id
+            ),
+            Forwarded::Disconnected => {
+                info!(
+                    "{} Client disconnected, stopped synthesis",
+                    colored_request_id
+                );
+                return;
+            }
+        }
+
+        let _session_time = session.start_time.elapsed();
+
+        // Log completion
+        let bytes_transferred = total_bytes.load(std::sync::atomic::Ordering::Relaxed);
+        // Calculate audio duration from the PCM chunks
+        let total_samples = bytes_transferred / (sample_bits as usize / 8);
+        let duration_seconds = total_samples as f64 / sample_rate as f64;
+        let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
+        info!(
+            "{} TTS session completed - {} chunks, {} bytes, {:.1}s audio",
+            colored_request_id, total_chunks, bytes_transferred, duration_seconds
+        );
+
+        // Send termination signal
+        let _ = audio_tx.send((total_chunks, vec![])).await; // Empty data as termination signal
+    });
+
+    Ok((total_chunks, audio_rx))
+}
+
+/// Run `synthesize(instance, instance_index, job_index, job)` over every job
+/// with one thread per instance, each taking the next job once it is done
+/// with its last, and return the results in job order. The first error, or
+/// `cancelled` being set, stops jobs from being started. An instance counts
+/// in `busy`, indexed like `instances`, while it runs a job.
+fn synthesize_in_order<I: Sync, J: Sync, T: Send>(
+    instances: &[I],
+    busy: &[AtomicUsize],
+    jobs: &[J],
+    cancelled: &std::sync::atomic::AtomicBool,
+    synthesize: impl Fn(&I, usize, usize, &J) -> Result<T, String> + Sync,
+) -> Result<Vec<T>, String> {
+    let next_job = AtomicUsize::new(0);
+    let failed = std::sync::atomic::AtomicBool::new(false);
+    let results: Mutex<Vec<Option<Result<T, String>>>> =
+        Mutex::new(jobs.iter().map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for (instance_index, instance) in instances.iter().enumerate() {
+            let (next_job, failed, results, synthesize) =
+                (&next_job, &failed, &results, &synthesize);
+            scope.spawn(move || {
+                loop {
+                    if failed.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let index = next_job.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+                    let result = if cancelled.load(Ordering::Relaxed) {
+                        Err("synthesis timed out".to_string())
+                    } else {
+                        busy[instance_index].fetch_add(1, Ordering::SeqCst);
+                        let result = synthesize(instance, instance_index, index, job);
+                        busy[instance_index].fetch_sub(1, Ordering::SeqCst);
+                        result
+                    };
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    // A job that was never started sits after the one that failed
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err("synthesis stopped".to_string())))
+        .collect()
+}
+
+/// Call `synthesize` with attempt numbers `0..=retries` until it succeeds,
+/// returning the last error when every attempt fails
+fn with_retries<T>(
+    retries: usize,
+    mut synthesize: impl FnMut(usize) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut attempt = 0;
+    loop {
+        match synthesize(attempt) {
+            Err(_) if attempt < retries => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Synthesis of one chunk, yielding `(chunk_id, pcm_i16_le)`
+type ChunkHandle = tokio::task::JoinHandle<Result<(usize, Vec<u8>), String>>;
+
+/// How [`forward_in_order`] ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Forwarded {
+    /// Every chunk was sent, or skipped after failing
+    Completed,
+    /// The deadline passed first
+    TimedOut,
+    /// The receiving side of `audio_tx` went away, e.g. the client hung up
+    Disconnected,
+}
+
+/// Run tasks through `spawn_chunk` with at most `window_size` in flight and
+/// forward their audio to `audio_tx` in task order, however they complete.
+/// Chunks that fail are skipped. Past `deadline`, or as soon as the receiver
+/// of `audio_tx` is dropped, no new tasks are started and chunks in flight
+/// are dropped. While `audio_tx` is full, forwarding waits, and with it the
+/// window, so no new tasks start until the receiver catches up. Every chunk
+/// sent or skipped is recorded in `order_check` when one is given.
+///
+/// The loop only wakes when a task arrives, a chunk finishes or the deadline
+/// passes, rather than polling.
+async fn forward_in_order<T>(
+    mut task_rx: mpsc::UnboundedReceiver<T>,
+    total_chunks_expected: usize,
+    window_size: usize,
+    deadline: Option<Instant>,
+    mut order_check: Option<&mut StreamOrderCheck>,
+    audio_tx: &mpsc::Sender<(usize, Vec<u8>)>,
+    mut spawn_chunk: impl FnMut(usize, T) -> ChunkHandle,
+) -> Forwarded {
+    use futures::stream::FuturesUnordered;
+    use std::collections::BTreeMap;
+
+    let mut chunk_counter = 0;
+    let mut in_flight = FuturesUnordered::new();
+    let mut abort_handles: Vec<tokio::task::AbortHandle> = Vec::new();
+    // Finished chunks waiting on an earlier one, `None` for a failed chunk
+    let mut finished: BTreeMap<usize, Option<(usize, Vec<u8>)>> = BTreeMap::new();
+    let mut next_to_send = 0;
+    let mut tasks_open = true;
+
+    let timed_out = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timed_out);
+
+    while next_to_send < total_chunks_expected {
+        if !tasks_open && in_flight.is_empty() {
+            // This shouldn't happen, but log it for debugging
+            eprintln!(
+                "Warning: Early termination detected - processed {} of {} chunks",
+                next_to_send, total_chunks_expected
+            );
+            break;
+        }
+
+        // Chunks finished out of order still hold their place in the window
+        let window_open = in_flight.len() + finished.len() < window_size;
+        tokio::select! {
+            biased;
+            _ = &mut timed_out => {
+                // Blocking synthesis already running can't be interrupted, but
+                // aborting drops its result and nothing new gets queued
+                for handle in &abort_handles {
+                    handle.abort();
+                }
+                return Forwarded::TimedOut;
+            }
+            _ = audio_tx.closed() => {
+                // Nobody is listening any more
+                for handle in &abort_handles {
+                    handle.abort();
+                }
+                return Forwarded::Disconnected;
+            }
+            task = task_rx.recv(), if tasks_open && window_open => match task {
+                Some(task) => {
+                    let handle = spawn_chunk(chunk_counter, task);
+                    abort_handles.push(handle.abort_handle());
+                    let chunk_id = chunk_counter;
+                    in_flight.push(async move { (chunk_id, handle.await) });
+                    chunk_counter += 1;
+                }
+                None => tasks_open = false,
+            },
+            Some((chunk_id, joined)) = in_flight.next(), if !in_flight.is_empty() => {
+                finished.insert(chunk_id, joined.ok().and_then(Result::ok));
+                while let Some(result) = finished.remove(&next_to_send) {
+                    if let Some(check) = order_check.as_deref_mut() {
+                        match &result {
+                            Some((id, _)) => check.sent(*id),
+                            None => check.skipped(next_to_send),
+                        }
+                    }
+                    next_to_send += 1;
+                    let Some(chunk) = result else {
+                        continue;
+                    };
+                    // Waits while a slow reader leaves the channel full
+                    let sent = tokio::select! {
+                        biased;
+                        _ = &mut timed_out => Forwarded::TimedOut,
+                        sent = audio_tx.send(chunk) => match sent {
+                            Ok(()) => Forwarded::Completed,
+                            Err(_) => Forwarded::Disconnected,
+                        },
+                    };
+                    if sent != Forwarded::Completed {
+                        for handle in &abort_handles {
+                            handle.abort();
+                        }
+                        return sent;
+                    }
+                }
+            }
+            else => break,
+        }
+    }
+    if let Some(check) = order_check {
+        check.finish(total_chunks_expected);
+    }
+    Forwarded::Completed
+}
+
+/// Self-check of the order [`forward_in_order`] sends chunks in, for
+/// [`ServerConfig::verify_stream_order`]. Chunk ids must come out strictly
+/// increasing, each exactly once, with only chunks that failed left out.
+/// Violations are logged as errors as they are found.
+#[derive(Debug, Default)]
+struct StreamOrderCheck {
+    /// Id the next chunk sent or skipped should have
+    next: usize,
+    violations: Vec<String>,
+}
+
+impl StreamOrderCheck {
+    /// Chunk `id` was sent
+    fn sent(&mut self, id: usize) {
+        if id < self.next {
+            self.violation(format!(
+                "chunk {} sent again or out of order, expected chunk {}",
+                id, self.next
+            ));
+            return;
+        }
+        if id > self.next {
+            self.violation(format!(
+                "chunks {} to {} were dropped without failing",
+                self.next,
+                id - 1
+            ));
+        }
+        self.next = id + 1;
+    }
+
+    /// Chunk `id` failed and was left out
+    fn skipped(&mut self, id: usize) {
+        if id != self.next {
+            self.violation(format!(
+                "skipped chunk {} out of order, expected chunk {}",
+                id, self.next
+            ));
+        }
+        self.next = self.next.max(id + 1);
+    }
+
+    /// The stream completed with `total` chunks expected
+    fn finish(&mut self, total: usize) {
+        if self.next != total {
+            self.violation(format!(
+                "stream completed after {} of {} chunks",
+                self.next, total
+            ));
+        }
+    }
+
+    fn violation(&mut self, violation: String) {
+        error!("Stream order check failed: {}", violation);
+        self.violations.push(violation);
+    }
+}
+
+/// Convert little-endian PCM chunks at `sample_bits` (16, or 24 packed
+/// into three bytes, see [`transport_bits`]) back to f32 samples
+fn pcm_to_f32(data: &[u8], sample_bits: u16) -> Vec<f32> {
+    if sample_bits != 24 {
+        return pcm_i16_to_f32(data);
+    }
+    data.chunks_exact(3)
+        .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8388607.0)
+        .collect()
+}
+
+/// Convert 16-bit little-endian PCM back to f32 samples
+fn pcm_i16_to_f32(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0)
+        .collect()
+}
+
+/// Encode the ordered PCM chunks of [`start_ordered_synthesis`] as a WAV byte stream
+fn wav_stream(
+    mode: WavStreamingMode,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    sample_bits: u16,
+    mut audio_rx: OrderedAudio,
+) -> futures::stream::BoxStream<'static, Vec<u8>> {
+    let header = WavHeader::new(1, sample_rate, bits_per_sample);
+    let bytes_per_sample = bits_per_sample as usize / 8;
+    match mode {
+        WavStreamingMode::Placeholder => {
+            let mut header_bytes = Vec::new();
+            // Writing to a Vec can't fail
+            let _ = header.write_header(&mut header_bytes);
+            let samples = tokio_stream::wrappers::ReceiverStream::new(audio_rx)
+                .take_while(|(_, pcm)| futures::future::ready(!pcm.is_empty()))
+                .map(move |(_, pcm)| {
+                    let samples = pcm_to_f32(&pcm, sample_bits);
+                    let mut data = Vec::with_capacity(samples.len() * bytes_per_sample);
+                    let _ = write_samples(&mut data, &samples, bits_per_sample);
+                    data
+                });
+            futures::stream::once(futures::future::ready(header_bytes))
+                .chain(samples)
+                .boxed()
+        }
+        WavStreamingMode::Buffered => futures::stream::once(async move {
+            let mut samples = Vec::new();
+            while let Some((_, pcm)) = audio_rx.recv().await {
+                if pcm.is_empty() {
+                    break;
+                }
+                samples.extend(pcm_to_f32(&pcm, sample_bits));
+            }
+            let mut wav_data = Vec::with_capacity(44 + samples.len() * bytes_per_sample);
+            let data_len = u32::try_from(samples.len() * bytes_per_sample).unwrap_or(u32::MAX);
+            let _ = header.write_sized_header(&mut wav_data, data_len);
+            let _ = write_samples(&mut wav_data, &samples, bits_per_sample);
+            wav_data
+        })
+        .boxed(),
+    }
+}
+
+/// The ordered PCM chunks of [`start_ordered_synthesis`], at `sample_bits`,
+/// as raw PCM at `bits_per_sample` in the given byte order
+fn pcm_stream(
+    bits_per_sample: u16,
+    sample_bits: u16,
+    endianness: PcmEndianness,
+    audio_rx: OrderedAudio,
+) -> futures::stream::BoxStream<'static, Vec<u8>> {
+    tokio_stream::wrappers::ReceiverStream::new(audio_rx)
+        .take_while(|(_, pcm)| futures::future::ready(!pcm.is_empty()))
+        // The bit depth was validated with the request
+        .map(move |(_, pcm)| {
+            pcm_bytes(&pcm_to_f32(&pcm, sample_bits), bits_per_sample, endianness)
+                .unwrap_or_default()
+        })
+        .boxed()
+}
+
+/// Bits per sample of the PCM chunks [`start_ordered_synthesis`] passes
+/// on: 24 for 24-bit WAV and PCM streams, so they keep precision 16-bit
+/// chunks would drop, and 16 for everything else
+fn transport_bits(format: AudioFormat, bits_per_sample: u16) -> u16 {
+    match format {
+        AudioFormat::Wav | AudioFormat::Pcm if bits_per_sample == 24 => 24,
+        _ => 16,
+    }
+}
+
+/// Companded G.711 bytes for each chunk. Chunks are resampled on their own,
+/// which is seamless as each one starts and ends on a pause.
+fn g711_stream(
+    encode: fn(&[f32], u32, audio::ResampleQuality) -> Vec<u8>,
+    sample_rate: u32,
+    quality: audio::ResampleQuality,
+    audio_rx: OrderedAudio,
+) -> futures::stream::BoxStream<'static, Vec<u8>> {
+    tokio_stream::wrappers::ReceiverStream::new(audio_rx)
+        .take_while(|(_, pcm)| futures::future::ready(!pcm.is_empty()))
+        .map(move |(_, pcm)| encode(&pcm_i16_to_f32(&pcm), sample_rate, quality))
+        .boxed()
+}
+
+/// A live WebM stream of the ordered 16-bit chunks: the header, then each
+/// chunk as clusters as they come
+fn webm_stream(
+    sample_rate: u32,
+    audio_rx: OrderedAudio,
+) -> futures::stream::BoxStream<'static, Vec<u8>> {
+    let mut muxer = WebmMuxer::pcm(1, sample_rate);
+    let header = muxer.header();
+    let clusters = tokio_stream::wrappers::ReceiverStream::new(audio_rx)
+        .take_while(|(_, pcm)| futures::future::ready(!pcm.is_empty()))
+        .map(move |(_, pcm)| muxer.write_pcm(&pcm));
+    futures::stream::once(futures::future::ready(header))
+        .chain(clusters)
+        .boxed()
+}
+
+/// Silence sent ahead of the first chunk by [`ServerConfig::stream_keep_alive`].
+/// The encoder pads it to one MP3 frame, so listeners hear at most ~50ms.
+const KEEP_ALIVE_SILENCE_MS: u32 = 20;
+
+/// Ordered PCM chunks transcoded to MP3 one at a time, each with a fresh
+/// encoder (more stable). With `keep_alive` a short burst of encoded
+/// silence goes out before waiting on the first chunk.
+fn mp3_stream(
+    sample_rate: u32,
+    bitrate_kbps: u32,
+    keep_alive: bool,
+    mut audio_rx: OrderedAudio,
+) -> futures::stream::BoxStream<'static, Vec<u8>> {
+    // Buffering happens on `audio_rx`, this only hands frames to the body
+    let (encoded_tx, encoded_rx) = mpsc::channel::<Vec<u8>>(1);
+    tokio::spawn(async move {
+        if keep_alive {
+            let silence = vec![0.0; (sample_rate * KEEP_ALIVE_SILENCE_MS / 1000) as usize];
+            if let Ok(mp3_bytes) =
+                kokoros::utils::mp3::pcm_to_mp3(&silence, sample_rate, bitrate_kbps)
+            {
+                let _ = encoded_tx.send(mp3_bytes).await;
+            }
+        }
+        loop {
+            // Stop as soon as the response body is dropped, which drops
+            // `audio_rx` and so ends synthesis too
+            let received = tokio::select! {
+                received = audio_rx.recv() => received,
+                _ = encoded_tx.closed() => None,
+            };
+            let Some((_chunk_id, data)) = received else {
+                break;
+            };
+            if data.is_empty() {
+                break; // end of stream
+            }
+            // Convert PCM i16 bytes back to f32 for encoder API
+            let samples_f32 = pcm_i16_to_f32(&data);
+            match tokio::task::spawn_blocking(move || {
+                kokoros::utils::mp3::pcm_to_mp3(&samples_f32, sample_rate, bitrate_kbps)
+            })
+            .await
+            {
+                Ok(Ok(mp3_bytes)) => {
+                    if !mp3_bytes.is_empty() && encoded_tx.send(mp3_bytes).await.is_err() {
+                        break;
+                    }
+                }
+                _ => {
+                    // skip on error
+                }
+            }
+        }
+        // closing encoded_tx ends the stream
+    });
+    tokio_stream::wrappers::ReceiverStream::new(encoded_rx).boxed()
+}
+
+/// Handle streaming TTS requests with true async processing
+///
+/// Synthesis runs through [`start_ordered_synthesis`]; each ordered chunk is
+/// transcoded to MP3 as it arrives, or written out as WAV (see
+/// [`WavStreamingMode`]) when `wav` was requested.
+async fn handle_tts_streaming(
+    worker_pool: TTSWorkerPool,
+    segments: Vec<String>,
+    settings: StreamSettings,
+    response_format: AudioFormat,
+    encode_settings: EncodeSettings,
+    request_id: String,
+    request_start: Instant,
+) -> Result<Response, SpeechError> {
+    let StreamSettings {
+        sample_rate,
+        wav_streaming_mode,
+        keep_alive,
+        ..
+    } = settings;
+    let sample_bits = transport_bits(
+        response_format,
+        encode_settings.bits_per_sample(response_format),
+    );
+    let (_total_chunks, audio_rx) = start_ordered_synthesis(
+        worker_pool,
+        segments,
+        settings,
+        sample_bits,
+        request_id,
+        request_start,
+    )?;
+
+    // No ordering needed - sequential processing guarantees order
+    streaming_response(
+        response_format,
+        &encode_settings,
+        sample_rate,
+        wav_streaming_mode,
+        keep_alive,
+        audio_rx,
+    )
+}
+
+/// The chunked response for the ordered chunks of [`start_ordered_synthesis`],
+/// encoding each chunk as it arrives
+fn streaming_response(
+    response_format: AudioFormat,
+    encode_settings: &EncodeSettings,
+    sample_rate: u32,
+    wav_streaming_mode: WavStreamingMode,
+    keep_alive: bool,
+    audio_rx: OrderedAudio,
+) -> Result<Response, SpeechError> {
+    let bits_per_sample = encode_settings.bits_per_sample(response_format);
+    let pcm_endianness = encode_settings.pcm_endianness();
+    let mp3_bitrate_kbps = encode_settings.mp3_bitrate_kbps(response_format);
+    let resample_quality = encode_settings.resample_quality();
+    let sample_bits = transport_bits(response_format, bits_per_sample);
+
+    // Stream MP3 for formats without a streaming encoder, for compatibility
+    let (content_type, encoded) = match response_format {
+        AudioFormat::Wav => (
+            "audio/wav",
+            wav_stream(
+                wav_streaming_mode,
+                sample_rate,
+                bits_per_sample,
+                sample_bits,
+                audio_rx,
+            ),
+        ),
+        AudioFormat::Pcm => (
+            "audio/pcm",
+            pcm_stream(bits_per_sample, sample_bits, pcm_endianness, audio_rx),
+        ),
+        AudioFormat::Ulaw => (
+            "audio/basic",
+            g711_stream(g711::encode_ulaw, sample_rate, resample_quality, audio_rx),
+        ),
+        AudioFormat::Alaw => (
+            "audio/PCMA",
+            g711_stream(g711::encode_alaw, sample_rate, resample_quality, audio_rx),
+        ),
+        AudioFormat::Webm => ("audio/webm", webm_stream(sample_rate, audio_rx)),
+        _ => (
+            "audio/mpeg",
+            mp3_stream(sample_rate, mp3_bitrate_kbps, keep_alive, audio_rx),
+        ),
+    };
+
+    // Create streaming body from encoded bytes
+    let stream = encoded.map(|data| -> Result<Vec<u8>, std::io::Error> { Ok(data) });
+
+    // Convert to HTTP body with explicit ordering
+    let body = Body::from_stream(stream);
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONNECTION, "keep-alive")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header("X-Accel-Buffering", "no") // Disable nginx buffering
+        .header("Transfer-Encoding", "chunked") // Enable HTTP chunked transfer encoding
+        .body(body)
+        .map_err(|e| {
+            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::Other, e))
+        })?)
+}
+
+/// Progress event emitted on `/v1/audio/speech/events` as each chunk completes
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct ChunkProgress {
+    /// 1-based index of the completed chunk
+    chunk: usize,
+    total: usize,
+    /// Audio generated so far, including this chunk
+    audio_seconds: f64,
+    /// PCM bytes generated so far, for [`StreamUsage`]
+    #[serde(skip)]
+    bytes: usize,
+}
+
+/// Totals of a stream, sent as the `usage` event when requested through
+/// [`StreamOptions::include_usage`]
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+struct StreamUsage {
+    chunks: usize,
+    /// 16-bit PCM bytes, before encoding
+    bytes: usize,
+    audio_seconds: f64,
+}
+
+/// One event of `/v1/audio/speech/events`
+#[derive(Debug, Clone, PartialEq)]
+enum SpeechEvent {
+    Progress(ChunkProgress),
+    Usage(StreamUsage),
+    Done { total: usize },
+}
+
+impl SpeechEvent {
+    fn into_event(self) -> Result<Event, axum::Error> {
+        match self {
+            Self::Progress(progress) => Event::default().event("progress").json_data(progress),
+            Self::Usage(usage) => Event::default().event("usage").json_data(usage),
+            Self::Done { total } => Event::default()
+                .event("done")
+                .json_data(serde_json::json!({ "total": total })),
+        }
+    }
+}
+
+/// Map the ordered PCM chunks of [`start_ordered_synthesis`] to progress updates.
+/// Chunks that failed to synthesize are skipped, so `chunk` may jump.
+fn chunk_progress(
+    total: usize,
+    sample_rate: u32,
+    audio_rx: OrderedAudio,
+) -> impl futures::Stream<Item = ChunkProgress> {
+    tokio_stream::wrappers::ReceiverStream::new(audio_rx)
+        .take_while(|(_, pcm)| futures::future::ready(!pcm.is_empty()))
+        .scan(0usize, move |bytes, (chunk_id, pcm)| {
+            *bytes += pcm.len();
+            // 16-bit PCM, 2 bytes per sample
+            let samples = *bytes / 2;
+            futures::future::ready(Some(ChunkProgress {
+                chunk: chunk_id + 1,
+                total,
+                audio_seconds: samples as f64 / sample_rate as f64,
+                bytes: *bytes,
+            }))
+        })
+}
+
+/// The events of `/v1/audio/speech/events`: a progress update per chunk,
+/// the [`StreamUsage`] totals when `include_usage` is set, then `done`
+fn speech_events(
+    total: usize,
+    sample_rate: u32,
+    audio_rx: OrderedAudio,
+    include_usage: bool,
+) -> impl futures::Stream<Item = SpeechEvent> {
+    let usage = Arc::new(Mutex::new(StreamUsage::default()));
+    let tally = usage.clone();
+    chunk_progress(total, sample_rate, audio_rx)
+        .map(move |progress| {
+            let mut usage = tally.lock().unwrap();
+            usage.chunks += 1;
+            usage.bytes = progress.bytes;
+            usage.audio_seconds = progress.audio_seconds;
+            SpeechEvent::Progress(progress)
+        })
+        .chain(
+            futures::stream::once(async move {
+                include_usage.then(|| SpeechEvent::Usage(*usage.lock().unwrap()))
+            })
+            .filter_map(futures::future::ready),
+        )
+        .chain(futures::stream::once(
+            async move { SpeechEvent::Done { total } },
+        ))
+}
+
+/// Handle /v1/audio/speech/events
+///
+/// Runs the same chunked synthesis as streaming `/v1/audio/speech` but emits
+/// `text/event-stream` progress events (`progress`, then a final `done`)
+/// instead of audio, for clients that want to drive a progress UI. With
+/// `stream_options.include_usage` a `usage` event precedes `done`. A voice
+/// replaced by the fallback is named in the [`VOICE_FALLBACK_HEADER`].
+async fn handle_tts_events(
+    State(AppState {
+        tts_single,
+        tts_instances,
+        config,
+        sample_rate,
+        busy,
+        in_flight,
+        ..
+    }): State<AppState>,
+    request_info: Option<Extension<(String, Instant)>>,
+    headers: HeaderMap,
+    Json(mut request): Json<SpeechRequest>,
+) -> Result<Response, SpeechError> {
+    let (request_id, request_start) = request_info
+        .map(|Extension(info)| info)
+        .unwrap_or_else(|| ("unknown".to_string(), Instant::now()));
+    let queue_slot =
+        QueueSlot::try_acquire(&in_flight, config.max_queue_depth).ok_or(SpeechError::Busy)?;
+    request.instance_from_header(&headers)?;
+
+    let SpeechRequest {
+        input,
+        voice,
+        speed,
+        initial_silence,
+        initial_silence_ms,
+        max_chunks,
+        words_per_chunk,
+        remove_dc,
+        deterministic,
+        instance,
+        lang_code,
+        espeak_variant,
+        keep_acronyms,
+        stream_options,
+        ..
+    } = request;
+    check_input_length(&input, &config)?;
+    check_control_chars(&input, &config)?;
+    let include_usage = stream_options.is_some_and(|options| options.include_usage);
+    let instance = resolve_instance(
+        instance,
+        tts_instances.len(),
+        &get_colored_request_id_with_relative(&request_id, request_start),
+    )?;
+    let tts_instances = if deterministic {
+        deterministic_instances(tts_instances)
+    } else {
+        tts_instances
+    };
+    let (voice, replaced_voice) =
+        config.voice_or_fallback(config.voice(voice), |voice| tts_single.has_voice(voice));
+    if let Some(replaced) = &replaced_voice {
+        warn!(
+            "{} Voice {} not found, using fallback {}",
+            get_colored_request_id_with_relative(&request_id, request_start),
+            replaced,
+            voice
+        );
+    }
+    let speed = config.speed(speed);
+    let initial_silence = resolve_initial_silence(initial_silence, initial_silence_ms)?;
+    let max_chunks = resolve_max_chunks(max_chunks, &config)?;
+    let words_per_chunk = resolve_words_per_chunk(words_per_chunk)?;
+    let segments = preprocess_segments(tts_instances.first(), input, keep_acronyms)?;
+    let (language, language_source) = config.language(lang_code, &voice, &segments);
+    let language = resolve_espeak_variant(language, espeak_variant)?;
+    debug!(
+        "{} Language: {} (from {})",
+        get_colored_request_id_with_relative(&request_id, request_start),
+        language,
+        language_source
+    );
+
+    let settings = StreamSettings {
+        voice,
+        language,
+        speed,
+        initial_silence,
+        remove_dc,
+        max_chunks,
+        words_per_chunk,
+        ..StreamSettings::from_config(&config, sample_rate)
+    };
+    let worker_pool =
+        TTSWorkerPool::new(tts_instances, busy, config.stream_window).pinned(instance);
+    let (total_chunks, audio_rx) = start_ordered_synthesis(
+        worker_pool,
+        segments,
+        settings,
+        16,
+        request_id,
+        request_start,
+    )?;
+    // The last chunk is the empty completion terminator
+    let total = total_chunks.saturating_sub(1);
+
+    // The slot goes with the event stream, like a streamed speech body
+    let events = speech_events(total, sample_rate, audio_rx, include_usage).map(move |event| {
+        let _slot = &queue_slot;
+        event.into_event()
+    });
+
+    let response = Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    Ok(with_voice_fallback(response, replaced_voice.as_deref()))
+}
+
+/// Handle /v1/debug/synthesize-tokens
+///
+/// Feeds the given token ids straight to the model, skipping espeak and
+/// tokenization. Requires the admin token, and like speech requests takes
+/// a queue slot and the least busy instance.
+async fn handle_debug_synthesize_tokens(
+    State(AppState {
+        tts_instances,
+        config,
+        sample_rate,
+        busy,
+        in_flight,
+        ..
+    }): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SynthesizeTokensRequest>,
+) -> Result<Response, SpeechError> {
+    check_admin_token(&headers, &config)?;
+    let _queue_slot =
+        QueueSlot::try_acquire(&in_flight, config.max_queue_depth).ok_or(SpeechError::Busy)?;
+
+    let SynthesizeTokensRequest {
+        tokens,
+        voice: Voice(voice),
+        speed: Speed(speed),
+        response_format,
+    } = request;
+
+    validate_tokens(&tokens).map_err(SpeechError::BadRequest)?;
+
+    let worker_pool = TTSWorkerPool::new(tts_instances, busy, None);
+    let raw_audio = run_with_timeout(config.request_timeout, move |_| {
+        let lease = worker_pool.acquire(0);
+        lease
+            .instance
+            .tts_raw_audio_from_tokens(&tokens, &voice, speed, None, Some(&lease.instance_id))
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+
+    let (content_type, audio_data, _format_name) = encode_audio(
+        response_format,
+        raw_audio,
+        sample_rate,
+        &EncodeSettings::default(),
+    )?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(audio_data.into())
+        .map_err(|e| SpeechError::Mp3Conversion(std::io::Error::other(e)))
+}
+
+/// Reload the voices of `instances` with `reload`, once for each set of
+/// voices they share. Returns the voice count of the last successful reload
+/// and each instance, by index, left with its old voices, with the reason.
+fn reload_shared_voices(
+    instances: &[TTSKoko],
+    reload: impl Fn(&TTSKoko) -> Result<usize, String>,
+) -> (usize, Vec<(usize, String)>) {
+    let mut reloaded: Vec<(&TTSKoko, Result<usize, String>)> = Vec::new();
+    let mut voices = 0;
+    let mut failed = Vec::new();
+    for (index, tts) in instances.iter().enumerate() {
+        let outcome = match reloaded
+            .iter()
+            .find(|(done, _)| done.shares_voices_with(tts))
+        {
+            Some((_, outcome)) => outcome.clone(),
+            None => {
+                let outcome = reload(tts);
+                reloaded.push((tts, outcome.clone()));
+                outcome
+            }
+        };
+        match outcome {
+            Ok(count) => voices = count,
+            Err(e) => failed.push((index, e)),
+        }
+    }
+    (voices, failed)
+}
+
+/// Handle /v1/admin/reload-voices
+///
+/// Re-reads the voices files so voices added or changed on disk can be used
+/// without a restart, once per set of voices the instances share. Requests
+/// already synthesizing finish with the voices they started with. Requires
+/// the admin token. When some instances keep their old voices the response
+/// is a `voice_reload_failed` error naming them.
+async fn handle_reload_voices(
+    State(AppState {
+        tts_instances,
+        config,
+        audio_cache,
+        ..
+    }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, SpeechError> {
+    check_admin_token(&headers, &config)?;
+
+    let count = tts_instances.len();
+    let (voices, failed) = run_with_timeout(None, move |_| {
+        Ok(reload_shared_voices(&tts_instances, |tts| {
+            tts.reload_voices().map_err(|e| e.to_string())
+        }))
+    })
+    .await?;
+
+    // Cached audio may have been rendered with a voice that just changed
+    if let Some(cache) = &audio_cache {
+        cache.lock().unwrap().clear();
+    }
+    if !failed.is_empty() {
+        let failures: Vec<String> = failed
+            .iter()
+            .map(|(index, error)| format!("[{:02x}] {}", index, error))
+            .collect();
+        let message = format!(
+            "{} of {} instances kept their old voices: {}",
+            failed.len(),
+            count,
+            failures.join("; ")
+        );
+        error!("Voice reload failed, {}", message);
+        return Err(SpeechError::VoiceReload(message));
+    }
+    info!("Reloaded voices ({} total)", voices);
+
+    Ok(Json(serde_json::json!({ "voices": voices })))
+}
+
+/// Handle /v1/debug/chunks
+///
+/// Returns the chunks `split_text_into_speech_chunks` produces for the preprocessed input,
+/// with their phonemes and token counts, without generating any audio. The
+/// language is resolved from `voice` and `lang_code` as for speech requests.
+async fn handle_debug_chunks(
+    State(AppState {
+        tts_single, config, ..
+    }): State<AppState>,
+    Json(request): Json<DebugChunksRequest>,
+) -> Result<Json<DebugChunksResponse>, SpeechError> {
+    if request.words_per_chunk == 0 {
+        return Err(SpeechError::BadRequest(
+            "words_per_chunk must be at least 1".to_string(),
+        ));
+    }
+
+    let voice = config.voice(request.voice);
+    let init_config = tts_single.init_config().clone();
+    let input = init_config.preprocess(&request.input);
+    let (language, _) = config.language(request.lang_code, &voice, std::slice::from_ref(&input));
+    let chunk_language = language.clone();
+    // Phonemizing blocks, so it stays off the async workers
+    let chunks = tokio::task::spawn_blocking(move || {
+        koko::split_text_into_speech_chunks(&input, request.words_per_chunk)
+            .into_iter()
+            .map(|text| {
+                let phonemes = init_config.phonemize(&text, &chunk_language)?;
+                Ok(DebugChunk {
+                    token_count: tokenize(&phonemes).len(),
+                    text,
+                    phonemes,
+                })
+            })
+            .collect::<Result<Vec<_>, PlanError>>()
+    })
+    .await
+    .map_err(|e| SpeechError::Koko(Box::new(e)))??;
+
+    Ok(Json(DebugChunksResponse { language, chunks }))
+}
+
+/// Count the chunks and tokens of the chunks [`planned_tokens`] plans, SSML
+/// pauses and initial silence included
+fn estimate_tokens(planned: &[(String, Vec<i64>)]) -> DebugTokensResponse {
+    DebugTokensResponse {
+        total_tokens: planned.iter().map(|(_, tokens)| tokens.len()).sum(),
+        chunks: planned.len(),
+    }
+}
+
+/// Handle /v1/debug/tokens
+///
+/// Estimates the size of a synthesis request without generating audio, so
+/// clients can pre-chunk long documents.
+async fn handle_debug_tokens(
+    State(AppState {
+        tts_single, config, ..
+    }): State<AppState>,
+    Json(request): Json<DebugTokensRequest>,
+) -> Result<Json<DebugTokensResponse>, SpeechError> {
+    let voice = config.voice(request.voice);
+    let segments = preprocess_segments(Some(&tts_single), request.input, request.keep_acronyms)?;
+    let (language, _) = config.language(request.lang_code, &voice, &segments);
+    let initial_silence = resolve_initial_silence(request.initial_silence, None)?;
+    // Phonemizing blocks, so it stays off the async workers
+    let init_config = tts_single.init_config().clone();
+    let planned = tokio::task::spawn_blocking(move || {
+        planned_tokens(&init_config, &segments, &language, initial_silence)
+    })
+    .await
+    .map_err(|e| SpeechError::Koko(Box::new(e)))??;
+    Ok(Json(estimate_tokens(&planned)))
+}
+
+/// Each chunk non-streaming synthesis plans for `segments`, with the
+/// unpadded tokens it feeds the model, initial silence included
+fn planned_tokens(
+    init_config: &TTSKokoInitConfig,
+    segments: &[String],
+    language: &str,
+    initial_silence: Option<usize>,
+) -> Result<Vec<(String, Vec<i64>)>, PlanError> {
+    let mut planned = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let silence = if index == 0 { initial_silence } else { None };
+        for (text, silence) in init_config.plan_chunks(segment, silence) {
+            let mut tokens = tokenize(&init_config.phonemize(&text, language)?);
+            init_config.prepend_silence(&mut tokens, silence);
+            planned.push((text, tokens));
+        }
+    }
+    Ok(planned)
+}
+
+/// The model input for each chunk of [`planned_tokens`], with
+/// `style_length` giving the style vector length for a chunk's unpadded
+/// token count
+fn model_inputs(
+    planned: Vec<(String, Vec<i64>)>,
+    style_length: impl Fn(usize) -> Result<usize, SpeechError>,
+) -> Result<Vec<DebugModelInput>, SpeechError> {
+    planned
+        .into_iter()
+        .map(|(text, tokens)| {
+            Ok(DebugModelInput {
+                style_length: style_length(tokens.len())?,
+                tokens: pad_tokens(&tokens),
+                text,
+            })
+        })
+        .collect()
+}
+
+/// Handle /v1/debug/tokenize
+///
+/// Returns the token ids each chunk of a non-streaming request would feed
+/// the model, without generating audio.
+async fn handle_debug_tokenize(
+    State(AppState {
+        tts_single, config, ..
+    }): State<AppState>,
+    Json(request): Json<DebugTokenizeRequest>,
+) -> Result<Json<DebugTokenizeResponse>, SpeechError> {
+    let voice = config.voice(request.voice);
+    let segments = preprocess_segments(Some(&tts_single), request.input, request.keep_acronyms)?;
+    let (language, _) = config.language(request.lang_code, &voice, &segments);
+    let initial_silence = resolve_initial_silence(request.initial_silence, None)?;
+    let init_config = tts_single.init_config().clone();
+    let planned_language = language.clone();
+    let planned = tokio::task::spawn_blocking(move || {
+        planned_tokens(&init_config, &segments, &planned_language, initial_silence)
+    })
+    .await
+    .map_err(|e| SpeechError::Koko(Box::new(e)))??;
+    let chunks = model_inputs(planned, |token_count| {
+        let styles = tts_single
+            .mix_styles(&voice, token_count)
+            .map_err(|e| SpeechError::BadRequest(e.to_string()))?;
+        Ok(styles.first().map_or(0, Vec::len))
+    })?;
+    Ok(Json(DebugTokenizeResponse { language, chunks }))
+}
+
+/// Handle /v1/audio/voices
+///
+/// Lists voices with metadata derived from their prefix; `?flat=true`
+/// returns the plain list of names instead. `?language=en-us` restricts the
+/// list to one language (an unknown language yields an empty list).
+async fn handle_voices(
+    State(AppState { tts_single, .. }): State<AppState>,
+    Query(query): Query<VoicesQuery>,
+) -> Response {
+    let mut voices = tts_single.get_available_voices();
+    if let Some(language) = &query.language {
+        voices = voices::filter_by_language(&voices, language);
+    }
+    if query.flat {
+        return Json(VoicesResponse { voices }).into_response();
+    }
+
+    let voices = voices
+        .iter()
+        .map(|name| {
+            let info = voice_metadata(name);
+            VoiceObject {
+                id: info.id,
+                language: info.language,
+                gender: info.gender,
+                region: info.region,
+            }
+        })
+        .collect();
+    Json(VoiceDetailsResponse { voices }).into_response()
+}
+
+/// Model ids kept for OpenAI SDK compatibility, all served by the loaded model
+const MODEL_ALIASES: [&str; 3] = ["tts-1", "tts-1-hd", "kokoro"];
+
+/// `created` of the [`MODEL_ALIASES`]
+const MODEL_ALIASES_CREATED: u64 = 1686935002;
+
+fn model_object(id: &str, created: u64) -> ModelObject {
+    ModelObject {
+        id: id.to_string(),
+        object: "model".to_string(),
+        created,
+        owned_by: "kokoro".to_string(),
+    }
+}
+
+/// The [`MODEL_ALIASES`] followed by the model file actually loaded, named
+/// after its file stem (`kokoro-v1.0` for `checkpoints/kokoro-v1.0.onnx`)
+/// and created when the file was last modified
+fn available_models(model_path: &str) -> Vec<ModelObject> {
+    let mut models: Vec<ModelObject> = MODEL_ALIASES
+        .iter()
+        .map(|id| model_object(id, MODEL_ALIASES_CREATED))
+        .collect();
+
+    let path = std::path::Path::new(model_path);
+    if let Some(id) = path.file_stem().and_then(|stem| stem.to_str())
+        && !MODEL_ALIASES.contains(&id)
+    {
+        let created = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(MODEL_ALIASES_CREATED, |since| since.as_secs());
+        models.push(model_object(id, created));
+    }
+    models
+}
+
+/// Handle /v1/models endpoint
+///
+/// Lists the loaded model alongside the OpenAI model names, which clients
+/// may keep using. Every entry runs on the same Kokoro model.
+async fn handle_models(
+    State(AppState { tts_single, .. }): State<AppState>,
+) -> Json<ModelsResponse> {
+    Json(ModelsResponse {
+        object: "list".to_string(),
+        data: available_models(tts_single.model_path()),
+    })
+}
+
+async fn handle_model(
+    State(AppState { tts_single, .. }): State<AppState>,
+    Path(model_id): Path<String>,
+) -> Result<Json<ModelObject>, StatusCode> {
+    available_models(tts_single.model_path())
+        .into_iter()
+        .find(|model| model.id == model_id)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn get_colored_request_id_with_relative(request_id: &str, start_time: Instant) -> String {
+    kokoros::utils::debug::get_colored_request_id_with_relative(request_id, start_time)
+}
+
+async fn request_id_middleware(
+    mut request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let method = request.method().clone();
+    let uri = request.uri().path().to_string();
+    let user_agent = request
+        .headers()
+        .get("user-agent")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+
+    let request_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let start = std::time::Instant::now();
+    let colored_request_id = get_colored_request_id_with_relative(&request_id, start);
+    request.extensions_mut().insert((request_id.clone(), start));
+
+    info!(
+        "{} {} {} \"{}\"",
+        colored_request_id, method, uri, user_agent
+    );
+
+    let response = next.run(request).await;
+    let _latency = start.elapsed();
+
+    let colored_request_id_response = get_colored_request_id_with_relative(&request_id, start);
+    info!("{} {}", colored_request_id_response, response.status());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_chunks_have_no_empty_non_terminator() {
+        let input = "Hello there. .. .. .. How are you today?";
+        let chunks = kokoros::tts::koko::split_text_into_speech_chunks(input, 20);
+        let chunks =
+            prepare_stream_chunks(chunks, 20, 8, 40, &ChunkDelimiters::default().break_words);
+
+        let (terminator, spoken) = chunks.split_last().unwrap();
+        assert!(terminator.is_empty());
+        assert!(!spoken.is_empty());
+        assert!(spoken.iter().all(|c| c.chars().any(char::is_alphanumeric)));
+
+        // Punctuation-only input leaves just the terminator
+        let chunks = kokoros::tts::koko::split_text_into_speech_chunks(".. .. ..", 20);
+        assert_eq!(
+            prepare_stream_chunks(chunks, 20, 8, 40, &ChunkDelimiters::default().break_words),
+            vec![String::new()]
+        );
+    }
+
+    #[test]
+    fn test_custom_break_words_move_chunk_boundaries() {
+        let chunks = || {
+            vec![
+                "Fuimos al mercado del barrio a comprar pan fresco y leche,".to_string(),
+                "y luego volvimos a casa caminando muy despacio por la calle.".to_string(),
+            ]
+        };
+        let default =
+            prepare_stream_chunks(chunks(), 20, 8, 40, &ChunkDelimiters::default().break_words);
+        assert!(default[1].starts_with("y luego"));
+
+        let spanish = ChunkDelimiters {
+            break_words: vec!["y".to_string(), "pero".to_string()],
+            ..ChunkDelimiters::default()
+        };
+        let custom = prepare_stream_chunks(chunks(), 20, 8, 40, &spanish.break_words);
+        assert!(custom[0].ends_with("leche, y"), "{:?}", custom);
+        assert!(custom[1].starts_with("luego"));
+
+        // Sentence punctuation is configurable too
+        let input = "我们去了市场 买了面包。然后 我们回家了。";
+        let split = |sentence: &[char]| {
+            koko::split_text_into_speech_chunks_with(input, 20, sentence, koko::CLAUSE_PUNCTUATION)
+        };
+        assert_eq!(split(koko::SENTENCE_PUNCTUATION).len(), 1);
+        assert_eq!(split(&['。']).len(), 2);
+    }
+
+    #[test]
+    fn test_long_clause_is_split_by_word_count() {
+        // No punctuation and no break words to split at
+        let words: Vec<String> = (0..100).map(|i| format!("word{}", i)).collect();
+        let input = format!("{}.", words.join(" "));
+        let plan = |max_chunks, max_chunk_words| {
+            let plan = plan_stream_chunks(
+                std::slice::from_ref(&input),
+                20,
+                max_chunks,
+                max_chunk_words,
+                &ChunkDelimiters::default(),
+            );
+            assert_eq!(plan.chunks.last().map(String::as_str), Some(""));
+            let spoken = plan.chunks[..plan.chunks.len() - 1].to_vec();
+            assert_eq!(spoken.join(" ").replace('.', ""), words.join(" "));
+            spoken.iter().map(|c| count_words(c)).collect::<Vec<_>>()
+        };
+
+        // By default the cap follows the words-per-chunk target
+        let words_per_chunk = plan(None, None);
+        assert_eq!(words_per_chunk, [34, 34, 32]);
+        assert!(
+            words_per_chunk
+                .iter()
+                .all(|&n| n <= 20 * STREAM_MAX_CHUNK_FACTOR)
+        );
+
+        // A configured cap holds
+        assert!(plan(None, Some(15)).iter().all(|&n| n <= 15));
+
+        // and the chunk cap can't coarsen chunks past it
+        assert_eq!(plan(Some(1), None), [100]);
+        assert_eq!(plan(Some(1), Some(30)), [25, 25, 25, 25]);
+    }
+
+    #[test]
+    fn test_chunk_cap_raises_words_per_chunk() {
+        // 30 sentences of 6 words each split into 30 chunks by default
+        let input = "This sentence has exactly six words. ".repeat(30);
+        let uncapped = plan_stream_chunks(
+            std::slice::from_ref(&input),
+            STREAM_TARGET_WORDS,
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert_eq!(uncapped.words_per_chunk, STREAM_TARGET_WORDS);
+        assert!(uncapped.chunks.len() - 1 > 5);
+
+        let capped = plan_stream_chunks(
+            std::slice::from_ref(&input),
+            STREAM_TARGET_WORDS,
+            Some(5),
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert!(capped.words_per_chunk > STREAM_TARGET_WORDS);
+        let capped = capped.chunks;
+        assert!(capped.len() - 1 <= 5);
+        assert!(capped.last().unwrap().is_empty());
+
+        // Nothing is dropped, only regrouped
+        let spoken_words: usize = capped.iter().map(|c| count_words(c)).sum();
+        assert_eq!(spoken_words, count_words(&input));
+    }
+
+    #[test]
+    fn test_smaller_words_per_chunk_gives_more_chunks() {
+        let input =
+            "The quick brown fox jumps over the lazy dog, then runs off into the woods. ".repeat(6);
+        let request: SpeechRequest =
+            serde_json::from_str(r#"{"model": "tts-1", "input": "Hi.", "words_per_chunk": 5}"#)
+                .unwrap();
+        let words_per_chunk = resolve_words_per_chunk(request.words_per_chunk).unwrap();
+
+        let default = plan_stream_chunks(
+            std::slice::from_ref(&input),
+            resolve_words_per_chunk(None).unwrap(),
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        let small = plan_stream_chunks(
+            std::slice::from_ref(&input),
+            words_per_chunk,
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert!(
+            small.chunks.len() > default.chunks.len(),
+            "{} vs {} chunks",
+            small.chunks.len(),
+            default.chunks.len()
+        );
+        let spoken_words: usize = small.chunks.iter().map(|c| count_words(c)).sum();
+        assert_eq!(spoken_words, count_words(&input));
+
+        assert!(resolve_words_per_chunk(Some(1)).is_err());
+        assert!(resolve_words_per_chunk(Some(1000)).is_err());
     }
-}
 
-impl IntoResponse for SpeechError {
-    fn into_response(self) -> Response {
-        // None of these errors make sense to expose to the user of the API
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    #[test]
+    fn test_array_input_keeps_segment_boundaries() {
+        let request: SpeechRequest =
+            serde_json::from_str(r#"{"model": "tts-1", "input": ["Hi.", "  ", "Short line."]}"#)
+                .unwrap();
+        let segments = request.input.into_segments();
+        assert_eq!(segments, vec!["Hi.", "Short line."]);
+
+        let request: SpeechRequest =
+            serde_json::from_str(r#"{"model": "tts-1", "input": "Hi. Short line."}"#).unwrap();
+        assert_eq!(
+            request.input,
+            SpeechInput::Text("Hi. Short line.".to_string())
+        );
+
+        // Short sentences would normally merge into one chunk, segments never do
+        let merged = plan_stream_chunks(
+            &["Hi. Short line.".to_string()],
+            STREAM_TARGET_WORDS,
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert_eq!(merged.chunks.len(), 2);
+        assert!(merged.segment_ends.is_empty());
+
+        let plan = plan_stream_chunks(
+            &segments,
+            STREAM_TARGET_WORDS,
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert_eq!(plan.chunks, vec!["Hi.", "Short line.", ""]);
+        // Silence follows the first segment only, not the last one
+        assert_eq!(plan.segment_ends, vec![0]);
+        assert_eq!(segment_silence_samples(24000), 12000);
     }
-}
 
-/// Returns a 200 OK response to make it easier to check if the server is
-/// running.
-async fn handle_home() -> &'static str {
-    "OK"
-}
+    #[tokio::test]
+    async fn test_error_body_is_openai_shaped() {
+        let body = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
 
-async fn handle_tts(
-    State((tts_single, tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
-    request: axum::extract::Request,
-) -> Result<Response, SpeechError> {
-    let (request_id, request_start) = request
-        .extensions()
-        .get::<(String, Instant)>()
-        .cloned()
-        .unwrap_or_else(|| ("unknown".to_string(), Instant::now()));
+        // A failed synthesis names no internals
+        let response = SpeechError::Koko("ort session exploded".into()).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(
+            body(response).await,
+            serde_json::json!({"error": {
+                "message": "speech synthesis failed",
+                "type": "server_error",
+                "code": "synthesis_failed",
+            }})
+        );
 
-    // OpenAI TTS always streams by default - client decides how to consume
-    // Only send complete file when explicitly requested via stream: false
+        let response =
+            SpeechError::BadRequest("speed must be positive".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error = &body(response).await["error"];
+        assert_eq!(error["message"], "speed must be positive");
+        assert_eq!(error["type"], "invalid_request_error");
+        assert_eq!(error["code"], "invalid_request");
+    }
 
-    // Parse the JSON body
-    let bytes = axum::body::to_bytes(request.into_body(), usize::MAX)
-        .await
-        .map_err(|e| {
-            error!("Error reading request body: {:?}", e);
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
-        })?;
+    #[tokio::test]
+    async fn test_invalid_json_is_a_bad_request() {
+        use tower::ServiceExt;
+
+        for body in [r#"{"input": "Hello"#, r#"{"input": 5}"#] {
+            let router = mock_server(ServerConfig::default()).await;
+            let response = router
+                .oneshot(post_json("/v1/audio/speech", body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{body}");
+            assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            let error = &json["error"];
+            assert_eq!(error["type"], "invalid_request_error", "{body}");
+            assert_eq!(error["code"], "invalid_request", "{body}");
+            assert!(
+                error["message"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("invalid request body"),
+                "{body}"
+            );
+        }
+    }
 
-    let speech_request: SpeechRequest = serde_json::from_slice(&bytes).map_err(|e| {
-        error!("JSON parsing error: {:?}", e);
-        SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
-    })?;
+    #[tokio::test]
+    async fn test_blank_input_is_rejected() {
+        for input in [r#""""#, r#"" \n\t ""#, r#"["", "  "]"#] {
+            let body = format!(r#"{{"model": "tts-1", "input": {}}}"#, input);
+            let request: SpeechRequest = serde_json::from_str(&body).unwrap();
+            let response = preprocess_segments(None, request.input, None)
+                .unwrap_err()
+                .into_response();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", input);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(
+                String::from_utf8_lossy(&body).contains("input contains no text"),
+                "{}",
+                input
+            );
+        }
+    }
 
-    let SpeechRequest {
-        input,
-        voice: Voice(voice),
-        response_format,
-        speed: Speed(speed),
-        initial_silence,
-        stream,
-        ..
-    } = speech_request;
+    #[test]
+    fn test_repeat_audio() {
+        let once = vec![0.5, -0.5, 0.25, 0.1];
+        let repeated = repeat_audio(once.clone(), 3, 2);
+        assert_eq!(repeated.len(), 3 * once.len() + 2 * 2);
+        assert_eq!(&repeated[..4], &once[..]);
+        assert_eq!(&repeated[4..6], [0.0, 0.0]);
+        assert_eq!(&repeated[12..], &once[..]);
+        // No gap after the last copy
+        assert_eq!(repeat_audio(once.clone(), 1, 2), once);
+
+        assert!(resolve_repeat(Some(3), None, 24000).is_ok());
+        assert_eq!(resolve_repeat(None, None, 24000).unwrap(), (1, 12000));
+        assert!(resolve_repeat(Some(0), None, 24000).is_err());
+        assert!(resolve_repeat(Some(MAX_REPEAT + 1), None, 24000).is_err());
+        assert!(resolve_repeat(Some(2), Some(MAX_REPEAT_SILENCE_MS + 1), 24000).is_err());
+    }
 
-    // OpenAI-compliant behavior: Stream by default, only send complete file if stream: false
-    let should_stream = stream.unwrap_or(true); // Default to streaming like OpenAI
+    #[test]
+    fn test_control_chars_strip_or_reject() {
+        let input = SpeechInput::Segments(vec!["Fine.".to_string(), "Be\u{7}ll".to_string()]);
+        // Stripped later by preprocessing unless the server rejects them
+        assert!(check_control_chars(&input, &ServerConfig::default()).is_ok());
 
-    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
-    debug!(
-        "{} Streaming decision: stream_param={:?}, final_decision={}",
-        colored_request_id, stream, should_stream
-    );
+        let config = ServerConfig {
+            reject_control_chars: true,
+            ..ServerConfig::default()
+        };
+        let response = check_control_chars(&input, &config)
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let clean = SpeechInput::Text("Line one.\n\tLine two.".to_string());
+        assert!(check_control_chars(&clean, &config).is_ok());
+    }
 
-    if should_stream {
-        return handle_tts_streaming(
-            tts_instances,
-            input,
-            voice,
-            response_format,
-            speed,
-            initial_silence,
-            request_id,
-            request_start,
+    #[test]
+    fn test_ssml_breaks_survive_stream_chunking() {
+        let ssml = r#"<speak>Hi. <break time="750ms"/> Short line.</speak>"#.to_string();
+        let plan = plan_stream_chunks(
+            &[ssml],
+            STREAM_TARGET_WORDS,
+            None,
+            None,
+            &ChunkDelimiters::default(),
+        );
+        assert_eq!(
+            plan.chunks,
+            vec![
+                "Hi.",
+                r#"<speak><break time="750ms"/></speak>"#,
+                "Short line.",
+                ""
+            ]
+        );
+        assert!(plan.segment_ends.is_empty());
+    }
+
+    #[test]
+    fn test_parallel_synthesis_matches_serial() {
+        // Each "instance" synthesizes the same audio for a job, taking
+        // scrambled times so jobs finish out of order
+        let synthesize = |job: &usize| -> Vec<f32> { vec![*job as f32 * 0.01; job % 7 + 1] };
+        let jobs: Vec<usize> = (0..40).collect();
+        let serial: Vec<Vec<f32>> = jobs.iter().map(synthesize).collect();
+
+        let used = Mutex::new(std::collections::HashSet::new());
+        let cancelled = std::sync::atomic::AtomicBool::new(false);
+        let busy = instance_load(4);
+        let parallel = synthesize_in_order(
+            &[(); 4],
+            &busy,
+            &jobs,
+            &cancelled,
+            |_, instance, index, job| {
+                assert_eq!(index, *job);
+                used.lock().unwrap().insert(instance);
+                std::thread::sleep(Duration::from_millis((job * 7 % 5) as u64));
+                Ok(synthesize(job))
+            },
         )
+        .unwrap();
+        assert_eq!(parallel, serial);
+        assert!(used.lock().unwrap().len() > 1);
+
+        let failing = synthesize_in_order(&[(); 4], &busy, &jobs, &cancelled, |_, _, _, job| {
+            if *job == 9 {
+                return Err("ORT error".to_string());
+            }
+            Ok(synthesize(job))
+        });
+        assert_eq!(failing, Err("ORT error".to_string()));
+    }
+
+    #[test]
+    fn test_pinned_instance() {
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let pinned = tracing::subscriber::with_default(subscriber, || {
+            resolve_instance(Some(2), 4, "[request]")
+        })
+        .unwrap();
+        assert_eq!(pinned, Some(2));
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("[request] Pinned to instance 02"), "{}", logs);
+
+        // Every chunk goes to the pinned instance, even with others idle
+        let pool = TTSWorkerPool::new(vec![(); 4], instance_load(4), None).pinned(pinned);
+        let first = pool.acquire(0);
+        let retry = pool.acquire(first.index + 1);
+        assert_eq!((first.index, retry.instance_id.as_str()), (2, "02"));
+
+        assert!(resolve_instance(Some(4), 4, "").is_err());
+        let parse = |header: &str| {
+            let mut request: SpeechRequest =
+                serde_json::from_str(r#"{"model": "tts-1", "input": "Hi."}"#).unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert(INSTANCE_HEADER, HeaderValue::from_str(header).unwrap());
+            request
+                .instance_from_header(&headers)
+                .map(|()| request.instance)
+        };
+        assert_eq!(parse("1").unwrap(), Some(1));
+        assert!(parse("first").is_err());
+    }
+
+    #[test]
+    fn test_busy_instances_are_skipped() {
+        // Instances are counters of the chunks they ran
+        let pool = TTSWorkerPool::new(
+            (0..4).map(|_| AtomicUsize::new(0)).collect(),
+            instance_load(4),
+            None,
+        );
+
+        // Chunks that all start counting from instance 0 still each get an
+        // instance to themselves while the others are busy
+        let barrier = Arc::new(std::sync::Barrier::new(4));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let lease = pool.acquire(0);
+                    lease.instance.fetch_add(1, Ordering::SeqCst);
+                    // Hold the lease until every chunk has one
+                    barrier.wait();
+                    lease.index
+                })
+            })
+            .collect();
+        let mut used: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        used.sort();
+        assert_eq!(used, vec![0, 1, 2, 3]);
+        for instance in &pool.tts_instances {
+            assert_eq!(instance.load(Ordering::SeqCst), 1);
+        }
+
+        // With every instance busy it takes the least busy one
+        let mut held: Vec<_> = (0..4).map(|i| pool.acquire(i)).collect();
+        held.push(pool.acquire(5));
+        assert_eq!(held[4].index, 1);
+        assert_eq!(pool.acquire(1).index, 2);
+        drop(held);
+        // Leases are released on drop
+        assert_eq!(pool.acquire(2).index, 2);
+    }
+
+    #[test]
+    fn test_concurrent_requests_share_instance_load() {
+        let busy = instance_load(2);
+        let first = TTSWorkerPool::new(vec![(); 2], busy.clone(), None);
+        let second = TTSWorkerPool::new(vec![(); 2], busy.clone(), None);
+
+        // A chunk of one stream keeps another stream off its instance
+        let held = first.acquire(0);
+        assert_eq!(second.acquire(0).index, 1);
+        drop(held);
+
+        // So does non-streaming synthesis, here on instance 1 alone
+        let cancelled = std::sync::atomic::AtomicBool::new(false);
+        let leased = synthesize_in_order(&[()], &busy[1..], &[()], &cancelled, |_, _, _, _| {
+            Ok(first.acquire(1).index)
+        })
+        .unwrap();
+        assert_eq!(leased, vec![0]);
+        assert!(busy.iter().all(|count| count.load(Ordering::SeqCst) == 0));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_window_larger_than_instance_count_keeps_order() {
+        // One "model" behind a lock, like a single TTS instance, with a window
+        // of 4 so later chunks queue on it while earlier ones are in flight
+        let model = Arc::new(Mutex::new(()));
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..8usize {
+            task_tx.send(id).unwrap();
+        }
+        drop(task_tx);
+
+        let (audio_tx, mut audio_rx) = mpsc::channel(100);
+        forward_in_order(task_rx, 8, 4, None, None, &audio_tx, |_, id| {
+            let model = model.clone();
+            tokio::task::spawn_blocking(move || {
+                let _guard = model.lock().unwrap();
+                // Later chunks are quicker, so completion order differs
+                std::thread::sleep(std::time::Duration::from_millis(8 - id as u64));
+                Ok((id, vec![id as u8; 2]))
+            })
+        })
         .await;
+        drop(audio_tx);
+
+        let mut order = Vec::new();
+        while let Some((id, pcm)) = audio_rx.recv().await {
+            assert_eq!(pcm, vec![id as u8; 2]);
+            order.push(id);
+        }
+        assert_eq!(order, (0..8).collect::<Vec<_>>());
     }
 
-    // Non-streaming mode (existing implementation)
-    let raw_audio = tts_single
-        .tts_raw_audio(
-            &input,
-            "en-us",
-            &voice,
-            speed,
-            initial_silence,
-            Some(&request_id),
-            Some("00"),
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_dropped_response_stops_synthesis() {
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..100usize {
+            task_tx.send(id).unwrap();
+        }
+        drop(task_tx);
+
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = started.clone();
+        let (audio_tx, audio_rx) = mpsc::channel(100);
+        let forwarding = tokio::spawn(async move {
+            forward_in_order(task_rx, 100, 2, None, None, &audio_tx, |_, id| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::task::spawn_blocking(move || {
+                    std::thread::sleep(Duration::from_millis(5));
+                    Ok((id, vec![0; 2]))
+                })
+            })
+            .await
+        });
+
+        // The client takes two chunks of the MP3 stream, then hangs up
+        let mut body = mp3_stream(24000, 64, false, audio_rx);
+        body.next().await.unwrap();
+        body.next().await.unwrap();
+        drop(body);
+
+        let forwarded = tokio::time::timeout(Duration::from_secs(5), forwarding)
+            .await
+            .expect("synthesis kept going after the client left")
+            .unwrap();
+        assert_eq!(forwarded, Forwarded::Disconnected);
+        let stopped_at = started.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started.load(Ordering::SeqCst), stopped_at);
+        assert!(stopped_at < 100, "{} chunks started", stopped_at);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_reader_throttles_synthesis() {
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..50usize {
+            task_tx.send(id).unwrap();
+        }
+        drop(task_tx);
+
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = started.clone();
+        let buffer = ServerConfig::default().stream_buffer();
+        assert_eq!(buffer, DEFAULT_STREAM_BUFFER);
+        let (audio_tx, mut audio_rx) = mpsc::channel(buffer);
+        let forwarding = tokio::spawn(async move {
+            forward_in_order(task_rx, 50, 2, None, None, &audio_tx, |_, id| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move { Ok((id, vec![0; 4800])) })
+            })
+            .await
+        });
+
+        // Nothing is read yet: synthesis stops once the buffer, the chunk
+        // waiting to go in and the window are full
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stopped_at = started.load(Ordering::SeqCst);
+        assert!(
+            stopped_at <= buffer + 1 + 2,
+            "{} chunks started",
+            stopped_at
+        );
+
+        // Reading lets it finish, in order
+        let mut order = Vec::new();
+        while let Some((id, _)) = audio_rx.recv().await {
+            order.push(id);
+        }
+        assert_eq!(order, (0..50).collect::<Vec<_>>());
+        assert_eq!(forwarding.await.unwrap(), Forwarded::Completed);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_stream_order_under_stress() {
+        // Many tiny chunks over four instances, finishing in scrambled order,
+        // with every 37th failing
+        let total = 400;
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..total {
+            task_tx.send(id).unwrap();
+        }
+        drop(task_tx);
+
+        let pool = TTSWorkerPool::new(vec![(); 4], instance_load(4), Some(8));
+        let (audio_tx, mut audio_rx) = mpsc::channel(4);
+        let reader = tokio::spawn(async move {
+            let mut order = Vec::new();
+            while let Some((id, pcm)) = audio_rx.recv().await {
+                assert_eq!(pcm, vec![id as u8; 2]);
+                order.push(id);
+            }
+            order
+        });
+        let mut check = StreamOrderCheck::default();
+        let spawn_chunk = |chunk_number, id: usize| {
+            let pool = pool.clone();
+            tokio::task::spawn_blocking(move || {
+                let _lease = pool.acquire(chunk_number);
+                std::thread::sleep(Duration::from_micros((id * 7919 % 13) as u64 * 50));
+                if id % 37 == 5 {
+                    return Err("ORT error".to_string());
+                }
+                Ok((id, vec![id as u8; 2]))
+            })
+        };
+        let forwarded = forward_in_order(
+            task_rx,
+            total,
+            8,
             None,
+            Some(&mut check),
+            &audio_tx,
+            spawn_chunk,
         )
-        .map_err(SpeechError::Koko)?;
+        .await;
+        drop(audio_tx);
+        assert_eq!(forwarded, Forwarded::Completed);
 
-    let sample_rate = TTSKokoInitConfig::default().sample_rate;
+        let expected: Vec<usize> = (0..total).filter(|id| id % 37 != 5).collect();
+        assert_eq!(reader.await.unwrap(), expected);
+        assert!(check.violations.is_empty(), "{:?}", check.violations);
+    }
 
-    let (content_type, audio_data, format_name) = match response_format {
-        AudioFormat::Wav => {
-            let mut wav_data = Vec::default();
-            let header = WavHeader::new(1, sample_rate, 32);
-            header
-                .write_header(&mut wav_data)
-                .map_err(SpeechError::Header)?;
-            write_audio_chunk(&mut wav_data, &raw_audio).map_err(SpeechError::Chunk)?;
+    #[test]
+    fn test_stream_order_check_flags_violations() {
+        let mut check = StreamOrderCheck::default();
+        check.sent(0);
+        check.skipped(1);
+        check.sent(2);
+        check.finish(3);
+        assert!(check.violations.is_empty());
+
+        // A duplicate, a chunk that vanished, and a short stream
+        let mut check = StreamOrderCheck::default();
+        check.sent(0);
+        check.sent(0);
+        check.sent(2);
+        check.finish(4);
+        assert_eq!(check.violations.len(), 3, "{:?}", check.violations);
+    }
 
-            ("audio/wav", wav_data, "WAV")
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_chunks_are_forwarded_in_order() {
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..32usize {
+            task_tx.send(id).unwrap();
         }
-        AudioFormat::Mp3 => {
-            let mp3_data =
-                pcm_to_mp3(&raw_audio, sample_rate).map_err(|e| SpeechError::Mp3Conversion(e))?;
+        drop(task_tx);
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let (audio_tx, mut audio_rx) = mpsc::channel(100);
+        let forwarded = forward_in_order(task_rx, 32, 4, None, None, &audio_tx, |_, id| {
+            let running = running.clone();
+            let most_running = most_running.clone();
+            tokio::spawn(async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now, Ordering::SeqCst);
+                // Scrambled durations so chunks finish out of order
+                tokio::time::sleep(Duration::from_millis((id * 7 % 5) as u64)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                if id == 9 {
+                    return Err("ORT error".to_string());
+                }
+                Ok((id, vec![id as u8; 2]))
+            })
+        })
+        .await;
+        drop(audio_tx);
+        assert_eq!(forwarded, Forwarded::Completed);
 
-            ("audio/mpeg", mp3_data, "MP3")
+        let mut order = Vec::new();
+        while let Some((id, pcm)) = audio_rx.recv().await {
+            assert_eq!(pcm, vec![id as u8; 2]);
+            order.push(id);
         }
-        AudioFormat::Pcm => {
-            // For PCM, we return the raw audio data directly
-            // Convert f32 samples to 16-bit PCM
-            let mut pcm_data = Vec::with_capacity(raw_audio.len() * 2);
-            for sample in raw_audio {
-                let pcm_sample = (sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
-                pcm_data.extend_from_slice(&pcm_sample.to_le_bytes());
+        // The failed chunk is skipped and everything else arrives in order
+        let expected: Vec<usize> = (0..32).filter(|&id| id != 9).collect();
+        assert_eq!(order, expected);
+        assert!(most_running.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_healthz_caches_probe_result() {
+        let probes = Arc::new(AtomicUsize::new(0));
+        let probe = || {
+            let probes = probes.clone();
+            async move {
+                probes.fetch_add(1, Ordering::Relaxed);
+                true
             }
-            ("audio/pcm", pcm_data, "PCM")
+        };
+
+        let health = HealthCache::default();
+        assert!(health.check(probe).await);
+        assert_eq!(health_response(true).status(), StatusCode::OK);
+        // A second probe within the TTL reuses the first result
+        assert!(health.check(probe).await);
+        assert_eq!(probes.load(Ordering::Relaxed), 1);
+
+        assert_eq!(
+            health_response(false).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn test_failed_chunk_is_retried() {
+        // Fails on the first instance, succeeds on the next
+        let mut tried = Vec::new();
+        let result = with_retries(2, |attempt| {
+            tried.push(attempt);
+            if attempt == 0 {
+                Err("ORT error".to_string())
+            } else {
+                Ok(vec![0.5f32; 4])
+            }
+        });
+        assert_eq!(result, Ok(vec![0.5; 4]));
+        assert_eq!(tried, [0, 1]);
+
+        // Without retries the first failure is final
+        let mut calls = 0;
+        let result: Result<(), _> = with_retries(0, |_| {
+            calls += 1;
+            Err("ORT error".to_string())
+        });
+        assert_eq!(result, Err("ORT error".to_string()));
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_stops_long_synthesis() {
+        // Synthesis that would run forever: it only returns once let go,
+        // which happens after the timeout has fired
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let (saw_flag_tx, saw_flag_rx) = tokio::sync::oneshot::channel();
+        let result = run_with_timeout(Some(Duration::from_millis(1)), move |cancelled| {
+            release_rx.recv().unwrap();
+            let stop = cancelled.load(std::sync::atomic::Ordering::Relaxed);
+            saw_flag_tx.send(stop).unwrap();
+            if stop {
+                return Err("synthesis timed out".to_string());
+            }
+            Ok(())
+        })
+        .await;
+        assert!(matches!(result, Err(SpeechError::Timeout)));
+        assert_eq!(
+            SpeechError::Timeout.into_response().status(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+
+        // The worker sees the flag at its next check and stops
+        release_tx.send(()).unwrap();
+        assert!(saw_flag_rx.await.unwrap());
+
+        // Streaming: the first two chunks finish, the rest never do, and the
+        // sender gives up at the deadline with only the finished ones sent.
+        // With the clock paused it jumps to the deadline once nothing else
+        // can make progress.
+        tokio::time::pause();
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
+        for id in 0..100usize {
+            task_tx.send(id).unwrap();
         }
-        // For now, unsupported formats fall back to MP3
-        _ => {
-            let mp3_data =
-                pcm_to_mp3(&raw_audio, sample_rate).map_err(|e| SpeechError::Mp3Conversion(e))?;
+        drop(task_tx);
+        let (audio_tx, mut audio_rx) = mpsc::channel(100);
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let forwarded =
+            forward_in_order(task_rx, 100, 2, Some(deadline), None, &audio_tx, |_, id| {
+                if id < 2 {
+                    tokio::spawn(async move { Ok((id, vec![0; 2])) })
+                } else {
+                    tokio::spawn(std::future::pending())
+                }
+            })
+            .await;
+        drop(audio_tx);
+        assert_eq!(forwarded, Forwarded::TimedOut);
 
-            ("audio/mpeg", mp3_data, "MP3")
+        let mut sent = Vec::new();
+        while let Some((id, _)) = audio_rx.recv().await {
+            sent.push(id);
         }
-    };
+        assert_eq!(sent, vec![0, 1]);
+    }
 
-    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
-    info!(
-        "{} TTS non-streaming completed - {} bytes, {} format",
-        colored_request_id,
-        audio_data.len(),
-        format_name
-    );
+    #[test]
+    fn test_espeak_variant_is_appended_and_validated() {
+        let resolve = |language: &str, variant: Option<&str>| {
+            resolve_espeak_variant(language.to_string(), variant.map(str::to_string))
+        };
+        assert_eq!(resolve("en-us", Some("f3")).unwrap(), "en-us+f3");
+        assert_eq!(resolve("en-us+klatt", None).unwrap(), "en-us+klatt");
+        assert_eq!(resolve("fr-fr", None).unwrap(), "fr-fr");
+
+        for (language, variant) in [
+            ("en-us", Some("f3 --path=/etc")),
+            ("en-us", Some("")),
+            ("en-us+", None),
+            ("en-us+m1", Some("f3")),
+        ] {
+            assert!(
+                matches!(resolve(language, variant), Err(SpeechError::BadRequest(_))),
+                "{} {:?}",
+                language,
+                variant
+            );
+        }
 
-    Ok(Response::builder()
-        .header(header::CONTENT_TYPE, content_type)
-        .body(audio_data.into())
-        .map_err(|e| {
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::Other, e))
-        })?)
-}
+        let request: SpeechRequest = serde_json::from_str(
+            r#"{"model": "tts-1", "input": "Hi", "espeak_variant": "whisper"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.espeak_variant.as_deref(), Some("whisper"));
+    }
 
-/// Handle streaming TTS requests with true async processing
-///
-/// Uses micro-chunking and parallel processing for low-latency streaming.
-/// Maintains speech order while allowing out-of-order chunk completion.
-async fn handle_tts_streaming(
-    tts_instances: Vec<TTSKoko>,
-    input: String,
-    voice: String,
-    response_format: AudioFormat,
-    speed: f32,
-    initial_silence: Option<usize>,
-    request_id: String,
-    request_start: Instant,
-) -> Result<Response, SpeechError> {
-    // Stream MP3 regardless of requested format for compatibility
-    let content_type = "audio/mpeg";
+    #[test]
+    fn test_initial_silence_is_capped() {
+        assert_eq!(resolve_initial_silence(Some(200), None).unwrap(), Some(200));
+        assert_eq!(
+            resolve_initial_silence(None, Some(5000)).unwrap(),
+            Some(200)
+        );
+        for (tokens, ms) in [
+            (Some(201), None),
+            (Some(usize::MAX), None),
+            (None, Some(u32::MAX)),
+        ] {
+            assert!(
+                matches!(
+                    resolve_initial_silence(tokens, ms),
+                    Err(SpeechError::BadRequest(_))
+                ),
+                "{:?} {:?}",
+                tokens,
+                ms
+            );
+        }
+    }
 
-    // Create worker pool with vector of TTS instances for true parallelism
-    let worker_pool = TTSWorkerPool::new(tts_instances.clone());
+    #[test]
+    fn test_language_resolution() {
+        let french = vec!["Bonjour, je suis très content de vous voir dans la ville.".to_string()];
+        let detecting = ServerConfig {
+            detect_language: true,
+            ..ServerConfig::default()
+        };
 
-    // Reuse library's sentence/clause chunker for better prosody
-    let target_words = 20usize; // tuned target 18–24; choose 20
-    let min_words = 8usize;     // merge threshold for very short chunks
-    let mut chunks = if let Some(first) = tts_instances.first() {
-        first.split_text_into_speech_chunks(&input, target_words)
-    } else {
-        vec![input.clone()]
-    };
+        assert_eq!(
+            detecting.language(Some("de".to_string()), "ff_siwis", &french),
+            ("de".to_string(), "lang_code")
+        );
+        assert_eq!(
+            detecting.language(None, "bf_emma", &french),
+            ("en-gb".to_string(), "voice")
+        );
+        // Every language voices come in, whatever the input looks like
+        for (voice, language) in [
+            ("zf_xiaobei", "zh"),
+            ("jf_alpha", "ja"),
+            ("em_alex", "es"),
+            ("if_sara", "it"),
+            ("pm_alex", "pt-br"),
+            ("hf_alpha", "hi"),
+        ] {
+            assert_eq!(
+                detecting.language(None, voice, &french),
+                (language.to_string(), "voice")
+            );
+        }
+        // Obviously French input with a voice that doesn't say
+        assert_eq!(
+            detecting.language(None, "my_voice", &french),
+            ("fr-fr".to_string(), "detected")
+        );
+        assert_eq!(
+            ServerConfig::default().language(None, "my_voice", &french),
+            ("en-us".to_string(), "default")
+        );
 
-    // Normalize chunks: merge very short ones and avoid leading conjunctions
-    chunks = normalize_chunks(chunks, target_words, min_words);
+        // Warmup phonemizes in each voice's language too
+        let warmup = ServerConfig {
+            warmup_voices: vec!["jf_alpha".into(), "af_sky".into(), "my_voice".into()],
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            warmup_languages(&warmup),
+            [
+                ("jf_alpha".to_string(), "ja".to_string()),
+                ("af_sky".to_string(), "en-us".to_string()),
+                ("my_voice".to_string(), "en-us".to_string()),
+            ]
+        );
+    }
 
-    // Add empty chunk at end as completion signal to client
-    chunks.push(String::new());
-    let total_chunks = chunks.len();
+    #[test]
+    fn test_openai_voice_names_resolve_to_kokoro_voices() {
+        let request: SpeechRequest =
+            serde_json::from_str(r#"{"model": "tts-1", "input": "Hi.", "voice": "alloy"}"#)
+                .unwrap();
+        let config = ServerConfig::default();
+        assert_eq!(config.voice(request.voice), "af_alloy");
+        assert_eq!(config.voice(Some(Voice("af_sky".to_string()))), "af_sky");
+
+        // Configured aliases replace the defaults and apply to the default voice
+        let config = ServerConfig {
+            default_voice: Some("narrator".to_string()),
+            voice_aliases: HashMap::from([
+                ("alloy".to_string(), "af_heart".to_string()),
+                ("narrator".to_string(), "bm_george".to_string()),
+            ]),
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.voice(Some(Voice("alloy".to_string()))), "af_heart");
+        assert_eq!(config.voice(Some(Voice("nova".to_string()))), "af_nova");
+        assert_eq!(config.voice(None), "bm_george");
+    }
 
-    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
-    debug!(
-        "{} Processing {} chunks for streaming with window size {}",
-        colored_request_id,
-        total_chunks,
-        worker_pool.instance_count()
-    );
+    #[tokio::test]
+    async fn test_max_input_chars() {
+        let config = ServerConfig {
+            max_input_chars: Some(10),
+            ..ServerConfig::default()
+        };
 
-    if chunks.is_empty() {
-        return Err(SpeechError::Mp3Conversion(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "No text to process",
-        )));
+        // Characters, not bytes, are counted
+        let just_under = SpeechInput::Text("é".repeat(10));
+        assert!(check_input_length(&just_under, &config).is_ok());
+
+        let just_over = SpeechInput::Segments(vec!["a".repeat(5), "b".repeat(6)]);
+        let response = check_input_length(&just_over, &config)
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // The body cap leaves room for a fully escaped input, but no more
+        let limit = config.max_body_bytes();
+        let fits = axum::body::to_bytes(Body::from(vec![b'a'; limit]), limit).await;
+        assert!(fits.is_ok());
+        let too_big = axum::body::to_bytes(Body::from(vec![b'a'; limit + 1]), limit).await;
+        assert!(
+            too_big
+                .unwrap_err()
+                .into_inner()
+                .is::<http_body_util::LengthLimitError>()
+        );
+    }
+
+    /// The server's router over one [`TTSKoko::mock`] instance
+    async fn mock_server(config: ServerConfig) -> Router {
+        let tts = TTSKoko::mock(TTSKokoInitConfig::default(), &["af_sky", "am_adam"]);
+        create_server_with_config(vec![tts], config).await.unwrap()
+    }
+
+    /// POST `body` as JSON to `path`
+    fn post_json(path: &str, body: &str) -> axum::http::Request<Body> {
+        axum::http::Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_speech_route_with_mock_model() {
+        use tower::ServiceExt;
+
+        let router = mock_server(ServerConfig::default()).await;
+        let speech = |body: &str| router.clone().oneshot(post_json("/v1/audio/speech", body));
+
+        let response = speech(
+            r#"{"model": "tts-1", "input": "Hello there. How are you?", "response_format": "wav", "stream": false}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/wav");
+        assert!(response.headers().contains_key("X-Audio-Duration-Seconds"));
+        let wav = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // Every token is a tone of the mock's length, so there's audio past the header
+        assert!(wav.len() > 44 + 4 * kokoros::onn::ort_koko::MOCK_SAMPLES_PER_TOKEN);
+
+        // Streams end once every chunk is in
+        for (format, content_type) in [("mp3", "audio/mpeg"), ("webm", "audio/webm")] {
+            let body = format!(
+                r#"{{"model": "tts-1", "input": "One. Two. Three.", "response_format": "{}"}}"#,
+                format
+            );
+            let response = speech(&body).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], content_type);
+            let streamed = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(!streamed.is_empty());
+        }
+
+        let response = speech(r#"{"model": "tts-1", "input": "Hi.", "instance": 3}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_request_is_served_from_cache() {
+        use tower::ServiceExt;
+
+        let tts = TTSKoko::mock(TTSKokoInitConfig::default(), &["af_sky"]);
+        let config = ServerConfig {
+            cache_size: 4,
+            ..ServerConfig::default()
+        };
+        let router = create_server_with_config(vec![tts.clone()], config)
+            .await
+            .unwrap();
+        let body = r#"{"model": "tts-1", "input": "Hello there.", "response_format": "wav", "stream": false}"#;
+        let speech = || async {
+            let response = router
+                .clone()
+                .oneshot(post_json("/v1/audio/speech", body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+
+        let first = speech().await;
+        let inferences = tts.mock_inferences();
+        assert!(inferences > 0);
+        assert_eq!(speech().await, first);
+        assert_eq!(tts.mock_inferences(), inferences);
+    }
+
+    #[tokio::test]
+    async fn test_head_on_speech_route() {
+        use tower::ServiceExt;
+
+        let router = mock_server(ServerConfig::default()).await;
+        let response = router
+            .oneshot(
+                axum::http::Request::head("/v1/audio/speech")
+                    .header(header::ORIGIN, "https://example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/mpeg");
+        let allow_origin: Vec<_> = response
+            .headers()
+            .get_all(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .iter()
+            .collect();
+        assert_eq!(allow_origin, ["*"]);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_format_query_overrides_body() {
+        let body = r#"{"model": "tts-1", "input": "Hi.", "response_format": "wav"}"#;
+        let parse = |uri: &str| {
+            let mut request: SpeechRequest = serde_json::from_str(body).unwrap();
+            SpeechOverrides::from_uri(&uri.parse().unwrap())
+                .map(|overrides| overrides.apply(&mut request))
+                .map(|()| request.response_format)
+        };
+
+        assert_eq!(
+            parse("/v1/audio/speech?format=flac").unwrap(),
+            Some(AudioFormat::Flac)
+        );
+        assert_eq!(parse("/v1/audio/speech").unwrap(), Some(AudioFormat::Wav));
+        let response = parse("/v1/audio/speech?format=wma")
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_accept_header_picks_format() {
+        let parse = |body: &str, accept: &str| {
+            let mut request: SpeechRequest = serde_json::from_str(body).unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            request.negotiate_format(&headers);
+            request.response_format
+        };
+        let no_format = r#"{"model": "tts-1", "input": "Hi."}"#;
+
+        let format = parse(no_format, "audio/wav").unwrap();
+        let (content_type, audio, _) =
+            encode_audio(format, vec![0.0; 2400], 24000, &EncodeSettings::default()).unwrap();
+        assert_eq!(content_type, "audio/wav");
+        assert_eq!(&audio[..4], b"RIFF");
+
+        // The body still wins
+        let flac = r#"{"model": "tts-1", "input": "Hi.", "response_format": "flac"}"#;
+        assert_eq!(parse(flac, "audio/wav"), Some(AudioFormat::Flac));
+        // Highest q among known types, wildcards ignored
+        assert_eq!(
+            parse(no_format, "*/*, audio/mpeg;q=0.5, audio/basic;q=0.9"),
+            Some(AudioFormat::Ulaw)
+        );
+        assert_eq!(parse(no_format, "*/*"), None);
+    }
+
+    #[test]
+    fn test_get_speech_query() {
+        let uri: axum::http::Uri =
+            "/v1/audio/speech?input=Hello%2C%20world%21&voice=alloy&speed=1.5&response_format=wav"
+                .parse()
+                .unwrap();
+        let Query(query) = Query::<SpeechQuery>::try_from_uri(&uri).unwrap();
+        let request = SpeechRequest::from(query);
+        assert_eq!(
+            request.input,
+            SpeechInput::Text("Hello, world!".to_string())
+        );
+        assert_eq!(request.response_format, Some(AudioFormat::Wav));
+
+        let config = ServerConfig::default();
+        assert_eq!(config.voice(request.voice), "af_alloy");
+        assert_eq!(config.speed(request.speed), 1.5);
+        assert!(check_input_length(&request.input, &config).is_ok());
+
+        // Served with the same content type as the POST route
+        let (content_type, _, _) = encode_audio(
+            request.response_format.unwrap(),
+            vec![0.0; 2400],
+            24000,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(content_type, "audio/wav");
+
+        let uri: axum::http::Uri = "/v1/audio/speech?voice=af_sky".parse().unwrap();
+        assert!(Query::<SpeechQuery>::try_from_uri(&uri).is_err());
     }
 
-    // Create channels for sequential chunk processing
-    let (task_tx, mut task_rx) = mpsc::unbounded_channel::<TTSTask>();
-    let (audio_tx, mut audio_rx) = mpsc::unbounded_channel::<(usize, Vec<u8>)>(); // Tag chunks with order ID
+    #[tokio::test]
+    async fn test_cors_allowlist() {
+        use tower::ServiceExt;
 
-    // Track total bytes transferred
-    let total_bytes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let router = with_layers(
+            Router::new().route("/v1/audio/speech", axum::routing::head(handle_tts_head)),
+            cors_layer(&["https://app.example.com".to_string()]),
+        );
+        let allow_origin = |origin: &'static str| {
+            let router = router.clone();
+            async move {
+                let response = router
+                    .oneshot(
+                        axum::http::Request::head("/v1/audio/speech")
+                            .header(header::ORIGIN, origin)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                response
+                    .headers()
+                    .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                    .cloned()
+            }
+        };
 
-    // Create session for tracking
-    let session = StreamingSession {
-        session_id: Uuid::new_v4(),
-        start_time: Instant::now(),
-    };
+        assert_eq!(
+            allow_origin("https://app.example.com").await.unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(allow_origin("https://evil.example.com").await, None);
+    }
 
-    let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
-    info!(
-        "{} TTS session started - {} chunks streaming",
-        colored_request_id, total_chunks
-    );
+    #[tokio::test]
+    async fn test_wav_response_is_compressed_on_request() {
+        use std::io::Read;
+        use tower::ServiceExt;
 
-    // Queue all tasks in order for sequential processing
-    for (id, chunk) in chunks.into_iter().enumerate() {
-        let task = TTSTask {
-            id,
-            chunk,
-            voice: voice.clone(),
-            speed,
-            initial_silence: if id == 0 { initial_silence } else { None },
-            result_tx: audio_tx.clone(),
+        let (_, wav, _) = encode_audio(
+            AudioFormat::Wav,
+            vec![0.0; 24000],
+            24000,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        let served = wav.clone();
+        let wav_handler = move || async move { ([(header::CONTENT_TYPE, "audio/wav")], served) };
+        let mp3_handler = || async { ([(header::CONTENT_TYPE, "audio/mpeg")], vec![0u8; 4096]) };
+        let routes = Router::new()
+            .route("/wav", get(wav_handler))
+            .route("/mp3", get(mp3_handler));
+        let router = with_layers(routes, cors_layer(&[]));
+        let request = |path: &str| {
+            axum::http::Request::get(path)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
         };
 
-        task_tx.send(task).unwrap();
+        let response = router.clone().oneshot(request("/wav")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.len() < wav.len());
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, wav);
+
+        let response = router.oneshot(request("/mp3")).await.unwrap();
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
     }
 
-    // Drop the task sender to signal completion
-    drop(task_tx);
+    #[tokio::test]
+    async fn test_busy_when_queue_is_full() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+
+        // A first request holds the only slot while its worker is blocked
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let first_in_flight = in_flight.clone();
+        let first = tokio::spawn(async move {
+            let _slot = QueueSlot::try_acquire(&first_in_flight, Some(1)).unwrap();
+            tokio::task::spawn_blocking(move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            })
+            .await
+            .unwrap();
+        });
+        started_rx.await.unwrap();
+
+        // A concurrent second request is turned away straight away
+        let second = QueueSlot::try_acquire(&in_flight, Some(1));
+        assert!(second.is_none());
+        let response = SpeechError::Busy.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        release_tx.send(()).unwrap();
+        first.await.unwrap();
+        assert_eq!(in_flight.load(Ordering::Acquire), 0);
+        assert!(QueueSlot::try_acquire(&in_flight, Some(1)).is_some());
+        assert!(QueueSlot::try_acquire(&in_flight, None).is_some());
+    }
 
-    // Windowed parallel processing: allow chunks to process concurrently up to available TTS instances
-    let worker_pool_clone = worker_pool.clone();
-    let total_bytes_clone = total_bytes.clone();
-    let audio_tx_clone = audio_tx.clone();
-    let total_chunks_expected = total_chunks;
-    tokio::spawn(async move {
-        use std::collections::BTreeMap;
+    #[test]
+    fn test_admin_token() {
+        let config = ServerConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..ServerConfig::default()
+        };
+        let bearer = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            headers
+        };
+        assert!(check_admin_token(&bearer("s3cret"), &config).is_ok());
+        for token in ["s3creT", "s3cre", "s3crets", ""] {
+            assert!(
+                check_admin_token(&bearer(token), &config).is_err(),
+                "{token}"
+            );
+        }
+        assert!(check_admin_token(&HeaderMap::new(), &config).is_err());
+        // No configured token locks the admin endpoints
+        assert!(check_admin_token(&bearer("s3cret"), &ServerConfig::default()).is_err());
+    }
 
-        let mut chunk_counter = 0;
-        let mut pending_chunks: BTreeMap<
-            usize,
-            tokio::task::JoinHandle<Result<(usize, Vec<u8>), String>>,
-        > = BTreeMap::new();
-        let mut next_to_send = 0;
-        let mut chunks_processed = 0;
-        let window_size = worker_pool_clone.instance_count(); // Allow chunks to process in parallel up to available TTS instances
+    #[tokio::test]
+    async fn test_synthesize_tokens_route() {
+        use tower::ServiceExt;
 
-        loop {
-            // Receive new tasks while we have window space and tasks are available
-            while pending_chunks.len() < window_size {
-                // Use a non-blocking approach but with proper channel closure detection
-                match task_rx.try_recv() {
-                    Ok(task) => {
-                        let task_id = task.id;
-                        let worker_pool_clone = worker_pool_clone.clone();
-                        let total_bytes_clone = total_bytes_clone.clone();
-                        let request_id_clone = request_id.clone();
-
-                        // Process chunk with dedicated TTS instance (alternates between instances)
-                        let (tts_instance, actual_instance_id) =
-                            worker_pool_clone.get_instance(chunk_counter);
-                        let chunk_text = task.chunk.clone();
-                        let voice = task.voice.clone();
-                        let speed = task.speed;
-                        let initial_silence = task.initial_silence;
-                        let chunk_num = chunk_counter;
-
-                        // Spawn parallel processing
-                        let handle = tokio::spawn(async move {
-                            // Handle empty chunks (completion signals) without TTS processing
-                            if chunk_text.trim().is_empty() {
-                                // Empty chunk - send as completion signal
-                                return Ok((task_id, Vec::new()));
-                            }
-
-                            let result = tokio::task::spawn_blocking(move || {
-                                let audio_result = tts_instance.tts_raw_audio(
-                                    &chunk_text,
-                                    "en-us",
-                                    &voice,
-                                    speed,
-                                    initial_silence,
-                                    Some(&request_id_clone),
-                                    Some(&actual_instance_id),
-                                    Some(chunk_num),
-                                );
-
-                                audio_result
-                                    .map(|audio| audio)
-                                    .map_err(|e| format!("TTS processing error: {:?}", e))
-                            })
-                            .await;
-
-                            // Convert audio to PCM
-                            match result {
-                                Ok(Ok(audio_samples)) => {
-                                    let mut pcm_data = Vec::with_capacity(audio_samples.len() * 2);
-                                    for sample in audio_samples {
-                                        let pcm_sample =
-                                            (sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
-                                        pcm_data.extend_from_slice(&pcm_sample.to_le_bytes());
-                                    }
-                                    total_bytes_clone.fetch_add(
-                                        pcm_data.len(),
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    );
-                                    Ok((task_id, pcm_data))
-                                }
-                                Ok(Err(e)) => Err(e),
-                                Err(e) => Err(format!("Task execution error: {:?}", e)),
-                            }
-                        });
-
-                        pending_chunks.insert(chunk_counter, handle);
-                        chunk_counter += 1;
-                    }
-                    Err(tokio::sync::mpsc::error::TryRecvError::Empty) => {
-                        // No tasks available right now, break inner loop to check completed chunks
-                        break;
-                    }
-                    Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => {
-                        // Channel is closed, no more tasks will come
-                        break;
-                    }
-                }
-            }
+        let config = ServerConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..ServerConfig::default()
+        };
+        let request = |router: Router| {
+            let request = axum::http::Request::post("/v1/debug/synthesize-tokens")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::from(
+                    r#"{"tokens": [0, 50, 83, 0], "voice": "af_sky", "response_format": "pcm"}"#,
+                ))
+                .unwrap();
+            router.oneshot(request)
+        };
 
-            // Check if we can send the next chunk in order
-            if let Some(handle) = pending_chunks.remove(&next_to_send) {
-                if handle.is_finished() {
-                    match handle.await {
-                        Ok(Ok((task_id, pcm_data))) => {
-                            if let Err(_) = audio_tx_clone.send((task_id, pcm_data)) {
-                                break;
-                            }
-                            next_to_send += 1;
-                            chunks_processed += 1;
-                        }
-                        Ok(Err(_e)) => {
-                            // TTS processing error - skip this chunk
-                            next_to_send += 1;
-                            chunks_processed += 1;
-                        }
-                        Err(_e) => {
-                            // Task execution error - skip this chunk
-                            next_to_send += 1;
-                            chunks_processed += 1;
-                        }
-                    }
+        let response = request(mock_server(config.clone()).await).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let pcm = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            pcm.len(),
+            2 * 4 * kokoros::onn::ort_koko::MOCK_SAMPLES_PER_TOKEN
+        );
+
+        // It counts against the queue like any synthesis
+        let busy = ServerConfig {
+            max_queue_depth: Some(0),
+            ..config
+        };
+        let response = request(mock_server(busy).await).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_shared_voices_are_reloaded_once() {
+        let first = TTSKoko::mock(TTSKokoInitConfig::default(), &["af_sky"]);
+        let separate = TTSKoko::mock(TTSKokoInitConfig::default(), &["af_sky"]);
+        let instances = [first.clone(), first.clone(), separate.clone()];
+        assert!(instances[1].shares_voices_with(&first));
+        assert!(!separate.shares_voices_with(&first));
+
+        let reloads = std::sync::atomic::AtomicUsize::new(0);
+        let failing = |broken: &TTSKoko| {
+            let broken = broken.clone();
+            let reloads = &reloads;
+            move |tts: &TTSKoko| {
+                reloads.fetch_add(1, Ordering::SeqCst);
+                if tts.shares_voices_with(&broken) {
+                    Err("unreadable voices file".to_string())
                 } else {
-                    // Not finished yet, put it back
-                    pending_chunks.insert(next_to_send, handle);
+                    Ok(3)
                 }
             }
+        };
 
-            // Check if all chunks have been processed and sent
-            // We're done when we've processed all expected chunks
-            if chunks_processed >= total_chunks_expected {
-                break;
-            }
+        // One reload per voice set, and the instance that failed is named
+        let (voices, failed) = reload_shared_voices(&instances, failing(&separate));
+        assert_eq!(reloads.swap(0, Ordering::SeqCst), 2);
+        assert_eq!(voices, 3);
+        assert_eq!(failed, [(2, "unreadable voices file".to_string())]);
+
+        // Instances sharing a failed set all keep their old voices
+        let (_, failed) = reload_shared_voices(&instances, failing(&first));
+        assert_eq!(reloads.load(Ordering::SeqCst), 2);
+        let failed: Vec<usize> = failed.into_iter().map(|(index, _)| index).collect();
+        assert_eq!(failed, [0, 1]);
+    }
 
-            // Also check if we have no more work to do (fallback safety check)
-            if pending_chunks.is_empty()
-                && task_rx.is_empty()
-                && chunks_processed < total_chunks_expected
-            {
-                // This shouldn't happen, but log it for debugging
-                eprintln!(
-                    "Warning: Early termination detected - processed {} of {} chunks",
-                    chunks_processed, total_chunks_expected
-                );
-                break;
-            }
+    #[tokio::test]
+    async fn test_debug_chunks_resolve_language() {
+        use tower::ServiceExt;
 
-            // Small delay to prevent busy waiting
-            tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
+        let config = ServerConfig {
+            debug_endpoints: true,
+            ..ServerConfig::default()
+        };
+        for (body, expected) in [
+            (r#"{"input": "Hello there."}"#, "en-us"),
+            (r#"{"input": "Hello there.", "voice": "bf_emma"}"#, "en-gb"),
+            (
+                r#"{"input": "Hola.", "voice": "bf_emma", "lang_code": "es"}"#,
+                "es",
+            ),
+        ] {
+            let router = mock_server(config.clone()).await;
+            let response = router
+                .oneshot(post_json("/v1/debug/chunks", body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{body}");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(json["language"], expected, "{body}");
+            assert_eq!(json["chunks"].as_array().unwrap().len(), 1, "{body}");
         }
+    }
 
-        // Wait for any remaining chunks to complete and collect them
-        // This fixes the previous issue where only chunks matching next_to_send exactly were processed
-        let mut remaining_chunks = Vec::new();
+    #[tokio::test]
+    async fn test_busy_routes_answer_503() {
+        use tower::ServiceExt;
 
-        for (chunk_id, handle) in pending_chunks {
-            match handle.await {
-                Ok(Ok((task_id, pcm_data))) => {
-                    // Collect all successful chunks regardless of order
-                    remaining_chunks.push((chunk_id, task_id, pcm_data));
-                }
-                Ok(Err(_e)) => {
-                    // TTS processing error - still count as processed
-                    chunks_processed += 1;
-                }
-                Err(_e) => {
-                    // Task execution error - still count as processed
-                    chunks_processed += 1;
-                }
-            }
+        let router = mock_server(ServerConfig {
+            max_queue_depth: Some(0),
+            ..ServerConfig::default()
+        })
+        .await;
+        let body = r#"{"model": "tts-1", "input": "Hello there."}"#;
+        for path in ["/v1/audio/speech", "/v1/audio/speech/events"] {
+            let response = router.clone().oneshot(post_json(path, body)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE, "{path}");
+            assert_eq!(response.headers()[header::RETRY_AFTER], "1", "{path}");
         }
 
-        // Sort remaining chunks by chunk_id to maintain proper order
-        // This ensures audio continuity even for out-of-order completions
-        remaining_chunks.sort_by_key(|(chunk_id, _, _)| *chunk_id);
+        // Without a limit the same events request goes through
+        let router = mock_server(ServerConfig::default()).await;
+        let response = router
+            .oneshot(post_json("/v1/audio/speech/events", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_events_use_the_fallback_voice() {
+        use tower::ServiceExt;
 
-        // Send all remaining chunks in order, preventing data loss
-        for (chunk_id, task_id, pcm_data) in remaining_chunks {
-            // Only send chunks that are in the expected sequence (>= next_to_send)
-            // This prevents duplicate sends while ensuring no valid chunks are skipped
-            if chunk_id >= next_to_send {
-                let _ = audio_tx_clone.send((task_id, pcm_data));
-                chunks_processed += 1;
+        let router = mock_server(ServerConfig {
+            fallback_voice: Some("am_adam".to_string()),
+            ..ServerConfig::default()
+        })
+        .await;
+        let body = r#"{"model": "tts-1", "input": "Hello there.", "voice": "af_typo"}"#;
+        let response = router
+            .oneshot(post_json("/v1/audio/speech/events", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[VOICE_FALLBACK_HEADER], "af_typo");
+        let events = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let events = String::from_utf8_lossy(&events);
+        assert!(events.contains("event: progress"), "{events}");
+        assert!(events.contains("event: done"), "{events}");
+    }
+
+    #[test]
+    fn test_token_estimate_matches_synthesis_pipeline() {
+        let init_config = TTSKokoInitConfig::default();
+        let phrase = "Hello world.";
+        let expected_tokens = tokenize(&init_config.phonemize(phrase, "en-us").unwrap()).len();
+
+        let estimate = |segments: &[&str], initial_silence| {
+            let segments: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+            estimate_tokens(
+                &planned_tokens(&init_config, &segments, "en-us", initial_silence).unwrap(),
+            )
+        };
+        assert_eq!(
+            estimate(&[phrase], None),
+            DebugTokensResponse {
+                total_tokens: expected_tokens,
+                chunks: 1,
+            }
+        );
+
+        // Each array element is chunked on its own
+        let two = estimate(&[phrase, phrase], None);
+        assert_eq!(two.chunks, 2);
+        assert_eq!(two.total_tokens, 2 * expected_tokens);
+
+        // Silence counts, and an SSML pause is a chunk of its own
+        assert_eq!(
+            estimate(&[phrase], Some(4)).total_tokens,
+            expected_tokens + 4
+        );
+        let ssml = format!(r#"<speak>{phrase}<break time="500ms"/>{phrase}</speak>"#);
+        assert_eq!(
+            estimate(&[&ssml], None),
+            DebugTokensResponse {
+                total_tokens: 2 * expected_tokens + silence_tokens_for_ms(500),
+                chunks: 3,
             }
+        );
+    }
+
+    #[test]
+    fn test_model_inputs_are_padded_tokens() {
+        let init_config = TTSKokoInitConfig::default();
+        let phrase = "Hello world.";
+        let mut expected = vec![0];
+        expected.extend(tokenize(&init_config.phonemize(phrase, "en-us").unwrap()));
+        expected.push(0);
+
+        let segments = [phrase.to_string()];
+        let planned = planned_tokens(&init_config, &segments, "en-us", None).unwrap();
+        let inputs = model_inputs(planned, |_| Ok(256)).unwrap();
+        assert_eq!(
+            inputs,
+            vec![DebugModelInput {
+                text: phrase.to_string(),
+                tokens: expected.clone(),
+                style_length: 256,
+            }]
+        );
+
+        // Initial silence goes between the leading padding and the phonemes
+        let planned = planned_tokens(&init_config, &segments, "en-us", Some(2)).unwrap();
+        let inputs = model_inputs(planned, |count| {
+            assert_eq!(count, expected.len());
+            Ok(256)
+        })
+        .unwrap();
+        let silence = init_config.silence_token;
+        assert_eq!(inputs[0].tokens[..4], [0, silence, silence, expected[1]]);
+        assert_eq!(inputs[0].tokens.len(), expected.len() + 2);
+    }
+
+    #[test]
+    fn test_disable_chunking_plans_one_inference_per_segment() {
+        let request: SpeechRequest =
+            serde_json::from_str(r#"{"model": "tts-1", "input": "Hi.", "disable_chunking": true}"#)
+                .unwrap();
+        assert!(request.disable_chunking);
+
+        let phrase = "Hi there! How are you today? Fine, thanks.";
+        let segments = [phrase.to_string(), "Bye.".to_string()];
+        let planned =
+            plan_whole_segments(&TTSKokoInitConfig::default(), &segments, "en-us", Some(1))
+                .unwrap();
+        assert_eq!(
+            planned,
+            vec![vec![(phrase.to_string(), 1)], vec![("Bye.".to_string(), 0)]]
+        );
+
+        let tight = TTSKokoInitConfig {
+            max_tokens: 5,
+            ..TTSKokoInitConfig::default()
+        };
+        assert!(matches!(
+            plan_whole_segments(&tight, &segments, "en-us", None),
+            Err(PlanError::TooLong(_))
+        ));
+    }
+
+    fn ordered_audio(chunks: &[&[i16]]) -> OrderedAudio {
+        let (tx, rx) = mpsc::channel(chunks.len() + 1);
+        for (id, chunk) in chunks.iter().enumerate() {
+            let pcm = chunk.iter().flat_map(|s| s.to_le_bytes()).collect();
+            tx.try_send((id, pcm)).unwrap();
         }
+        tx.try_send((chunks.len(), Vec::new())).unwrap();
+        rx
+    }
 
-        let _session_time = session.start_time.elapsed();
+    #[tokio::test]
+    async fn test_wav_streaming_placeholder_mode() {
+        let audio = ordered_audio(&[&[0, 16384], &[-16384]]);
+        let parts: Vec<Vec<u8>> = wav_stream(WavStreamingMode::Placeholder, 24000, 32, 16, audio)
+            .collect()
+            .await;
+
+        // Header first, then one part per chunk
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].len(), 44);
+        assert_eq!(&parts[0][4..8], &[0xFF; 4]);
+        assert_eq!(&parts[0][40..44], &[0xFF; 4]);
+        assert_eq!(parts[1].len(), 8);
+        assert_eq!(parts[2].len(), 4);
+        let last = f32::from_le_bytes(parts[2][..4].try_into().unwrap());
+        assert!((last + 0.5).abs() < 1e-3);
+    }
 
-        // Log completion
-        let bytes_transferred = total_bytes.load(std::sync::atomic::Ordering::Relaxed);
-        // Calculate audio duration: 16-bit PCM (2 bytes per sample) at 24000 Hz
-        let total_samples = bytes_transferred / 2;
-        let duration_seconds = total_samples as f64 / 24000.0;
-        let colored_request_id = get_colored_request_id_with_relative(&request_id, request_start);
-        info!(
-            "{} TTS session completed - {} chunks, {} bytes, {:.1}s audio, MP3 stream",
-            colored_request_id, total_chunks, bytes_transferred, duration_seconds
+    #[tokio::test]
+    async fn test_wav_streaming_buffered_mode() {
+        let audio = ordered_audio(&[&[0, 16384], &[-16384]]);
+        let parts: Vec<Vec<u8>> = wav_stream(WavStreamingMode::Buffered, 24000, 32, 16, audio)
+            .collect()
+            .await;
+
+        assert_eq!(parts.len(), 1);
+        let wav = &parts[0];
+        assert_eq!(wav.len(), 44 + 3 * 4);
+        assert_eq!(&wav[4..8], &(36u32 + 12).to_le_bytes());
+        assert_eq!(&wav[40..44], &12u32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_mp3_keep_alive_sends_bytes_before_slow_first_chunk() {
+        let (tx, rx) = mpsc::channel(8);
+        let mut parts = mp3_stream(24000, 64, true, rx);
+
+        // Nothing has been synthesized yet, but silence is already on the wire
+        let first = tokio::time::timeout(std::time::Duration::from_secs(5), parts.next())
+            .await
+            .expect("keep-alive should arrive without waiting for a chunk")
+            .unwrap();
+        assert!(!first.is_empty());
+
+        tx.try_send((0, vec![0u8; 4800])).unwrap();
+        tx.try_send((1, Vec::new())).unwrap();
+        let rest: Vec<Vec<u8>> = parts.collect().await;
+        assert_eq!(rest.len(), 1);
+
+        // Off by default: the stream stays quiet until the first chunk
+        let (_tx, rx) = mpsc::channel(1);
+        let mut parts = mp3_stream(24000, 64, false, rx);
+        let waited =
+            tokio::time::timeout(std::time::Duration::from_millis(100), parts.next()).await;
+        assert!(waited.is_err());
+    }
+
+    #[test]
+    fn test_phonemes_header() {
+        let response = audio_response(
+            "audio/wav",
+            Bytes::new(),
+            "0.000".to_string(),
+            Some("həlˈoʊ\nwˈɜːld"),
+        )
+        .unwrap();
+        let value = response.headers()[PHONEMES_HEADER].as_bytes();
+        assert_eq!(std::str::from_utf8(value).unwrap(), "həlˈoʊ wˈɜːld");
+
+        // Off: no header at all
+        let response =
+            audio_response("audio/wav", Bytes::new(), "0.000".to_string(), None).unwrap();
+        assert!(!response.headers().contains_key(PHONEMES_HEADER));
+
+        // Long phonemes are cut on a character boundary and marked
+        let long = "ə".repeat(MAX_PHONEMES_HEADER_BYTES);
+        let value = phonemes_header_value(&long);
+        let value = std::str::from_utf8(value.as_bytes()).unwrap();
+        assert!(value.len() <= MAX_PHONEMES_HEADER_BYTES + "…".len());
+        assert!(value.ends_with("ə…"));
+    }
+
+    #[test]
+    fn test_unknown_voice_uses_fallback() {
+        let exists = |voice: &str| voice == "af_sky" || voice == "bm_george";
+        let config = ServerConfig {
+            fallback_voice: Some("bm_george".to_string()),
+            ..ServerConfig::default()
+        };
+        let (voice, replaced) = config.voice_or_fallback("af_typo".to_string(), exists);
+        assert_eq!(voice, "bm_george");
+        assert_eq!(replaced.as_deref(), Some("af_typo"));
+
+        let response =
+            audio_response("audio/wav", Bytes::new(), "0.000".to_string(), None).unwrap();
+        let response = with_voice_fallback(response, replaced.as_deref());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[VOICE_FALLBACK_HEADER], "af_typo");
+
+        // Known voices are kept, and without a fallback nothing is replaced
+        assert_eq!(
+            config.voice_or_fallback("af_sky".to_string(), exists),
+            ("af_sky".to_string(), None)
+        );
+        assert_eq!(
+            ServerConfig::default().voice_or_fallback("af_typo".to_string(), exists),
+            ("af_typo".to_string(), None)
         );
+    }
 
-        // Send termination signal
-        let _ = audio_tx.send((total_chunks, vec![])); // Empty data as termination signal
-    });
+    #[test]
+    fn test_content_length_matches_wav_bytes() {
+        let (content_type, wav, _) = encode_audio(
+            AudioFormat::Wav,
+            vec![0.25; 2400],
+            24000,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        let len = wav.len();
+        let response =
+            audio_response(content_type, Bytes::from(wav), "0.100".to_string(), None).unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            len.to_string().as_str()
+        );
+    }
 
-    // No ordering needed - sequential processing guarantees order
+    #[test]
+    fn test_g711_formats() {
+        let request: SpeechRequest = serde_json::from_str(
+            r#"{"model": "tts-1", "input": "Hi.", "response_format": "ulaw"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.response_format, Some(AudioFormat::Ulaw));
+
+        // 0.1s at 24kHz comes out as 800 bytes at 8kHz
+        for (format, expected_type) in [
+            (AudioFormat::Ulaw, "audio/basic"),
+            (AudioFormat::Alaw, "audio/PCMA"),
+        ] {
+            let (content_type, data, _) =
+                encode_audio(format, vec![0.0; 2400], 24000, &EncodeSettings::default()).unwrap();
+            assert_eq!(content_type, expected_type);
+            assert_eq!(data.len(), 800);
+        }
 
-    // Transcode ordered PCM chunks to MP3 per chunk using a fresh encoder (more stable)
-    let (encoded_tx, encoded_rx) = mpsc::unbounded_channel::<Vec<u8>>();
-    tokio::spawn(async move {
-        let sample_rate = 24000u32;
-        while let Some((_chunk_id, data)) = audio_rx.recv().await {
-            if data.is_empty() {
-                break; // end of stream
+        let request: SpeechRequest = serde_json::from_str(
+            r#"{"model": "tts-1", "input": "Hi.", "resample_quality": "sinc"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.resample_quality, Some(audio::ResampleQuality::Sinc));
+        assert!(
+            serde_json::from_str::<SpeechRequest>(
+                r#"{"model": "tts-1", "input": "Hi.", "resample_quality": "nearest"}"#
+            )
+            .is_err()
+        );
+
+        // Only G.711 resamples, so only it takes a resampler
+        let settings = EncodeSettings {
+            resample_quality: Some(audio::ResampleQuality::Sinc),
+            ..EncodeSettings::default()
+        };
+        let settings = settings.validate(AudioFormat::Ulaw).unwrap();
+        let (_, data, _) =
+            encode_audio(AudioFormat::Ulaw, vec![0.0; 2400], 24000, &settings).unwrap();
+        assert_eq!(data.len(), 800);
+        assert!(matches!(
+            settings.validate(AudioFormat::Mp3),
+            Err(SpeechError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_webm_format() {
+        let request: SpeechRequest = serde_json::from_str(
+            r#"{"model": "tts-1", "input": "Hi.", "response_format": "webm"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.response_format, Some(AudioFormat::Webm));
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, "audio/webm".parse().unwrap());
+        assert_eq!(format_from_accept(&headers), Some(AudioFormat::Webm));
+        let clusters = |webm: &[u8]| {
+            webm.windows(4)
+                .filter(|w| *w == [0x1F, 0x43, 0xB6, 0x75])
+                .count()
+        };
+
+        let (content_type, webm, _) = encode_audio(
+            AudioFormat::Webm,
+            vec![0.0; 2400],
+            24000,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(content_type, "audio/webm");
+        assert_eq!(&webm[..4], &[0x1A, 0x45, 0xDF, 0xA3]);
+        assert_eq!(clusters(&webm), 1);
+
+        // Streamed, the header goes out first and every chunk follows as it comes
+        let (tx, rx) = mpsc::channel(8);
+        tx.try_send((0, vec![0; 960])).unwrap();
+        tx.try_send((1, vec![0; 960])).unwrap();
+        tx.try_send((2, Vec::new())).unwrap();
+        let response = streaming_response(
+            AudioFormat::Webm,
+            &EncodeSettings::default(),
+            24000,
+            WavStreamingMode::default(),
+            false,
+            rx,
+        )
+        .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/webm");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..4], &[0x1A, 0x45, 0xDF, 0xA3]);
+        assert!(body.windows(13).any(|w| w == b"A_PCM/INT/LIT"));
+        assert_eq!(clusters(&body), 2);
+
+        assert!(
+            EncodeSettings {
+                bit_depth: Some(24),
+                ..EncodeSettings::default()
             }
-            // Convert PCM i16 bytes back to f32 for encoder API
-            let mut samples_f32 = Vec::with_capacity(data.len() / 2);
-            for b in data.chunks_exact(2) {
-                let s = i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0;
-                samples_f32.push(s);
+            .validate(AudioFormat::Webm)
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_loaded_model_is_listed() {
+        let path = std::env::temp_dir().join(format!("kokoro-test-{}.onnx", std::process::id()));
+        std::fs::write(&path, b"onnx").unwrap();
+        let modified = std::fs::metadata(&path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let models = available_models(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let ids: Vec<&str> = models.iter().map(|model| model.id.as_str()).collect();
+        let loaded = format!("kokoro-test-{}", std::process::id());
+        assert_eq!(ids, ["tts-1", "tts-1-hd", "kokoro", loaded.as_str()]);
+        assert_eq!(models[3].created, modified);
+
+        // A model file named like an alias isn't listed twice
+        assert_eq!(available_models("checkpoints/kokoro.onnx").len(), 3);
+    }
+
+    #[test]
+    fn test_bit_depth() {
+        for (format, bits, header_len) in [
+            (AudioFormat::Wav, 16u16, 44),
+            (AudioFormat::Wav, 24, 44),
+            (AudioFormat::Wav, 32, 44),
+            (AudioFormat::Pcm, 16, 0),
+            (AudioFormat::Pcm, 24, 0),
+            (AudioFormat::Pcm, 32, 0),
+        ] {
+            let settings = EncodeSettings {
+                bit_depth: Some(bits),
+                ..EncodeSettings::default()
             }
-            match tokio::task::spawn_blocking(move || {
-                kokoros::utils::mp3::pcm_to_mp3(&samples_f32, sample_rate)
-            })
-            .await
-            {
-                Ok(Ok(mp3_bytes)) => {
-                    if !mp3_bytes.is_empty() {
-                        let _ = encoded_tx.send(mp3_bytes);
-                    }
-                }
-                _ => {
-                    // skip on error
-                }
+            .validate(format)
+            .unwrap();
+            let (_, data, _) = encode_audio(format, vec![0.25; 2400], 24000, &settings).unwrap();
+            assert_eq!(data.len(), header_len + 2400 * bits as usize / 8);
+            if format == AudioFormat::Wav {
+                let format_code = if bits == 32 { 3u16 } else { 1 };
+                assert_eq!(&data[20..22], &format_code.to_le_bytes());
+                assert_eq!(&data[34..36], &bits.to_le_bytes());
             }
         }
-        // closing encoded_tx ends the stream
-    });
 
-    // Create streaming body from encoded bytes
-    let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(encoded_rx)
-        .map(|data| -> Result<Vec<u8>, std::io::Error> { Ok(data) });
+        // Defaults stay 32-bit float WAV and 16-bit PCM
+        let (_, pcm, _) = encode_audio(
+            AudioFormat::Pcm,
+            vec![0.0; 10],
+            24000,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(pcm.len(), 20);
+
+        for (format, bits) in [(AudioFormat::Wav, 8), (AudioFormat::Mp3, 16)] {
+            let settings = EncodeSettings {
+                bit_depth: Some(bits),
+                ..EncodeSettings::default()
+            };
+            assert!(matches!(
+                settings.validate(format),
+                Err(SpeechError::BadRequest(_))
+            ));
+        }
+    }
 
-    // Convert to HTTP body with explicit ordering
-    let body = Body::from_stream(stream);
+    #[tokio::test]
+    async fn test_big_endian_pcm() {
+        let samples = [0.5, -0.25, 0.001, -1.0];
+        for bits in [16u16, 24, 32] {
+            let little = pcm_bytes(&samples, bits, PcmEndianness::Le).unwrap();
+            let big = pcm_bytes(&samples, bits, PcmEndianness::Be).unwrap();
+            let swapped: Vec<u8> = little
+                .chunks(bits as usize / 8)
+                .flat_map(|sample| sample.iter().rev().copied())
+                .collect();
+            assert_eq!(big, swapped, "{} bits", bits);
+        }
 
-    Ok(Response::builder()
-        .header(header::CONTENT_TYPE, content_type)
-        .header(header::CONNECTION, "keep-alive")
-        .header(header::CACHE_CONTROL, "no-cache")
-        .header("X-Accel-Buffering", "no") // Disable nginx buffering
-        .header("Transfer-Encoding", "chunked") // Enable HTTP chunked transfer encoding
-        .header("Access-Control-Allow-Origin", "*") // CORS for browser clients
-        .body(body)
-        .map_err(|e| {
-            SpeechError::Mp3Conversion(std::io::Error::new(std::io::ErrorKind::Other, e))
-        })?)
-}
+        let request: SpeechRequest = serde_json::from_str(
+            r#"{"model": "tts-1", "input": "Hi.", "response_format": "pcm", "pcm_endianness": "be"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.pcm_endianness, Some(PcmEndianness::Be));
+        let settings = EncodeSettings {
+            pcm_endianness: Some(PcmEndianness::Be),
+            ..EncodeSettings::default()
+        };
+        assert!(settings.validate(AudioFormat::Wav).is_err());
+
+        // Streamed PCM is byte-swapped the same way
+        let (tx, rx) = mpsc::channel(8);
+        tx.try_send((0, 0x1234i16.to_le_bytes().to_vec())).unwrap();
+        tx.try_send((1, Vec::new())).unwrap();
+        let streamed: Vec<Vec<u8>> = pcm_stream(16, 16, PcmEndianness::Be, rx).collect().await;
+        assert_eq!(streamed, vec![0x1234i16.to_be_bytes().to_vec()]);
+    }
 
-async fn handle_voices(
-    State((tts_single, _tts_instances)): State<(TTSKoko, Vec<TTSKoko>)>,
-) -> Json<VoicesResponse> {
-    let voices = tts_single.get_available_voices();
-    Json(VoicesResponse { voices })
-}
+    #[tokio::test]
+    async fn test_24_bit_pcm_stream() {
+        assert_eq!(transport_bits(AudioFormat::Pcm, 24), 24);
+        assert_eq!(transport_bits(AudioFormat::Pcm, 32), 16);
+        assert_eq!(transport_bits(AudioFormat::Mp3, 24), 16);
+
+        // Chunks carry 24-bit samples end to end, three bytes each
+        let samples = [0.5f32, -0.25, 1.0 / 65536.0];
+        let mut chunk = Vec::new();
+        write_samples(&mut chunk, &samples, 24).unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        tx.try_send((0, chunk.clone())).unwrap();
+        tx.try_send((1, chunk.clone())).unwrap();
+        tx.try_send((2, Vec::new())).unwrap();
+        let streamed: Vec<u8> = pcm_stream(24, 24, PcmEndianness::Le, rx)
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        assert_eq!(streamed.len(), samples.len() * 2 * 3);
+        assert_eq!(&streamed[..chunk.len()], chunk.as_slice());
+        // Below 16-bit resolution, yet not lost
+        assert_ne!(&streamed[6..9], &[0, 0, 0]);
+    }
 
-/// Handle /v1/models endpoint
-///
-/// Returns a static list of models for OpenAI API compatibility.
-/// Note: All models use the same underlying Kokoro TTS engine.
-async fn handle_models() -> Json<ModelsResponse> {
-    let models = vec![
-        ModelObject {
-            id: "tts-1".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        ModelObject {
-            id: "tts-1-hd".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        ModelObject {
-            id: "kokoro".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-    ];
+    #[tokio::test]
+    async fn test_wav_header_uses_model_sample_rate() {
+        // A model loaded at a non-default rate is described at that rate
+        let sample_rate = TTSKokoInitConfig {
+            sample_rate: 16000,
+            ..TTSKokoInitConfig::default()
+        }
+        .sample_rate;
+        let header_rate = |wav: &[u8]| u32::from_le_bytes(wav[24..28].try_into().unwrap());
+
+        let (_, wav, _) = encode_audio(
+            AudioFormat::Wav,
+            vec![0.0; 1600],
+            sample_rate,
+            &EncodeSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(header_rate(&wav), 16000);
 
-    Json(ModelsResponse {
-        object: "list".to_string(),
-        data: models,
-    })
-}
+        let (tx, rx) = mpsc::channel(8);
+        tx.try_send((0, Vec::new())).unwrap();
+        let streamed: Vec<u8> = wav_stream(WavStreamingMode::Placeholder, sample_rate, 16, 16, rx)
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        assert_eq!(header_rate(&streamed), 16000);
+    }
 
-async fn handle_model(Path(model_id): Path<String>) -> Result<Json<ModelObject>, StatusCode> {
-    let model = match model_id.as_str() {
-        "tts-1" => ModelObject {
-            id: "tts-1".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        "tts-1-hd" => ModelObject {
-            id: "tts-1-hd".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        "kokoro" => ModelObject {
-            id: "kokoro".to_string(),
-            object: "model".to_string(),
-            created: 1686935002,
-            owned_by: "kokoro".to_string(),
-        },
-        _ => return Err(StatusCode::NOT_FOUND),
-    };
+    #[test]
+    fn test_jingles_bracket_speech() {
+        let write_wav = |name: &str, sample_rate: u32, len: usize| {
+            let mut data = Vec::new();
+            write_samples(&mut data, &vec![0.5; len], 16).unwrap();
+            let mut wav = Vec::new();
+            WavHeader::new(1, sample_rate, 16)
+                .write_sized_header(&mut wav, data.len() as u32)
+                .unwrap();
+            wav.extend(data);
+            let path = std::env::temp_dir().join(format!("{}-{}.wav", name, Uuid::new_v4()));
+            std::fs::write(&path, wav).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let config = ServerConfig {
+            // 0.1s at 48kHz, resampled to 2400 samples
+            prefix_audio_path: Some(write_wav("prefix", 48000, 4800)),
+            suffix_audio_path: Some(write_wav("suffix", 24000, 1200)),
+            ..ServerConfig::default()
+        };
+        let jingles = Jingles::load(&config, 24000).unwrap();
+        let output = jingles.wrap(vec![0.0; 6000]);
+        assert_eq!(output.len(), 2400 + 6000 + 1200);
+        assert!(output[..2400].iter().all(|&s| s > 0.25));
+        assert!(output[2400..8400].iter().all(|&s| s == 0.0));
+
+        for path in [&config.prefix_audio_path, &config.suffix_audio_path] {
+            std::fs::remove_file(path.as_deref().unwrap()).unwrap();
+        }
+        assert!(Jingles::load(&config, 24000).is_err());
+        assert_eq!(Jingles::default().wrap(vec![0.0; 10]).len(), 10);
+    }
 
-    Ok(Json(model))
-}
+    #[tokio::test]
+    async fn test_missing_jingle_fails_server_creation() {
+        let tts = TTSKoko::mock(TTSKokoInitConfig::default(), &["af_sky"]);
+        let config = ServerConfig {
+            prefix_audio_path: Some("/nonexistent/prefix.wav".to_string()),
+            ..ServerConfig::default()
+        };
+        let err = create_server_with_config(vec![tts], config)
+            .await
+            .unwrap_err();
+        assert!(err.contains("/nonexistent/prefix.wav"), "{err}");
+    }
 
-fn get_colored_request_id_with_relative(request_id: &str, start_time: Instant) -> String {
-    kokoros::utils::debug::get_colored_request_id_with_relative(request_id, start_time)
-}
+    #[test]
+    fn test_json_format_carries_base64_wav() {
+        let samples = vec![0.25f32; 2400];
+        let settings = EncodeSettings {
+            bit_depth: Some(16),
+            ..EncodeSettings::default()
+        }
+        .validate(AudioFormat::Json)
+        .unwrap();
+        let (content_type, body, _) =
+            encode_audio(AudioFormat::Json, samples.clone(), 24000, &settings).unwrap();
+        assert_eq!(content_type, "application/json");
+
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["format"], "wav");
+        let wav = BASE64_STANDARD
+            .decode(body["audio"].as_str().unwrap())
+            .unwrap();
+        let (_, expected, _) = encode_audio(AudioFormat::Wav, samples, 24000, &settings).unwrap();
+        assert_eq!(wav, expected);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..12], b"WAVE");
+    }
 
-async fn request_id_middleware(
-    mut request: axum::extract::Request,
-    next: axum::middleware::Next,
-) -> axum::response::Response {
-    let method = request.method().clone();
-    let uri = request.uri().path().to_string();
-    let user_agent = request
-        .headers()
-        .get("user-agent")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("-")
-        .to_string();
+    #[tokio::test]
+    async fn test_audio_duration_header() {
+        use tower::ServiceExt;
+
+        let router = mock_server(ServerConfig::default()).await;
+        let response = router
+            .clone()
+            .oneshot(post_json(
+                "/v1/audio/speech",
+                r#"{"model": "tts-1", "input": "Hello there.", "response_format": "pcm", "stream": false}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let header = response.headers()["x-audio-duration-seconds"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let pcm = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // The body is 16-bit mono samples at the model's rate
+        assert!(!pcm.is_empty());
+        assert_eq!(header, audio_duration_seconds(pcm.len() / 2, 24000));
+
+        // A stream can't know its length up front
+        let response = router
+            .oneshot(post_json(
+                "/v1/audio/speech",
+                r#"{"model": "tts-1", "input": "Hello there.", "response_format": "pcm", "stream": true}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(AUDIO_DURATION_HEADER));
+    }
 
-    let request_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
-    let start = std::time::Instant::now();
-    let colored_request_id = get_colored_request_id_with_relative(&request_id, start);
-    request.extensions_mut().insert((request_id.clone(), start));
+    #[tokio::test]
+    async fn test_one_progress_event_per_chunk() {
+        let (tx, rx) = mpsc::channel(8);
+        // Three chunks of 0.5s each at 24kHz, then the empty terminator
+        for id in 0..3 {
+            tx.try_send((id, vec![0u8; 24000])).unwrap();
+        }
+        tx.try_send((3, Vec::new())).unwrap();
 
-    info!(
-        "{} {} {} \"{}\"",
-        colored_request_id, method, uri, user_agent
-    );
+        let events: Vec<ChunkProgress> = chunk_progress(3, 24000, rx).collect().await;
 
-    let response = next.run(request).await;
-    let _latency = start.elapsed();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].chunk, 3);
+        assert_eq!(events[2].total, 3);
+        assert!((events[2].audio_seconds - 1.5).abs() < 1e-9);
+    }
 
-    let colored_request_id_response = get_colored_request_id_with_relative(&request_id, start);
-    info!("{} {}", colored_request_id_response, response.status());
+    #[tokio::test]
+    async fn test_usage_event_only_when_requested() {
+        let audio_rx = || {
+            let (tx, rx) = mpsc::channel(8);
+            tx.try_send((0, vec![0u8; 24000])).unwrap();
+            tx.try_send((1, vec![0u8; 12000])).unwrap();
+            tx.try_send((2, Vec::new())).unwrap();
+            rx
+        };
 
-    response
+        let events: Vec<SpeechEvent> = speech_events(2, 24000, audio_rx(), true).collect().await;
+        assert_eq!(events.len(), 4);
+        assert_eq!(
+            events[2],
+            SpeechEvent::Usage(StreamUsage {
+                chunks: 2,
+                bytes: 36000,
+                audio_seconds: 0.75,
+            })
+        );
+        assert_eq!(events[3], SpeechEvent::Done { total: 2 });
+
+        let events: Vec<SpeechEvent> = speech_events(2, 24000, audio_rx(), false).collect().await;
+        assert_eq!(events.len(), 3);
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, SpeechEvent::Usage(_)))
+        );
+    }
 }
@@ -5,6 +5,27 @@ use ort::session::builder::SessionBuilder;
 use ort::session::Session;
 use ort::logging::LogLevel;
 
+/// Execution provider this build runs the model on, as named in configs
+#[cfg(feature = "cuda")]
+pub const EXECUTION_PROVIDER: &str = "cuda";
+
+/// Execution provider this build runs the model on, as named in configs
+#[cfg(not(feature = "cuda"))]
+pub const EXECUTION_PROVIDER: &str = "cpu";
+
+/// Resident memory of this process in bytes, read from `/proc/self/status`.
+/// `None` where that isn't available, i.e. anywhere but Linux.
+pub fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
 pub trait OrtBase {
     fn load_model(&mut self, model_path: String) -> Result<(), String> {
         #[cfg(feature = "cuda")]
@@ -29,27 +50,66 @@ pub trait OrtBase {
         }
     }
 
+    /// Log the model's inputs and outputs (at debug level), its execution
+    /// provider and how much resident memory loading it took, to size the
+    /// number of instances by. On CUDA most of the model lives in GPU
+    /// memory, which this doesn't see.
     fn print_info(&self) {
         if let Some(session) = self.sess() {
-            eprintln!("Input names:");
-            for input in &session.inputs {
-                eprintln!("  - {}", input.name);
-            }
-            eprintln!("Output names:");
-            for output in &session.outputs {
-                eprintln!("  - {}", output.name);
-            }
+            let inputs: Vec<&str> = session.inputs.iter().map(|i| i.name.as_str()).collect();
+            let outputs: Vec<&str> = session.outputs.iter().map(|o| o.name.as_str()).collect();
+            tracing::debug!("Model inputs: {:?}, outputs: {:?}", inputs, outputs);
 
-            #[cfg(feature = "cuda")]
-            eprintln!("Configured with: CUDA execution provider");
-
-            #[cfg(not(feature = "cuda"))]
-            eprintln!("Configured with: CPU execution provider");
+            let footprint = match self.load_memory_bytes() {
+                Some(bytes) => format!("{:.1} MiB", mib(bytes)),
+                None => "unknown".to_string(),
+            };
+            let total = match resident_memory_bytes() {
+                Some(bytes) => format!("{:.1} MiB", mib(bytes)),
+                None => "unknown".to_string(),
+            };
+            tracing::info!(
+                "Model on {} execution provider: {} resident memory at load, process total {}",
+                EXECUTION_PROVIDER,
+                footprint,
+                total
+            );
         } else {
-            eprintln!("Session is not initialized.");
+            tracing::warn!("Session is not initialized.");
         }
     }
 
+    /// Growth in resident memory while [`OrtBase::load_model`] ran, when measured
+    fn load_memory_bytes(&self) -> Option<u64> {
+        None
+    }
+
     fn set_sess(&mut self, sess: Session);
     fn sess(&self) -> Option<&Session>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unloaded;
+
+    impl OrtBase for Unloaded {
+        fn set_sess(&mut self, _sess: Session) {}
+
+        fn sess(&self) -> Option<&Session> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_print_info_and_resident_memory() {
+        // Loading a session needs the model file, so only the unloaded path
+        // runs here
+        Unloaded.print_info();
+        assert_eq!(Unloaded.load_memory_bytes(), None);
+
+        #[cfg(target_os = "linux")]
+        assert!(resident_memory_bytes().is_some_and(|bytes| bytes > 0));
+    }
+}
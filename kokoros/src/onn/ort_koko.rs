@@ -10,8 +10,20 @@ use super::ort_base;
 use ort_base::OrtBase;
 use crate::utils::debug::format_debug_prefix;
 
+/// Samples of audio per token a [`OrtKoko::mock`] model returns, 25ms at 24kHz
+#[cfg(feature = "mock")]
+pub const MOCK_SAMPLES_PER_TOKEN: usize = 600;
+
 pub struct OrtKoko {
     sess: Option<Session>,
+    /// See [`OrtBase::load_memory_bytes`]
+    load_memory: Option<u64>,
+    /// Answer with a test tone instead of running `sess`
+    #[cfg(feature = "mock")]
+    mock: bool,
+    /// Inferences a mock model has answered
+    #[cfg(feature = "mock")]
+    mock_inferences: usize,
 }
 impl ort_base::OrtBase for OrtKoko {
     fn set_sess(&mut self, sess: Session) {
@@ -21,14 +33,56 @@ impl ort_base::OrtBase for OrtKoko {
     fn sess(&self) -> Option<&Session> {
         self.sess.as_ref()
     }
+
+    fn load_memory_bytes(&self) -> Option<u64> {
+        self.load_memory
+    }
 }
 impl OrtKoko {
     pub fn new(model_path: String) -> Result<Self, String> {
-        let mut instance = OrtKoko { sess: None };
+        let mut instance = OrtKoko {
+            sess: None,
+            load_memory: None,
+            #[cfg(feature = "mock")]
+            mock: false,
+            #[cfg(feature = "mock")]
+            mock_inferences: 0,
+        };
+        let before = ort_base::resident_memory_bytes();
         instance.load_model(model_path)?;
+        instance.load_memory = before
+            .zip(ort_base::resident_memory_bytes())
+            .map(|(before, after)| after.saturating_sub(before));
         Ok(instance)
     }
 
+    /// A model without an ONNX file whose output is a 440Hz tone,
+    /// [`MOCK_SAMPLES_PER_TOKEN`] samples per token at speed 1, for tests
+    #[cfg(feature = "mock")]
+    pub fn mock() -> Self {
+        OrtKoko {
+            sess: None,
+            load_memory: None,
+            mock: true,
+            mock_inferences: 0,
+        }
+    }
+
+    /// Number of times a [`Self::mock`] model has run
+    #[cfg(feature = "mock")]
+    pub fn mock_inferences(&self) -> usize {
+        self.mock_inferences
+    }
+
+    #[cfg(feature = "mock")]
+    fn mock_audio(tokens: &[Vec<i64>], speed: f32) -> ArrayBase<OwnedRepr<f32>, IxDyn> {
+        let len = (tokens[0].len() * MOCK_SAMPLES_PER_TOKEN) as f32 / speed.max(0.01);
+        let tone: Vec<f32> = (0..len as usize)
+            .map(|i| 0.5 * (i as f32 * 440.0 * std::f32::consts::TAU / 24000.0).sin())
+            .collect();
+        ArrayBase::from_shape_vec(IxDyn(&[tone.len()]), tone).unwrap()
+    }
+
     pub fn infer(
         &mut self,
         tokens: Vec<Vec<i64>>,
@@ -38,6 +92,11 @@ impl OrtKoko {
         instance_id: Option<&str>,
         chunk_number: Option<usize>,
     ) -> Result<ArrayBase<OwnedRepr<f32>, IxDyn>, Box<dyn std::error::Error>> {
+        #[cfg(feature = "mock")]
+        if self.mock {
+            self.mock_inferences += 1;
+            return Ok(Self::mock_audio(&tokens, speed));
+        }
 
         let shape = [tokens.len(), tokens[0].len()];
         let tokens_flat: Vec<i64> = tokens.into_iter().flatten().collect();
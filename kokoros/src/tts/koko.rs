@@ -1,5 +1,13 @@
+use crate::onn::ort_base::OrtBase;
 use crate::onn::ort_koko::{self};
-use crate::tts::tokenize::tokenize;
+use crate::tts::lexicon::{Lexicon, Piece};
+use crate::tts::normalize::{Pipeline, strip_control_chars};
+use crate::tts::phoneme_cache::PhonemeCache;
+use crate::tts::phonemizer::PhonemeRules;
+use crate::tts::ssml;
+use crate::tts::tokenize::{tokenize, tokenize_verbose, unknown_phonemes};
+use crate::tts::vocab::{REVERSE_VOCAB, VOCAB};
+use crate::tts::voices::voice_metadata;
 use crate::utils;
 use crate::utils::debug::format_debug_prefix;
 use lazy_static::lazy_static;
@@ -9,9 +17,10 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 
-use espeak_rs::text_to_phonemes;
+use espeak_rs::{ESpeakError, text_to_phonemes};
 
 // Global mutex to serialize espeak-rs calls to prevent phoneme randomization
 // espeak-rs uses global state internally and is not thread-safe
@@ -19,9 +28,349 @@ lazy_static! {
     static ref ESPEAK_MUTEX: Mutex<()> = Mutex::new(());
 }
 
+/// Default for [`InitConfig::silence_token`], the token repeated before the
+/// text to produce a leading pause.
+///
+/// It is the token of `N`, not a silence symbol, because Kokoros has always
+/// used it and [`SILENCE_TOKEN_MS`] was measured against it; see
+/// [`space_token`] for the vocabulary's own word separator.
+pub fn default_silence_token() -> i64 {
+    VOCAB[&'N'] as i64
+}
+
+/// Token of the space between words in `VOCAB`, an alternative
+/// [`InitConfig::silence_token`] to experiment with
+pub fn space_token() -> i64 {
+    VOCAB[&' '] as i64
+}
+
+/// Approximate pause per silence token. The model emits 600 samples (25ms at
+/// 24kHz) per duration frame and a leading silence token is predicted roughly
+/// one frame long, so this is a conversion factor rather than an exact duration.
+pub const SILENCE_TOKEN_MS: u32 = 25;
+
+/// Number of silence tokens giving roughly `ms` milliseconds of leading pause
+pub fn silence_tokens_for_ms(ms: u32) -> usize {
+    (ms.saturating_add(SILENCE_TOKEN_MS / 2) / SILENCE_TOKEN_MS) as usize
+}
+
+/// Most silence tokens accepted ahead of a chunk, about 5 seconds. Chunks
+/// are planned with room for it within [`InitConfig::max_tokens`].
+pub const MAX_INITIAL_SILENCE_TOKENS: usize = 200;
+
+/// Phonemized ahead of the text in deterministic mode, see
+/// [`InitConfig::deterministic_phonemes`]
+const ESPEAK_RESET_TEXT: &str = ".";
+
+/// Phonemize `text`, consulting (and filling) the phoneme cache when one is configured.
+/// In `deterministic` mode the cache is bypassed and espeak is reset first.
+fn phonemize(
+    cache: Option<&PhonemeCache>,
+    text: &str,
+    lan: &str,
+    deterministic: bool,
+) -> Result<String, ESpeakError> {
+    let cache = cache.filter(|_| !deterministic);
+    if let Some(phonemes) = cache.and_then(|c| c.get(lan, text)) {
+        return Ok(phonemes);
+    }
+    let phonemes = {
+        let _guard = ESPEAK_MUTEX.lock().unwrap();
+        if deterministic {
+            text_to_phonemes(ESPEAK_RESET_TEXT, lan, None, true, false)?;
+        }
+        text_to_phonemes(text, lan, None, true, false)?.join("")
+    };
+    if let Some(cache) = cache {
+        cache.insert(lan, text, &phonemes);
+    }
+    Ok(phonemes)
+}
+
+/// Group sentences into chunks of at most `max_tokens`, measured by `count_tokens`.
+/// Sentences that are too long on their own are split between words.
+fn split_text_into_chunks(
+    text: &str,
+    max_tokens: usize,
+    count_tokens: impl Fn(&str) -> usize,
+) -> Vec<String> {
+    let mut chunks = Vec::new();
+
+    // First split by sentences - using common sentence ending punctuation
+    let sentences: Vec<&str> = text
+        .split(|c| c == '.' || c == '?' || c == '!' || c == ';')
+        .filter(|s| !s.trim().is_empty())
+        .collect();
+
+    let mut current_chunk = String::new();
+
+    for sentence in sentences {
+        // Clean up the sentence and add back punctuation
+        let sentence = format!("{}.", sentence.trim());
+
+        // Convert to phonemes to check token count
+        let token_count = count_tokens(&sentence);
+
+        if token_count > max_tokens {
+            // If single sentence is too long, split by words
+            let words: Vec<&str> = sentence.split_whitespace().collect();
+            let mut word_chunk = String::new();
+
+            for word in words {
+                let test_chunk = if word_chunk.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", word_chunk, word)
+                };
+
+                let test_tokens = count_tokens(&test_chunk);
+
+                if test_tokens > max_tokens {
+                    if !word_chunk.is_empty() {
+                        chunks.push(word_chunk);
+                    }
+                    word_chunk = word.to_string();
+                } else {
+                    word_chunk = test_chunk;
+                }
+            }
+
+            if !word_chunk.is_empty() {
+                chunks.push(word_chunk);
+            }
+        } else if !current_chunk.is_empty() {
+            // Try to append to current chunk
+            let test_text = format!("{} {}", current_chunk, sentence);
+            let test_tokens = count_tokens(&test_text);
+
+            if test_tokens > max_tokens {
+                // If combining would exceed limit, start new chunk
+                chunks.push(current_chunk);
+                current_chunk = sentence;
+            } else {
+                current_chunk = test_text;
+            }
+        } else {
+            current_chunk = sentence;
+        }
+    }
+
+    // Add the last chunk if not empty
+    if !current_chunk.is_empty() {
+        chunks.push(current_chunk);
+    }
+
+    chunks
+}
+
+/// Punctuation that ends a sentence for [`split_text_into_speech_chunks`]
+pub const SENTENCE_PUNCTUATION: &[char] = &['.', '!', '?'];
+
+/// Punctuation long sentences are split at for [`split_text_into_speech_chunks`]
+pub const CLAUSE_PUNCTUATION: &[char] = &[',', ';', ':'];
+
+/// Chunker behind [`TTSKoko::split_text_into_speech_chunks`], usable without a loaded model
+pub fn split_text_into_speech_chunks(text: &str, max_words: usize) -> Vec<String> {
+    split_text_into_speech_chunks_with(text, max_words, SENTENCE_PUNCTUATION, CLAUSE_PUNCTUATION)
+}
+
+/// [`split_text_into_speech_chunks`] with its own sentence and clause
+/// punctuation, for languages that don't use the English marks
+pub fn split_text_into_speech_chunks_with(
+    text: &str,
+    max_words: usize,
+    sentence_punctuation: &[char],
+    clause_punctuation: &[char],
+) -> Vec<String> {
+    let mut chunks = Vec::new();
+
+    // Split by sentence-ending punctuation first
+    let sentences: Vec<&str> = text
+        .split(sentence_punctuation)
+        .filter(|s| !s.trim().is_empty())
+        .collect();
+
+    for sentence in sentences {
+        let sentence = sentence.trim();
+        if sentence.is_empty() {
+            continue;
+        }
+
+        // Count words in this sentence
+        let words: Vec<&str> = sentence.split_whitespace().collect();
+        let word_count = words.len();
+
+        if word_count <= max_words {
+            // Small sentence - add as complete chunk (preserve original punctuation)
+            chunks.push(format!("{}.", sentence));
+        } else {
+            // Large sentence - split by punctuation marks while preserving them
+            let mut sub_clauses = Vec::new();
+            let mut current_pos = 0;
+
+            for (i, ch) in sentence.char_indices() {
+                if clause_punctuation.contains(&ch) {
+                    if i > current_pos {
+                        let clause_with_punct = format!("{}{}", &sentence[current_pos..i], ch);
+                        sub_clauses.push(clause_with_punct);
+                    }
+                    current_pos = i + ch.len_utf8();
+                }
+            }
+
+            // Add remaining text
+            if current_pos < sentence.len() {
+                sub_clauses.push(sentence[current_pos..].to_string());
+            }
+
+            let sub_clauses: Vec<&str> = sub_clauses
+                .iter()
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            let mut current_chunk = String::new();
+            let mut current_word_count = 0;
+
+            for clause in sub_clauses {
+                let clause = clause.trim();
+                let clause_words: Vec<&str> = clause.split_whitespace().collect();
+                let clause_word_count = clause_words.len();
+
+                if current_word_count + clause_word_count <= max_words {
+                    // Add clause to current chunk (preserve original punctuation)
+                    if current_chunk.is_empty() {
+                        current_chunk = clause.to_string();
+                    } else {
+                        current_chunk = format!("{} {}", current_chunk, clause);
+                    }
+                    current_word_count += clause_word_count;
+                } else {
+                    // Start new chunk (preserve original punctuation)
+                    if !current_chunk.is_empty() {
+                        chunks.push(current_chunk);
+                    }
+                    current_chunk = clause.to_string();
+                    current_word_count = clause_word_count;
+                }
+            }
+
+            // Add final chunk (preserve original punctuation)
+            if !current_chunk.is_empty() {
+                chunks.push(current_chunk);
+            }
+        }
+    }
+
+    // If no sentences found, fall back to word-based chunking
+    if chunks.is_empty() {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut current_chunk = String::new();
+        let mut current_word_count = 0;
+
+        for word in words {
+            if current_word_count + 1 <= max_words {
+                if current_chunk.is_empty() {
+                    current_chunk = word.to_string();
+                } else {
+                    current_chunk = format!("{} {}", current_chunk, word);
+                }
+                current_word_count += 1;
+            } else {
+                if !current_chunk.is_empty() {
+                    chunks.push(current_chunk);
+                }
+                current_chunk = word.to_string();
+                current_word_count = 1;
+            }
+        }
+
+        if !current_chunk.is_empty() {
+            chunks.push(current_chunk);
+        }
+    }
+
+    chunks
+}
+
 // Flag to ensure voice styles are only logged once
 static VOICES_LOGGED: AtomicBool = AtomicBool::new(false);
 
+/// When characters dropped by tokenizing were last warned about
+static DROPPED_PHONEMES_WARNED: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Least time between two warnings about dropped characters, so a voice or
+/// language that keeps hitting the same gap in `VOCAB` doesn't flood the log
+const DROPPED_PHONEMES_WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Warn that tokenizing a chunk dropped `dropped`, at most once per
+/// [`DROPPED_PHONEMES_WARN_INTERVAL`]
+fn warn_dropped_phonemes(debug_prefix: &str, chunk_info: &str, dropped: &[char]) {
+    if dropped.is_empty() {
+        return;
+    }
+    let mut last = DROPPED_PHONEMES_WARNED.lock().unwrap();
+    if last.is_some_and(|at| at.elapsed() < DROPPED_PHONEMES_WARN_INTERVAL) {
+        return;
+    }
+    *last = Some(Instant::now());
+    // Every dropped character is unknown, so this just dedupes them
+    let unique = unknown_phonemes(&dropped.iter().collect::<String>());
+    tracing::warn!(
+        "{} {}dropped {} phoneme(s) not in the vocabulary: {:?}",
+        debug_prefix,
+        chunk_info,
+        dropped.len(),
+        unique
+    );
+}
+
+/// Each voice holds one style vector per token count (511 entries), so inputs
+/// longer than this can't be given a style
+pub const MAX_STYLE_TOKENS: usize = 510;
+
+/// Default for [`InitConfig::max_tokens`]
+pub const DEFAULT_MAX_TOKENS: usize = 500;
+
+/// `tokens` between the `0` the model expects at either end of its input
+pub fn pad_tokens(tokens: &[i64]) -> Vec<i64> {
+    let mut padded = Vec::with_capacity(tokens.len() + 2);
+    padded.push(0);
+    padded.extend_from_slice(tokens);
+    padded.push(0);
+    padded
+}
+
+/// Check that raw token ids can be fed to the model as-is
+pub fn validate_tokens(tokens: &[i64]) -> Result<(), String> {
+    if tokens.is_empty() {
+        return Err("token list is empty".to_string());
+    }
+    if tokens.len() > MAX_STYLE_TOKENS {
+        return Err(format!(
+            "too many tokens: {} (max {})",
+            tokens.len(),
+            MAX_STYLE_TOKENS
+        ));
+    }
+    if let Some(bad) = tokens
+        .iter()
+        .find(|&&t| t < 0 || !REVERSE_VOCAB.contains_key(&(t as usize)))
+    {
+        return Err(format!("token {} is not in the vocabulary", bad));
+    }
+    Ok(())
+}
+
+/// Request, instance and chunk a synthesis call logs under, all unset by
+/// default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogContext<'a> {
+    pub request_id: Option<&'a str>,
+    pub instance_id: Option<&'a str>,
+    pub chunk_number: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TTSOpts<'a> {
     pub txt: &'a str,
@@ -38,17 +387,59 @@ pub struct TTSKoko {
     #[allow(dead_code)]
     model_path: String,
     model: Arc<Mutex<ort_koko::OrtKoko>>,
-    styles: HashMap<String, Vec<[[f32; 256]; 1]>>,
+    styles: Arc<VoiceStyles>,
     init_config: InitConfig,
 }
 
+type StyleMap = HashMap<String, Vec<[[f32; 256]; 1]>>;
+
+/// Voice styles merged from one or more npz files, which can be re-read at
+/// runtime. Readers take a snapshot of the map, so a reload never changes the
+/// voices a request already started with.
+struct VoiceStyles {
+    paths: Vec<String>,
+    current: RwLock<Arc<StyleMap>>,
+}
+
+impl VoiceStyles {
+    fn load(paths: &[&str]) -> Result<Self, Box<dyn std::error::Error>> {
+        let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+        let map = Self::read(&paths)?;
+        Ok(Self {
+            paths,
+            current: RwLock::new(Arc::new(map)),
+        })
+    }
+
+    fn read(paths: &[String]) -> Result<StyleMap, Box<dyn std::error::Error>> {
+        let mut map = HashMap::new();
+        for path in paths {
+            TTSKoko::load_voices(&mut map, path)?;
+        }
+        Ok(map)
+    }
+
+    fn snapshot(&self) -> Arc<StyleMap> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Re-read every file and swap the new map in, returning its voice count.
+    /// On error the current voices stay in place.
+    fn reload(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let map = Self::read(&self.paths)?;
+        let count = map.len();
+        *self.current.write().unwrap() = Arc::new(map);
+        Ok(count)
+    }
+}
+
 /// Parallel TTS with multiple ONNX instances for true concurrency
 #[derive(Clone)]
 pub struct TTSKokoParallel {
     #[allow(dead_code)]
     model_path: String,
     models: Vec<Arc<Mutex<ort_koko::OrtKoko>>>,
-    styles: HashMap<String, Vec<[[f32; 256]; 1]>>,
+    styles: Arc<VoiceStyles>,
     init_config: InitConfig,
 }
 
@@ -57,6 +448,36 @@ pub struct InitConfig {
     pub model_url: String,
     pub voices_url: String,
     pub sample_rate: u32,
+    /// Shared text → phonemes cache consulted before calling espeak
+    pub phoneme_cache: Option<Arc<PhonemeCache>>,
+    /// User pronunciations applied to the text before espeak sees it
+    pub lexicon: Option<Arc<Lexicon>>,
+    /// Replacements applied to espeak's output, the kokoro fix-ups by default
+    pub phoneme_rules: Arc<PhonemeRules>,
+    /// Text transforms applied by [`TTSKoko::preprocess`] before chunking
+    pub preprocessing: Pipeline,
+    /// Largest chunk, in tokens, that synthesis feeds the model at once. The
+    /// default of 500 leaves margin under [`MAX_STYLE_TOKENS`] for the two
+    /// padding tokens and some initial silence.
+    pub max_tokens: usize,
+    /// Make identical text phonemize identically regardless of what came
+    /// before it. espeak-ng keeps clause and voice state between calls, so
+    /// a call can be influenced by the previous one (say, text in another
+    /// language, or text that stopped mid-clause). In this mode every call
+    /// first phonemizes a lone full stop under the same lock, which runs
+    /// espeak through a sentence boundary in the requested voice, and the
+    /// phoneme cache is skipped since it may hold output from before.
+    ///
+    /// This doesn't cover espeak itself changing: different espeak-ng
+    /// versions or `espeak-ng-data` dictionaries still phonemize differently.
+    pub deterministic_phonemes: bool,
+    /// Download `voices_url` over the voices file even when it exists, to
+    /// pick up newer voices. Without it the file is only downloaded when
+    /// missing.
+    pub force_voices_download: bool,
+    /// Token repeated before the text for initial silence, see
+    /// [`default_silence_token`]
+    pub silence_token: i64,
 }
 
 impl Default for InitConfig {
@@ -65,245 +486,382 @@ impl Default for InitConfig {
             model_url: "https://github.com/thewh1teagle/kokoro-onnx/releases/download/model-files-v1.0/kokoro-v1.0.onnx".into(),
             voices_url: "https://github.com/thewh1teagle/kokoro-onnx/releases/download/model-files-v1.0/voices-v1.0.bin".into(),
             sample_rate: 24000,
+            phoneme_cache: None,
+            lexicon: None,
+            phoneme_rules: Arc::new(PhonemeRules::default()),
+            preprocessing: Pipeline::default(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            deterministic_phonemes: false,
+            force_voices_download: false,
+            silence_token: default_silence_token(),
         }
     }
 }
 
+impl InitConfig {
+    /// Put `count` of [`InitConfig::silence_token`] in front of `tokens`
+    pub fn prepend_silence(&self, tokens: &mut Vec<i64>, count: usize) {
+        tokens.splice(0..0, std::iter::repeat_n(self.silence_token, count));
+    }
+
+    /// Run the configured preprocessing pipeline over raw input text, after
+    /// removing control characters, which are never part of the speech
+    pub fn preprocess(&self, text: &str) -> String {
+        self.preprocess_with(text, None)
+    }
+
+    /// [`Self::preprocess`], with `keep_acronyms` overriding the pipeline's
+    /// own setting when given
+    pub fn preprocess_with(&self, text: &str, keep_acronyms: Option<bool>) -> String {
+        let keep_acronyms = keep_acronyms.unwrap_or(self.preprocessing.keep_acronyms());
+        self.preprocessing
+            .run_with(&strip_control_chars(text), keep_acronyms)
+    }
+
+    /// Phonemize text the same way synthesis does, applying the lexicon and
+    /// going through the phoneme cache when those are configured. The
+    /// phoneme rules rewrite what espeak returns, lexicon IPA is kept as is.
+    pub fn phonemize(&self, text: &str, lan: &str) -> Result<String, ESpeakError> {
+        // Text that skipped preprocessing still can't hand espeak control characters
+        let text = &strip_control_chars(text);
+        let espeak = |text: &str| {
+            phonemize(
+                self.phoneme_cache.as_deref(),
+                text,
+                lan,
+                self.deterministic_phonemes,
+            )
+            .map(|phonemes| self.phoneme_rules.apply(&phonemes))
+        };
+        let Some(lexicon) = &self.lexicon else {
+            return espeak(text);
+        };
+
+        // espeak sees the text between lexicon phonemes piece by piece, so
+        // spacing between the pieces is restored from the text
+        let mut phonemes = String::new();
+        let mut space_before = false;
+        for piece in lexicon.apply(text) {
+            let (spoken, space_after) = match piece {
+                Piece::Text(text) => {
+                    space_before |= text.starts_with(char::is_whitespace);
+                    let mut spoken = espeak(&text)?;
+                    // espeak-rs ends every clause with a full stop, which
+                    // only belongs where the text has one
+                    if spoken.ends_with('.') && !text.trim_end().ends_with(['.', '!', '?']) {
+                        spoken.pop();
+                    }
+                    (
+                        spoken.trim().to_string(),
+                        text.ends_with(char::is_whitespace),
+                    )
+                }
+                Piece::Phonemes(spoken) => (spoken, false),
+            };
+            if space_before && !phonemes.is_empty() && !spoken.is_empty() {
+                phonemes.push(' ');
+            }
+            phonemes.push_str(&spoken);
+            space_before = space_after;
+        }
+        Ok(phonemes)
+    }
+
+    /// Chunk text for synthesis so each chunk fits within `max_tokens`.
+    /// Needs no model, so it can be used to inspect what synthesis would do.
+    pub fn split_text_into_chunks(&self, text: &str) -> Vec<String> {
+        self.split_within(text, self.max_tokens)
+    }
+
+    fn split_within(&self, text: &str, max_tokens: usize) -> Vec<String> {
+        split_text_into_chunks(text, max_tokens, |chunk| {
+            tokenize(&self.phonemize(chunk, "en").unwrap_or_default()).len()
+        })
+    }
+
+    /// Chunks to synthesize, each with the silence tokens to put before it.
+    /// Plain text gets `initial_silence` on every chunk. [`ssml`] input gets
+    /// it on the first chunk only, and each of its pauses becomes a chunk of
+    /// nothing but silence tokens. Text is split short enough that a chunk
+    /// and its silence stay within `max_tokens`.
+    pub fn plan_chunks(&self, text: &str, initial_silence: Option<usize>) -> Vec<(String, usize)> {
+        self.plan_split_chunks(text, initial_silence, |text, max_tokens| {
+            self.split_within(text, max_tokens)
+        })
+    }
+
+    /// Like [`Self::plan_chunks`] without splitting the text, so plain text
+    /// is synthesized in one inference (SSML pauses stay chunks of their
+    /// own). Fails when a chunk, phonemized for `lan`, doesn't fit within
+    /// `max_tokens`.
+    pub fn plan_whole(
+        &self,
+        text: &str,
+        lan: &str,
+        initial_silence: Option<usize>,
+    ) -> Result<Vec<(String, usize)>, PlanError> {
+        let planned = self.plan_split_chunks(text, initial_silence, |text, _| {
+            (!text.trim().is_empty())
+                .then(|| text.trim().to_string())
+                .into_iter()
+                .collect()
+        });
+        for (chunk, silence) in &planned {
+            let tokens = tokenize(&self.phonemize(chunk, lan)?).len() + silence;
+            if tokens > self.max_tokens {
+                return Err(PlanError::TooLong(format!(
+                    "input is {} tokens, over the {} token limit of one inference; \
+                     enable chunking or shorten it",
+                    tokens, self.max_tokens
+                )));
+            }
+        }
+        Ok(planned)
+    }
+
+    /// [`Self::plan_chunks`] with `split` turning text into chunks of at
+    /// most the tokens it is given
+    fn plan_split_chunks(
+        &self,
+        text: &str,
+        initial_silence: Option<usize>,
+        split: impl Fn(&str, usize) -> Vec<String>,
+    ) -> Vec<(String, usize)> {
+        let initial_silence = initial_silence.unwrap_or(0);
+        let text_tokens = self.max_tokens.saturating_sub(initial_silence).max(1);
+        if !ssml::is_ssml(text) {
+            return split(text, text_tokens)
+                .into_iter()
+                .map(|chunk| (chunk, initial_silence))
+                .collect();
+        }
+
+        let mut planned = Vec::new();
+        for segment in ssml::parse(text) {
+            if segment.pause_ms > 0 {
+                planned.push((String::new(), silence_tokens_for_ms(segment.pause_ms)));
+            }
+            for chunk in split(&segment.text, text_tokens) {
+                planned.push((chunk, 0));
+            }
+        }
+        match planned.first_mut() {
+            // A long pause has no room left, so the silence goes before it
+            Some((chunk, silence))
+                if chunk.is_empty() && *silence + initial_silence > self.max_tokens =>
+            {
+                planned.insert(0, (String::new(), initial_silence));
+            }
+            Some((_, silence)) => *silence += initial_silence,
+            None => {}
+        }
+        planned
+    }
+}
+
+/// Why [`InitConfig::plan_whole`] couldn't plan a text
+#[derive(Debug)]
+pub enum PlanError {
+    /// A chunk and its silence are over `max_tokens`
+    TooLong(String),
+    /// espeak failed on a chunk
+    Phonemize(ESpeakError),
+}
+
+impl From<ESpeakError> for PlanError {
+    fn from(error: ESpeakError) -> Self {
+        PlanError::Phonemize(error)
+    }
+}
+
+impl std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::TooLong(message) => f.write_str(message),
+            PlanError::Phonemize(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// Download `cfg.voices_url` to `voices_path` when the file is missing, or
+/// regardless when [`InitConfig::force_voices_download`] is set
+async fn fetch_voices(
+    cfg: &InitConfig,
+    voices_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if cfg.force_voices_download || !Path::new(voices_path).exists() {
+        utils::fileio::download_file_from_url(&cfg.voices_url, voices_path).await?;
+    }
+    Ok(())
+}
+
+/// Error for a chunk the model failed on, with what it was fed: the
+/// phonemes, how many tokens they became and which characters tokenizing
+/// dropped, which is usually espeak emitting a symbol missing from `VOCAB`
+fn inference_error(
+    error: impl std::fmt::Debug,
+    chunk: &str,
+    phonemes: &str,
+    token_count: usize,
+) -> std::io::Error {
+    let dropped = unknown_phonemes(phonemes);
+    let dropped = if dropped.is_empty() {
+        "none".to_string()
+    } else {
+        format!("{:?}", dropped)
+    };
+    std::io::Error::other(format!(
+        "Chunk processing failed: {:?} (text: {:?}, phonemes: {:?}, {} tokens, dropped out-of-vocab chars: {})",
+        error, chunk, phonemes, token_count, dropped
+    ))
+}
+
 impl TTSKoko {
     pub async fn new(model_path: &str, voices_path: &str) -> Self {
         Self::from_config(model_path, voices_path, InitConfig::default()).await
     }
 
     pub async fn from_config(model_path: &str, voices_path: &str, cfg: InitConfig) -> Self {
+        Self::from_config_with_voices(model_path, &[voices_path], cfg).await
+    }
+
+    /// Like [`Self::from_config`], merging the voices of several files.
+    /// A voice in a later file replaces a same-named one from an earlier file.
+    /// Only the first file is downloaded from `voices_url` when missing.
+    pub async fn from_config_with_voices(
+        model_path: &str,
+        voices_paths: &[&str],
+        cfg: InitConfig,
+    ) -> Self {
         if !Path::new(model_path).exists() {
             utils::fileio::download_file_from_url(cfg.model_url.as_str(), model_path)
                 .await
                 .expect("download model failed.");
         }
 
-        if !Path::new(voices_path).exists() {
-            utils::fileio::download_file_from_url(cfg.voices_url.as_str(), voices_path)
+        if let Some(voices_path) = voices_paths.first() {
+            fetch_voices(&cfg, voices_path)
                 .await
                 .expect("download voices data file failed.");
         }
 
-        let model = Arc::new(Mutex::new(
-            ort_koko::OrtKoko::new(model_path.to_string())
-                .expect("Failed to create Kokoro TTS model"),
-        ));
-        // TODO: if(not streaming) { model.print_info(); }
-        // model.print_info();
+        let model = ort_koko::OrtKoko::new(model_path.to_string())
+            .expect("Failed to create Kokoro TTS model");
+        model.print_info();
+        let model = Arc::new(Mutex::new(model));
 
-        let styles = Self::load_voices(voices_path);
+        let styles = VoiceStyles::load(voices_paths).expect("load voices failed.");
+        Self::log_voices(&styles.snapshot());
 
         TTSKoko {
             model_path: model_path.to_string(),
             model,
-            styles,
+            styles: Arc::new(styles),
             init_config: cfg,
         }
     }
 
-    fn split_text_into_chunks(&self, text: &str, max_tokens: usize) -> Vec<String> {
-        let mut chunks = Vec::new();
-
-        // First split by sentences - using common sentence ending punctuation
-        let sentences: Vec<&str> = text
-            .split(|c| c == '.' || c == '?' || c == '!' || c == ';')
-            .filter(|s| !s.trim().is_empty())
-            .collect();
-
-        let mut current_chunk = String::new();
-
-        for sentence in sentences {
-            // Clean up the sentence and add back punctuation
-            let sentence = format!("{}.", sentence.trim());
-
-            // Convert to phonemes to check token count
-            let sentence_phonemes = {
-                let _guard = ESPEAK_MUTEX.lock().unwrap();
-                text_to_phonemes(&sentence, "en", None, true, false)
-                    .unwrap_or_default()
-                    .join("")
-            };
-            let token_count = tokenize(&sentence_phonemes).len();
-
-            if token_count > max_tokens {
-                // If single sentence is too long, split by words
-                let words: Vec<&str> = sentence.split_whitespace().collect();
-                let mut word_chunk = String::new();
-
-                for word in words {
-                    let test_chunk = if word_chunk.is_empty() {
-                        word.to_string()
-                    } else {
-                        format!("{} {}", word_chunk, word)
-                    };
-
-                    let test_phonemes = {
-                        let _guard = ESPEAK_MUTEX.lock().unwrap();
-                        text_to_phonemes(&test_chunk, "en", None, true, false)
-                            .unwrap_or_default()
-                            .join("")
-                    };
-                    let test_tokens = tokenize(&test_phonemes).len();
-
-                    if test_tokens > max_tokens {
-                        if !word_chunk.is_empty() {
-                            chunks.push(word_chunk);
-                        }
-                        word_chunk = word.to_string();
-                    } else {
-                        word_chunk = test_chunk;
-                    }
-                }
+    /// Another instance with a model session of its own, loaded from the
+    /// same file, that shares this one's voices: they are read once, and
+    /// [`Self::reload_voices`] on either instance reloads both.
+    pub fn new_instance_sharing_voices(&self) -> Self {
+        let model = ort_koko::OrtKoko::new(self.model_path.clone())
+            .expect("Failed to create Kokoro TTS model");
 
-                if !word_chunk.is_empty() {
-                    chunks.push(word_chunk);
-                }
-            } else if !current_chunk.is_empty() {
-                // Try to append to current chunk
-                let test_text = format!("{} {}", current_chunk, sentence);
-                let test_phonemes = {
-                    let _guard = ESPEAK_MUTEX.lock().unwrap();
-                    text_to_phonemes(&test_text, "en", None, true, false)
-                        .unwrap_or_default()
-                        .join("")
-                };
-                let test_tokens = tokenize(&test_phonemes).len();
-
-                if test_tokens > max_tokens {
-                    // If combining would exceed limit, start new chunk
-                    chunks.push(current_chunk);
-                    current_chunk = sentence;
-                } else {
-                    current_chunk = test_text;
-                }
-            } else {
-                current_chunk = sentence;
-            }
-        }
-
-        // Add the last chunk if not empty
-        if !current_chunk.is_empty() {
-            chunks.push(current_chunk);
+        TTSKoko {
+            model_path: self.model_path.clone(),
+            model: Arc::new(Mutex::new(model)),
+            styles: Arc::clone(&self.styles),
+            init_config: self.init_config.clone(),
         }
-
-        chunks
     }
 
-    /// Smart word-based chunking for async streaming
-    /// Creates chunks based on natural speech boundaries using word count and punctuation
-    pub fn split_text_into_speech_chunks(&self, text: &str, max_words: usize) -> Vec<String> {
-        let mut chunks = Vec::new();
+    /// Whether this instance and `other` share voices, so reloading them
+    /// on one reloads them on the other
+    pub fn shares_voices_with(&self, other: &TTSKoko) -> bool {
+        Arc::ptr_eq(&self.styles, &other.styles)
+    }
 
-        // Split by sentence-ending punctuation first
-        let sentences: Vec<&str> = text
-            .split(|c| c == '.' || c == '!' || c == '?')
-            .filter(|s| !s.trim().is_empty())
+    /// An instance that needs no model or voices files, for testing what
+    /// surrounds synthesis: every chunk comes out as a tone whose length
+    /// follows its token count, see [`ort_koko::OrtKoko::mock`]. `voices`
+    /// all get the same flat style. Reloading voices leaves none.
+    #[cfg(feature = "mock")]
+    pub fn mock(cfg: InitConfig, voices: &[&str]) -> Self {
+        let style = vec![[[0.0; 256]; 1]; MAX_STYLE_TOKENS + 1];
+        let map: StyleMap = voices
+            .iter()
+            .map(|voice| (voice.to_string(), style.clone()))
             .collect();
+        TTSKoko {
+            model_path: "mock.onnx".to_string(),
+            model: Arc::new(Mutex::new(ort_koko::OrtKoko::mock())),
+            styles: Arc::new(VoiceStyles {
+                paths: Vec::new(),
+                current: RwLock::new(Arc::new(map)),
+            }),
+            init_config: cfg,
+        }
+    }
 
-        for sentence in sentences {
-            let sentence = sentence.trim();
-            if sentence.is_empty() {
-                continue;
-            }
+    /// Inferences run by a [`Self::mock`] instance and its clones
+    #[cfg(feature = "mock")]
+    pub fn mock_inferences(&self) -> usize {
+        self.lock_model().mock_inferences()
+    }
 
-            // Count words in this sentence
-            let words: Vec<&str> = sentence.split_whitespace().collect();
-            let word_count = words.len();
+    /// Path of the ONNX model file this instance loaded
+    pub fn model_path(&self) -> &str {
+        &self.model_path
+    }
 
-            if word_count <= max_words {
-                // Small sentence - add as complete chunk (preserve original punctuation)
-                chunks.push(format!("{}.", sentence));
-            } else {
-                // Large sentence - split by punctuation marks while preserving them
-                let mut sub_clauses = Vec::new();
-                let mut current_pos = 0;
-
-                for (i, ch) in sentence.char_indices() {
-                    if ch == ',' || ch == ';' || ch == ':' {
-                        if i > current_pos {
-                            let clause_with_punct = format!("{}{}", &sentence[current_pos..i], ch);
-                            sub_clauses.push(clause_with_punct);
-                        }
-                        current_pos = i + 1;
-                    }
-                }
+    /// Settings this instance was created with
+    pub fn init_config(&self) -> &InitConfig {
+        &self.init_config
+    }
 
-                // Add remaining text
-                if current_pos < sentence.len() {
-                    sub_clauses.push(sentence[current_pos..].to_string());
-                }
+    /// This instance with [`InitConfig::deterministic_phonemes`] switched
+    /// on or off. The clone shares the model and voices.
+    pub fn with_deterministic_phonemes(mut self, deterministic: bool) -> Self {
+        self.init_config.deterministic_phonemes = deterministic;
+        self
+    }
 
-                let sub_clauses: Vec<&str> = sub_clauses
-                    .iter()
-                    .map(|s| s.trim())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-
-                let mut current_chunk = String::new();
-                let mut current_word_count = 0;
-
-                for clause in sub_clauses {
-                    let clause = clause.trim();
-                    let clause_words: Vec<&str> = clause.split_whitespace().collect();
-                    let clause_word_count = clause_words.len();
-
-                    if current_word_count + clause_word_count <= max_words {
-                        // Add clause to current chunk (preserve original punctuation)
-                        if current_chunk.is_empty() {
-                            current_chunk = clause.to_string();
-                        } else {
-                            current_chunk = format!("{} {}", current_chunk, clause);
-                        }
-                        current_word_count += clause_word_count;
-                    } else {
-                        // Start new chunk (preserve original punctuation)
-                        if !current_chunk.is_empty() {
-                            chunks.push(current_chunk);
-                        }
-                        current_chunk = clause.to_string();
-                        current_word_count = clause_word_count;
-                    }
-                }
+    /// Re-read the voices files this instance was created with and swap the
+    /// new voices in, returning how many there are now. Clones of this
+    /// instance share the voices, so they see the reload too.
+    pub fn reload_voices(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        self.styles.reload()
+    }
 
-                // Add final chunk (preserve original punctuation)
-                if !current_chunk.is_empty() {
-                    chunks.push(current_chunk);
-                }
-            }
-        }
+    /// The model, even when inference panicked while holding it. A run
+    /// leaves nothing behind in the session, so one bad input (as when
+    /// `koko validate-voices` catches a panic) doesn't fail every later call.
+    fn lock_model(&self) -> MutexGuard<'_, ort_koko::OrtKoko> {
+        self.model.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        // If no sentences found, fall back to word-based chunking
-        if chunks.is_empty() {
-            let words: Vec<&str> = text.split_whitespace().collect();
-            let mut current_chunk = String::new();
-            let mut current_word_count = 0;
+    /// See [`InitConfig::preprocess`]
+    pub fn preprocess(&self, text: &str) -> String {
+        self.init_config.preprocess(text)
+    }
 
-            for word in words {
-                if current_word_count + 1 <= max_words {
-                    if current_chunk.is_empty() {
-                        current_chunk = word.to_string();
-                    } else {
-                        current_chunk = format!("{} {}", current_chunk, word);
-                    }
-                    current_word_count += 1;
-                } else {
-                    if !current_chunk.is_empty() {
-                        chunks.push(current_chunk);
-                    }
-                    current_chunk = word.to_string();
-                    current_word_count = 1;
-                }
-            }
+    /// See [`InitConfig::preprocess_with`]
+    pub fn preprocess_with(&self, text: &str, keep_acronyms: Option<bool>) -> String {
+        self.init_config.preprocess_with(text, keep_acronyms)
+    }
 
-            if !current_chunk.is_empty() {
-                chunks.push(current_chunk);
-            }
-        }
+    /// See [`InitConfig::phonemize`]
+    pub fn phonemize(&self, text: &str, lan: &str) -> Result<String, ESpeakError> {
+        self.init_config.phonemize(text, lan)
+    }
 
-        chunks
+    /// Smart word-based chunking for async streaming
+    /// Creates chunks based on natural speech boundaries using word count and punctuation
+    pub fn split_text_into_speech_chunks(&self, text: &str, max_words: usize) -> Vec<String> {
+        split_text_into_speech_chunks(text, max_words)
     }
 
     pub fn tts_raw_audio(
@@ -313,74 +871,21 @@ impl TTSKoko {
         style_name: &str,
         speed: f32,
         initial_silence: Option<usize>,
-        request_id: Option<&str>,
-        instance_id: Option<&str>,
-        chunk_number: Option<usize>,
+        log: LogContext,
     ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-        // Split text into appropriate chunks
-        let chunks = self.split_text_into_chunks(txt, 500); // Using 500 to leave 12 tokens of margin
         let mut final_audio = Vec::new();
-
-        for chunk in chunks {
-            // Convert chunk to phonemes
-            let phonemes = {
-                let _guard = ESPEAK_MUTEX.lock().unwrap();
-                text_to_phonemes(&chunk, lan, None, true, false)
-                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-                    .join("")
-            };
-            let debug_prefix = format_debug_prefix(request_id, instance_id);
-            let chunk_info = chunk_number
-                .map(|n| format!("Chunk: {}, ", n))
-                .unwrap_or_default();
-            tracing::debug!(
-                "{} {}text: '{}' -> phonemes: '{}'",
-                debug_prefix,
-                chunk_info,
-                chunk,
-                phonemes
-            );
-            let mut tokens = tokenize(&phonemes);
-
-            for _ in 0..initial_silence.unwrap_or(0) {
-                tokens.insert(0, 30);
-            }
-
-            // Get style vectors once
-            let styles = self.mix_styles(style_name, tokens.len())?;
-
-            // pad a 0 to start and end of tokens
-            let mut padded_tokens = vec![0];
-            for &token in &tokens {
-                padded_tokens.push(token);
-            }
-            padded_tokens.push(0);
-
-            let tokens = vec![padded_tokens];
-
-            match self.model.lock().unwrap().infer(
-                tokens,
-                styles.clone(),
-                speed,
-                request_id,
-                instance_id,
-                chunk_number,
-            ) {
-                Ok(chunk_audio) => {
-                    let chunk_audio: Vec<f32> = chunk_audio.iter().cloned().collect();
-                    final_audio.extend_from_slice(&chunk_audio);
-                }
-                Err(e) => {
-                    eprintln!("Error processing chunk: {:?}", e);
-                    eprintln!("Chunk text was: {:?}", chunk);
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Chunk processing failed: {:?}", e),
-                    )));
-                }
-            }
-        }
-
+        self.tts_raw_audio_streaming(
+            txt,
+            lan,
+            style_name,
+            speed,
+            initial_silence,
+            log,
+            |chunk_audio| {
+                final_audio.extend_from_slice(&chunk_audio);
+                Ok(())
+            },
+        )?;
         Ok(final_audio)
     }
 
@@ -392,79 +897,133 @@ impl TTSKoko {
         style_name: &str,
         speed: f32,
         initial_silence: Option<usize>,
-        request_id: Option<&str>,
-        instance_id: Option<&str>,
-        chunk_number: Option<usize>,
+        log: LogContext,
         mut chunk_callback: F,
     ) -> Result<(), Box<dyn std::error::Error>>
     where
         F: FnMut(Vec<f32>) -> Result<(), Box<dyn std::error::Error>>,
     {
         // Split text into appropriate chunks
-        let chunks = self.split_text_into_chunks(txt, 500); // Using 500 to leave 12 tokens of margin
-
-        for chunk in chunks {
-            // Convert chunk to phonemes
-            let phonemes = {
-                let _guard = ESPEAK_MUTEX.lock().unwrap();
-                text_to_phonemes(&chunk, lan, None, true, false)
-                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-                    .join("")
-            };
-            let debug_prefix = format_debug_prefix(request_id, instance_id);
-            let chunk_info = chunk_number
-                .map(|n| format!("Chunk: {}, ", n))
-                .unwrap_or_default();
-            tracing::debug!(
-                "{} {}text: '{}' -> phonemes: '{}'",
-                debug_prefix,
-                chunk_info,
-                chunk,
-                phonemes
-            );
-            let mut tokens = tokenize(&phonemes);
+        let chunks = self.init_config.plan_chunks(txt, initial_silence);
 
-            for _ in 0..initial_silence.unwrap_or(0) {
-                tokens.insert(0, 30);
-            }
+        for (chunk, silence) in chunks {
+            let (chunk_audio, _) =
+                self.tts_planned_chunk(&chunk, silence, lan, style_name, speed, log)?;
+            // Yield this chunk via callback
+            chunk_callback(chunk_audio)?;
+        }
 
-            // Get style vectors once
-            let styles = self.mix_styles(style_name, tokens.len())?;
+        Ok(())
+    }
 
-            // pad a 0 to start and end of tokens
-            let mut padded_tokens = vec![0];
-            for &token in &tokens {
-                padded_tokens.push(token);
-            }
-            padded_tokens.push(0);
+    /// Synthesize one chunk from [`InitConfig::plan_chunks`] as is, after
+    /// `silence` silence tokens. Running a text's planned chunks through this
+    /// in order gives the same audio as [`TTSKoko::tts_raw_audio`], so they
+    /// can be spread over several instances. Returns the audio and the
+    /// phonemes it was synthesized from.
+    pub fn tts_planned_chunk(
+        &self,
+        chunk: &str,
+        silence: usize,
+        lan: &str,
+        style_name: &str,
+        speed: f32,
+        log: LogContext,
+    ) -> Result<(Vec<f32>, String), Box<dyn std::error::Error>> {
+        let LogContext {
+            request_id,
+            instance_id,
+            chunk_number,
+        } = log;
+        // Convert chunk to phonemes
+        let started = Instant::now();
+        let phonemes = self.phonemize(chunk, lan)?;
+        let phonemize_time = started.elapsed();
+        let debug_prefix = format_debug_prefix(request_id, instance_id);
+        let chunk_info = chunk_number
+            .map(|n| format!("Chunk: {}, ", n))
+            .unwrap_or_default();
+        tracing::debug!(
+            "{} {}text: '{}' -> phonemes: '{}'",
+            debug_prefix,
+            chunk_info,
+            chunk,
+            phonemes
+        );
+        let started = Instant::now();
+        let (mut tokens, dropped) = tokenize_verbose(&phonemes);
+        let tokenize_time = started.elapsed();
+        warn_dropped_phonemes(&debug_prefix, &chunk_info, &dropped);
 
-            let tokens = vec![padded_tokens];
+        self.init_config.prepend_silence(&mut tokens, silence);
 
-            match self.model.lock().unwrap().infer(
-                tokens,
-                styles.clone(),
-                speed,
-                request_id,
-                instance_id,
-                chunk_number,
-            ) {
-                Ok(chunk_audio) => {
-                    let chunk_audio: Vec<f32> = chunk_audio.iter().cloned().collect();
-                    // Yield this chunk via callback
-                    chunk_callback(chunk_audio)?;
-                }
-                Err(e) => {
-                    eprintln!("Error processing chunk: {:?}", e);
-                    eprintln!("Chunk text was: {:?}", chunk);
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Chunk processing failed: {:?}", e),
-                    )));
-                }
+        // Get style vectors once
+        let styles = self.mix_styles(style_name, tokens.len())?;
+
+        let padded_tokens = pad_tokens(&tokens);
+        let token_count = padded_tokens.len();
+
+        let tokens = vec![padded_tokens];
+
+        let mut model = self.lock_model();
+        let started = Instant::now();
+        let result = model.infer(
+            tokens,
+            styles.clone(),
+            speed,
+            request_id,
+            instance_id,
+            chunk_number,
+        );
+        let infer_time = started.elapsed();
+        drop(model);
+        tracing::debug!(
+            "{} {}phonemize: {:?}, tokenize: {:?}, infer: {:?}",
+            debug_prefix,
+            chunk_info,
+            phonemize_time,
+            tokenize_time,
+            infer_time
+        );
+
+        match result {
+            Ok(chunk_audio) => Ok((chunk_audio.iter().cloned().collect(), phonemes)),
+            Err(e) => {
+                let error = inference_error(e, chunk, &phonemes, token_count);
+                eprintln!("Error processing chunk: {}", error);
+                Err(Box::new(error))
             }
         }
+    }
 
-        Ok(())
+    /// Run inference on caller-supplied token ids, skipping espeak and tokenization.
+    ///
+    /// Tokens are fed to the model exactly as given (no `0` padding is added), which
+    /// makes this useful for probing the model's behavior per token.
+    pub fn tts_raw_audio_from_tokens(
+        &self,
+        tokens: &[i64],
+        style_name: &str,
+        speed: f32,
+        request_id: Option<&str>,
+        instance_id: Option<&str>,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        validate_tokens(tokens)?;
+
+        let styles = self.mix_styles(style_name, tokens.len())?;
+        let debug_prefix = format_debug_prefix(request_id, instance_id);
+        tracing::debug!("{} raw tokens: {:?}", debug_prefix, tokens);
+
+        let audio = self.lock_model().infer(
+            vec![tokens.to_vec()],
+            styles,
+            speed,
+            request_id,
+            instance_id,
+            None,
+        )?;
+
+        Ok(audio.iter().cloned().collect())
     }
 
     pub fn tts(
@@ -479,18 +1038,28 @@ impl TTSKoko {
             initial_silence,
         }: TTSOpts,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let txt = self.preprocess(txt);
         let audio = self.tts_raw_audio(
             &txt,
             lan,
             style_name,
             speed,
             initial_silence,
-            None,
-            None,
-            None,
+            LogContext::default(),
         )?;
 
-        // Save to file
+        self.save_wav(save_path, &audio, mono)?;
+        eprintln!("Audio saved to {}", save_path);
+        Ok(())
+    }
+
+    /// Write mono samples to a float WAV file, duplicated to two channels unless `mono`
+    pub fn save_wav(
+        &self,
+        save_path: &str,
+        audio: &[f32],
+        mono: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         if mono {
             let spec = hound::WavSpec {
                 channels: 1,
@@ -500,7 +1069,7 @@ impl TTSKoko {
             };
 
             let mut writer = hound::WavWriter::create(save_path, spec)?;
-            for &sample in &audio {
+            for &sample in audio {
                 writer.write_sample(sample)?;
             }
             writer.finalize()?;
@@ -513,13 +1082,12 @@ impl TTSKoko {
             };
 
             let mut writer = hound::WavWriter::create(save_path, spec)?;
-            for &sample in &audio {
+            for &sample in audio {
                 writer.write_sample(sample)?;
                 writer.write_sample(sample)?;
             }
             writer.finalize()?;
         }
-        eprintln!("Audio saved to {}", save_path);
         Ok(())
     }
 
@@ -528,8 +1096,14 @@ impl TTSKoko {
         style_name: &str,
         tokens_len: usize,
     ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        if tokens_len > MAX_STYLE_TOKENS {
+            return Err(
+                format!("too many tokens: {} (max {})", tokens_len, MAX_STYLE_TOKENS).into(),
+            );
+        }
+        let styles_map = self.styles.snapshot();
         if !style_name.contains("+") {
-            if let Some(style) = self.styles.get(style_name) {
+            if let Some(style) = styles_map.get(style_name) {
                 let styles = vec![style[tokens_len][0].to_vec()];
                 Ok(styles)
             } else {
@@ -555,7 +1129,7 @@ impl TTSKoko {
             let mut blended_style = vec![vec![0.0; 256]; 1];
 
             for (name, portion) in style_names.iter().zip(style_portions.iter()) {
-                if let Some(style) = self.styles.get(*name) {
+                if let Some(style) = styles_map.get(*name) {
                     let style_slice = &style[tokens_len][0]; // This is a [256] array
                     // Blend into the blended_style
                     for j in 0..256 {
@@ -567,13 +1141,15 @@ impl TTSKoko {
         }
     }
 
-    fn load_voices(voices_path: &str) -> HashMap<String, Vec<[[f32; 256]; 1]>> {
-        let mut npz = NpzReader::new(File::open(voices_path).unwrap()).unwrap();
-        let mut map = HashMap::new();
+    /// Add the voices in an npz file to `map`, replacing any of the same name
+    fn load_voices(
+        map: &mut StyleMap,
+        voices_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut npz = NpzReader::new(File::open(voices_path)?)?;
 
-        for voice in npz.names().unwrap() {
-            let voice_data: Result<Array3<f32>, _> = npz.by_name(&voice);
-            let voice_data = voice_data.unwrap();
+        for voice in npz.names()? {
+            let voice_data: Array3<f32> = npz.by_name(&voice)?;
             let mut tensor = vec![[[0.0; 256]; 1]; 511];
             for (i, inner_value) in voice_data.outer_iter().enumerate() {
                 for (j, inner_inner_value) in inner_value.outer_iter().enumerate() {
@@ -584,7 +1160,10 @@ impl TTSKoko {
             }
             map.insert(voice, tensor);
         }
+        Ok(())
+    }
 
+    fn log_voices(map: &StyleMap) {
         let _sorted_voices = {
             let mut voices = map.keys().collect::<Vec<_>>();
             voices.sort();
@@ -608,26 +1187,7 @@ impl TTSKoko {
                 }
 
                 for (prefix, voices_in_group) in grouped_voices {
-                    let category = match prefix {
-                        "af" => "American Female(af)",
-                        "am" => "American Male(am)",
-                        "bf" => "British Female(bf)",
-                        "bm" => "British Male(bm)",
-                        "ef" => "European Female(ef)",
-                        "em" => "European Male(em)",
-                        "ff" => "French Female(ff)",
-                        "hf" => "Hindi Female(hf)",
-                        "hm" => "Hindi Male(hm)",
-                        "if" => "Italian Female(if)",
-                        "im" => "Italian Male(im)",
-                        "jf" => "Japanese Female(jf)",
-                        "jm" => "Japanese Male(jm)",
-                        "pf" => "Portuguese Female(pf)",
-                        "pm" => "Portuguese Male(pm)",
-                        "zf" => "Chinese Female(zf)",
-                        "zm" => "Chinese Male(zm)",
-                        _ => prefix,
-                    };
+                    let category = voice_metadata(prefix).category();
 
                     let voices_str = voices_in_group.join(", ");
                     // Gray out the voice information
@@ -639,16 +1199,24 @@ impl TTSKoko {
 
             voices
         };
-
-        map
     }
 
     // Returns a sorted list of available voice names
     pub fn get_available_voices(&self) -> Vec<String> {
-        let mut voices: Vec<String> = self.styles.keys().cloned().collect();
+        let mut voices: Vec<String> = self.styles.snapshot().keys().cloned().collect();
         voices.sort();
         voices
     }
+
+    /// Whether [`TTSKoko::mix_styles`] knows `style_name`, a voice or every
+    /// voice of a blend such as `af_sky.4+af_nicole.5`
+    pub fn has_voice(&self, style_name: &str) -> bool {
+        let styles_map = self.styles.snapshot();
+        style_name
+            .split('+')
+            .map(|style| style.split_once('.').map_or(style, |(name, _)| name))
+            .all(|name| styles_map.contains_key(name))
+    }
 }
 
 impl TTSKokoParallel {
@@ -678,11 +1246,9 @@ impl TTSKokoParallel {
                 .expect("download model failed.");
         }
 
-        if !Path::new(voices_path).exists() {
-            utils::fileio::download_file_from_url(cfg.voices_url.as_str(), voices_path)
-                .await
-                .expect("download voices data file failed.");
-        }
+        fetch_voices(&cfg, voices_path)
+            .await
+            .expect("download voices data file failed.");
 
         // Create multiple ONNX model instances
         let mut models = Vec::new();
@@ -693,19 +1259,19 @@ impl TTSKokoParallel {
                 i + 1,
                 num_instances
             );
-            let model = Arc::new(Mutex::new(
-                ort_koko::OrtKoko::new(model_path.to_string())
-                    .expect("Failed to create Kokoro TTS model"),
-            ));
-            models.push(model);
+            let model = ort_koko::OrtKoko::new(model_path.to_string())
+                .expect("Failed to create Kokoro TTS model");
+            model.print_info();
+            models.push(Arc::new(Mutex::new(model)));
         }
 
-        let styles = TTSKoko::load_voices(voices_path);
+        let styles = VoiceStyles::load(&[voices_path]).expect("load voices failed.");
+        TTSKoko::log_voices(&styles.snapshot());
 
         TTSKokoParallel {
             model_path: model_path.to_string(),
             models,
-            styles,
+            styles: Arc::new(styles),
             init_config: cfg,
         }
     }
@@ -724,17 +1290,16 @@ impl TTSKokoParallel {
         style_name: &str,
         speed: f32,
         initial_silence: Option<usize>,
-        request_id: Option<&str>,
-        instance_id: Option<&str>,
-        chunk_number: Option<usize>,
+        log: LogContext,
         model_instance: Arc<Mutex<ort_koko::OrtKoko>>,
     ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let LogContext {
+            request_id,
+            instance_id,
+            chunk_number,
+        } = log;
         // Convert text to phonemes
-        let phonemes = {
-            let _guard = ESPEAK_MUTEX.lock().unwrap();
-            text_to_phonemes(text, language, None, true, false)?
-        };
-        let phonemes = phonemes.join("");
+        let phonemes = self.init_config.phonemize(text, language)?;
         let debug_prefix = format_debug_prefix(request_id, instance_id);
         tracing::debug!(
             "{} text: '{}' -> phonemes: '{}'",
@@ -744,43 +1309,43 @@ impl TTSKokoParallel {
         );
 
         // Tokenize phonemes
-        let mut tokens = tokenize(&phonemes);
+        let (mut tokens, dropped) = tokenize_verbose(&phonemes);
+        warn_dropped_phonemes(&debug_prefix, "", &dropped);
 
         // Add initial silence if specified
-        for _ in 0..initial_silence.unwrap_or(0) {
-            tokens.insert(0, 30);
-        }
+        self.init_config
+            .prepend_silence(&mut tokens, initial_silence.unwrap_or(0));
 
         // Get style vectors - create temporary TTSKoko instance to use mix_styles
         let temp_tts = TTSKoko {
             model_path: self.model_path.clone(),
             model: Arc::clone(&self.models[0]), // Just for interface compatibility
-            styles: self.styles.clone(),
+            styles: Arc::clone(&self.styles),
             init_config: self.init_config.clone(),
         };
         let styles = temp_tts.mix_styles(style_name, tokens.len())?;
 
-        // pad a 0 to start and end of tokens
-        let mut padded_tokens = vec![0];
-        for &token in &tokens {
-            padded_tokens.push(token);
-        }
-        padded_tokens.push(0);
+        let padded_tokens = pad_tokens(&tokens);
+        let token_count = padded_tokens.len();
 
         let tokens_vec = vec![padded_tokens];
 
         tracing::debug!("shape_style: {:?}", styles.len());
 
         // Run TTS inference with provided model instance
-        let mut model = model_instance.lock().unwrap();
-        let audio = model.infer(
-            tokens_vec,
-            styles.clone(),
-            speed,
-            request_id,
-            instance_id,
-            chunk_number,
-        )?;
+        let mut model = model_instance
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let audio = model
+            .infer(
+                tokens_vec,
+                styles.clone(),
+                speed,
+                request_id,
+                instance_id,
+                chunk_number,
+            )
+            .map_err(|e| inference_error(e, text, &phonemes, token_count))?;
 
         // Convert ndarray to Vec<f32>
         let audio_vec: Vec<f32> = audio.iter().cloned().collect();
@@ -793,7 +1358,7 @@ impl TTSKokoParallel {
         let temp_tts = TTSKoko {
             model_path: self.model_path.clone(),
             model: Arc::clone(&self.models[0]), // Just for interface compatibility
-            styles: self.styles.clone(),
+            styles: Arc::clone(&self.styles),
             init_config: self.init_config.clone(),
         };
         temp_tts.split_text_into_speech_chunks(text, max_words)
@@ -801,8 +1366,313 @@ impl TTSKokoParallel {
 
     /// Get available voices
     pub fn get_available_voices(&self) -> Vec<String> {
-        let mut voices: Vec<String> = self.styles.keys().cloned().collect();
+        let mut voices: Vec<String> = self.styles.snapshot().keys().cloned().collect();
         voices.sort();
         voices
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inference_error_names_dropped_phonemes() {
+        let error = inference_error("shape mismatch", "Hi", "hˈaɪ☃", 5).to_string();
+        assert!(error.contains("\"shape mismatch\""), "{}", error);
+        assert!(error.contains("phonemes: \"hˈaɪ☃\""), "{}", error);
+        assert!(error.contains("5 tokens"), "{}", error);
+        assert!(
+            error.contains("dropped out-of-vocab chars: ['☃']"),
+            "{}",
+            error
+        );
+
+        let error = inference_error("shape mismatch", "Hi", "hˈaɪ", 5).to_string();
+        assert!(
+            error.contains("dropped out-of-vocab chars: none"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_split_speech_chunks_handles_tiny_inputs() {
+        assert!(split_text_into_speech_chunks("", 10).is_empty());
+        assert!(split_text_into_speech_chunks("   ", 10).is_empty());
+        assert_eq!(split_text_into_speech_chunks("Hello", 10), vec!["Hello."]);
+        assert_eq!(split_text_into_speech_chunks("Hello.", 10), vec!["Hello."]);
+    }
+
+    #[test]
+    fn test_lower_token_limit_gives_more_chunks() {
+        let text = "One two three four. Five six seven eight. Nine ten eleven twelve.";
+        let count_words = |chunk: &str| chunk.split_whitespace().count();
+
+        let wide = split_text_into_chunks(text, 500, count_words);
+        let narrow = split_text_into_chunks(text, 4, count_words);
+        let tiny = split_text_into_chunks(text, 2, count_words);
+
+        assert_eq!(wide.len(), 1);
+        assert_eq!(narrow.len(), 3);
+        assert!(tiny.len() > narrow.len());
+        assert!(tiny.iter().all(|c| count_words(c) <= 2));
+    }
+
+    #[test]
+    fn test_initial_silence_fits_within_max_tokens() {
+        assert_eq!(silence_tokens_for_ms(u32::MAX), 171798691);
+
+        let config = InitConfig {
+            max_tokens: 40,
+            ..InitConfig::default()
+        };
+        let text = "The quick brown fox jumps over the lazy dog and keeps on running far away.";
+        let tokens = |chunk: &str| tokenize(&config.phonemize(chunk, "en").unwrap()).len();
+        let planned = config.plan_chunks(text, Some(15));
+        assert!(planned.len() > 1);
+        for (chunk, silence) in &planned {
+            assert!(tokens(chunk) + silence <= 40, "{:?}", chunk);
+        }
+
+        // A pause too long to take the silence too keeps it as its own chunk
+        let ssml = r#"<speak><break time="1500ms"/>Hi.</speak>"#;
+        assert_eq!(
+            config.plan_chunks(ssml, Some(15))[..2],
+            [(String::new(), 15), (String::new(), 60)]
+        );
+    }
+
+    #[test]
+    fn test_plan_whole_is_one_inference() {
+        let text = "Hi there! How are you today? Fine, thanks.";
+        let config = InitConfig {
+            max_tokens: 24,
+            ..InitConfig::default()
+        };
+        assert!(config.plan_chunks(text, None).len() > 1);
+        assert_eq!(
+            InitConfig::default()
+                .plan_whole(text, "en", Some(2))
+                .unwrap(),
+            vec![(text.to_string(), 2)]
+        );
+
+        let tight = InitConfig {
+            max_tokens: 5,
+            ..InitConfig::default()
+        };
+        assert!(matches!(
+            tight.plan_whole(text, "en", None),
+            Err(PlanError::TooLong(message)) if message.contains("5 token limit")
+        ));
+    }
+
+    #[test]
+    fn test_deterministic_phonemes_repeat() {
+        let config = InitConfig {
+            deterministic_phonemes: true,
+            ..InitConfig::default()
+        };
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let first = tokenize(&config.phonemize(text, "en-us").unwrap());
+        // Leave espeak in another voice, mid-clause, before the second run
+        config.phonemize("ein unvollendeter Satz", "de").unwrap();
+        let second = tokenize(&config.phonemize(text, "en-us").unwrap());
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_ssml_break_becomes_silence_chunk() {
+        let config = InitConfig::default();
+        let planned = config.plan_chunks(
+            r#"<speak>First part. <break time="500ms"/>Second part.</speak>"#,
+            Some(2),
+        );
+        assert_eq!(
+            planned,
+            vec![
+                ("First part.".to_string(), 2),
+                (String::new(), silence_tokens_for_ms(500)),
+                ("Second part.".to_string(), 0),
+            ]
+        );
+        assert_eq!(silence_tokens_for_ms(500), 20);
+
+        // Plain text keeps the silence on every chunk, tags and all
+        let plain = config.plan_chunks("Say <break/> aloud.", Some(2));
+        assert_eq!(plain, vec![("Say <break/> aloud.".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_lexicon_overrides_phonemes() {
+        let text = "Say Kokoro twice.";
+        let default = InitConfig::default().phonemize(text, "en-us").unwrap();
+        assert!(!default.contains("kˈoʊkəɹoʊ"));
+
+        let config = InitConfig {
+            lexicon: Some(Arc::new(Lexicon::new(HashMap::from([(
+                "kokoro".to_string(),
+                "/kˈoʊkəɹoʊ/".to_string(),
+            )])))),
+            ..InitConfig::default()
+        };
+        let phonemes = config.phonemize(text, "en-us").unwrap();
+        let say = InitConfig::default().phonemize("Say", "en-us").unwrap();
+        assert!(
+            phonemes.starts_with(&format!("{} kˈoʊkəɹoʊ ", say.trim_end_matches('.'))),
+            "{}",
+            phonemes
+        );
+    }
+
+    #[test]
+    fn test_prepend_silence_uses_configured_token() {
+        let mut tokens = vec![50, 83];
+        InitConfig::default().prepend_silence(&mut tokens, 2);
+        assert_eq!(tokens, vec![30, 30, 50, 83]);
+        assert_eq!(default_silence_token(), 30);
+
+        let config = InitConfig {
+            silence_token: space_token(),
+            ..InitConfig::default()
+        };
+        let mut tokens = vec![50, 83];
+        config.prepend_silence(&mut tokens, 3);
+        assert_eq!(tokens, vec![16, 16, 16, 50, 83]);
+        assert_eq!(REVERSE_VOCAB[&16], ' ');
+    }
+
+    #[test]
+    fn test_silence_tokens_for_ms() {
+        assert_eq!(silence_tokens_for_ms(0), 0);
+        assert_eq!(silence_tokens_for_ms(10), 0);
+        assert_eq!(silence_tokens_for_ms(25), 1);
+        assert_eq!(silence_tokens_for_ms(500), 20);
+    }
+
+    #[test]
+    fn test_forced_voices_download_replaces_file() {
+        use std::io::{Read, Write};
+
+        // Serves one response, so a second download would fail
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/voices.bin", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nnew voices",
+                )
+                .unwrap();
+        });
+
+        let path = std::env::temp_dir().join(format!("kokoros-fetch-{}.bin", std::process::id()));
+        std::fs::write(&path, "old voices").unwrap();
+        let path = path.to_str().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // An existing file is kept unless forced
+        let mut cfg = InitConfig {
+            voices_url: url,
+            ..InitConfig::default()
+        };
+        runtime.block_on(fetch_voices(&cfg, path)).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "old voices");
+
+        cfg.force_voices_download = true;
+        runtime.block_on(fetch_voices(&cfg, path)).unwrap();
+        server.join().unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "new voices");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_later_voices_file_overrides_earlier() {
+        use ndarray_npy::NpzWriter;
+
+        let dir = std::env::temp_dir().join(format!("kokoros-voices-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_npz = |name: &str, voices: &[(&str, f32)]| {
+            let path = dir.join(name);
+            let mut npz = NpzWriter::new(File::create(&path).unwrap());
+            for (voice, value) in voices {
+                npz.add_array(*voice, &Array3::<f32>::from_elem((2, 1, 256), *value))
+                    .unwrap();
+            }
+            npz.finish().unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let base = write_npz("base.npz", &[("af_base", 1.0), ("af_shared", 1.0)]);
+        let custom = write_npz("custom.npz", &[("af_custom", 2.0), ("af_shared", 2.0)]);
+
+        let mut styles = HashMap::new();
+        TTSKoko::load_voices(&mut styles, &base).unwrap();
+        TTSKoko::load_voices(&mut styles, &custom).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut names: Vec<_> = styles.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["af_base", "af_custom", "af_shared"]);
+        assert_eq!(styles["af_base"][0][0][0], 1.0);
+        assert_eq!(styles["af_shared"][1][0][255], 2.0);
+    }
+
+    #[test]
+    fn test_reload_picks_up_new_voice() {
+        use ndarray_npy::NpzWriter;
+
+        let dir = std::env::temp_dir().join(format!("kokoros-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("voices.npz");
+        let write_npz = |voices: &[&str]| {
+            let mut npz = NpzWriter::new(File::create(&path).unwrap());
+            for voice in voices {
+                npz.add_array(*voice, &Array3::<f32>::zeros((2, 1, 256)))
+                    .unwrap();
+            }
+            npz.finish().unwrap();
+        };
+
+        write_npz(&["af_one"]);
+        let styles = VoiceStyles::load(&[&path.to_string_lossy()]).unwrap();
+        let before = styles.snapshot();
+        assert!(!before.contains_key("af_two"));
+
+        write_npz(&["af_one", "af_two"]);
+        assert_eq!(styles.reload().unwrap(), 2);
+        assert!(styles.snapshot().contains_key("af_two"));
+        // A snapshot taken before the reload keeps the old voices
+        assert!(!before.contains_key("af_two"));
+
+        // A failed reload leaves the current voices in place
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(styles.reload().is_err());
+        assert!(styles.snapshot().contains_key("af_two"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_model_survives_a_panic_while_locked() {
+        let tts = TTSKoko::mock(InitConfig::default(), &["af_sky"]);
+        let model = Arc::clone(&tts.model);
+        let panicked = std::thread::spawn(move || {
+            let _guard = model.lock().unwrap();
+            panic!("inference panicked");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(tts.model.is_poisoned());
+
+        let audio = tts
+            .tts_raw_audio_from_tokens(&[0, 50, 0], "af_sky", 1.0, None, None)
+            .unwrap();
+        assert!(!audio.is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+//! Guessing the language of input text, for requests that neither name a
+//! language nor use a voice that implies one.
+//!
+//! Non-Latin scripts decide on their own (Devanagari is Hindi, kana is
+//! Japanese, other Han text is Chinese). Latin text is scored on common
+//! function words, which takes a sentence or so to be reliable, so short or
+//! mixed input gives `None` rather than a guess.
+
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+
+/// Function words per espeak language, frequent enough that a sentence
+/// almost always contains a few of them
+const STOPWORDS: [(&str, &str); 6] = [
+    (
+        "en-us",
+        "the and is are was were of to in that it with for on this you not be have has what",
+    ),
+    (
+        "fr-fr",
+        "le la les des est et une un du dans que qui pour pas sur avec je nous vous ce sont au",
+    ),
+    (
+        "es",
+        "el la los las es y una un del en que por para con no se lo como está son pero muy",
+    ),
+    (
+        "it",
+        "il la lo gli le è e una un del della che di per con non sono nel ma anche più questo",
+    ),
+    (
+        "pt-br",
+        "o a os as é e uma um do da que em para com não se por mais como mas você são isso",
+    ),
+    (
+        "de",
+        "der die das und ist nicht ein eine zu mit sich auf für den dem ich sie wir auch es",
+    ),
+];
+
+/// Fewest function words Latin text needs before a language is picked
+const MIN_STOPWORD_HITS: usize = 2;
+
+lazy_static! {
+    static ref STOPWORD_SETS: Vec<(&'static str, HashSet<&'static str>)> = STOPWORDS
+        .iter()
+        .map(|(language, words)| (*language, words.split_whitespace().collect()))
+        .collect();
+}
+
+/// The espeak language `text` is most likely written in, `None` when no
+/// language stands out
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let (mut devanagari, mut kana, mut han, mut latin) = (0, 0, 0, 0);
+    for c in text.chars() {
+        match c {
+            '\u{0900}'..='\u{097F}' => devanagari += 1,
+            '\u{3040}'..='\u{30FF}' => kana += 1,
+            '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => han += 1,
+            c if c.is_alphabetic() => latin += 1,
+            _ => {}
+        }
+    }
+    if devanagari > latin && devanagari >= kana + han {
+        return Some("hi");
+    }
+    if kana + han > latin {
+        // Japanese mixes kanji with kana, Chinese has none
+        return Some(if kana > 0 { "ja" } else { "zh" });
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut scores: Vec<(&'static str, usize)> = STOPWORD_SETS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|word| stopwords.contains(word.as_str()))
+                .count();
+            (*language, hits)
+        })
+        .collect();
+    scores.sort_by_key(|&(_, hits)| std::cmp::Reverse(hits));
+    match scores[..] {
+        [(language, best), (_, second), ..] if best >= MIN_STOPWORD_HITS && best > second => {
+            Some(language)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_french() {
+        assert_eq!(
+            detect_language("Bonjour, je suis très content de vous voir dans la ville."),
+            Some("fr-fr")
+        );
+        assert_eq!(
+            detect_language("The weather is nice and the sun is out."),
+            Some("en-us")
+        );
+        assert_eq!(detect_language("Der Hund ist nicht im Haus."), Some("de"));
+    }
+
+    #[test]
+    fn test_scripts_and_unclear_input() {
+        assert_eq!(detect_language("こんにちは、世界"), Some("ja"));
+        assert_eq!(detect_language("你好世界"), Some("zh"));
+        assert_eq!(detect_language("नमस्ते दुनिया"), Some("hi"));
+        // Too short to tell
+        assert_eq!(detect_language("Hello"), None);
+        assert_eq!(detect_language("42"), None);
+    }
+}
@@ -0,0 +1,141 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+/// User pronunciations for words espeak gets wrong, loaded from JSON.
+///
+/// Values between slashes are IPA and are used as the word's phonemes
+/// as is; anything else is a respelling that espeak reads instead of the
+/// word, e.g. `{"Kokoro": "/kˈoʊkəɹoʊ/", "nginx": "engine x"}`. Words are
+/// matched whole and case-insensitively.
+pub struct Lexicon {
+    /// Lowercased word → pronunciation
+    entries: HashMap<String, Pronunciation>,
+    /// Every word, longest first so multi-word entries win over their parts
+    pattern: Option<Regex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pronunciation {
+    Phonemes(String),
+    Respelling(String),
+}
+
+/// Part of the text after applying a [`Lexicon`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    /// Text still to be phonemized
+    Text(String),
+    /// Phonemes from the lexicon, which skip espeak
+    Phonemes(String),
+}
+
+impl Lexicon {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let entries: HashMap<String, String> =
+            serde_json::from_str(&data).map_err(|e| format!("invalid lexicon {}: {}", path, e))?;
+        Ok(Self::new(entries))
+    }
+
+    pub fn new(entries: HashMap<String, String>) -> Self {
+        let entries: HashMap<String, Pronunciation> = entries
+            .into_iter()
+            .filter(|(word, _)| !word.trim().is_empty())
+            .map(|(word, value)| {
+                let value = value.trim();
+                let ipa = value.strip_prefix('/').and_then(|v| v.strip_suffix('/'));
+                let pronunciation = match ipa {
+                    Some(phonemes) => Pronunciation::Phonemes(phonemes.to_string()),
+                    None => Pronunciation::Respelling(value.to_string()),
+                };
+                (word.trim().to_lowercase(), pronunciation)
+            })
+            .collect();
+
+        let mut words: Vec<&String> = entries.keys().collect();
+        words.sort_by_key(|word| std::cmp::Reverse(word.chars().count()));
+        let alternatives: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+        let pattern = (!alternatives.is_empty())
+            .then(|| Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).unwrap());
+
+        Self { entries, pattern }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Split `text` around lexicon words. Respellings are substituted into
+    /// the surrounding text, IPA entries become their own pieces.
+    pub fn apply(&self, text: &str) -> Vec<Piece> {
+        let Some(pattern) = &self.pattern else {
+            return vec![Piece::Text(text.to_string())];
+        };
+
+        let mut pieces = Vec::new();
+        let mut pending = String::new();
+        let mut last = 0;
+        for found in pattern.find_iter(text) {
+            pending.push_str(&text[last..found.start()]);
+            last = found.end();
+            // `(?i)` folds more than lowercasing does (the long s `ſ`
+            // matches `s`), so such a match has no entry and stays as is
+            let Some(pronunciation) = self.entries.get(&found.as_str().to_lowercase()) else {
+                pending.push_str(found.as_str());
+                continue;
+            };
+            match pronunciation {
+                Pronunciation::Respelling(respelling) => pending.push_str(respelling),
+                Pronunciation::Phonemes(phonemes) => {
+                    if !pending.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut pending)));
+                    }
+                    pieces.push(Piece::Phonemes(phonemes.clone()));
+                }
+            }
+        }
+        pending.push_str(&text[last..]);
+        if !pending.is_empty() {
+            pieces.push(Piece::Text(pending));
+        }
+        pieces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whole_words_match_case_insensitively() {
+        let lexicon = Lexicon::new(HashMap::from([
+            ("Kokoro".to_string(), "/kˈoʊkəɹoʊ/".to_string()),
+            ("nginx".to_string(), "engine x".to_string()),
+        ]));
+
+        assert_eq!(
+            lexicon.apply("I run NGINX and KOKORO."),
+            vec![
+                Piece::Text("I run engine x and ".to_string()),
+                Piece::Phonemes("kˈoʊkəɹoʊ".to_string()),
+                Piece::Text(".".to_string()),
+            ]
+        );
+        // Only whole words
+        assert_eq!(
+            lexicon.apply("Kokoros"),
+            vec![Piece::Text("Kokoros".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_case_folded_match_without_entry_is_kept() {
+        let lexicon = Lexicon::new(HashMap::from([("sky".to_string(), "/skˈaɪ/".to_string())]));
+        // The regex folds `ſ` to `s`, lowercasing doesn't
+        assert_eq!(lexicon.apply("ſky"), vec![Piece::Text("ſky".to_string())]);
+    }
+}
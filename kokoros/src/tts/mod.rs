@@ -1,5 +1,10 @@
 pub mod koko;
+pub mod language;
+pub mod lexicon;
 pub mod normalize;
+pub mod phoneme_cache;
 pub mod phonemizer;
+pub mod ssml;
 pub mod tokenize;
 pub mod vocab;
+pub mod voices;
@@ -4,30 +4,253 @@ use regex::Regex;
 lazy_static! {
     static ref WHITESPACE_RE: Regex = Regex::new(r"[^\S \n]").unwrap();
     static ref MULTI_SPACE_RE: Regex = Regex::new(r"  +").unwrap();
-    static ref NEWLINE_SPACE_RE: Regex = Regex::new(r"(?<=\n) +(?=\n)").unwrap();
-    static ref DOCTOR_RE: Regex = Regex::new(r"\bD[Rr]\.(?= [A-Z])").unwrap();
-    static ref MISTER_RE: Regex = Regex::new(r"\b(?:Mr\.|MR\.(?= [A-Z]))").unwrap();
-    static ref MISS_RE: Regex = Regex::new(r"\b(?:Ms\.|MS\.(?= [A-Z]))").unwrap();
-    static ref MRS_RE: Regex = Regex::new(r"\b(?:Mrs\.|MRS\.(?= [A-Z]))").unwrap();
-    static ref ETC_RE: Regex = Regex::new(r"\betc\.(?! [A-Z])").unwrap();
+    static ref NEWLINE_SPACE_RE: Regex = Regex::new(r"\n +\n").unwrap();
+    static ref DOCTOR_RE: Regex = Regex::new(r"\bD[Rr]\.( [A-Z])").unwrap();
+    static ref MISTER_RE: Regex = Regex::new(r"\b(?:Mr\.|MR\.( [A-Z]))").unwrap();
+    static ref MISS_RE: Regex = Regex::new(r"\b(?:Ms\.|MS\.( [A-Z]))").unwrap();
+    static ref MRS_RE: Regex = Regex::new(r"\b(?:Mrs\.|MRS\.( [A-Z]))").unwrap();
+    static ref ETC_RE: Regex = Regex::new(r"\betc\.( [A-Z])?").unwrap();
     static ref YEAH_RE: Regex = Regex::new(r"(?i)\b(y)eah?\b").unwrap();
-    static ref NUMBERS_RE: Regex =
-        Regex::new(r"\d*\.\d+|\b\d{4}s?\b|(?<!:)\b(?:[1-9]|1[0-2]):[0-5]\d\b(?!:)").unwrap();
-    static ref COMMA_NUM_RE: Regex = Regex::new(r"(?<=\d),(?=\d)").unwrap();
+    static ref COMMA_NUM_RE: Regex = Regex::new(r"(\d),(\d)").unwrap();
     static ref MONEY_RE: Regex = Regex::new(
         r"(?i)[$£]\d+(?:\.\d+)?(?: hundred| thousand| (?:[bm]|tr)illion)*\b|[$£]\d+\.\d\d?\b"
     )
     .unwrap();
     static ref POINT_NUM_RE: Regex = Regex::new(r"\d*\.\d+").unwrap();
-    static ref RANGE_RE: Regex = Regex::new(r"(?<=\d)-(?=\d)").unwrap();
-    static ref S_AFTER_NUM_RE: Regex = Regex::new(r"(?<=\d)S").unwrap();
-    static ref POSSESSIVE_RE: Regex = Regex::new(r"(?<=[BCDFGHJ-NP-TV-Z])'?s\b").unwrap();
-    static ref X_POSSESSIVE_RE: Regex = Regex::new(r"(?<=X')S\b").unwrap();
+    static ref RANGE_RE: Regex = Regex::new(r"(\d)-(\d)").unwrap();
+    static ref S_AFTER_NUM_RE: Regex = Regex::new(r"(\d)S").unwrap();
+    static ref POSSESSIVE_RE: Regex = Regex::new(r"([BCDFGHJ-NP-TV-Z])'?s\b").unwrap();
+    static ref X_POSSESSIVE_RE: Regex = Regex::new(r"X'S\b").unwrap();
     static ref INITIALS_RE: Regex = Regex::new(r"(?:[A-Za-z]\.){2,} [a-z]").unwrap();
-    static ref ACRONYM_RE: Regex = Regex::new(r"(?i)(?<=[A-Z])\.(?=[A-Z])").unwrap();
+    static ref ACRONYM_RE: Regex = Regex::new(r"(?i)([A-Z])\.([A-Z])").unwrap();
+
+    // Preprocessing steps
+    static ref MD_FENCE_RE: Regex = Regex::new(r"(?m)^\s*```.*$").unwrap();
+    static ref MD_RULE_RE: Regex = Regex::new(r"(?m)^\s*(?:[-*_]\s*){3,}$").unwrap();
+    static ref MD_IMAGE_RE: Regex = Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap();
+    static ref MD_LINK_RE: Regex = Regex::new(r"\[([^\]]+)\]\([^)]*\)").unwrap();
+    static ref MD_HEADING_RE: Regex = Regex::new(r"(?m)^\s{0,3}#{1,6}\s+").unwrap();
+    static ref MD_QUOTE_RE: Regex = Regex::new(r"(?m)^\s*>\s?").unwrap();
+    static ref MD_BULLET_RE: Regex = Regex::new(r"(?m)^\s*[-*+]\s+").unwrap();
+    // Emphasis hugs its text and doesn't touch a word, so "2*3*4" and
+    // "a * b" stay as they are. The character before is captured and put back.
+    static ref MD_STRONG_RE: Regex =
+        Regex::new(r"(^|[^\w*])(?:\*\*([^*\s](?:[^*\n]*[^*\s])?)\*\*|__([^_\s](?:[^_\n]*[^_\s])?)__)").unwrap();
+    static ref MD_EMPHASIS_RE: Regex =
+        Regex::new(r"(^|[^\w*])\*([^*\s](?:[^*\n]*[^*\s])?)\*").unwrap();
+    static ref MD_CODE_RE: Regex = Regex::new(r"`([^`]+)`").unwrap();
+    static ref URL_RE: Regex = Regex::new(r"(?:https?://|www\.)[^\s<>]+").unwrap();
+    static ref THOUSANDS_RE: Regex = Regex::new(r"\b\d{1,3}(?:,\d{3})+\b").unwrap();
+    // Whole hyphenated number runs, so dates and phone numbers are seen entire
+    static ref NUM_RANGE_RE: Regex = Regex::new(r"\b\d+(?:-\d+)+\b").unwrap();
+    static ref INLINE_SPACE_RE: Regex = Regex::new(r"[^\S\n]+").unwrap();
+    static ref BLANK_LINES_RE: Regex = Regex::new(r"\n{3,}").unwrap();
+}
+
+/// A named text transform applied before chunking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreprocessStep {
+    /// Drop markdown syntax (headings, emphasis, code, links), keeping the readable text
+    StripMarkdown,
+    /// Spell out URLs, e.g. `https://example.com/docs` -> "example dot com slash docs"
+    ExpandUrls,
+    /// Remove thousands separators and read ascending ranges, e.g. `1-5` ->
+    /// "1 to 5". Dates (`2024-01-15`) and phone numbers (`555-1234`) are left alone.
+    NormalizeNumbers,
+    /// Kokoro's reference normalization, see [`normalize_text`]. Dotted
+    /// acronyms are hyphenated unless [`Pipeline::keep_acronyms`] is set.
+    NormalizeText,
+    /// Collapse runs of spaces and blank lines
+    NormalizeWhitespace,
+}
+
+impl PreprocessStep {
+    pub const ALL: [PreprocessStep; 5] = [
+        PreprocessStep::StripMarkdown,
+        PreprocessStep::ExpandUrls,
+        PreprocessStep::NormalizeNumbers,
+        PreprocessStep::NormalizeText,
+        PreprocessStep::NormalizeWhitespace,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PreprocessStep::StripMarkdown => "strip_markdown",
+            PreprocessStep::ExpandUrls => "expand_urls",
+            PreprocessStep::NormalizeNumbers => "normalize_numbers",
+            PreprocessStep::NormalizeText => "normalize_text",
+            PreprocessStep::NormalizeWhitespace => "normalize_whitespace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|step| step.name() == name)
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        self.apply_with(text, false)
+    }
+
+    /// Like [`Self::apply`], with `keep_acronyms` passed to [`normalize_text`]
+    pub fn apply_with(self, text: &str, keep_acronyms: bool) -> String {
+        match self {
+            PreprocessStep::StripMarkdown => strip_markdown(text),
+            PreprocessStep::ExpandUrls => expand_urls(text),
+            PreprocessStep::NormalizeNumbers => normalize_numbers(text),
+            PreprocessStep::NormalizeText => normalize_text(text, keep_acronyms),
+            PreprocessStep::NormalizeWhitespace => normalize_whitespace(text),
+        }
+    }
+}
+
+/// Ordered list of preprocessing steps.
+///
+/// The default runs none, so text reaches chunking as it was sent. Steps run
+/// best in the order of [`PreprocessStep::ALL`]: markdown goes first so link
+/// targets are dropped before URLs get spelled out, and whitespace last to
+/// clean up after the others.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    steps: Vec<PreprocessStep>,
+    keep_acronyms: bool,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<PreprocessStep>) -> Self {
+        Self {
+            steps,
+            keep_acronyms: false,
+        }
+    }
+
+    /// Leave dotted acronyms (`U.S.A.`) for espeak in the `normalize_text`
+    /// step instead of hyphenating them
+    pub fn with_keep_acronyms(mut self, keep_acronyms: bool) -> Self {
+        self.keep_acronyms = keep_acronyms;
+        self
+    }
+
+    pub fn keep_acronyms(&self) -> bool {
+        self.keep_acronyms
+    }
+
+    /// Build a pipeline from step names; `none` or an empty list disables preprocessing
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for name in names.iter().map(|n| n.as_ref().trim()) {
+            if name.is_empty() || name == "none" {
+                continue;
+            }
+            let step = PreprocessStep::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = PreprocessStep::ALL.iter().map(|s| s.name()).collect();
+                format!(
+                    "unknown preprocessing step '{}' (expected one of: {})",
+                    name,
+                    known.join(", ")
+                )
+            })?;
+            steps.push(step);
+        }
+        Ok(Self::new(steps))
+    }
+
+    pub fn steps(&self) -> &[PreprocessStep] {
+        &self.steps
+    }
+
+    pub fn run(&self, text: &str) -> String {
+        self.run_with(text, self.keep_acronyms)
+    }
+
+    /// Like [`Self::run`], with `keep_acronyms` overriding the pipeline's own
+    pub fn run_with(&self, text: &str, keep_acronyms: bool) -> String {
+        self.steps.iter().fold(text.to_string(), |text, step| {
+            step.apply_with(&text, keep_acronyms)
+        })
+    }
+}
+
+/// Whether `c` is a control character espeak shouldn't see: every C0/C1
+/// control except newline and tab, plus U+FFFD, which stands in for bytes
+/// that weren't valid UTF-8 when the text was decoded lossily
+pub fn is_stray_control(c: char) -> bool {
+    (c.is_control() && c != '\n' && c != '\t') || c == char::REPLACEMENT_CHARACTER
+}
+
+/// Remove the characters [`is_stray_control`] matches, which otherwise
+/// confuse espeak into odd phonemes or errors
+pub fn strip_control_chars(text: &str) -> String {
+    text.chars().filter(|&c| !is_stray_control(c)).collect()
+}
+
+fn strip_markdown(text: &str) -> String {
+    let text = MD_FENCE_RE.replace_all(text, "");
+    let text = MD_RULE_RE.replace_all(&text, "");
+    let text = MD_IMAGE_RE.replace_all(&text, "$1");
+    let text = MD_LINK_RE.replace_all(&text, "$1");
+    let text = MD_HEADING_RE.replace_all(&text, "");
+    let text = MD_QUOTE_RE.replace_all(&text, "");
+    let text = MD_BULLET_RE.replace_all(&text, "");
+    let text = MD_STRONG_RE.replace_all(&text, "$1$2$3");
+    let text = MD_EMPHASIS_RE.replace_all(&text, "$1$2");
+    MD_CODE_RE.replace_all(&text, "$1").to_string()
 }
 
-pub fn normalize_text(text: &str) -> String {
+fn expand_urls(text: &str) -> String {
+    URL_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            let url = &caps[0];
+            // Sentence punctuation right after a URL isn't part of it
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+            let trailing = &url[trimmed.len()..];
+
+            let spoken = trimmed
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .trim_start_matches("www.")
+                .trim_end_matches('/')
+                .replace('.', " dot ")
+                .replace('/', " slash ");
+            format!("{}{}", spoken, trailing)
+        })
+        .to_string()
+}
+
+fn normalize_numbers(text: &str) -> String {
+    let text = THOUSANDS_RE.replace_all(text, |caps: &regex::Captures| caps[0].replace(',', ""));
+    NUM_RANGE_RE
+        .replace_all(&text, |caps: &regex::Captures| {
+            let run = &caps[0];
+            match run.split_once('-') {
+                Some((from, to)) if is_range(from, to) => format!("{} to {}", from, to),
+                _ => run.to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Whether `from-to` reads as a range: one hyphen, counting up, and not
+/// shaped like a phone number (`555-1234`)
+fn is_range(from: &str, to: &str) -> bool {
+    let phone_shaped = from.len() == 3 && to.len() == 4;
+    match (from.parse::<u64>(), to.parse::<u64>()) {
+        (Ok(start), Ok(end)) => start < end && !phone_shaped,
+        // A second hyphen or an unparseable run
+        _ => false,
+    }
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    let text = INLINE_SPACE_RE.replace_all(text, " ");
+    let text = text.lines().map(str::trim).collect::<Vec<_>>().join("\n");
+    BLANK_LINES_RE.replace_all(&text, "\n\n").trim().to_string()
+}
+
+/// Normalize text the way Kokoro's reference implementation does before
+/// phonemizing. Dots in initials and acronyms become hyphens (`U.S.A.` ->
+/// `U-S-A.`) unless `keep_acronyms` is set, which leaves them for espeak's
+/// own handling.
+pub fn normalize_text(text: &str, keep_acronyms: bool) -> String {
     let mut text = text.to_string();
 
     // Replace special quotes and brackets
@@ -44,28 +267,148 @@ pub fn normalize_text(text: &str) -> String {
         text = text.replace(*from, &format!("{} ", to));
     }
 
-    // Apply regex replacements
+    // Apply regex replacements. The `regex` crate has no lookaround, so the
+    // context is captured and put back, and patterns whose matches can
+    // share a character ("1,2,3") run twice.
     text = WHITESPACE_RE.replace_all(&text, " ").to_string();
     text = MULTI_SPACE_RE.replace_all(&text, " ").to_string();
-    text = NEWLINE_SPACE_RE.replace_all(&text, "").to_string();
-    text = DOCTOR_RE.replace_all(&text, "Doctor").to_string();
-    text = MISTER_RE.replace_all(&text, "Mister").to_string();
-    text = MISS_RE.replace_all(&text, "Miss").to_string();
-    text = MRS_RE.replace_all(&text, "Mrs").to_string();
-    text = ETC_RE.replace_all(&text, "etc").to_string();
+    for _ in 0..2 {
+        text = NEWLINE_SPACE_RE.replace_all(&text, "\n\n").to_string();
+    }
+    text = DOCTOR_RE.replace_all(&text, "Doctor$1").to_string();
+    text = MISTER_RE.replace_all(&text, "Mister$1").to_string();
+    text = MISS_RE.replace_all(&text, "Miss$1").to_string();
+    text = MRS_RE.replace_all(&text, "Mrs$1").to_string();
+    // "etc." stays when it ends a sentence
+    text = ETC_RE
+        .replace_all(&text, |caps: &regex::Captures| match caps.get(1) {
+            Some(_) => caps[0].to_string(),
+            None => "etc".to_string(),
+        })
+        .to_string();
     text = YEAH_RE.replace_all(&text, "${1}e'a").to_string();
     // Note: split_num, flip_money, and point_num functions need to be implemented
-    text = COMMA_NUM_RE.replace_all(&text, "").to_string();
-    text = RANGE_RE.replace_all(&text, " to ").to_string();
-    text = S_AFTER_NUM_RE.replace_all(&text, " S").to_string();
-    text = POSSESSIVE_RE.replace_all(&text, "'S").to_string();
-    text = X_POSSESSIVE_RE.replace_all(&text, "s").to_string();
+    for _ in 0..2 {
+        text = COMMA_NUM_RE.replace_all(&text, "$1$2").to_string();
+        text = RANGE_RE.replace_all(&text, "$1 to $2").to_string();
+    }
+    text = S_AFTER_NUM_RE.replace_all(&text, "$1 S").to_string();
+    text = POSSESSIVE_RE.replace_all(&text, "$1'S").to_string();
+    text = X_POSSESSIVE_RE.replace_all(&text, "X's").to_string();
 
     // Handle initials and acronyms
-    text = INITIALS_RE
-        .replace_all(&text, |caps: &regex::Captures| caps[0].replace('.', "-"))
-        .to_string();
-    text = ACRONYM_RE.replace_all(&text, "-").to_string();
+    if !keep_acronyms {
+        text = INITIALS_RE
+            .replace_all(&text, |caps: &regex::Captures| caps[0].replace('.', "-"))
+            .to_string();
+        for _ in 0..2 {
+            text = ACRONYM_RE.replace_all(&text, "$1-$2").to_string();
+        }
+    }
 
     text.trim().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_control_chars() {
+        assert_eq!(
+            strip_control_chars("Hel\u{0}lo\u{7},\u{1b}[31m world\u{7f}\u{85}\u{fffd}!"),
+            "Hello,[31m world!"
+        );
+        // Line breaks and tabs are layout, not noise
+        assert_eq!(strip_control_chars("One.\n\tTwo."), "One.\n\tTwo.");
+        assert!(!is_stray_control('é'));
+    }
+
+    #[test]
+    fn test_preprocessing_steps() {
+        assert_eq!(
+            PreprocessStep::StripMarkdown
+                .apply("# Title\n\nSome **bold** and `code`, see [docs](https://x.io)."),
+            "Title\n\nSome bold and code, see docs."
+        );
+        assert_eq!(
+            PreprocessStep::ExpandUrls.apply("Visit https://example.com/docs."),
+            "Visit example dot com slash docs."
+        );
+        assert_eq!(
+            PreprocessStep::NormalizeNumbers.apply("Pages 1-5 of 1,000,000"),
+            "Pages 1 to 5 of 1000000"
+        );
+        assert_eq!(
+            PreprocessStep::NormalizeWhitespace.apply("  a \t b \n\n\n\n c  "),
+            "a b\n\nc"
+        );
+    }
+
+    #[test]
+    fn test_pipeline_order_and_names() {
+        let pipeline = Pipeline::from_names(&["strip_markdown", "expand_urls"]).unwrap();
+        assert_eq!(
+            pipeline.run("See [the site](https://a.io) or https://b.io"),
+            "See the site or b dot io"
+        );
+
+        assert!(Pipeline::from_names(&["none"]).unwrap().steps().is_empty());
+        assert!(Pipeline::from_names(&["bogus"]).is_err());
+        assert!(Pipeline::default().steps().is_empty());
+        assert_eq!(Pipeline::default().run("**as sent**"), "**as sent**");
+
+        let pipeline = Pipeline::from_names(&["normalize_text"]).unwrap();
+        assert_eq!(pipeline.run("The U.S.A. is big"), "The U-S-A- is big");
+        assert_eq!(
+            pipeline.run_with("The U.S.A. is big", true),
+            "The U.S.A. is big"
+        );
+        let pipeline = pipeline.with_keep_acronyms(true);
+        assert_eq!(pipeline.run("The U.S.A. is big"), "The U.S.A. is big");
+    }
+
+    #[test]
+    fn test_preprocessing_leaves_lookalikes_alone() {
+        let numbers = PreprocessStep::NormalizeNumbers;
+        for text in [
+            "Due 2024-01-15.",
+            "Call 555-1234 or 1-800-555-0199.",
+            "It ended 10-5.",
+            "Values 1,2345 and 12,34.",
+        ] {
+            assert_eq!(numbers.apply(text), text);
+        }
+        assert_eq!(
+            numbers.apply("In 1990-1995, 12,345,678 people"),
+            "In 1990 to 1995, 12345678 people"
+        );
+
+        let markdown = PreprocessStep::StripMarkdown;
+        for text in ["2*3*4 = 24", "a * b * c", "call snake_case_name"] {
+            assert_eq!(markdown.apply(text), text);
+        }
+        assert_eq!(markdown.apply("*one* and __two__"), "one and two");
+    }
+
+    #[test]
+    fn test_keep_acronyms() {
+        assert_eq!(
+            normalize_text("Made in the U.S.A.", false),
+            "Made in the U-S-A."
+        );
+        assert_eq!(
+            normalize_text("Made in the U.S.A.", true),
+            "Made in the U.S.A."
+        );
+        // Initials before a lowercase word lose their last dot too
+        assert_eq!(
+            normalize_text("The U.S.A. is big", false),
+            "The U-S-A- is big"
+        );
+        assert_eq!(
+            normalize_text("The U.S.A. is big", true),
+            "The U.S.A. is big"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Entries a cache keeps when no capacity is given
+pub const DEFAULT_PHONEME_CACHE_CAPACITY: usize = 100_000;
+
+/// Text → phonemes cache, grouped by espeak language, that can be persisted as JSON.
+///
+/// Pinning phonemes this way makes repeated runs skip espeak entirely and keeps
+/// their output reproducible. The file looks like `{"en-us": {"Hello.": "həlˈoʊ."}}`.
+/// Past its capacity the oldest entries are evicted first, so a long-running
+/// server doesn't grow it without bound.
+pub struct PhonemeCache {
+    entries: Mutex<Entries>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_language: HashMap<String, HashMap<String, String>>,
+    /// `(language, text)` keys, oldest first
+    order: VecDeque<(String, String)>,
+    /// Whether anything was inserted since the last save
+    changed: bool,
+}
+
+impl Entries {
+    fn evict_to(&mut self, capacity: usize) {
+        while self.order.len() > capacity {
+            let Some((language, text)) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(texts) = self.by_language.get_mut(&language) {
+                texts.remove(&text);
+                if texts.is_empty() {
+                    self.by_language.remove(&language);
+                }
+            }
+            self.changed = true;
+        }
+    }
+}
+
+impl Default for PhonemeCache {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::default(),
+            capacity: DEFAULT_PHONEME_CACHE_CAPACITY,
+        }
+    }
+}
+
+impl PhonemeCache {
+    /// Load a cache file, starting empty if it doesn't exist yet
+    pub fn load(path: &str) -> Result<Self, String> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let by_language: HashMap<String, HashMap<String, String>> = serde_json::from_str(&data)
+            .map_err(|e| format!("invalid phoneme cache {}: {}", path, e))?;
+        let order = by_language
+            .iter()
+            .flat_map(|(language, texts)| texts.keys().map(|text| (language.clone(), text.clone())))
+            .collect();
+        let cache = Self {
+            entries: Mutex::new(Entries {
+                by_language,
+                order,
+                changed: false,
+            }),
+            ..Self::default()
+        };
+        cache.entries.lock().unwrap().evict_to(cache.capacity);
+        Ok(cache)
+    }
+
+    /// Keep at most `capacity` entries, evicting the oldest beyond it
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        self.entries.lock().unwrap().evict_to(capacity);
+        Self { capacity, ..self }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let data = serde_json::to_string_pretty(&entries.by_language).map_err(|e| e.to_string())?;
+        std::fs::write(path, data).map_err(|e| e.to_string())?;
+        entries.changed = false;
+        Ok(())
+    }
+
+    /// Whether entries were added or evicted since the cache was loaded or last saved
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.entries.lock().unwrap().changed
+    }
+
+    pub fn get(&self, language: &str, text: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .by_language
+            .get(language)
+            .and_then(|texts| texts.get(text))
+            .cloned()
+    }
+
+    pub fn insert(&self, language: &str, text: &str, phonemes: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let previous = entries
+            .by_language
+            .entry(language.to_string())
+            .or_default()
+            .insert(text.to_string(), phonemes.to_string());
+        if previous.is_none() {
+            entries
+                .order
+                .push_back((language.to_string(), text.to_string()));
+        }
+        entries.changed = true;
+        entries.evict_to(self.capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phoneme_cache_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("phoneme-cache-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        let cache = PhonemeCache::load(path).unwrap();
+        assert!(cache.is_empty());
+        cache.insert("en-us", "Hello.", "həlˈoʊ.");
+        cache.save(path).unwrap();
+
+        let reloaded = PhonemeCache::load(path).unwrap();
+        assert_eq!(reloaded.get("en-us", "Hello."), Some("həlˈoʊ.".to_string()));
+        assert_eq!(reloaded.get("en-gb", "Hello."), None);
+        assert_eq!(reloaded.len(), 1);
+        assert!(!reloaded.has_unsaved_changes());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_phoneme_cache_evicts_oldest() {
+        let cache = PhonemeCache::default().with_capacity(2);
+        cache.insert("en-us", "One.", "wˈʌn.");
+        cache.insert("en-us", "Two.", "tˈuː.");
+        cache.insert("en-us", "One.", "wˈʌn.");
+        assert_eq!(cache.len(), 2);
+
+        cache.insert("en-gb", "Three.", "θɹˈiː.");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("en-us", "One."), None);
+        assert_eq!(cache.get("en-us", "Two."), Some("tˈuː.".to_string()));
+        assert_eq!(cache.get("en-gb", "Three."), Some("θɹˈiː.".to_string()));
+        assert!(cache.has_unsaved_changes());
+
+        let cache = cache.with_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("en-us", "Two."), None);
+    }
+}
@@ -9,6 +9,61 @@ lazy_static! {
     static ref NINETY_PATTERN: Regex = Regex::new(r"(?<=nˈaɪn)ti(?!ː)").unwrap();
 }
 
+/// Kokoro-specific fix-ups of espeak's output, the default [`PhonemeRules`]
+const KOKORO_RULES: [(&str, &str); 2] = [("kəkˈoːɹoʊ", "kˈoʊkəɹoʊ"), ("kəkˈɔːɹəʊ", "kˈəʊkəɹəʊ")];
+
+/// Regex replacements applied in order to espeak's phonemes, for fixing
+/// sequences espeak gets wrong without recompiling.
+///
+/// Loaded from a JSON list of `[pattern, replacement]` pairs, e.g.
+/// `[["kəkˈoːɹoʊ", "kˈoʊkəɹoʊ"]]`; replacements can refer to capture groups
+/// as `$1`. A loaded list replaces the built-in kokoro rules.
+#[derive(Debug, Clone)]
+pub struct PhonemeRules {
+    rules: Vec<(Regex, String)>,
+}
+
+impl PhonemeRules {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rules: Vec<(String, String)> = serde_json::from_str(&data)
+            .map_err(|e| format!("invalid phoneme rules {}: {}", path, e))?;
+        Self::new(rules)
+    }
+
+    pub fn new(rules: Vec<(String, String)>) -> Result<Self, String> {
+        let rules = rules
+            .into_iter()
+            .map(|(pattern, replacement)| {
+                let regex = Regex::new(&pattern)
+                    .map_err(|e| format!("invalid phoneme rule {:?}: {}", pattern, e))?;
+                Ok((regex, replacement))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn apply(&self, phonemes: &str) -> String {
+        let mut phonemes = phonemes.to_string();
+        for (pattern, replacement) in &self.rules {
+            phonemes = pattern
+                .replace_all(&phonemes, replacement.as_str())
+                .into_owned();
+        }
+        phonemes
+    }
+}
+
+impl Default for PhonemeRules {
+    fn default() -> Self {
+        let rules = KOKORO_RULES
+            .iter()
+            .map(|(from, to)| (regex::escape(from), to.to_string()))
+            .collect();
+        Self::new(rules).unwrap()
+    }
+}
+
 // Placeholder for the EspeakBackend struct
 struct EspeakBackend {
     language: String,
@@ -35,6 +90,7 @@ impl EspeakBackend {
 pub struct Phonemizer {
     lang: String,
     backend: EspeakBackend,
+    rules: PhonemeRules,
 }
 
 impl Phonemizer {
@@ -48,12 +104,19 @@ impl Phonemizer {
         Phonemizer {
             lang: lang.to_string(),
             backend,
+            rules: PhonemeRules::default(),
         }
     }
 
+    /// Use `rules` instead of the built-in kokoro replacements
+    pub fn with_rules(mut self, rules: PhonemeRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
     pub fn phonemize(&self, text: &str, normalize: bool) -> String {
         let text = if normalize {
-            normalize::normalize_text(text)
+            normalize::normalize_text(text, false)
         } else {
             text.to_string()
         };
@@ -64,10 +127,8 @@ impl Phonemizer {
             None => String::new(),
         };
 
-        // Apply kokoro-specific replacements
-        ps = ps
-            .replace("kəkˈoːɹoʊ", "kˈoʊkəɹoʊ")
-            .replace("kəkˈɔːɹəʊ", "kˈəʊkəɹəʊ");
+        // Apply kokoro-specific (or configured) replacements
+        ps = self.rules.apply(&ps);
 
         // Apply character replacements
         ps = ps
@@ -90,3 +151,25 @@ impl Phonemizer {
         ps.trim().to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phoneme_rules() {
+        let defaults = PhonemeRules::default();
+        assert_eq!(
+            defaults.apply("ðə kəkˈoːɹoʊ mˈɑːdəl"),
+            "ðə kˈoʊkəɹoʊ mˈɑːdəl"
+        );
+
+        // Custom rules replace the defaults and can use capture groups
+        let rules =
+            PhonemeRules::new(vec![(r"ɛ(n|m)dʒ".to_string(), "e${1}dʒ".to_string())]).unwrap();
+        assert_eq!(rules.apply("ˈɛndʒɪn ˈɛmdʒ"), "ˈendʒɪn ˈemdʒ");
+        assert_eq!(rules.apply("kəkˈoːɹoʊ"), "kəkˈoːɹoʊ");
+
+        assert!(PhonemeRules::new(vec![("(".to_string(), String::new())]).is_err());
+    }
+}
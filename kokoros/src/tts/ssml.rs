@@ -0,0 +1,169 @@
+//! A small subset of SSML, enough to place pauses in the text.
+//!
+//! Input opting in starts with a `<speak>` tag. Inside it:
+//! - `<break time="500ms"/>` or `<break time="1.5s"/>` inserts a pause, and
+//!   `<break strength="strong"/>` one of a fixed length per strength.
+//!   A bare `<break/>` is a medium pause.
+//! - `<emphasis>` is accepted but only its text is kept, as Kokoro has no way
+//!   to stress words on request.
+//! - Any other tag is dropped, keeping its text, and the five XML entities
+//!   (`&amp;` and friends) are decoded.
+//!
+//! Text without the `<speak>` prefix is never parsed, so a stray `<` in plain
+//! input is read as is.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref TAG_RE: Regex = Regex::new(r"<\s*(/?)\s*([A-Za-z][\w:-]*)([^>]*)>").unwrap();
+    static ref TIME_RE: Regex =
+        Regex::new(r#"time\s*=\s*["']\s*(\d+(?:\.\d+)?)\s*(ms|s)\s*["']"#).unwrap();
+    static ref STRENGTH_RE: Regex = Regex::new(r#"strength\s*=\s*["']\s*([\w-]+)\s*["']"#).unwrap();
+}
+
+/// Longest pause a single break can ask for, which keeps its silence tokens
+/// well within one model call
+pub const MAX_BREAK_MS: u32 = 10_000;
+
+/// Text to speak, preceded by a pause
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub pause_ms: u32,
+    pub text: String,
+}
+
+/// Whether `text` opts in to SSML parsing by starting with `<speak>`
+pub fn is_ssml(text: &str) -> bool {
+    let text = text.trim_start();
+    text.strip_prefix("<speak")
+        .is_some_and(|rest| rest.starts_with(['>', ' ', '\t', '\n', '\r', '/']))
+}
+
+/// Pause for a break's `strength`, `None` for an unknown one
+fn strength_ms(strength: &str) -> Option<u32> {
+    match strength {
+        "none" => Some(0),
+        "x-weak" => Some(100),
+        "weak" => Some(250),
+        "medium" => Some(500),
+        "strong" => Some(750),
+        "x-strong" => Some(1000),
+        _ => None,
+    }
+}
+
+/// Pause length of a `<break>` from its attributes, a `time` taking
+/// precedence over a `strength`
+fn break_ms(attributes: &str) -> u32 {
+    let ms = if let Some(caps) = TIME_RE.captures(attributes) {
+        let value: f64 = caps[1].parse().unwrap_or_default();
+        match &caps[2] {
+            "s" => value * 1000.0,
+            _ => value,
+        }
+        .round() as u32
+    } else {
+        STRENGTH_RE
+            .captures(attributes)
+            .and_then(|caps| strength_ms(&caps[1]))
+            .unwrap_or(500)
+    };
+    ms.min(MAX_BREAK_MS)
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Split SSML into text segments, each carrying the total pause of the
+/// breaks just before it. A pause after the last text ends up on a final
+/// segment with empty text. Tags are removed from the text.
+pub fn parse(ssml: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut pause_ms = 0;
+    let mut text = String::new();
+    let mut flush = |pause_ms: &mut u32, text: &mut String| {
+        let decoded = decode_entities(text);
+        let words: Vec<&str> = decoded.split_whitespace().collect();
+        if !words.is_empty() || *pause_ms > 0 {
+            segments.push(Segment {
+                pause_ms: *pause_ms,
+                text: words.join(" "),
+            });
+        }
+        *pause_ms = 0;
+        text.clear();
+    };
+
+    let mut last = 0;
+    for caps in TAG_RE.captures_iter(ssml) {
+        let tag = caps.get(0).unwrap();
+        text.push_str(&ssml[last..tag.start()]);
+        last = tag.end();
+
+        let closing = !caps[1].is_empty();
+        if !closing && caps[2].eq_ignore_ascii_case("break") {
+            if !text.trim().is_empty() {
+                flush(&mut pause_ms, &mut text);
+            }
+            pause_ms = (pause_ms + break_ms(&caps[3])).min(MAX_BREAK_MS);
+        } else if !caps[2].eq_ignore_ascii_case("emphasis") {
+            // Keep words on either side of a dropped tag apart
+            text.push(' ');
+        }
+    }
+    text.push_str(&ssml[last..]);
+    flush(&mut pause_ms, &mut text);
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_break_starts_a_segment_with_its_pause() {
+        let segments = parse(
+            r#"<speak>Hello &amp; welcome. <break time="500ms"/> How are <emphasis>you</emphasis>?<break time="1.2s"/></speak>"#,
+        );
+        assert_eq!(
+            segments,
+            vec![
+                Segment {
+                    pause_ms: 0,
+                    text: "Hello & welcome.".to_string()
+                },
+                Segment {
+                    pause_ms: 500,
+                    text: "How are you?".to_string()
+                },
+                Segment {
+                    pause_ms: 1200,
+                    text: String::new()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_break_attributes() {
+        assert_eq!(break_ms(r#" time="250ms""#), 250);
+        assert_eq!(break_ms(r#" time='2s' "#), 2000);
+        assert_eq!(break_ms(r#" strength="x-strong""#), 1000);
+        assert_eq!(break_ms(""), 500);
+        assert_eq!(break_ms(r#" time="60s""#), MAX_BREAK_MS);
+    }
+
+    #[test]
+    fn test_only_speak_prefix_is_ssml() {
+        assert!(is_ssml("<speak>Hi</speak>"));
+        assert!(is_ssml("  <speak version=\"1.1\">Hi</speak>"));
+        assert!(!is_ssml("<speaker>Hi"));
+        assert!(!is_ssml("1 < 2 and <break/> stays as text"));
+    }
+}
@@ -19,6 +19,32 @@ pub fn tokenize(phonemes: &str) -> Vec<i64> {
         .collect()
 }
 
+/// Like [`tokenize`], but also returns the characters it dropped for not
+/// being in `VOCAB`, in order and including repeats
+pub fn tokenize_verbose(phonemes: &str) -> (Vec<i64>, Vec<char>) {
+    let mut tokens = Vec::new();
+    let mut dropped = Vec::new();
+    for c in phonemes.chars() {
+        match VOCAB.get(&c) {
+            Some(&idx) => tokens.push(idx as i64),
+            None => dropped.push(c),
+        }
+    }
+    (tokens, dropped)
+}
+
+/// Characters of `phonemes` that [`tokenize`] drops for not being in `VOCAB`,
+/// each listed once in order of appearance
+pub fn unknown_phonemes(phonemes: &str) -> Vec<char> {
+    let mut unknown = Vec::new();
+    for c in phonemes.chars() {
+        if !VOCAB.contains_key(&c) && !unknown.contains(&c) {
+            unknown.push(c);
+        }
+    }
+    unknown
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,6 +69,23 @@ mod tests {
         let punct_tokens = tokenize(punct);
         assert_eq!(punct_tokens.len(), 3);
     }
+
+    #[test]
+    fn test_unknown_phonemes() {
+        assert_eq!(unknown_phonemes("hə☃lˈoʊ☃ ⁂"), vec!['☃', '⁂']);
+        assert!(unknown_phonemes("həlˈoʊ").is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_verbose_reports_dropped() {
+        let (tokens, dropped) = tokenize_verbose("hə☃lˈoʊ☃");
+        assert_eq!(tokens, tokenize("həlˈoʊ"));
+        assert_eq!(dropped, vec!['☃', '☃']);
+
+        let (tokens, dropped) = tokenize_verbose("həlˈoʊ");
+        assert_eq!(tokens, tokenize("həlˈoʊ"));
+        assert!(dropped.is_empty());
+    }
 }
 
 use crate::tts::vocab::REVERSE_VOCAB;
@@ -0,0 +1,137 @@
+/// Metadata derived from a voice name's two-character prefix, e.g. `af_sky`
+/// is an American (`a`) female (`f`) voice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceInfo {
+    pub id: String,
+    /// espeak language code matching the voice
+    pub language: Option<&'static str>,
+    pub gender: Option<&'static str>,
+    pub region: Option<&'static str>,
+}
+
+impl VoiceInfo {
+    /// Human readable group name used in logs, e.g. "American Female(af)"
+    pub fn category(&self) -> String {
+        let prefix = self.id.get(0..2).unwrap_or(&self.id);
+        match (self.region, self.gender) {
+            (Some(region), Some(gender)) => {
+                let gender = if gender == "female" { "Female" } else { "Male" };
+                format!("{} {}({})", region, gender, prefix)
+            }
+            _ => prefix.to_string(),
+        }
+    }
+}
+
+/// Look up language, gender and region for a voice from its prefix.
+/// Unknown prefixes yield `None` fields rather than an error.
+pub fn voice_metadata(name: &str) -> VoiceInfo {
+    let mut chars = name.chars();
+    let (region, language) = match chars.next() {
+        Some('a') => (Some("American"), Some("en-us")),
+        Some('b') => (Some("British"), Some("en-gb")),
+        Some('e') => (Some("European"), Some("es")),
+        Some('f') => (Some("French"), Some("fr-fr")),
+        Some('h') => (Some("Hindi"), Some("hi")),
+        Some('i') => (Some("Italian"), Some("it")),
+        Some('j') => (Some("Japanese"), Some("ja")),
+        Some('p') => (Some("Portuguese"), Some("pt-br")),
+        Some('z') => (Some("Chinese"), Some("zh")),
+        _ => (None, None),
+    };
+    let gender = match chars.next() {
+        Some('f') => Some("female"),
+        Some('m') => Some("male"),
+        _ => None,
+    };
+
+    // A voice is only categorized when both halves of the prefix are known
+    let known = region.is_some() && gender.is_some();
+    VoiceInfo {
+        id: name.to_string(),
+        language: if known { language } else { None },
+        gender: if known { gender } else { None },
+        region: if known { region } else { None },
+    }
+}
+
+/// Language implied by a style, which may be a mix such as
+/// `af_sky.4+af_nicole.5`. `None` unless every voice in it has the same
+/// known language.
+pub fn style_language(style: &str) -> Option<&'static str> {
+    let mut languages = style
+        .split('+')
+        .map(|part| voice_metadata(part.split_once('.').map_or(part, |(name, _)| name)).language);
+    let first = languages.next()??;
+    languages
+        .all(|language| language == Some(first))
+        .then_some(first)
+}
+
+/// Keep the voices whose prefix implies `language` (compared case-insensitively).
+/// An unknown language simply matches nothing.
+pub fn filter_by_language(names: &[String], language: &str) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| {
+            voice_metadata(name)
+                .language
+                .is_some_and(|lang| lang.eq_ignore_ascii_case(language))
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_metadata_prefixes() {
+        let sky = voice_metadata("af_sky");
+        assert_eq!(sky.language, Some("en-us"));
+        assert_eq!(sky.gender, Some("female"));
+        assert_eq!(sky.region, Some("American"));
+        assert_eq!(sky.category(), "American Female(af)");
+
+        let george = voice_metadata("bm_george");
+        assert_eq!(george.language, Some("en-gb"));
+        assert_eq!(george.gender, Some("male"));
+        assert_eq!(george.category(), "British Male(bm)");
+
+        assert_eq!(voice_metadata("jf_alpha").language, Some("ja"));
+        assert_eq!(voice_metadata("zm_yunxi").category(), "Chinese Male(zm)");
+
+        let unknown = voice_metadata("xx_custom");
+        assert_eq!(unknown.language, None);
+        assert_eq!(unknown.category(), "xx");
+    }
+
+    #[test]
+    fn test_style_language() {
+        assert_eq!(style_language("ff_siwis"), Some("fr-fr"));
+        assert_eq!(style_language("af_sky.4+af_nicole.6"), Some("en-us"));
+        // Mixed languages or unknown voices don't imply one
+        assert_eq!(style_language("af_sky.5+ff_siwis.5"), None);
+        assert_eq!(style_language("my_voice"), None);
+    }
+
+    #[test]
+    fn test_filter_by_language() {
+        let names: Vec<String> = ["af_sky", "jf_alpha", "bm_george", "jm_kumo", "xx_custom"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let japanese = filter_by_language(&names, "ja");
+        assert_eq!(japanese, vec!["jf_alpha", "jm_kumo"]);
+        assert!(
+            japanese
+                .iter()
+                .all(|v| v.starts_with("jf_") || v.starts_with("jm_"))
+        );
+
+        assert_eq!(filter_by_language(&names, "EN-US"), vec!["af_sky"]);
+        assert!(filter_by_language(&names, "tlh").is_empty());
+    }
+}
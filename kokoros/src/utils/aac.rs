@@ -0,0 +1,91 @@
+use fdk_aac::enc::{AudioObjectType, BitRate, ChannelMode, Encoder, EncoderParams, Transport};
+
+/// Constant bitrate used for speech; plenty for mono 24kHz AAC-LC
+pub const DEFAULT_BITRATE_BPS: u32 = 64_000;
+
+/// Sampling frequencies with an ADTS sampling-frequency index, in index order
+const SAMPLING_FREQUENCIES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// Samples per AAC-LC frame
+const FRAME_SAMPLES: usize = 1024;
+
+/// ADTS sampling-frequency index for a sample rate, if AAC supports it
+pub fn sampling_frequency_index(sample_rate: u32) -> Option<u8> {
+    SAMPLING_FREQUENCIES
+        .iter()
+        .position(|&rate| rate == sample_rate)
+        .map(|index| index as u8)
+}
+
+/// Encode mono samples as an ADTS-framed AAC-LC stream.
+///
+/// There is no resampler here, so sample rates AAC can't signal are rejected
+/// with `InvalidInput`.
+pub fn pcm_to_aac(pcm_data: &[f32], sample_rate: u32) -> Result<Vec<u8>, std::io::Error> {
+    if sampling_frequency_index(sample_rate).is_none() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Unsupported AAC sample rate: {} Hz", sample_rate),
+        ));
+    }
+
+    let encoder = Encoder::new(EncoderParams {
+        bit_rate: BitRate::Cbr(DEFAULT_BITRATE_BPS),
+        sample_rate,
+        transport: Transport::Adts,
+        channels: ChannelMode::Mono,
+        audio_object_type: AudioObjectType::Mpeg4LowComplexity,
+    })
+    .map_err(|e| std::io::Error::other(format!("Encoder init failed: {}", e)))?;
+
+    let mut samples: Vec<i16> = pcm_data
+        .iter()
+        .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
+        .collect();
+    // The wrapper can't signal end of stream, so push the encoder's
+    // lookahead out with a few frames of silence instead
+    samples.resize(samples.len() + 3 * FRAME_SAMPLES, 0);
+
+    let mut aac_data = Vec::new();
+    let mut buffer = vec![0u8; 8192];
+    let mut position = 0;
+    while position < samples.len() {
+        let info = encoder
+            .encode(&samples[position..], &mut buffer)
+            .map_err(|e| std::io::Error::other(format!("Encoding failed: {}", e)))?;
+        aac_data.extend_from_slice(&buffer[..info.output_size]);
+        if info.input_consumed == 0 && info.output_size == 0 {
+            break;
+        }
+        position += info.input_consumed;
+    }
+
+    Ok(aac_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adts_header_sample_rate() {
+        let tone: Vec<f32> = (0..24000).map(|i| 0.3 * (i as f32 * 0.05).sin()).collect();
+        let aac = pcm_to_aac(&tone, 24000).unwrap();
+
+        // ADTS syncword, then the sampling-frequency index in bits 2..6 of byte 2
+        assert!(aac.len() > 7);
+        assert_eq!(aac[0], 0xFF);
+        assert_eq!(aac[1] & 0xF0, 0xF0);
+        let index = (aac[2] >> 2) & 0x0F;
+        assert_eq!(Some(index), sampling_frequency_index(24000));
+        assert_eq!(index, 6);
+    }
+
+    #[test]
+    fn test_unsupported_sample_rate_is_rejected() {
+        let err = pcm_to_aac(&[0.0; 100], 23000).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
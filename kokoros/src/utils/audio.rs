@@ -0,0 +1,629 @@
+/// Gating block length for integrated loudness (ITU-R BS.1770)
+const BLOCK_MS: u32 = 400;
+
+/// Blocks overlap by 75%, so a new block starts every 100ms
+const BLOCK_STEP_MS: u32 = 100;
+
+/// Blocks quieter than this are ignored entirely
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Blocks this far below the ungated loudness are ignored as pauses
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+/// Analysis frame for the pitch shifter's time stretch
+const STRETCH_FRAME_MS: u32 = 40;
+
+/// Zero crossings of the resampling filter on either side of each output
+/// sample, at the lower of the two rates
+const RESAMPLE_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Fraction of the ceiling below which [`peak_limit`] leaves samples as they are
+const LIMITER_KNEE: f32 = 0.8;
+
+/// Quiet samples [`trim_leading_silence`] keeps before the onset, 10 ms at
+/// Kokoro's 24 kHz
+pub const TRIM_MARGIN_SAMPLES: usize = 240;
+
+/// Direct form I biquad, coefficients normalized so `a0 == 1`
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// The two K-weighting stages, a high shelf modelling the head followed by a
+/// high-pass, derived for any sample rate the way libebur128 does
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+fn mean_square_to_lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Integrated loudness of mono samples in LUFS, or `None` for silence.
+///
+/// A basic ITU-R BS.1770 measurement: K-weighting, 400ms blocks with 75%
+/// overlap, then the absolute and relative gates. Input shorter than one
+/// block is measured as a single block.
+pub fn integrated_loudness(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let [mut shelf, mut high_pass] = k_weighting(sample_rate);
+    let weighted: Vec<f64> = samples
+        .iter()
+        .map(|&s| {
+            let s = high_pass.process(shelf.process(s as f64));
+            s * s
+        })
+        .collect();
+
+    let block_len = (sample_rate * BLOCK_MS / 1000) as usize;
+    let step = (sample_rate * BLOCK_STEP_MS / 1000) as usize;
+    let blocks: Vec<f64> = if weighted.len() <= block_len {
+        vec![weighted.iter().sum::<f64>() / weighted.len().max(1) as f64]
+    } else {
+        (0..=(weighted.len() - block_len) / step)
+            .map(|i| {
+                let block = &weighted[i * step..i * step + block_len];
+                block.iter().sum::<f64>() / block_len as f64
+            })
+            .collect()
+    };
+
+    let gated_mean = |threshold_lufs: f64| {
+        let passing: Vec<f64> = blocks
+            .iter()
+            .copied()
+            .filter(|&z| z > 0.0 && mean_square_to_lufs(z) > threshold_lufs)
+            .collect();
+        (!passing.is_empty()).then(|| passing.iter().sum::<f64>() / passing.len() as f64)
+    };
+
+    let ungated = gated_mean(ABSOLUTE_GATE_LUFS)?;
+    let relative_gate = mean_square_to_lufs(ungated) - RELATIVE_GATE_LU;
+    let integrated = gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS))?;
+    Some(mean_square_to_lufs(integrated) as f32)
+}
+
+/// Scale samples so their integrated loudness lands on `target_lufs`
+/// (e.g. -16 for speech on the web, -23 for EBU R128 broadcast).
+///
+/// Only a gain is applied, there is no limiter: raising quiet audio can push
+/// peaks past full scale, which the encoders then clip. Silence is left as is.
+pub fn normalize_loudness(samples: &mut [f32], sample_rate: u32, target_lufs: f32) {
+    let Some(measured) = integrated_loudness(samples, sample_rate) else {
+        return;
+    };
+    let gain = 10f32.powf((target_lufs - measured) / 20.0);
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+/// Subtract the mean so the samples are centred on zero.
+/// A constant bias left on a chunk pops where chunks are joined.
+pub fn remove_dc_offset(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+    let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64;
+    for sample in samples.iter_mut() {
+        *sample -= mean as f32;
+    }
+}
+
+/// Drop the quiet samples before the first one louder than `threshold`,
+/// keeping [`TRIM_MARGIN_SAMPLES`] of them so the onset of the first phoneme,
+/// which rises from below the threshold, isn't clipped. Audio that never
+/// crosses `threshold` is left alone.
+pub fn trim_leading_silence(samples: &mut Vec<f32>, threshold: f32) {
+    if let Some(onset) = samples.iter().position(|s| s.abs() > threshold) {
+        samples.drain(..onset.saturating_sub(TRIM_MARGIN_SAMPLES));
+    }
+}
+
+/// Softly limit peaks so no sample exceeds `ceiling`, for audio such as
+/// blended voices that would otherwise clip when converted to 16-bit.
+///
+/// Levels up to [`LIMITER_KNEE`] of the ceiling pass unchanged; above that
+/// they bend smoothly towards the ceiling along a tanh curve, which keeps the
+/// waveform continuous instead of flattening it into clicks. Audio already
+/// within the ceiling is left alone.
+pub fn peak_limit(samples: &mut [f32], ceiling: f32) {
+    if samples.iter().all(|s| s.abs() <= ceiling) {
+        return;
+    }
+    let knee = ceiling * LIMITER_KNEE;
+    let headroom = ceiling - knee;
+    for sample in samples.iter_mut() {
+        let level = sample.abs();
+        if level > knee {
+            let limited = knee + headroom * ((level - knee) / headroom).tanh();
+            *sample = limited.copysign(*sample);
+        }
+    }
+}
+
+/// Ramp the first `len` samples linearly up from silence, so audio doesn't
+/// start with a click. The first sample becomes zero.
+pub fn fade_in(samples: &mut [f32], len: usize) {
+    let len = len.min(samples.len());
+    for (i, sample) in samples[..len].iter_mut().enumerate() {
+        *sample *= i as f32 / len as f32;
+    }
+}
+
+/// Ramp the last `len` samples linearly down to silence, the mirror of
+/// [`fade_in`]. The last sample becomes zero.
+pub fn fade_out(samples: &mut [f32], len: usize) {
+    let len = len.min(samples.len());
+    let start = samples.len() - len;
+    for (i, sample) in samples[start..].iter_mut().rev().enumerate() {
+        *sample *= i as f32 / len as f32;
+    }
+}
+
+/// First-order high-pass at `cutoff_hz`, for drift too slow to be a
+/// constant offset but still below anything audible
+pub fn high_pass(samples: &mut [f32], sample_rate: u32, cutoff_hz: f32) {
+    let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff_hz);
+    let dt = 1.0 / sample_rate as f32;
+    let alpha = rc / (rc + dt);
+
+    let mut previous_input = samples.first().copied().unwrap_or_default();
+    let mut previous_output = 0.0;
+    for sample in samples.iter_mut() {
+        let input = *sample;
+        previous_output = alpha * (previous_output + input - previous_input);
+        previous_input = input;
+        *sample = previous_output;
+    }
+}
+
+/// WSOLA time stretch: Hann-windowed frames at 50% overlap, each taken from
+/// within a quarter frame of its nominal input position wherever it best
+/// continues the previous frame, so periodic speech doesn't phase-cancel.
+/// Output is `factor` times as long.
+fn time_stretch(samples: &[f32], sample_rate: u32, factor: f64) -> Vec<f32> {
+    let frame = ((sample_rate * STRETCH_FRAME_MS / 1000) as usize).max(4) & !1;
+    let hop = frame / 2;
+    let tolerance = (frame / 4) as isize;
+    let out_len = (samples.len() as f64 * factor).round() as usize;
+
+    // Periodic Hann, which sums to exactly one at 50% overlap
+    let window: Vec<f32> = (0..frame)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / frame as f32).cos())
+        .collect();
+    let at = |position: isize| {
+        usize::try_from(position)
+            .ok()
+            .and_then(|position| samples.get(position))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let mut output = vec![0.0; out_len];
+    let mut previous: Option<isize> = None;
+    // Start half a frame early so the first samples get full overlap too
+    let mut out_position = -(hop as isize);
+    while out_position < out_len as isize {
+        let nominal = (out_position as f64 / factor).round() as isize;
+        let start = match previous {
+            None => nominal,
+            Some(previous) => {
+                let natural = previous + hop as isize;
+                (nominal - tolerance..=nominal + tolerance)
+                    .map(|candidate| {
+                        let correlation = (0..hop as isize)
+                            .map(|i| at(candidate + i) * at(natural + i))
+                            .sum::<f32>();
+                        (candidate, correlation)
+                    })
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map_or(nominal, |(candidate, _)| candidate)
+            }
+        };
+
+        for (i, weight) in window.iter().enumerate() {
+            if let Ok(index) = usize::try_from(out_position + i as isize)
+                && let Some(out) = output.get_mut(index)
+            {
+                *out += weight * at(start + i as isize);
+            }
+        }
+        previous = Some(start);
+        out_position += hop as isize;
+    }
+    output
+}
+
+/// Shift pitch by `semitones` without changing duration: time-stretch by
+/// the pitch ratio, then resample back to the original length.
+/// The output has exactly as many samples as the input.
+pub fn shift_pitch(samples: &[f32], sample_rate: u32, semitones: f32) -> Vec<f32> {
+    if semitones == 0.0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = 2f64.powf(semitones as f64 / 12.0);
+    let stretched = time_stretch(samples, sample_rate, ratio);
+
+    (0..samples.len())
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let current = stretched.get(index).copied().unwrap_or_default();
+            let next = stretched.get(index + 1).copied().unwrap_or(current);
+            current + (next - current) * fraction
+        })
+        .collect()
+}
+
+/// Play `samples` at `tempo` times the speed while keeping their pitch, by
+/// time-stretching them to `1 / tempo` of the length: 0.8 makes speech 25%
+/// longer. Unlike Kokoro's own speed control nothing is synthesized again,
+/// so the prosody stays as it was.
+pub fn change_tempo(samples: &[f32], sample_rate: u32, tempo: f32) -> Vec<f32> {
+    if tempo == 1.0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+    time_stretch(samples, sample_rate, 1.0 / tempo as f64)
+}
+
+/// Interpolation used by [`resample`], trading quality for CPU time.
+///
+/// Per output sample, `Linear` blends 2 input samples and `Cubic` 4
+/// (Catmull-Rom), both a handful of multiply-adds. `Sinc` filters
+/// [`RESAMPLE_ZERO_CROSSINGS`] input samples either side at the lower of the
+/// two rates (96 taps from 24 kHz to 8 kHz), each with a sine and a cosine,
+/// which makes it one to two orders of magnitude slower. Only `Sinc`
+/// removes content above the new Nyquist frequency when downsampling; the
+/// others fold it back down as aliasing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleQuality {
+    #[default]
+    Linear,
+    Cubic,
+    Sinc,
+}
+
+impl std::str::FromStr for ResampleQuality {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "linear" => Ok(Self::Linear),
+            "cubic" => Ok(Self::Cubic),
+            "sinc" => Ok(Self::Sinc),
+            _ => Err(format!(
+                "unknown resample quality {:?} (expected linear, cubic or sinc)",
+                name
+            )),
+        }
+    }
+}
+
+/// Resample `samples` from `from_rate` to `to_rate` with the interpolation
+/// `quality` selects
+pub fn resample(
+    samples: &[f32],
+    from_rate: u32,
+    to_rate: u32,
+    quality: ResampleQuality,
+) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let step = from_rate as f64 / to_rate as f64;
+    let out_len = (samples.len() as f64 / step).round() as usize;
+    let last = samples.len() as isize - 1;
+    let at = |j: isize| samples[j.clamp(0, last) as usize];
+
+    match quality {
+        ResampleQuality::Linear => (0..out_len)
+            .map(|i| {
+                let position = i as f64 * step;
+                let index = position as isize;
+                let t = (position - index as f64) as f32;
+                at(index) + (at(index + 1) - at(index)) * t
+            })
+            .collect(),
+        ResampleQuality::Cubic => (0..out_len)
+            .map(|i| {
+                let position = i as f64 * step;
+                let index = position as isize;
+                let t = (position - index as f64) as f32;
+                let (p0, p1, p2, p3) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+                0.5 * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                    + (3.0 * (p1 - p2) + p3 - p0) * t * t * t)
+            })
+            .collect(),
+        ResampleQuality::Sinc => resample_sinc(samples, step, out_len),
+    }
+}
+
+/// Band-limited resampling by Hann-windowed sinc interpolation. When
+/// downsampling, the filter's cutoff drops to the new Nyquist frequency so
+/// content above it is removed instead of aliasing.
+fn resample_sinc(samples: &[f32], step: f64, out_len: usize) -> Vec<f32> {
+    let cutoff = (1.0 / step).min(1.0);
+    let half_width = RESAMPLE_ZERO_CROSSINGS / cutoff;
+
+    (0..out_len)
+        .map(|i| {
+            let center = i as f64 * step;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width) as usize).min(samples.len() - 1);
+            (first..=last)
+                .map(|j| {
+                    let x = j as f64 - center;
+                    let window = 0.5 + 0.5 * (std::f64::consts::PI * x / half_width).cos();
+                    let arg = std::f64::consts::PI * x * cutoff;
+                    let sinc = if arg == 0.0 { 1.0 } else { arg.sin() / arg };
+                    samples[j] as f64 * cutoff * sinc * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, frequency: f32, sample_rate: u32, seconds: f32) -> Vec<f32> {
+        let len = (sample_rate as f32 * seconds) as usize;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                amplitude * (2.0 * std::f32::consts::PI * frequency * t).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_normalize_loudness_hits_target() {
+        // BS.1770 is calibrated so a full-scale 1kHz sine reads about -3 LUFS
+        let mut tone = sine(0.1, 1000.0, 24000, 3.0);
+        let before = integrated_loudness(&tone, 24000).unwrap();
+        assert!((before - -23.0).abs() < 0.5, "measured {}", before);
+
+        normalize_loudness(&mut tone, 24000, -16.0);
+        let after = integrated_loudness(&tone, 24000).unwrap();
+        assert!((after - -16.0).abs() < 0.1, "measured {}", after);
+
+        let mut silence = vec![0.0; 24000];
+        normalize_loudness(&mut silence, 24000, -16.0);
+        assert!(silence.iter().all(|&s| s == 0.0));
+        assert_eq!(integrated_loudness(&silence, 24000), None);
+    }
+
+    #[test]
+    fn test_remove_dc_offset() {
+        let mut biased: Vec<f32> = sine(0.3, 440.0, 24000, 0.5)
+            .into_iter()
+            .map(|s| s + 0.2)
+            .collect();
+        remove_dc_offset(&mut biased);
+        let mean = biased.iter().sum::<f32>() / biased.len() as f32;
+        assert!(mean.abs() < 1e-4, "mean {}", mean);
+
+        // The high-pass settles a constant input to zero
+        let mut constant = vec![0.2; 24000];
+        high_pass(&mut constant, 24000, 20.0);
+        assert!(constant[12000..].iter().all(|s| s.abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_shift_pitch() {
+        let tone = sine(0.3, 200.0, 24000, 1.0);
+        let unshifted = shift_pitch(&tone, 24000, 0.0);
+        assert_eq!(unshifted.len(), tone.len());
+        assert!(
+            unshifted
+                .iter()
+                .zip(&tone)
+                .all(|(a, b)| (a - b).abs() < 1e-6)
+        );
+
+        // An octave up doubles the frequency, counted in rising zero crossings
+        // away from the edges
+        let rising = |samples: &[f32]| {
+            samples[2400..21600]
+                .windows(2)
+                .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+                .count()
+        };
+        let octave_up = shift_pitch(&tone, 24000, 12.0);
+        assert_eq!(octave_up.len(), tone.len());
+        let (before, after) = (rising(&tone), rising(&octave_up));
+        assert!(after.abs_diff(2 * before) <= 4, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn test_change_tempo() {
+        let tone = sine(0.3, 200.0, 24000, 1.0);
+        assert_eq!(change_tempo(&tone, 24000, 1.0), tone);
+
+        // Half the tempo doubles the length at the same pitch
+        let slow = change_tempo(&tone, 24000, 0.5);
+        assert_eq!(slow.len(), 2 * tone.len());
+        let rising = |samples: &[f32]| {
+            samples[2400..21600]
+                .windows(2)
+                .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+                .count()
+        };
+        let (before, after) = (rising(&tone), rising(&slow));
+        assert!(after.abs_diff(before) <= 4, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn test_resample_filters_above_new_nyquist() {
+        let rms = |samples: &[f32]| {
+            let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+            (middle.iter().map(|s| s * s).sum::<f32>() / middle.len() as f32).sqrt()
+        };
+
+        let tone = sine(0.5, 1000.0, 24000, 1.0);
+        let down = resample(&tone, 24000, 8000, ResampleQuality::Sinc);
+        assert_eq!(down.len(), 8000);
+        assert!((rms(&down) - rms(&tone)).abs() < 0.01, "rms {}", rms(&down));
+
+        // 6kHz can't be represented at 8kHz and must not alias down to 2kHz
+        let high = resample(
+            &sine(0.5, 6000.0, 24000, 1.0),
+            24000,
+            8000,
+            ResampleQuality::Sinc,
+        );
+        assert!(rms(&high) < 0.01, "rms {}", rms(&high));
+    }
+
+    #[test]
+    fn test_resample_quality() {
+        let rms = |samples: &[f32]| {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+
+        // A sweep from 5 to 11kHz lies entirely above 8kHz's Nyquist
+        // frequency, so whatever comes out is aliasing
+        let sweep: Vec<f32> = (0..24000)
+            .map(|i| {
+                let t = i as f32 / 24000.0;
+                let phase = 2.0 * std::f32::consts::PI * (5000.0 * t + 3000.0 * t * t);
+                0.5 * phase.sin()
+            })
+            .collect();
+        let aliasing = |quality| rms(&resample(&sweep, 24000, 8000, quality)[400..7600]);
+        let (linear, cubic, sinc) = (
+            aliasing(ResampleQuality::Linear),
+            aliasing(ResampleQuality::Cubic),
+            aliasing(ResampleQuality::Sinc),
+        );
+        assert!(sinc < linear / 10.0, "sinc {} linear {}", sinc, linear);
+        assert!(sinc < cubic / 10.0, "sinc {} cubic {}", sinc, cubic);
+
+        // Upsampling a low tone works with any of them
+        let tone = sine(0.5, 200.0, 8000, 0.5);
+        for quality in [ResampleQuality::Linear, ResampleQuality::Cubic] {
+            let up = resample(&tone, 8000, 24000, quality);
+            assert_eq!(up.len(), 12000);
+            assert!((rms(&up) - rms(&tone)).abs() < 0.01, "{:?}", quality);
+        }
+
+        assert_eq!("cubic".parse(), Ok(ResampleQuality::Cubic));
+        assert!("nearest".parse::<ResampleQuality>().is_err());
+    }
+
+    #[test]
+    fn test_peak_limit() {
+        let mut loud = sine(1.6, 440.0, 24000, 0.1);
+        let largest_step = |samples: &[f32]| {
+            samples
+                .windows(2)
+                .map(|w| (w[1] - w[0]).abs())
+                .fold(0.0, f32::max)
+        };
+        let step_before = largest_step(&loud);
+
+        peak_limit(&mut loud, 0.98);
+        assert!(loud.iter().all(|s| s.abs() < 0.98));
+        // Bent rather than clipped, so no jump is larger than in the input
+        assert!(largest_step(&loud) <= step_before);
+        assert!(loud.iter().any(|s| s.abs() > 0.95));
+
+        // Audio that fits is untouched, even above the knee
+        let mut quiet = sine(0.9, 440.0, 24000, 0.1);
+        let original = quiet.clone();
+        peak_limit(&mut quiet, 0.98);
+        assert_eq!(quiet, original);
+    }
+
+    #[test]
+    fn test_trim_leading_silence() {
+        let mut samples = vec![0.0; 2000];
+        samples.extend([0.001, 0.005, 0.2, 0.5, 0.2]);
+
+        trim_leading_silence(&mut samples, 0.01);
+        // The margin before the onset is kept
+        assert_eq!(samples.len(), TRIM_MARGIN_SAMPLES + 3);
+        assert_eq!(samples[TRIM_MARGIN_SAMPLES], 0.2);
+        assert_eq!(&samples[TRIM_MARGIN_SAMPLES - 2..][..2], [0.001, 0.005]);
+
+        // Speech starting inside the margin, and silence, are untouched
+        let mut early = vec![0.0, 0.0, 0.3];
+        trim_leading_silence(&mut early, 0.01);
+        assert_eq!(early, [0.0, 0.0, 0.3]);
+        let mut silence = vec![0.0; 1000];
+        trim_leading_silence(&mut silence, 0.01);
+        assert_eq!(silence.len(), 1000);
+    }
+
+    #[test]
+    fn test_fades_ramp_ends_to_silence() {
+        let mut samples = vec![0.5; 1000];
+        fade_in(&mut samples, 100);
+        fade_out(&mut samples, 100);
+
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[999], 0.0);
+        // Strictly rising over the fade-in, falling over the fade-out
+        assert!(samples[..100].windows(2).all(|w| w[0] < w[1]));
+        assert!(samples[900..].windows(2).all(|w| w[0] > w[1]));
+        assert!(samples[100..900].iter().all(|&s| s == 0.5));
+
+        // A fade longer than the audio covers all of it
+        let mut short = vec![1.0; 10];
+        fade_in(&mut short, 100);
+        assert_eq!(short[0], 0.0);
+        assert!((short[9] - 0.9).abs() < 1e-6);
+    }
+}
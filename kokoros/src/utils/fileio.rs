@@ -24,7 +24,10 @@ pub async fn download_file_from_url(
             .unwrap()
             .progress_chars("#>-"));
 
-        let mut file = File::create(path).await?;
+        // Download next to the target and move it into place at the end, so
+        // a failed download never replaces a good file
+        let partial = format!("{}.part", path);
+        let mut file = File::create(&partial).await?;
         let mut downloaded = 0;
 
         while let Some(chunk) = resp.chunk().await? {
@@ -32,6 +35,9 @@ pub async fn download_file_from_url(
             downloaded += chunk.len();
             pb.set_position(downloaded.try_into()?);
         }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&partial, path).await?;
 
         pb.finish_with_message("Download completed");
         Ok(())
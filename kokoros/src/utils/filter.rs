@@ -0,0 +1,80 @@
+/// How long the gate stays open after the signal drops below the threshold.
+/// Keeps short dips between syllables from being chopped.
+const GATE_HOLD_MS: f32 = 50.0;
+
+/// Gain applied while the gate is closed (about -26 dB) rather than full silence,
+/// so anything mistakenly gated stays faintly audible
+const GATE_FLOOR: f32 = 0.05;
+
+/// Smoothing coefficient for a one-pole ramp lasting roughly `ms` milliseconds
+fn ramp_coeff(ms: f32, sample_rate: u32) -> f32 {
+    let ramp_samples = ms * sample_rate as f32 / 1000.0;
+    if ramp_samples <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / ramp_samples).exp()
+    }
+}
+
+/// Attenuate segments whose level stays below `threshold` (e.g. breaths between phrases).
+///
+/// `attack_ms` is how fast the gate opens once the signal crosses the threshold,
+/// `release_ms` how fast it closes after the hold time has elapsed.
+pub fn noise_gate(
+    samples: &mut [f32],
+    sample_rate: u32,
+    threshold: f32,
+    attack_ms: f32,
+    release_ms: f32,
+) {
+    let attack = ramp_coeff(attack_ms, sample_rate);
+    let release = ramp_coeff(release_ms, sample_rate);
+    let hold_samples = (GATE_HOLD_MS * sample_rate as f32 / 1000.0) as usize;
+
+    let mut gain = 1.0f32;
+    let mut hold = hold_samples;
+
+    for sample in samples.iter_mut() {
+        if sample.abs() >= threshold {
+            hold = hold_samples;
+        } else {
+            hold = hold.saturating_sub(1);
+        }
+
+        let target = if hold > 0 { 1.0 } else { GATE_FLOOR };
+        let coeff = if target > gain { attack } else { release };
+        gain += (target - gain) * coeff;
+
+        *sample *= gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_gate_quiet_then_loud() {
+        let sample_rate = 24000;
+        let half = sample_rate as usize / 2;
+
+        // 0.5s of faint noise followed by 0.5s of a loud tone
+        let mut samples: Vec<f32> = (0..half)
+            .map(|i| if i % 2 == 0 { 0.002 } else { -0.002 })
+            .chain((0..half).map(|i| 0.5 * (i as f32 * 0.05).sin()))
+            .collect();
+        let original = samples.clone();
+
+        noise_gate(&mut samples, sample_rate, 0.01, 5.0, 50.0);
+
+        // The tail of the quiet section is attenuated
+        let quiet_tail = &samples[half - 2400..half];
+        assert!(quiet_tail.iter().all(|s| s.abs() < 0.002 * 0.1));
+
+        // The loud section passes once the gate has opened
+        let attack_done = half + 1200;
+        for (gated, orig) in samples[attack_done..].iter().zip(&original[attack_done..]) {
+            assert!((gated - orig).abs() < 0.01);
+        }
+    }
+}
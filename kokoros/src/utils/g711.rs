@@ -0,0 +1,161 @@
+//! G.711 companding for telephony: 8-bit mu-law (North America, Japan) and
+//! A-law (everywhere else), both at 8000 Hz. Encoders follow the reference
+//! segment tables of ITU-T G.711, so output plays in any PBX or SIP stack.
+
+use crate::utils::audio::{ResampleQuality, resample};
+
+/// The only sample rate G.711 is defined for
+pub const SAMPLE_RATE: u32 = 8000;
+
+/// Added to mu-law magnitudes so every segment starts on a power of two
+const ULAW_BIAS: i32 = 0x84;
+
+/// Largest magnitude mu-law can represent once the bias is added
+const ULAW_CLIP: i32 = 32635;
+
+/// Upper bound of each A-law segment, in 13-bit magnitudes
+const ALAW_SEGMENT_ENDS: [i32; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let sample = sample as i32;
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = sample.abs().min(ULAW_CLIP) + ULAW_BIAS;
+    let exponent = (31 - magnitude.leading_zeros() as i32 - 7).clamp(0, 7);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+pub fn ulaw_to_linear(code: u8) -> i16 {
+    let code = !code;
+    let exponent = (code >> 4) & 0x07;
+    let mantissa = (code & 0x0F) as i32;
+    let magnitude = (((mantissa << 3) + ULAW_BIAS) << exponent) - ULAW_BIAS;
+    if code & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    // A-law works on 13-bit samples
+    let sample = (sample as i32) >> 3;
+    let (mask, magnitude) = if sample >= 0 {
+        (0xD5, sample)
+    } else {
+        (0x55, -sample - 1)
+    };
+    let Some(segment) = ALAW_SEGMENT_ENDS.iter().position(|&end| magnitude <= end) else {
+        return 0x7F ^ mask;
+    };
+    let shift = if segment < 2 { 1 } else { segment };
+    let code = ((segment as i32) << 4) | ((magnitude >> shift) & 0x0F);
+    code as u8 ^ mask
+}
+
+pub fn alaw_to_linear(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let segment = (code & 0x70) >> 4;
+    let mut magnitude = ((code & 0x0F) as i32) << 4;
+    magnitude += if segment == 0 { 8 } else { 0x108 };
+    if segment > 1 {
+        magnitude <<= segment - 1;
+    }
+    if code & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample * 32767.0).clamp(-32768.0, 32767.0) as i16
+}
+
+/// Resample `samples` to [`SAMPLE_RATE`] and mu-law encode them, one byte per sample
+pub fn encode_ulaw(samples: &[f32], sample_rate: u32, quality: ResampleQuality) -> Vec<u8> {
+    resample(samples, sample_rate, SAMPLE_RATE, quality)
+        .into_iter()
+        .map(|sample| linear_to_ulaw(to_i16(sample)))
+        .collect()
+}
+
+/// Resample `samples` to [`SAMPLE_RATE`] and A-law encode them, one byte per sample
+pub fn encode_alaw(samples: &[f32], sample_rate: u32, quality: ResampleQuality) -> Vec<u8> {
+    resample(samples, sample_rate, SAMPLE_RATE, quality)
+        .into_iter()
+        .map(|sample| linear_to_alaw(to_i16(sample)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(linear_to_ulaw(0), 0xFF);
+        assert_eq!(linear_to_ulaw(i16::MAX), 0x80);
+        assert_eq!(linear_to_ulaw(i16::MIN), 0x00);
+        assert_eq!(ulaw_to_linear(0x00), -32124);
+        assert_eq!(ulaw_to_linear(0x80), 32124);
+
+        assert_eq!(linear_to_alaw(0), 0xD5);
+        assert_eq!(linear_to_alaw(i16::MAX), 0xAA);
+        assert_eq!(linear_to_alaw(i16::MIN), 0x2A);
+        assert_eq!(alaw_to_linear(0xD5), 8);
+        assert_eq!(alaw_to_linear(0xAA), 32256);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Every code decodes to a level that encodes back to it, except
+        // mu-law's negative zero
+        for code in 0..=255u8 {
+            assert_eq!(
+                linear_to_alaw(alaw_to_linear(code)),
+                code,
+                "A-law {:#04x}",
+                code
+            );
+            if code != 0x7F {
+                assert_eq!(
+                    linear_to_ulaw(ulaw_to_linear(code)),
+                    code,
+                    "mu-law {:#04x}",
+                    code
+                );
+            }
+        }
+
+        // Quantization error grows with the level but stays within a step
+        for sample in [-20000i16, -1000, -100, -5, 5, 100, 1000, 20000] {
+            let ulaw = ulaw_to_linear(linear_to_ulaw(sample)) as i32;
+            let alaw = alaw_to_linear(linear_to_alaw(sample)) as i32;
+            let tolerance = (sample as i32).abs() / 16 + 8;
+            assert!(
+                (ulaw - sample as i32).abs() <= tolerance,
+                "{} -> {}",
+                sample,
+                ulaw
+            );
+            assert!(
+                (alaw - sample as i32).abs() <= tolerance,
+                "{} -> {}",
+                sample,
+                alaw
+            );
+        }
+
+        // One byte per sample at 8000 Hz
+        let silence = vec![0.0; 24000];
+        assert_eq!(
+            encode_ulaw(&silence, 24000, ResampleQuality::Sinc).len(),
+            8000
+        );
+        assert_eq!(
+            encode_alaw(&silence, 24000, ResampleQuality::Linear),
+            vec![0xD5; 8000]
+        );
+    }
+}
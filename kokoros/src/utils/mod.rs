@@ -1,4 +1,9 @@
+pub mod aac;
+pub mod audio;
 pub mod debug;
 pub mod fileio;
+pub mod filter;
+pub mod g711;
 pub mod mp3;
 pub mod wav;
+pub mod webm;
@@ -1,6 +1,42 @@
-use mp3lame_encoder::{Builder, FlushNoGap, Id3Tag, MonoPcm};
+use mp3lame_encoder::{Bitrate, Builder, FlushNoGap, Id3Tag, MonoPcm};
+
+/// Bitrate used when the caller doesn't ask for a specific one
+pub const DEFAULT_BITRATE_KBPS: u32 = 192;
+
+/// Map a bitrate in kbps to one of the constant bitrates LAME supports
+pub fn bitrate_from_kbps(kbps: u32) -> Option<Bitrate> {
+    let bitrate = match kbps {
+        8 => Bitrate::Kbps8,
+        16 => Bitrate::Kbps16,
+        24 => Bitrate::Kbps24,
+        32 => Bitrate::Kbps32,
+        40 => Bitrate::Kbps40,
+        48 => Bitrate::Kbps48,
+        64 => Bitrate::Kbps64,
+        80 => Bitrate::Kbps80,
+        96 => Bitrate::Kbps96,
+        112 => Bitrate::Kbps112,
+        128 => Bitrate::Kbps128,
+        160 => Bitrate::Kbps160,
+        192 => Bitrate::Kbps192,
+        224 => Bitrate::Kbps224,
+        256 => Bitrate::Kbps256,
+        320 => Bitrate::Kbps320,
+        _ => return None,
+    };
+    Some(bitrate)
+}
+
+pub fn pcm_to_mp3(
+    pcm_data: &[f32],
+    sample_rate: u32,
+    bitrate_kbps: u32,
+) -> Result<Vec<u8>, std::io::Error> {
+    let bitrate = bitrate_from_kbps(bitrate_kbps).ok_or(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Unsupported MP3 bitrate: {} kbps", bitrate_kbps),
+    ))?;
 
-pub fn pcm_to_mp3(pcm_data: &[f32], sample_rate: u32) -> Result<Vec<u8>, std::io::Error> {
     let mut mp3_encoder = Builder::new().ok_or(std::io::Error::new(
         std::io::ErrorKind::Other,
         format!("Encoder init failed"),
@@ -18,14 +54,12 @@ pub fn pcm_to_mp3(pcm_data: &[f32], sample_rate: u32) -> Result<Vec<u8>, std::io
             format!("Set sample rate failed: {:?}", e),
         )
     })?;
-    mp3_encoder
-        .set_brate(mp3lame_encoder::Bitrate::Kbps192)
-        .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Set bitrate failed: {:?}", e),
-            )
-        })?;
+    mp3_encoder.set_brate(bitrate).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Set bitrate failed: {:?}", e),
+        )
+    })?;
     mp3_encoder
         .set_quality(mp3lame_encoder::Quality::Best)
         .map_err(|e| {
@@ -196,3 +230,168 @@ impl Mp3StreamEncoder {
         Ok(out)
     }
 }
+
+/// Text frames set by [`tag_id3v2`]
+const LENGTH_FRAME: &[u8; 4] = b"TLEN";
+const TITLE_FRAME: &[u8; 4] = b"TIT2";
+
+/// ID3v2 header flags that change how the frames are laid out
+const ID3_UNSYNCHRONISATION: u8 = 0x80;
+const ID3_EXTENDED_HEADER: u8 = 0x40;
+const ID3_FOOTER: u8 = 0x10;
+
+fn to_syncsafe(n: usize) -> [u8; 4] {
+    [21, 14, 7, 0].map(|shift| ((n >> shift) & 0x7F) as u8)
+}
+
+fn from_syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, &b| (n << 7) | (b & 0x7F) as usize)
+}
+
+/// Encode an ID3v2 frame size, which is syncsafe from version 2.4 on
+fn frame_size_bytes(major: u8, size: usize) -> [u8; 4] {
+    if major >= 4 {
+        to_syncsafe(size)
+    } else {
+        (size as u32).to_be_bytes()
+    }
+}
+
+/// The version and frames of the ID3v2 tag at the start of `mp3`, plus
+/// where the audio after it begins. Frames of tags laid out in ways this
+/// doesn't parse come back empty, so the tag is replaced rather than kept.
+fn leading_id3v2(mp3: &[u8]) -> Option<(u8, Vec<&[u8]>, usize)> {
+    if mp3.len() < 10 || &mp3[..3] != b"ID3" {
+        return None;
+    }
+    let (major, flags) = (mp3[3], mp3[5]);
+    let size = from_syncsafe(&mp3[6..10]);
+    let footer = if flags & ID3_FOOTER != 0 { 10 } else { 0 };
+    let end = 10 + size + footer;
+    if end > mp3.len() {
+        return None;
+    }
+    if !(3..=4).contains(&major) || flags & (ID3_UNSYNCHRONISATION | ID3_EXTENDED_HEADER) != 0 {
+        return Some((major, Vec::new(), end));
+    }
+
+    let mut frames = Vec::new();
+    let mut body = &mp3[10..10 + size];
+    // Padding (zeros) or a truncated frame ends the frame list
+    while body.len() >= 10 && body[0] != 0 {
+        let frame_size = if major >= 4 {
+            from_syncsafe(&body[4..8])
+        } else {
+            u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize
+        };
+        if 10 + frame_size > body.len() {
+            break;
+        }
+        let (frame, rest) = body.split_at(10 + frame_size);
+        frames.push(frame);
+        body = rest;
+    }
+    Some((major, frames, end))
+}
+
+/// A text frame holding `text`, as UTF-8 in ID3v2.4 and UTF-16 before it
+fn text_frame(major: u8, id: &[u8; 4], text: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    if major >= 4 {
+        payload.push(0x03);
+        payload.extend_from_slice(text.as_bytes());
+    } else {
+        payload.extend_from_slice(&[0x01, 0xFF, 0xFE]);
+        payload.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    }
+
+    let mut frame = Vec::with_capacity(10 + payload.len());
+    frame.extend_from_slice(id);
+    frame.extend_from_slice(&frame_size_bytes(major, payload.len()));
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend(payload);
+    frame
+}
+
+/// Set the ID3v2 length frame (`TLEN`, in milliseconds), and the title
+/// (`TIT2`) when given, on encoded MP3 audio. Players read the length from
+/// the tag to size their scrubber without scanning the file. Other frames
+/// of an existing tag, such as the one LAME writes, are kept.
+pub fn tag_id3v2(mp3: &[u8], length_ms: u64, title: Option<&str>) -> Vec<u8> {
+    let (major, kept, audio_start) = match leading_id3v2(mp3) {
+        Some((major @ 3..=4, frames, end)) => (major, frames, end),
+        Some((_, _, end)) => (3, Vec::new(), end),
+        None => (3, Vec::new(), 0),
+    };
+    let replaced = |frame: &&[u8]| {
+        &frame[..4] == LENGTH_FRAME || (title.is_some() && &frame[..4] == TITLE_FRAME)
+    };
+
+    let mut frames: Vec<u8> = kept
+        .into_iter()
+        .filter(|frame| !replaced(frame))
+        .flatten()
+        .copied()
+        .collect();
+    frames.extend(text_frame(major, LENGTH_FRAME, &length_ms.to_string()));
+    if let Some(title) = title {
+        frames.extend(text_frame(major, TITLE_FRAME, title));
+    }
+
+    let mut tagged = Vec::with_capacity(10 + frames.len() + mp3.len() - audio_start);
+    tagged.extend_from_slice(&[b'I', b'D', b'3', major, 0, 0]);
+    tagged.extend_from_slice(&to_syncsafe(frames.len()));
+    tagged.extend(frames);
+    tagged.extend_from_slice(&mp3[audio_start..]);
+    tagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text of frame `id` in the leading tag, for either text encoding written
+    fn text_of(mp3: &[u8], id: &[u8; 4]) -> Option<String> {
+        let (_, frames, _) = leading_id3v2(mp3)?;
+        let frame = frames.into_iter().find(|frame| &frame[..4] == id)?;
+        match frame[10] {
+            0x03 => String::from_utf8(frame[11..].to_vec()).ok(),
+            _ => {
+                let units: Vec<u16> = frame[13..]
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+        }
+    }
+
+    #[test]
+    fn test_tag_id3v2_sets_length() {
+        // 1.5 s of audio
+        let mp3 = pcm_to_mp3(&vec![0.0; 36000], 24000, DEFAULT_BITRATE_KBPS).unwrap();
+        let tagged = tag_id3v2(&mp3, 1500, Some("Chapter 1 — Café"));
+
+        assert_eq!(&tagged[..3], b"ID3");
+        assert_eq!(text_of(&tagged, LENGTH_FRAME).as_deref(), Some("1500"));
+        assert_eq!(
+            text_of(&tagged, TITLE_FRAME).as_deref(),
+            Some("Chapter 1 — Café")
+        );
+        // The audio after the tag is untouched
+        let (_, _, audio_start) = leading_id3v2(&tagged).unwrap();
+        let (_, _, original_start) = leading_id3v2(&mp3).unwrap_or((0, Vec::new(), 0));
+        assert_eq!(&tagged[audio_start..], &mp3[original_start..]);
+
+        // Tagging again replaces the length rather than adding a second one
+        let retagged = tag_id3v2(&tagged, 2000, None);
+        let (_, frames, _) = leading_id3v2(&retagged).unwrap();
+        let lengths = frames.iter().filter(|f| &f[..4] == LENGTH_FRAME).count();
+        assert_eq!(lengths, 1);
+        assert_eq!(text_of(&retagged, LENGTH_FRAME).as_deref(), Some("2000"));
+        assert_eq!(
+            text_of(&retagged, TITLE_FRAME).as_deref(),
+            Some("Chapter 1 — Café")
+        );
+    }
+}
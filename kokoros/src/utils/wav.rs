@@ -1,5 +1,10 @@
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 
+/// Bit depths [`write_samples`] can write: 16 and 24-bit integer PCM, and
+/// 32-bit IEEE float
+pub const SUPPORTED_BIT_DEPTHS: [u16; 3] = [16, 24, 32];
+
+/// 32-bit samples are written as IEEE float, other depths as integer PCM
 pub struct WavHeader {
     pub channels: u16,
     pub sample_rate: u32,
@@ -15,16 +20,37 @@ impl WavHeader {
         }
     }
 
+    /// `WAVE_FORMAT_IEEE_FLOAT` (3) for 32-bit samples, `WAVE_FORMAT_PCM` (1) otherwise
+    pub fn format_code(&self) -> u16 {
+        if self.bits_per_sample == 32 { 3 } else { 1 }
+    }
+
+    /// Header with placeholder (`0xFFFFFFFF`) sizes, for output whose length
+    /// isn't known up front
     pub fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_header_with_sizes(writer, u32::MAX, u32::MAX)
+    }
+
+    /// Header for exactly `data_len` bytes of samples
+    pub fn write_sized_header<W: Write>(&self, writer: &mut W, data_len: u32) -> io::Result<()> {
+        self.write_header_with_sizes(writer, data_len.saturating_add(36), data_len)
+    }
+
+    fn write_header_with_sizes<W: Write>(
+        &self,
+        writer: &mut W,
+        riff_size: u32,
+        data_size: u32,
+    ) -> io::Result<()> {
         // RIFF header
         writer.write_all(b"RIFF")?;
-        writer.write_all(&[0xFF, 0xFF, 0xFF, 0xFF])?; // File size - 8 (placeholder)
+        writer.write_all(&riff_size.to_le_bytes())?; // File size - 8
         writer.write_all(b"WAVE")?;
 
         // Format chunk
         writer.write_all(b"fmt ")?;
         writer.write_all(&(16u32).to_le_bytes())?; // Format chunk size
-        writer.write_all(&(3u16).to_le_bytes())?; // Format = 3 (IEEE float)
+        writer.write_all(&self.format_code().to_le_bytes())?;
         writer.write_all(&self.channels.to_le_bytes())?;
         writer.write_all(&self.sample_rate.to_le_bytes())?;
         let byte_rate =
@@ -36,7 +62,7 @@ impl WavHeader {
 
         // Data chunk header
         writer.write_all(b"data")?;
-        writer.write_all(&[0xFF, 0xFF, 0xFF, 0xFF])?; // Data size (placeholder)
+        writer.write_all(&data_size.to_le_bytes())?;
 
         Ok(())
     }
@@ -48,3 +74,135 @@ pub fn write_audio_chunk<W: Write>(writer: &mut W, samples: &[f32]) -> io::Resul
     }
     Ok(())
 }
+
+/// Write `samples` little-endian at one of [`SUPPORTED_BIT_DEPTHS`], the
+/// sample format matching [`WavHeader::format_code`]
+pub fn write_samples<W: Write>(
+    writer: &mut W,
+    samples: &[f32],
+    bits_per_sample: u16,
+) -> io::Result<()> {
+    match bits_per_sample {
+        16 => {
+            for sample in samples {
+                let sample = (sample * 32767.0).clamp(-32768.0, 32767.0) as i16;
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+            Ok(())
+        }
+        24 => {
+            for sample in samples {
+                let sample = (sample * 8388607.0).clamp(-8388608.0, 8388607.0) as i32;
+                writer.write_all(&sample.to_le_bytes()[..3])?;
+            }
+            Ok(())
+        }
+        32 => write_audio_chunk(writer, samples),
+        bits => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported bit depth: {}", bits),
+        )),
+    }
+}
+
+/// Reverse the bytes of each `bytes_per_sample` sample in `data`, turning
+/// the little-endian output of [`write_samples`] big-endian
+pub fn swap_sample_bytes(data: &mut [u8], bytes_per_sample: usize) {
+    for sample in data.chunks_exact_mut(bytes_per_sample) {
+        sample.reverse();
+    }
+}
+
+/// Decode a WAV file of any depth hound reads, averaging its channels down
+/// to mono. Returns the samples and their rate.
+pub fn read_wav<R: Read>(reader: R) -> Result<(Vec<f32>, u32), String> {
+    let mut reader = hound::WavReader::new(reader).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect()
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((samples, spec.sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_sizes() {
+        let header = WavHeader::new(1, 24000, 32);
+
+        let mut streaming = Vec::new();
+        header.write_header(&mut streaming).unwrap();
+        assert_eq!(streaming.len(), 44);
+        assert_eq!(&streaming[4..8], &[0xFF; 4]);
+        assert_eq!(&streaming[40..44], &[0xFF; 4]);
+
+        let mut sized = Vec::new();
+        header.write_sized_header(&mut sized, 400).unwrap();
+        assert_eq!(&sized[4..8], &436u32.to_le_bytes());
+        assert_eq!(&sized[40..44], &400u32.to_le_bytes());
+        // Only the size fields differ
+        assert_eq!(&sized[8..40], &streaming[8..40]);
+    }
+
+    #[test]
+    fn test_bit_depths() {
+        let samples = [0.0, 0.5, -1.0, 1.0];
+        for (bits, format_code) in [(16u16, 1u16), (24, 1), (32, 3)] {
+            let bytes = bits as usize / 8;
+            let mut data = Vec::new();
+            write_samples(&mut data, &samples, bits).unwrap();
+            assert_eq!(data.len(), samples.len() * bytes, "{} bits", bits);
+
+            let mut wav = Vec::new();
+            WavHeader::new(1, 24000, bits)
+                .write_sized_header(&mut wav, data.len() as u32)
+                .unwrap();
+            assert_eq!(&wav[20..22], &format_code.to_le_bytes());
+            assert_eq!(&wav[28..32], &(24000 * bytes as u32).to_le_bytes());
+            assert_eq!(&wav[32..34], &(bytes as u16).to_le_bytes());
+            assert_eq!(&wav[34..36], &bits.to_le_bytes());
+            assert_eq!(&wav[40..44], &(data.len() as u32).to_le_bytes());
+        }
+
+        let mut data = Vec::new();
+        write_samples(&mut data, &[-1.0, 1.0], 24).unwrap();
+        assert_eq!(data, vec![0x01, 0x00, 0x80, 0xFF, 0xFF, 0x7F]);
+        assert!(write_samples(&mut data, &[0.0], 8).is_err());
+    }
+
+    #[test]
+    fn test_read_wav_mixes_to_mono() {
+        for bits in SUPPORTED_BIT_DEPTHS {
+            // Stereo frames: (0.5, 0.0) then (-0.5, -0.25)
+            let mut data = Vec::new();
+            write_samples(&mut data, &[0.5, 0.0, -0.5, -0.25], bits).unwrap();
+            let mut wav = Vec::new();
+            WavHeader::new(2, 16000, bits)
+                .write_sized_header(&mut wav, data.len() as u32)
+                .unwrap();
+            wav.extend(data);
+
+            let (samples, sample_rate) = read_wav(wav.as_slice()).unwrap();
+            assert_eq!(sample_rate, 16000);
+            assert_eq!(samples.len(), 2);
+            assert!((samples[0] - 0.25).abs() < 1e-3, "{} bits", bits);
+            assert!((samples[1] + 0.375).abs() < 1e-3, "{} bits", bits);
+        }
+        assert!(read_wav(&b"not a wav"[..]).is_err());
+    }
+}
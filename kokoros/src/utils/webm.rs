@@ -0,0 +1,414 @@
+//! Minimal streamable WebM (Matroska) muxer for Opus or PCM audio.
+//!
+//! Only the elements a player needs are written: the EBML header, a live
+//! (unknown-size) Segment with Info and Tracks, then one Cluster per batch of
+//! packets, so the file can be played while it is still being written.
+//!
+//! PCM tracks (`A_PCM/INT/LIT`) need no encoder and are what the server
+//! sends. They play in an `<audio>` element, but MediaSource Extensions don't
+//! accept them, so appending to a `SourceBuffer` needs the Opus track. The
+//! server has no Opus encoder, so that one is only for callers that bring
+//! their own.
+
+// Element ids
+const EBML: u32 = 0x1A45DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMECODE_SCALE: u32 = 0x2AD7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const CODEC_DELAY: u32 = 0x56AA;
+const SEEK_PRE_ROLL: u32 = 0x56BB;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const BIT_DEPTH: u32 = 0x6264;
+const CLUSTER: u32 = 0x1F43B675;
+const TIMECODE: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// "Unknown" element size, used for the live Segment
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Opus always runs at 48kHz internally
+const OPUS_RATE: u64 = 48000;
+
+/// Block timecodes are relative i16 offsets, so keep clusters well under 32.7s
+const MAX_CLUSTER_MS: u64 = 30_000;
+
+/// Length of the blocks [`WebmMuxer::write_pcm`] cuts PCM into
+const PCM_BLOCK_MS: u64 = 20;
+
+fn write_id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// EBML variable-length size, always using 8 bytes for simplicity
+fn write_size(out: &mut Vec<u8>, size: u64) {
+    let mut bytes = size.to_be_bytes();
+    bytes[0] = 0x01;
+    out.extend_from_slice(&bytes);
+}
+
+fn element(out: &mut Vec<u8>, id: u32, payload: &[u8]) {
+    write_id(out, id);
+    write_size(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
+
+fn uint_element(out: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(out, id, &bytes[skip..]);
+}
+
+fn float_element(out: &mut Vec<u8>, id: u32, value: f64) {
+    element(out, id, &value.to_be_bytes());
+}
+
+/// `OpusHead` identification header, stored as the track's CodecPrivate
+fn opus_head(channels: u8, pre_skip: u16, input_sample_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(channels);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // mapping family: mono/stereo
+    head
+}
+
+/// What the track of a [`WebmMuxer`] carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebmCodec {
+    Opus {
+        pre_skip: u16,
+        input_sample_rate: u32,
+        /// Duration of each Opus packet, e.g. 20ms
+        frame_ms: u64,
+    },
+    /// 16-bit little-endian PCM, blocks of any length
+    Pcm { sample_rate: u32 },
+}
+
+pub struct WebmMuxer {
+    channels: u8,
+    codec: WebmCodec,
+    /// Start of the next packet, in samples at [`Self::clock_rate`]
+    position: u64,
+}
+
+impl WebmMuxer {
+    pub fn opus(channels: u8, pre_skip: u16, input_sample_rate: u32, frame_ms: u64) -> Self {
+        Self::new(
+            channels,
+            WebmCodec::Opus {
+                pre_skip,
+                input_sample_rate,
+                frame_ms,
+            },
+        )
+    }
+
+    pub fn pcm(channels: u8, sample_rate: u32) -> Self {
+        Self::new(channels, WebmCodec::Pcm { sample_rate })
+    }
+
+    pub fn new(channels: u8, codec: WebmCodec) -> Self {
+        Self {
+            channels,
+            codec,
+            position: 0,
+        }
+    }
+
+    /// Samples per second the position counts in
+    fn clock_rate(&self) -> u64 {
+        match self.codec {
+            WebmCodec::Opus { .. } => OPUS_RATE,
+            WebmCodec::Pcm { sample_rate } => sample_rate.max(1) as u64,
+        }
+    }
+
+    /// Samples a packet holds
+    fn packet_samples(&self, packet: &[u8]) -> u64 {
+        match self.codec {
+            WebmCodec::Opus { frame_ms, .. } => frame_ms * OPUS_RATE / 1000,
+            WebmCodec::Pcm { .. } => (packet.len() / (2 * self.channels.max(1) as usize)) as u64,
+        }
+    }
+
+    fn position_ms(&self) -> u64 {
+        self.position * 1000 / self.clock_rate()
+    }
+
+    /// EBML header, Segment start, Info and Tracks; must be sent before any cluster
+    pub fn header(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        let mut ebml = Vec::new();
+        uint_element(&mut ebml, EBML_VERSION, 1);
+        uint_element(&mut ebml, EBML_READ_VERSION, 1);
+        uint_element(&mut ebml, EBML_MAX_ID_LENGTH, 4);
+        uint_element(&mut ebml, EBML_MAX_SIZE_LENGTH, 8);
+        element(&mut ebml, DOC_TYPE, b"webm");
+        uint_element(&mut ebml, DOC_TYPE_VERSION, 4);
+        uint_element(&mut ebml, DOC_TYPE_READ_VERSION, 2);
+        element(&mut out, EBML, &ebml);
+
+        // Live segment of unknown length, clusters follow as they're produced
+        write_id(&mut out, SEGMENT);
+        out.extend_from_slice(&UNKNOWN_SIZE);
+
+        let mut info = Vec::new();
+        uint_element(&mut info, TIMECODE_SCALE, 1_000_000); // timecodes in ms
+        element(&mut info, MUXING_APP, b"kokoros");
+        element(&mut info, WRITING_APP, b"kokoros");
+        element(&mut out, INFO, &info);
+
+        let mut audio = Vec::new();
+        float_element(&mut audio, SAMPLING_FREQUENCY, self.clock_rate() as f64);
+        uint_element(&mut audio, CHANNELS, self.channels as u64);
+
+        let mut track = Vec::new();
+        uint_element(&mut track, TRACK_NUMBER, 1);
+        uint_element(&mut track, TRACK_UID, 1);
+        uint_element(&mut track, TRACK_TYPE, 2); // audio
+        match self.codec {
+            WebmCodec::Opus {
+                pre_skip,
+                input_sample_rate,
+                ..
+            } => {
+                element(&mut track, CODEC_ID, b"A_OPUS");
+                element(
+                    &mut track,
+                    CODEC_PRIVATE,
+                    &opus_head(self.channels, pre_skip, input_sample_rate),
+                );
+                uint_element(
+                    &mut track,
+                    CODEC_DELAY,
+                    pre_skip as u64 * 1_000_000_000 / OPUS_RATE,
+                );
+                uint_element(&mut track, SEEK_PRE_ROLL, 80_000_000);
+            }
+            WebmCodec::Pcm { .. } => {
+                element(&mut track, CODEC_ID, b"A_PCM/INT/LIT");
+                uint_element(&mut audio, BIT_DEPTH, 16);
+            }
+        }
+        element(&mut track, AUDIO, &audio);
+
+        let mut tracks = Vec::new();
+        element(&mut tracks, TRACK_ENTRY, &track);
+        element(&mut out, TRACKS, &tracks);
+
+        out
+    }
+
+    /// Wrap packets in one or more clusters, continuing from the previous call's timestamp
+    pub fn write_packets(&mut self, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut cluster: Option<(u64, Vec<u8>)> = None;
+
+        for packet in packets {
+            let position_ms = self.position_ms();
+            if cluster
+                .as_ref()
+                .is_some_and(|(start, _)| position_ms - start >= MAX_CLUSTER_MS)
+            {
+                let (_, finished) = cluster.take().unwrap();
+                element(&mut out, CLUSTER, &finished);
+            }
+            let (cluster_start, cluster) = cluster.get_or_insert_with(|| {
+                let mut cluster = Vec::new();
+                uint_element(&mut cluster, TIMECODE, position_ms);
+                (position_ms, cluster)
+            });
+
+            let relative = (position_ms - *cluster_start) as i16;
+            let mut block = vec![0x81]; // track number 1 as a 1-byte vint
+            block.extend_from_slice(&relative.to_be_bytes());
+            block.push(0x80); // keyframe
+            block.extend_from_slice(packet);
+            element(cluster, SIMPLE_BLOCK, &block);
+            self.position += self.packet_samples(packet);
+        }
+
+        if let Some((_, cluster)) = cluster {
+            element(&mut out, CLUSTER, &cluster);
+        }
+        out
+    }
+
+    /// Cut 16-bit PCM into short blocks and wrap them like [`Self::write_packets`]
+    pub fn write_pcm(&mut self, pcm: &[u8]) -> Vec<u8> {
+        let block_bytes = (self.clock_rate() * PCM_BLOCK_MS / 1000).max(1) as usize
+            * 2
+            * self.channels.max(1) as usize;
+        let blocks: Vec<Vec<u8>> = pcm.chunks(block_bytes).map(<[u8]>::to_vec).collect();
+        self.write_packets(&blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_header_describes_opus_track() {
+        let header = WebmMuxer::opus(1, 312, 24000, 20).header();
+
+        assert_eq!(&header[..4], &[0x1A, 0x45, 0xDF, 0xA3]);
+        assert!(contains(&header, b"webm"));
+        assert!(contains(&header, b"A_OPUS"));
+        assert!(contains(&header, b"OpusHead"));
+        // Segment has unknown size so the stream can grow
+        assert!(contains(&header, &[0x18, 0x53, 0x80, 0x67, 0x01, 0xFF]));
+    }
+
+    #[test]
+    fn test_packets_continue_timestamps_across_calls() {
+        let mut muxer = WebmMuxer::opus(1, 312, 24000, 20);
+        let first = muxer.write_packets(&[vec![1, 2], vec![3, 4]]);
+        let second = muxer.write_packets(&[vec![5, 6]]);
+
+        assert_eq!(&first[..4], &[0x1F, 0x43, 0xB6, 0x75]);
+        // Second cluster starts at 40ms: Timecode element holding 0x28
+        assert!(contains(
+            &second,
+            &[0xE7, 0x01, 0, 0, 0, 0, 0, 0, 0x01, 0x28]
+        ));
+        // Second packet of the first cluster sits 20ms in
+        assert!(contains(&first, &[0x81, 0x00, 0x14, 0x80, 3, 4]));
+    }
+
+    #[test]
+    fn test_long_input_is_split_into_clusters() {
+        let mut muxer = WebmMuxer::opus(1, 312, 24000, 20);
+        let packets = vec![vec![0u8; 4]; 2000]; // 40s of audio
+        let out = muxer.write_packets(&packets);
+
+        let clusters = out
+            .windows(4)
+            .filter(|w| *w == [0x1F, 0x43, 0xB6, 0x75])
+            .count();
+        assert_eq!(clusters, 2);
+    }
+
+    /// Length and bytes of the variable-length integer `data` starts with
+    fn vint(data: &[u8]) -> (usize, &[u8]) {
+        let length = data[0].leading_zeros() as usize + 1;
+        (length, &data[..length])
+    }
+
+    /// EBML element: id (marker bits kept), payload, or `None` for the live Segment's unknown size
+    fn read_element(data: &[u8]) -> (u32, Option<&[u8]>, &[u8]) {
+        let (id_length, id) = vint(data);
+        let id = id.iter().fold(0u32, |id, &b| id << 8 | b as u32);
+        let (size_length, size) = vint(&data[id_length..]);
+        let rest = &data[id_length + size_length..];
+        if size == UNKNOWN_SIZE {
+            return (id, None, rest);
+        }
+        let size =
+            size.iter().fold(0u64, |size, &b| size << 8 | b as u64) & !(1u64 << (7 * size_length));
+        let (payload, rest) = rest.split_at(size as usize);
+        (id, Some(payload), rest)
+    }
+
+    fn children(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut elements = Vec::new();
+        while !data.is_empty() {
+            let (id, payload, rest) = read_element(data);
+            elements.push((id, payload.unwrap()));
+            data = rest;
+        }
+        elements
+    }
+
+    #[test]
+    fn test_pcm_stream_parses_as_webm() {
+        let mut muxer = WebmMuxer::pcm(1, 24000);
+        let pcm: Vec<u8> = (0..4800u32).map(|i| i as u8).collect();
+        let mut webm = muxer.header();
+        webm.extend(muxer.write_pcm(&pcm[..2400]));
+        webm.extend(muxer.write_pcm(&pcm[2400..]));
+
+        let (id, ebml, rest) = read_element(&webm);
+        assert_eq!(id, EBML);
+        assert!(children(ebml.unwrap()).contains(&(DOC_TYPE, b"webm".as_slice())));
+        let (id, size, segment) = read_element(rest);
+        assert_eq!((id, size), (SEGMENT, None));
+
+        let segment = children(segment);
+        let ids: Vec<u32> = segment.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [INFO, TRACKS, CLUSTER, CLUSTER]);
+        let (_, track) = children(segment[1].1)[0];
+        let track = children(track);
+        assert!(track.contains(&(CODEC_ID, b"A_PCM/INT/LIT".as_slice())));
+        let (_, audio) = track.iter().find(|(id, _)| *id == AUDIO).unwrap();
+        assert!(children(audio).contains(&(BIT_DEPTH, [16].as_slice())));
+
+        // The blocks hold the PCM in order, each cluster restarting its
+        // relative timecodes where the previous one left off
+        let mut samples = Vec::new();
+        let mut block_ms = Vec::new();
+        for (_, cluster) in &segment[2..] {
+            let cluster = children(cluster);
+            assert_eq!(cluster[0].0, TIMECODE);
+            let start = cluster[0].1.iter().fold(0u64, |t, &b| t << 8 | b as u64);
+            for (id, block) in &cluster[1..] {
+                assert_eq!(*id, SIMPLE_BLOCK);
+                assert_eq!(block[0], 0x81);
+                let relative = i16::from_be_bytes([block[1], block[2]]);
+                block_ms.push(start + relative as u64);
+                samples.extend_from_slice(&block[4..]);
+            }
+        }
+        assert_eq!(samples, pcm);
+        assert_eq!(block_ms, [0, 20, 40, 50, 70, 90]);
+    }
+
+    #[test]
+    fn test_pcm_track_times_blocks_by_samples() {
+        let mut muxer = WebmMuxer::pcm(1, 24000);
+        let header = muxer.header();
+        assert!(contains(&header, b"A_PCM/INT/LIT"));
+        assert!(!contains(&header, b"OpusHead"));
+
+        // 50ms: two 20ms blocks and a 10ms one
+        let first = muxer.write_pcm(&[0u8; 2400]);
+        let blocks = first.windows(2).filter(|w| *w == [0xA3, 0x01]).count();
+        assert_eq!(blocks, 3);
+        assert!(contains(&first, &[0x81, 0x00, 0x28, 0x80]));
+
+        // The next chunk carries on at 50ms
+        let second = muxer.write_pcm(&[0u8; 960]);
+        assert!(contains(
+            &second,
+            &[0xE7, 0x01, 0, 0, 0, 0, 0, 0, 0x01, 0x32]
+        ));
+    }
+}